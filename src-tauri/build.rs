@@ -1,3 +1,69 @@
+// every command in `commands!` in src/lib.rs. Each gets an allow-<command> permission,
+// see permissions/ for how they're grouped and capabilities/ for who gets them.
+const COMMANDS: &[&str] = &[
+    "get_settings",
+    "get_locked_settings",
+    "update_settings",
+    "update_setting",
+    "get_autostart",
+    "set_autostart",
+    "handle_download_request",
+    "confirm_private_targets",
+    "get_speed_history",
+    "get_thumbnail",
+    "decrypt_download",
+    "extract_links",
+    "get_activity",
+    "get_download_details",
+    "await_download",
+    "get_app_state",
+    "get_dashboard_state",
+    "get_io_metrics",
+    "get_transfer_stats",
+    "export_statistics",
+    "get_recent_destinations",
+    "get_free_space",
+    "reload_engine",
+    "restore_session",
+    "retry_failed",
+    "test_rule",
+    "preview_rewrite",
+    "run_action",
+    "release_quarantine",
+    "refresh_presigned_url",
+    "fetch_preview",
+    "list_contents",
+    "select_files",
+    "peek_archive",
+    "download_archive_member",
+    "pause_download",
+    "pin_download",
+    "download_again",
+    "redownload",
+    "set_retry_policy",
+    "set_speed_limit",
+    "cancel_download",
+    "get_salvageable",
+    "run_maintenance",
+    "get_history_encryption",
+    "set_history_encryption",
+    "start_upload",
+    "sync_history",
+    "get_available_locales",
+    "get_notification_history",
+    "lock_app",
+    "unlock_app",
+    "list_profiles",
+    "switch_profile",
+    "test_notification_channel",
+    "test_webhook",
+    "run_benchmark",
+];
+
 fn main() {
-    tauri_build::build()
+    tauri_build::try_build(
+        tauri_build::Attributes::new()
+            .app_manifest(tauri_build::AppManifest::new().commands(COMMANDS)),
+    )
+    .expect("failed to run tauri-build");
 }
@@ -1,12 +1,40 @@
 use std::env;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::bench::BenchOptions;
+use crate::cli::{Entry, NetworkFlags, Output, Review};
+use crate::i18n::{t, tf};
 
 #[derive(Debug, Clone)]
 pub struct AppArgs {
     pub minimized: bool,
     pub debug: bool,
+    pub chaos: bool,
     pub deep_link: Option<String>,
     pub help: bool,
     pub version: bool,
+    pub bench: Option<BenchOptions>,
+    /// `tur upload <file> <endpoint>`
+    pub upload: Option<(PathBuf, String)>,
+    /// `tur verify-checksums <file> [--dir <dir>]`
+    pub verify: Option<(PathBuf, Option<PathBuf>)>,
+    /// `tur wait <id>`
+    pub wait: Option<String>,
+    /// `tur gen-bindings [path]`
+    pub gen_bindings: Option<PathBuf>,
+    /// plain http(s) urls, downloaded in the terminal
+    pub urls: Vec<Entry>,
+    /// `-`, read more urls from stdin
+    pub stdin: bool,
+    /// `-f`, url files with one `url` or `url > name` per line
+    pub input_files: Vec<PathBuf>,
+    pub output: Output,
+    /// `--dry-run` or `--confirm`
+    pub review: Review,
+    pub network: NetworkFlags,
+    /// `--data-dir <dir>`, in place of the active profile's folder
+    pub data_dir: Option<PathBuf>,
 }
 
 impl Default for AppArgs {
@@ -14,9 +42,22 @@ impl Default for AppArgs {
         Self {
             minimized: false,
             debug: false,
+            chaos: false,
             deep_link: None,
             help: false,
             version: false,
+            bench: None,
+            upload: None,
+            verify: None,
+            wait: None,
+            gen_bindings: None,
+            urls: Vec::new(),
+            stdin: false,
+            input_files: Vec::new(),
+            output: Output::Directory,
+            review: Review::None,
+            network: NetworkFlags::default(),
+            data_dir: None,
         }
     }
 }
@@ -35,6 +76,9 @@ impl AppArgs {
                 "--debug" | "-d" => {
                     parsed.debug = true;
                 }
+                "--chaos" => {
+                    parsed.chaos = true;
+                }
                 "--help" | "-h" => {
                     parsed.help = true;
                 }
@@ -44,6 +88,86 @@ impl AppArgs {
                 arg if arg.starts_with("tur://") => {
                     parsed.deep_link = Some(arg.to_string());
                 }
+                "-" => {
+                    parsed.stdin = true;
+                }
+                "--stdout" => {
+                    parsed.output = Output::Stdout;
+                }
+                "-O" | "--output-file" if i + 1 < args.len() => {
+                    i += 1;
+                    parsed.output = match args[i].as_str() {
+                        "-" => Output::Stdout,
+                        name => Output::File(PathBuf::from(name)),
+                    };
+                }
+                "-f" | "--input-file" if i + 1 < args.len() => {
+                    i += 1;
+                    parsed.input_files.push(PathBuf::from(&args[i]));
+                }
+                "--dry-run" => {
+                    parsed.review = Review::DryRun;
+                }
+                "--confirm" => {
+                    parsed.review = Review::Confirm;
+                }
+                "--data-dir" if i + 1 < args.len() => {
+                    i += 1;
+                    parsed.data_dir = Some(PathBuf::from(&args[i]));
+                }
+                "--insecure" | "-k" => {
+                    parsed.network.insecure = true;
+                }
+                flag @ ("--proxy" | "--cacert" | "--user-agent" | "-A") if i + 1 < args.len() => {
+                    i += 1;
+                    apply_network_flag(&mut parsed.network, flag, &args[i]);
+                }
+                arg if arg.starts_with("http://") || arg.starts_with("https://") => {
+                    match Url::parse(arg) {
+                        Ok(url) => parsed.urls.push(Entry { url, filename: None }),
+                        Err(e) => eprintln!("{}", tf("cli.invalid_url", &[("url", &arg), ("error", &e)])),
+                    }
+                }
+                "bench" if i == 1 => {
+                    parsed.bench = Some(BenchOptions::default());
+                }
+                "upload" if i == 1 && i + 2 < args.len() => {
+                    parsed.upload = Some((PathBuf::from(&args[i + 1]), args[i + 2].clone()));
+                    i += 2;
+                }
+"verify-checksums" if i == 1 && i + 1 < args.len() => {
+                    i += 1;
+                    parsed.verify = Some((PathBuf::from(&args[i]), None));
+                }
+                "wait" if i == 1 && i + 1 < args.len() => {
+                    i += 1;
+                    parsed.wait = Some(args[i].clone());
+                }
+                "gen-bindings" if i == 1 => {
+                    let path = match args.get(i + 1) {
+                        Some(path) if !path.starts_with('-') => {
+                            i += 1;
+                            path.as_str()
+                        }
+                        _ => crate::bindings::DEFAULT_PATH,
+                    };
+                    parsed.gen_bindings = Some(PathBuf::from(path));
+                }
+                "--dir" if parsed.verify.is_some() && i + 1 < args.len() => {
+                    i += 1;
+                    if let Some((_, dir)) = parsed.verify.as_mut() {
+                        *dir = Some(PathBuf::from(&args[i]));
+                    }
+                }
+                flag @ ("--size" | "--latency" | "--rate" | "--threads" | "--chunk" | "--io-threads"
+                | "--fairness")
+                    if parsed.bench.is_some() && i + 1 < args.len() =>
+                {
+                    i += 1;
+                    if let Some(options) = parsed.bench.as_mut() {
+                        apply_bench_flag(options, flag, &args[i]);
+                    }
+                }
                 _ => {
                     // Unknown argument, ignore for now
                 }
@@ -65,6 +189,9 @@ impl AppArgs {
                 "--debug" | "-d" => {
                     parsed.debug = true;
                 }
+                "--chaos" => {
+                    parsed.chaos = true;
+                }
                 "--help" | "-h" => {
                     parsed.help = true;
                 }
@@ -84,22 +211,81 @@ impl AppArgs {
     }
     
     pub fn print_help() {
-        println!("tur - A fast, modern download manager");
+        println!("{}", t("cli.about"));
         println!();
-        println!("USAGE:");
+        println!("{}", t("cli.usage"));
         println!("    tur [OPTIONS] [URL]");
+        println!("    tur [OPTIONS] <URL>...");
+        println!("    tur [OPTIONS] - < urls.txt");
+        println!("    tur bench [BENCH OPTIONS]");
+        println!("    tur upload <FILE> <ENDPOINT>");
+        println!("    tur verify-checksums <SUMS FILE> [--dir <DIR>]");
+        println!("    tur wait <ID>");
+        println!("    tur gen-bindings [PATH]");
+        println!();
+        println!("{}", t("cli.options"));
+        println!("    -m, --minimized    {}", t("cli.opt.minimized"));
+        println!("    -d, --debug        {}", t("cli.opt.debug"));
+        println!("        --chaos        {}", t("cli.opt.chaos"));
+        println!("    -h, --help         {}", t("cli.opt.help"));
+        println!("    -v, --version      {}", t("cli.opt.version"));
+        println!("        --proxy <URL>  {}", t("cli.opt.proxy"));
+        println!("    -k, --insecure     {}", t("cli.opt.insecure"));
+        println!("        --cacert <PEM> {}", t("cli.opt.cacert"));
+        println!("    -O -, --stdout     {}", t("cli.opt.stdout"));
+        println!("    -O, --output-file <NAME>");
+        println!("                       {}", t("cli.opt.output_file"));
+        println!("    -f, --input-file <FILE>");
+        println!("                       {}", t("cli.opt.input_file"));
+        println!("        --dry-run      {}", t("cli.opt.dry_run"));
+        println!("        --confirm      {}", t("cli.opt.confirm"));
+        println!("    -A, --user-agent <UA>");
+        println!("                       {}", t("cli.opt.user_agent"));
+        println!("        --data-dir <DIR>");
+        println!("                       {}", t("cli.opt.data_dir"));
+        println!();
+        println!("{}", t("cli.bench_options"));
+        println!("    --size <MiB>       {}", t("cli.bench.size"));
+        println!("    --latency <ms>     {}", t("cli.bench.latency"));
+        println!("    --rate <KiB/s>     {}", t("cli.bench.rate"));
+        println!("    --threads <list>   {}", t("cli.bench.threads"));
+        println!("    --chunk <MiB>      {}", t("cli.bench.chunk"));
+        println!("    --io-threads <n>   {}", t("cli.bench.io_threads"));
+        println!("    --fairness <MiB/s> {}", t("cli.bench.fairness"));
+        println!();
+        println!("{}", t("cli.upload"));
+        println!("    ENDPOINT           {}", t("cli.upload.endpoint"));
+        println!("                       {}", t("cli.upload.credentials"));
+        println!("                       {}", t("cli.upload.credentials_more"));
+        println!();
+        println!("{}", t("cli.verify"));
+        println!("    --dir <DIR>        {}", t("cli.verify.dir"));
         println!();
-        println!("OPTIONS:");
-        println!("    -m, --minimized    Start minimized to system tray");
-        println!("    -d, --debug        Enable debug logging");
-        println!("    -h, --help         Print this help message");
-        println!("    -v, --version      Print version information");
+        println!("{}", t("cli.wait"));
+        println!("    ID                 {}", t("cli.wait.id"));
+        println!("                       {}", t("cli.wait.exit_code"));
         println!();
-        println!("ARGUMENTS:");
-        println!("    URL                Deep link URL (tur://...)");
+        println!("{}", t("cli.gen_bindings"));
+        println!("    PATH               {}", tf("cli.gen_bindings.path", &[("path", &crate::bindings::DEFAULT_PATH)]));
         println!();
-        println!("EXAMPLES:");
+        println!("{}", t("cli.arguments"));
+        println!("    URL                {}", t("cli.arg.url"));
+        println!("    URL...             {}", t("cli.arg.download_url"));
+        println!("    -                  {}", t("cli.arg.stdin"));
+        println!();
+        println!("{}", t("cli.examples"));
         println!("    tur --minimized");
+        println!("    tur bench --size 256 --threads 1,4,16");
+        println!("    tur upload backup.tar s3://my-bucket/backups/");
+        println!("    tur verify-checksums SHA256SUMS");
+        println!("    tur wait 0199f0c2-7a4e-7d1b-9c3e-2f8a61b5d4e7 && unzip file.zip");
+        println!("    tur --proxy http://proxy:3128 https://example.com/file.zip");
+        println!("    cat urls.txt | tur -");
+        println!("    tur -O - https://example.com/src.tar.gz | tar xz");
+        println!("    tur -O latest.iso https://example.com/download?id=42");
+        println!("    tur -f urls.txt    # lines of 'URL' or 'URL > NAME'");
+        println!("    tur --dry-run -f urls.txt");
+        println!("    tur --data-dir /tmp/tur-test");
         println!("    tur 'tur://download?url=https://example.com/file.zip'");
     }
     
@@ -120,6 +306,228 @@ pub fn handle_early_args() -> bool {
         AppArgs::print_version();
         return true;
     }
+
+    if let Some(options) = &args.bench {
+        let mut options = options.clone();
+        options.chaos |= args.chaos;
+        run_bench(&options);
+        return true;
+    }
+
+    if let Some((file, endpoint)) = &args.upload {
+        run_upload(file, endpoint);
+        return true;
+    }
+
+    if let Some((file, dir)) = &args.verify {
+        run_verify(file, dir.as_deref());
+        return true;
+    }
+
+    if let Some(id) = &args.wait {
+        run_wait(id, args.data_dir.as_deref());
+        return true;
+    }
+
+    if let Some(path) = &args.gen_bindings {
+        run_gen_bindings(path);
+        return true;
+    }
+
+    // `tur -` reads urls from stdin, so does a pipe when there's nothing else to do
+    let mut urls = args.urls.clone();
+    for path in &args.input_files {
+        match std::fs::File::open(path) {
+            Ok(file) => urls.extend(crate::cli::read_urls(std::io::BufReader::new(file))),
+            Err(e) => {
+                eprintln!("{}", tf("cli.input_file_failed", &[("path", &path.display()), ("error", &e)]));
+                std::process::exit(1);
+            }
+        }
+    }
+    let piped = urls.is_empty()
+        && args.input_files.is_empty()
+        && args.deep_link.is_none()
+        && crate::cli::stdin_is_piped();
+    if args.stdin || piped {
+        urls.extend(crate::cli::read_urls(std::io::stdin().lock()));
+        if urls.is_empty() && args.stdin {
+            eprintln!("{}", t("cli.no_urls"));
+            std::process::exit(1);
+        }
+    }
+    if !urls.is_empty() {
+        run_downloads(&urls, &args.network, &args.output, args.review);
+        return true;
+    }
     
     false
-}
\ No newline at end of file
+}
+
+fn apply_bench_flag(options: &mut BenchOptions, flag: &str, value: &str) {
+    match flag {
+        "--size" => options.size_mib = value.parse().unwrap_or(options.size_mib),
+        "--latency" => options.latency_ms = value.parse().unwrap_or(options.latency_ms),
+        "--rate" => options.rate_kib = value.parse().unwrap_or(options.rate_kib),
+        "--chunk" => options.chunk_mib = value.parse().unwrap_or(options.chunk_mib),
+        "--io-threads" => options.io_threads = value.parse().unwrap_or(options.io_threads),
+        "--fairness" => options.fairness_mib = value.parse().unwrap_or(options.fairness_mib),
+        "--threads" => {
+            options.threads = value
+                .split(',')
+                .filter_map(|t| t.trim().parse().ok())
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+fn apply_network_flag(flags: &mut NetworkFlags, flag: &str, value: &str) {
+    match flag {
+        "--proxy" => flags.proxy = Some(value.to_string()),
+        "--cacert" => flags.cacert = Some(PathBuf::from(value)),
+        "--user-agent" | "-A" => flags.user_agent = Some(value.to_string()),
+        _ => {}
+    }
+}
+
+fn run_bench(options: &BenchOptions) {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", tf("cli.runtime_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+
+    // first one in, so the writer pool is the one being measured
+    crate::downloads::io::init(options.io_threads);
+    match runtime.block_on(crate::bench::run(options)) {
+        Ok(results) => {
+            print!("{}", crate::bench::format_table(&results));
+            if results.iter().any(|r| !r.verified) {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", tf("cli.bench_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    }
+    if options.fairness_mib == 0 {
+        return;
+    }
+    match runtime.block_on(crate::bench::run_fairness(options)) {
+        Ok(results) => {
+            println!();
+            print!("{}", crate::bench::format_fairness(&results));
+            if results.iter().any(|r| !r.verified()) {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", tf("cli.bench_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_upload(file: &std::path::Path, endpoint: &str) {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", tf("cli.runtime_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = runtime.block_on(crate::uploads::run_cli(file, endpoint)) {
+        eprintln!("{}", tf("cli.upload_failed", &[("error", &e)]));
+        std::process::exit(1);
+    }
+}
+
+fn run_verify(file: &std::path::Path, dir: Option<&std::path::Path>) {
+    let text = match std::fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{}", tf("cli.input_file_failed", &[("path", &file.display()), ("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+    let entries = crate::checksums::parse(&text);
+    if entries.is_empty() {
+        eprintln!("{}", tf("cli.verify.empty", &[("path", &file.display())]));
+        std::process::exit(1);
+    }
+    // names are relative to the sums file, then the download folder, unless --dir says otherwise
+    let bases = match dir {
+        Some(dir) => vec![dir.to_path_buf()],
+        None => vec![
+            file.parent().map(std::path::Path::to_path_buf).unwrap_or_default(),
+            PathBuf::from(crate::settings::DownloadConfig::default().download_location),
+        ],
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", tf("cli.runtime_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+    let results = runtime.block_on(crate::checksums::verify(entries, &bases));
+    print!("{}", crate::checksums::format_table(&results));
+    if results.iter().any(|r| r.status != crate::checksums::Status::Ok) {
+        std::process::exit(1);
+    }
+}
+
+fn run_wait(id: &str, data_dir: Option<&std::path::Path>) {
+    let id = match uuid::Uuid::parse_str(id) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("{}", tf("cli.wait.invalid_id", &[("id", &id), ("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", tf("cli.runtime_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+    match runtime.block_on(crate::cli::wait(id, data_dir)) {
+        Ok(path) => println!("{}", path.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_gen_bindings(path: &std::path::Path) {
+    match crate::bindings::export(path) {
+        Ok(()) => println!("{}", tf("cli.gen_bindings.written", &[("path", &path.display())])),
+        Err(e) => {
+            eprintln!("{}", tf("cli.gen_bindings.failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_downloads(urls: &[Entry], flags: &NetworkFlags, output: &Output, review: Review) {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", tf("cli.runtime_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = runtime.block_on(crate::cli::run_downloads(urls, flags, output, review)) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,356 @@
+pub mod server;
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::downloads::client::{HttpClient, RateLimit};
+use crate::downloads::core::{Download, Strategy, Transfer};
+use crate::downloads::fair::{self, Balancer, Flow};
+use crate::downloads::io;
+use crate::settings::{BandwidthConfig, ChaosConfig};
+use server::{ServerConfig, TestServer};
+
+/// Knobs for a benchmark run, anything left out falls back to the default
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct BenchOptions {
+    pub size_mib: u32,
+    pub latency_ms: u64,
+    /// per connection cap on the test server, 0 for unlimited
+    pub rate_kib: u64,
+    pub threads: Vec<u8>,
+    /// range size for the fixed strategy
+    pub chunk_mib: u32,
+    /// run through the fault injecting client, results then double as a retry self-test
+    pub chaos: bool,
+    /// writer threads, 0 for tokio's blocking pool; compare both through `lag_ms`.
+    /// Only `tur bench` starts them, `run_benchmark` measures the pool the app runs with.
+    pub io_threads: u8,
+    /// MiB/s shared by two downloads at once for `run_fairness`, 0 skips it
+    pub fairness_mib: u32,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            size_mib: 64,
+            latency_ms: 20,
+            rate_kib: 0,
+            threads: vec![1, 2, 4, 8],
+            chunk_mib: 4,
+            chaos: false,
+            io_threads: 2,
+            fairness_mib: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct BenchResult {
+    pub threads: u8,
+    pub strategy: String,
+    pub millis: u64,
+    pub mib_per_sec: f64,
+    /// file on disk matched what the server sent
+    pub verified: bool,
+    /// worst wake-up delay of a task ticking next to the download
+    pub lag_ms: f64,
+    /// GETs the server answered, each one a round trip the download waited on
+    pub requests: u64,
+}
+
+/// How two downloads split `fairness_mib` while both ran
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct FairnessResult {
+    /// "shared" for one limit every connection reads through, "balanced" for `fair`
+    pub mode: String,
+    pub threads: Vec<u8>,
+    pub mib_per_sec: Vec<f64>,
+    /// Jain's index, 1 is an even split and 0.5 one download taking everything
+    pub index: f64,
+}
+
+impl FairnessResult {
+    /// The balancer has to come close to an even split
+    pub fn verified(&self) -> bool {
+        self.mode != "balanced" || self.index >= 0.9
+    }
+}
+
+/// Download the same generated file for every thread count and strategy
+pub async fn run(options: &BenchOptions) -> Result<Vec<BenchResult>, String> {
+    let server = TestServer::start(ServerConfig {
+        size: (options.size_mib as usize) << 20,
+        latency: Duration::from_millis(options.latency_ms),
+        rate: options.rate_kib * 1024,
+    })
+    .await
+    .map_err(|e| format!("Failed to start test server: {}", e))?;
+
+    let probe = tokio::spawn(io::lag_probe(Duration::from_millis(10)));
+
+    let strategies = [
+        Strategy::Fibonacci,
+        Strategy::Fixed(options.chunk_mib),
+        Strategy::Adaptive,
+    ];
+    let mut results = Vec::new();
+    for &threads in &options.threads {
+        for strategy in strategies {
+            let result = run_one(&server, options, threads, strategy).await;
+            if result.is_err() {
+                probe.abort();
+            }
+            results.push(result?);
+        }
+    }
+    probe.abort();
+    Ok(results)
+}
+
+async fn run_one(
+    server: &TestServer,
+    options: &BenchOptions,
+    threads: u8,
+    strategy: Strategy,
+) -> Result<BenchResult, String> {
+    let size_mib = options.size_mib;
+    let size = (size_mib as usize) << 20;
+    let mut client = HttpClient::from(
+        reqwest::Client::builder()
+            .no_proxy()
+            .pool_max_idle_per_host(threads as usize)
+            .build()
+            .map_err(|e| e.to_string())?,
+    );
+    if options.chaos {
+        client = client.with_chaos(&ChaosConfig::default());
+    }
+    let path = std::env::temp_dir().join(format!("tur-bench-{}.bin", Uuid::now_v7().as_simple()));
+    let transfer = Arc::new(Transfer::default());
+
+    io::take_metrics();
+    server.take_requests();
+    let started = Instant::now();
+    let download = Download::new(size, threads, strategy)
+        .run(client, server.url(), &path, threads, transfer.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    let elapsed = started.elapsed();
+    let metrics = io::take_metrics();
+
+    let verified = transfer.error().is_none()
+        && download.is_complete()
+        && verify(&path, size).unwrap_or(false);
+    let _ = std::fs::remove_file(&path);
+
+    Ok(BenchResult {
+        threads,
+        strategy: strategy.to_string(),
+        millis: elapsed.as_millis() as u64,
+        mib_per_sec: size_mib as f64 / elapsed.as_secs_f64(),
+        verified,
+        lag_ms: metrics.max_runtime_lag_us as f64 / 1000.0,
+        requests: server.take_requests(),
+    })
+}
+
+/// Two downloads at once, with the most and the fewest connections in `threads`, under
+/// a total of `fairness_mib`: first through one shared limit, where the one with more
+/// connections takes most of it, then split by the bandwidth balancer
+pub async fn run_fairness(options: &BenchOptions) -> Result<Vec<FairnessResult>, String> {
+    let size = (options.size_mib as usize) << 20;
+    let server = TestServer::start(ServerConfig {
+        size,
+        latency: Duration::from_millis(options.latency_ms),
+        rate: options.rate_kib * 1024,
+    })
+    .await
+    .map_err(|e| format!("Failed to start test server: {}", e))?;
+    let most = options.threads.iter().copied().max().unwrap_or(8);
+    let fewest = options.threads.iter().copied().min().unwrap_or(1);
+    let config = BandwidthConfig {
+        total_limit: (options.fairness_mib as u64) << 10,
+        balance: true,
+        ..Default::default()
+    };
+    let mut results = Vec::new();
+    for balanced in [false, true] {
+        results.push(run_pair(&server, size, [most, fewest], &config, balanced).await?);
+    }
+    Ok(results)
+}
+
+async fn run_pair(
+    server: &TestServer,
+    size: usize,
+    threads: [u8; 2],
+    config: &BandwidthConfig,
+    balanced: bool,
+) -> Result<FairnessResult, String> {
+    let shared = Arc::new(RateLimit::new(config.total_limit * 1024));
+    let mut flows = Vec::new();
+    let mut clients = Vec::new();
+    for threads in threads {
+        let limit = match balanced {
+            true => Arc::new(RateLimit::new(0)),
+            false => shared.clone(),
+        };
+        let client = HttpClient::from(
+            reqwest::Client::builder()
+                .no_proxy()
+                .build()
+                .map_err(|e| e.to_string())?,
+        )
+        .with_rate_limit(limit.clone());
+        clients.push((client, threads));
+        flows.push(Flow {
+            id: Uuid::now_v7(),
+            weight: 1,
+            limit,
+            transfer: Arc::new(Transfer::default()),
+        });
+    }
+    // shares in place before the first byte, the limit would be late otherwise
+    let mut balancer = Balancer::default();
+    if balanced {
+        balancer.rebalance(config, &flows);
+    }
+    let mut tasks = Vec::new();
+    for ((client, threads), flow) in clients.into_iter().zip(&flows) {
+        let path =
+            std::env::temp_dir().join(format!("tur-bench-{}.bin", Uuid::now_v7().as_simple()));
+        let url = server.url();
+        let transfer = flow.transfer.clone();
+        tasks.push(tokio::spawn(async move {
+            let _ = Download::new(size, threads, Strategy::Fibonacci)
+                .run(client, url, &path, threads, transfer)
+                .await;
+            let _ = std::fs::remove_file(&path);
+        }));
+    }
+
+    // measured until the first one finishes, after that the other has the link alone
+    let started = Instant::now();
+    let mut interval = tokio::time::interval(fair::INTERVAL / 5);
+    let mut ticks = 0;
+    loop {
+        interval.tick().await;
+        ticks += 1;
+        if balanced && ticks % 5 == 0 {
+            balancer.rebalance(config, &flows);
+        }
+        let done = tasks.iter().any(|t| t.is_finished())
+            || flows.iter().any(|f| f.transfer.downloaded() >= size as u64);
+        let failed = flows.iter().find_map(|f| f.transfer.error());
+        if done || failed.is_some() {
+            let elapsed = started.elapsed().as_secs_f64();
+            let mib_per_sec: Vec<f64> = flows
+                .iter()
+                .map(|f| f.transfer.downloaded() as f64 / (1 << 20) as f64 / elapsed)
+                .collect();
+            for flow in &flows {
+                flow.transfer.stop();
+            }
+            for task in tasks {
+                let _ = task.await;
+            }
+            if let Some(e) = failed {
+                return Err(e);
+            }
+            let sum: f64 = mib_per_sec.iter().sum();
+            let squares: f64 = mib_per_sec.iter().map(|x| x * x).sum();
+            return Ok(FairnessResult {
+                mode: if balanced { "balanced" } else { "shared" }.into(),
+                threads: threads.to_vec(),
+                index: sum * sum / (mib_per_sec.len() as f64 * squares),
+                mib_per_sec,
+            });
+        }
+    }
+}
+
+fn verify(path: &Path, size: usize) -> std::io::Result<bool> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buf = vec![0u8; 1 << 20];
+    let mut pos = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if buf[..n]
+            .iter()
+            .enumerate()
+            .any(|(i, &b)| b != server::pattern_byte(pos + i as u64))
+        {
+            return Ok(false);
+        }
+        pos += n as u64;
+    }
+    Ok(pos == size as u64)
+}
+
+/// Plain text comparison table for the terminal
+pub fn format_table(results: &[BenchResult]) -> String {
+    let mut out = format!(
+        "{:>7}  {:<12} {:>9} {:>10} {:>8} {:>8}  {}\n",
+        "threads", "strategy", "time", "MiB/s", "lag", "requests", "check"
+    );
+    for r in results {
+        out.push_str(&format!(
+            "{:>7}  {:<12} {:>7}ms {:>10.1} {:>6.1}ms {:>8}  {}\n",
+            r.threads,
+            r.strategy,
+            r.millis,
+            r.mib_per_sec,
+            r.lag_ms,
+            r.requests,
+            if r.verified { "ok" } else { "FAILED" }
+        ));
+    }
+    out
+}
+
+/// Plain text table for `run_fairness`
+pub fn format_fairness(results: &[FairnessResult]) -> String {
+    let mut out = format!(
+        "{:<9} {:>8} {:>18} {:>6}  {}\n",
+        "mode", "threads", "MiB/s", "index", "check"
+    );
+    for r in results {
+        let threads: Vec<String> = r.threads.iter().map(u8::to_string).collect();
+        let speeds: Vec<String> = r.mib_per_sec.iter().map(|s| format!("{:.1}", s)).collect();
+        out.push_str(&format!(
+            "{:<9} {:>8} {:>18} {:>6.2}  {}\n",
+            r.mode,
+            threads.join("+"),
+            speeds.join(" / "),
+            r.index,
+            if r.verified() { "ok" } else { "FAILED" }
+        ));
+    }
+    out
+}
+
+/// Largest file `run_benchmark` asks for, the test server holds all of it in memory
+const MAX_SIZE_MIB: u32 = 1024;
+
+/// `run` for the GUI, with the file capped at `MAX_SIZE_MIB`. `io_threads` is ignored, the
+/// writer pool is the one `thread.io_threads` started with the app
+#[tauri::command]
+#[specta::specta]
+pub async fn run_benchmark(options: Option<BenchOptions>) -> Result<Vec<BenchResult>, String> {
+    let mut options = options.unwrap_or_default();
+    if options.threads.is_empty() || options.threads.contains(&0) {
+        return Err("Give at least one thread count, none of them 0".into());
+    }
+    options.size_mib = options.size_mib.clamp(1, MAX_SIZE_MIB);
+    options.chunk_mib = options.chunk_mib.max(1);
+    run(&options).await
+}
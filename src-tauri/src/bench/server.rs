@@ -0,0 +1,168 @@
+use bytes::Bytes;
+use futures_util::stream;
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Full, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{header, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use url::Url;
+
+type Body = UnsyncBoxBody<Bytes, Infallible>;
+
+/// What the local test server pretends to host
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// size of the served file in bytes
+    pub size: usize,
+    /// delay before every response, stands in for round trips
+    pub latency: Duration,
+    /// per connection cap in bytes per second, 0 for unlimited
+    pub rate: u64,
+}
+
+/// Range capable HTTP server on 127.0.0.1 serving one generated file
+pub struct TestServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+    requests: Arc<AtomicU64>,
+}
+
+impl TestServer {
+    pub async fn start(config: ServerConfig) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let data: Bytes = (0..config.size as u64)
+            .map(pattern_byte)
+            .collect::<Vec<u8>>()
+            .into();
+        let requests = Arc::new(AtomicU64::new(0));
+        let state = Arc::new(State {
+            config,
+            data,
+            requests: requests.clone(),
+        });
+
+        let task = tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    // e.g. out of file descriptors, trying again right away would spin
+                    Err(e) => {
+                        eprintln!("Test server failed to accept a connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| serve(req, state.clone()));
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        Ok(TestServer {
+            addr,
+            task,
+            requests,
+        })
+    }
+
+    pub fn url(&self) -> Url {
+        Url::parse(&format!("http://{}/file.bin", self.addr)).unwrap()
+    }
+
+    /// GET requests answered since the last call
+    pub fn take_requests(&self) -> u64 {
+        self.requests.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Byte expected at position `i` of the served file
+pub fn pattern_byte(i: u64) -> u8 {
+    (i % 251) as u8
+}
+
+struct State {
+    config: ServerConfig,
+    data: Bytes,
+    requests: Arc<AtomicU64>,
+}
+
+async fn serve(req: Request<Incoming>, state: Arc<State>) -> Result<Response<Body>, Infallible> {
+    let State { config, data, .. } = &*state;
+    if req.method() == Method::GET {
+        state.requests.fetch_add(1, Ordering::Relaxed);
+    }
+    tokio::time::sleep(config.latency).await;
+
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_range(v, data.len()));
+
+    let builder = Response::builder().header(header::ACCEPT_RANGES, "bytes");
+    let (builder, slice) = match range {
+        Some(r) => (
+            builder.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", r.start, r.end - 1, data.len()),
+            ),
+            data.slice(r),
+        ),
+        None => (builder.status(StatusCode::OK), data.clone()),
+    };
+    let builder = builder.header(header::CONTENT_LENGTH, slice.len());
+
+    let body = if req.method() == Method::HEAD {
+        Full::new(Bytes::new()).boxed_unsync()
+    } else if config.rate == 0 {
+        Full::new(slice).boxed_unsync()
+    } else {
+        throttled(slice, config.rate)
+    };
+    Ok(builder.body(body).unwrap())
+}
+
+// "bytes=a-b", "bytes=a-" or "bytes=-n"
+fn parse_range(value: &str, len: usize) -> Option<Range<usize>> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", n) => (len.saturating_sub(n.parse().ok()?), len),
+        (s, "") => (s.parse().ok()?, len),
+        (s, e) => (s.parse().ok()?, e.parse::<usize>().ok()?.saturating_add(1).min(len)),
+    };
+    (start < end).then_some(start..end)
+}
+
+fn throttled(data: Bytes, rate: u64) -> Body {
+    const PIECE: usize = 16 * 1024;
+    let delay = Duration::from_secs_f64(PIECE as f64 / rate as f64);
+    let pieces = stream::unfold(data, move |mut rest| async move {
+        if rest.is_empty() {
+            return None;
+        }
+        tokio::time::sleep(delay).await;
+        let piece = rest.split_to(PIECE.min(rest.len()));
+        Some((Ok::<_, Infallible>(Frame::data(piece)), rest))
+    });
+    StreamBody::new(pieces).boxed_unsync()
+}
@@ -0,0 +1,120 @@
+use specta::datatype::{DataType, FunctionResultVariant};
+use specta::TypeCollection;
+use specta_typescript::{BigIntExportBehavior, Typescript};
+use std::path::Path;
+
+use crate::events::EVENTS;
+
+/// Where `tur gen-bindings` writes by default, from the repository root
+pub const DEFAULT_PATH: &str = "src/lib/bindings.ts";
+
+const HEADER: &str = "// Generated by `tur gen-bindings` from the Rust types, do not edit.";
+
+const IMPORTS: &str = r#"import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";"#;
+
+const LISTEN: &str = r#"/** Listen to a backend event with its payload typed */
+export function listenTo<E extends keyof Events>(
+  event: E,
+  handler: (payload: Events[E]) => void,
+): Promise<UnlistenFn> {
+  return listen<Events[E]>(event, (e) => handler(e.payload));
+}"#;
+
+/// TypeScript for every command, event and the types they use
+pub fn render() -> Result<String, String> {
+    // serde_json writes 64-bit integers as plain numbers
+    let ts = Typescript::new()
+        .framework_header("")
+        .bigint(BigIntExportBehavior::Number);
+    let mut types = TypeCollection::default();
+    let commands = crate::command_types(&mut types);
+    let events: Vec<(&str, DataType)> = EVENTS
+        .iter()
+        .map(|(name, payload)| (*name, payload(&mut types)))
+        .collect();
+    let typescript = |typ: &DataType, types: &TypeCollection| {
+        specta_typescript::datatype(&ts, &FunctionResultVariant::Value(typ.clone()), types)
+            .map_err(|e| e.to_string())
+    };
+
+    let mut out = format!("{}\n\n{}\n\n", HEADER, IMPORTS);
+    out.push_str("export const commands = {\n");
+    for command in &commands {
+        let mut params = Vec::new();
+        let mut names = Vec::new();
+        for (name, typ) in command.args() {
+            // tauri matches arguments by their camelCase name
+            let name = camel_case(name);
+            params.push(format!("{}: {}", name, typescript(typ, &types)?));
+            names.push(name);
+        }
+        // an Err rejects the promise with its message
+        let result = match command.result() {
+            Some(FunctionResultVariant::Value(typ))
+            | Some(FunctionResultVariant::Result(typ, _)) => typescript(typ, &types)?,
+            None => "null".to_string(),
+        };
+        let docs: Vec<&str> = command.docs().lines().map(str::trim).collect();
+        let docs = docs.join(" ");
+        if !docs.trim().is_empty() {
+            out.push_str(&format!("  /** {} */\n", docs.trim()));
+        }
+        let args = match names.is_empty() {
+            true => String::new(),
+            false => format!(", {{ {} }}", names.join(", ")),
+        };
+        out.push_str(&format!(
+            "  {}: ({}) => invoke<{}>(\"{}\"{}),\n",
+            camel_case(command.name()),
+            params.join(", "),
+            result,
+            command.name(),
+            args
+        ));
+    }
+    out.push_str("};\n\n");
+
+    out.push_str("export type Events = {\n");
+    for (name, payload) in &events {
+        out.push_str(&format!(
+            "  \"{}\": {};\n",
+            name,
+            typescript(payload, &types)?
+        ));
+    }
+    out.push_str("};\n\n");
+    out.push_str(LISTEN);
+    out.push_str("\n\n");
+
+    // named types last
+    let named = ts.export(&types).map_err(|e| e.to_string())?;
+    out.push_str(named.trim());
+    out.push('\n');
+    Ok(out)
+}
+
+/// Write `render` to `path`
+pub fn export(path: &Path) -> Result<(), String> {
+    let out = render()?;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, out).map_err(|e| e.to_string())
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
@@ -0,0 +1,214 @@
+use sha2::{Digest, Sha256, Sha512};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::i18n;
+
+/// Digests a checksum file can list, told apart by their length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            64 => Some(Algorithm::Sha256),
+            128 => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a SHA256SUMS / SHA512SUMS file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    /// lowercase hex
+    pub expected: String,
+    pub algorithm: Algorithm,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Mismatch,
+    Missing,
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Verified {
+    pub name: String,
+    pub path: PathBuf,
+    pub status: Status,
+}
+
+/// Entries of a checksum file in GNU (`<hex>  name`, `<hex> *name`) or BSD
+/// (`SHA256 (name) = <hex>`) format. Comments and lines that are neither are skipped.
+pub fn parse(text: &str) -> Vec<Entry> {
+    text.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let entry = parse_bsd(line).or_else(|| parse_gnu(line));
+            if entry.is_none() {
+                eprintln!("{}", i18n::tf("cli.verify.skipped", &[("line", &line)]));
+            }
+            entry
+        })
+        .collect()
+}
+
+fn parse_gnu(line: &str) -> Option<Entry> {
+    let (hex, name) = line.split_once(' ')?;
+    // " name" is text mode, "*name" binary, both hash the same bytes
+    let name = name.strip_prefix(['*', ' ']).unwrap_or(name);
+    entry(hex, name)
+}
+
+fn parse_bsd(line: &str) -> Option<Entry> {
+    let (_, rest) = line.split_once(" (")?;
+    let (name, hex) = rest.rsplit_once(") = ")?;
+    entry(hex, name)
+}
+
+fn entry(hex: &str, name: &str) -> Option<Entry> {
+    let hex = hex.trim();
+    if name.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(Entry {
+        name: name.to_string(),
+        expected: hex.to_ascii_lowercase(),
+        algorithm: Algorithm::from_hex_len(hex.len())?,
+    })
+}
+
+/// Where `name` is: as listed when absolute, else the first of `bases` that has it
+pub fn resolve(name: &str, bases: &[PathBuf]) -> PathBuf {
+    let listed = Path::new(name);
+    if listed.is_absolute() {
+        return listed.to_path_buf();
+    }
+    bases
+        .iter()
+        .map(|base| base.join(listed))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| bases.first().map(|b| b.join(listed)).unwrap_or_default())
+}
+
+/// Hash every entry, a few files at a time, with a count on stderr
+pub async fn verify(entries: Vec<Entry>, bases: &[PathBuf]) -> Vec<Verified> {
+    let total = entries.len();
+    let done = Arc::new(AtomicUsize::new(0));
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let permits = Arc::new(Semaphore::new(workers));
+
+    let progress = {
+        let done = done.clone();
+        tokio::spawn(async move {
+            loop {
+                let line = i18n::tf(
+                    "cli.verify.progress",
+                    &[("done", &done.load(Ordering::Relaxed)), ("total", &total)],
+                );
+                eprint!("\r{}", line);
+                let _ = std::io::stderr().flush();
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+        })
+    };
+
+    let tasks: Vec<_> = entries
+        .into_iter()
+        .map(|entry| {
+            let path = resolve(&entry.name, bases);
+            let permits = permits.clone();
+            let done = done.clone();
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let name = entry.name.clone();
+                let status = tokio::task::spawn_blocking({
+                    let path = path.clone();
+                    move || check(&entry, &path)
+                })
+                .await
+                .unwrap_or_else(|e| Status::Error(e.to_string()));
+                done.fetch_add(1, Ordering::Relaxed);
+                Verified { name, path, status }
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(total);
+    for task in tasks {
+        if let Ok(verified) = task.await {
+            results.push(verified);
+        }
+    }
+    progress.abort();
+    eprintln!();
+    results
+}
+
+fn check(entry: &Entry, path: &Path) -> Status {
+    if !path.is_file() {
+        return Status::Missing;
+    }
+    let actual = match entry.algorithm {
+        Algorithm::Sha256 => digest::<Sha256>(path),
+        Algorithm::Sha512 => digest::<Sha512>(path),
+    };
+    match actual {
+        Ok(actual) if actual == entry.expected => Status::Ok,
+        Ok(_) => Status::Mismatch,
+        Err(e) => Status::Error(e.to_string()),
+    }
+}
+
+fn digest<D: Digest>(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = D::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// One row per file plus a totals line
+pub fn format_table(results: &[Verified]) -> String {
+    let mut out = format!("{:<8}  {}\n", "status", "file");
+    for r in results {
+        let status = match &r.status {
+            Status::Ok => "ok".to_string(),
+            Status::Mismatch => "MISMATCH".to_string(),
+            Status::Missing => "missing".to_string(),
+            Status::Error(e) => format!("error ({})", e),
+        };
+        out.push_str(&format!("{:<8}  {}\n", status, r.path.display()));
+    }
+    let count = |status: &Status| results.iter().filter(|r| &r.status == status).count();
+    let failed = results.len() - count(&Status::Ok);
+    out.push_str(&i18n::tf(
+        "cli.verify.summary",
+        &[
+            ("ok", &count(&Status::Ok)),
+            ("mismatched", &count(&Status::Mismatch)),
+            ("missing", &count(&Status::Missing)),
+            ("failed", &failed),
+        ],
+    ));
+    out.push('\n');
+    out
+}
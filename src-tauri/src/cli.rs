@@ -0,0 +1,562 @@
+use futures_util::future::join_all;
+use std::future::Future;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::downloads::client::{self, HttpClient};
+use crate::downloads::core::{self, Download, Strategy, Transfer};
+use crate::downloads::probe::{self, RemoteInfo};
+use crate::downloads::template;
+use crate::downloads::units::Units;
+use crate::i18n;
+use crate::settings::AppSettings;
+use crate::signals::Signals;
+
+/// `--proxy`, `--insecure`, `--cacert` and `--user-agent`, over the default settings
+/// since the app's settings store needs the GUI
+#[derive(Debug, Clone, Default)]
+pub struct NetworkFlags {
+    pub proxy: Option<String>,
+    pub insecure: bool,
+    pub cacert: Option<PathBuf>,
+    pub user_agent: Option<String>,
+}
+
+impl NetworkFlags {
+    pub fn apply(&self, settings: &mut AppSettings) {
+        if let Some(proxy) = &self.proxy {
+            settings.network.proxy = proxy.clone();
+        }
+        settings.network.allow_insecure |= self.insecure;
+        if let Some(cacert) = &self.cacert {
+            settings.network.ca_certificate = cacert.to_string_lossy().to_string();
+        }
+        if let Some(user_agent) = &self.user_agent {
+            settings.download.user_agent = user_agent.clone();
+        }
+    }
+}
+
+/// Where terminal downloads are written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Output {
+    /// named by the server, in the current directory
+    #[default]
+    Directory,
+    /// `-O -`, one download piped on
+    Stdout,
+    /// `-O <name>`, one download saved as exactly this
+    File(PathBuf),
+}
+
+/// Whether a batch is shown before it starts
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Review {
+    #[default]
+    None,
+    /// `--dry-run`, list what would be downloaded and stop
+    DryRun,
+    /// `--confirm`, list it and ask first
+    Confirm,
+}
+
+/// A url to download, with the name it was given on the command line or in a url file
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub url: Url,
+    pub filename: Option<String>,
+}
+
+impl Entry {
+    /// `url` or `url > name`
+    pub fn parse(line: &str) -> Result<Entry, url::ParseError> {
+        let (url, filename) = match line.split_once(char::is_whitespace) {
+            Some((url, rest)) => {
+                let name = rest.trim_start().strip_prefix('>').map(str::trim);
+                (url, name.filter(|n| !n.is_empty()).map(str::to_string))
+            }
+            None => (line, None),
+        };
+        Ok(Entry {
+            url: Url::parse(url)?,
+            filename,
+        })
+    }
+}
+
+/// One entry per line, blank lines and `#` comments skipped, anything unparsable reported
+pub fn read_urls(input: impl BufRead) -> Vec<Entry> {
+    input
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            Entry::parse(line)
+                .inspect_err(|e| {
+                    eprintln!(
+                        "{}",
+                        i18n::tf("cli.invalid_url", &[("url", &line), ("error", e)])
+                    )
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// stdin is a pipe or a redirected file, `cat urls.txt | tur`. A terminal or /dev/null,
+/// which is what a desktop launch gets, doesn't count
+#[cfg(unix)]
+pub fn stdin_is_piped() -> bool {
+    // SAFETY: fstat only writes the zeroed struct it's given
+    unsafe {
+        let mut stat: libc::stat = std::mem::zeroed();
+        if libc::fstat(libc::STDIN_FILENO, &mut stat) != 0 {
+            return false;
+        }
+        let kind = stat.st_mode & libc::S_IFMT;
+        kind == libc::S_IFIFO || kind == libc::S_IFREG
+    }
+}
+
+#[cfg(windows)]
+pub fn stdin_is_piped() -> bool {
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const FILE_TYPE_DISK: u32 = 1;
+    const FILE_TYPE_PIPE: u32 = 3;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(which: u32) -> *mut std::ffi::c_void;
+        fn GetFileType(handle: *mut std::ffi::c_void) -> u32;
+    }
+    // SAFETY: GetFileType accepts any handle, including null or invalid ones
+    let kind = unsafe { GetFileType(GetStdHandle(STD_INPUT_HANDLE)) };
+    kind == FILE_TYPE_PIPE || kind == FILE_TYPE_DISK
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn stdin_is_piped() -> bool {
+    false
+}
+
+/// How often `tur wait` looks at the app's database
+const WAIT_POLL: Duration = Duration::from_millis(500);
+
+/// `tur wait <ID>`: poll the app's database until the download completed or failed.
+/// Ok with its path when it completed, so scripts can chain on the exit code. The
+/// database is the active profile's unless `--data-dir` points elsewhere.
+pub async fn wait(id: Uuid, data_dir: Option<&Path>) -> Result<PathBuf, String> {
+    let path = crate::profiles::cli_data_dir(data_dir)
+        .map(|dir| dir.join("tur.db"))
+        .filter(|path| path.exists())
+        .ok_or_else(|| i18n::t("cli.wait.no_database"))?;
+    let db = Database::new(&path).map_err(|e| e.to_string())?;
+    let mut paused = false;
+    loop {
+        let record = db
+            .get_download_by_id(&id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| i18n::tf("cli.wait.not_found", &[("id", &id)]))?;
+        match record.status.as_deref() {
+            Some("completed") => return Ok(PathBuf::from(record.destination)),
+            Some("failed") => {
+                return Err(i18n::tf("cli.wait.failed", &[("filename", &record.filename)]))
+            }
+            Some("cancelled") => {
+                return Err(i18n::tf("cli.wait.cancelled", &[("filename", &record.filename)]))
+            }
+            // said once, the wait goes on until someone resumes it
+            Some("paused") if !paused => {
+                paused = true;
+                eprintln!("{}", i18n::tf("cli.wait.paused", &[("filename", &record.filename)]));
+            }
+            Some("paused") => {}
+            _ => paused = false,
+        }
+        tokio::time::sleep(WAIT_POLL).await;
+    }
+}
+
+/// `tur <URL>...`: download into the current directory one after another,
+/// progress on stderr. Fails if any of them did.
+pub async fn run_downloads(
+    entries: &[Entry],
+    flags: &NetworkFlags,
+    output: &Output,
+    review: Review,
+) -> Result<(), String> {
+    let mut settings = AppSettings::default();
+    flags.apply(&mut settings);
+    settings.validate();
+    let client = HttpClient::from(client::create(&settings)?);
+    let base = std::env::current_dir().map_err(|e| e.to_string())?;
+    if *output != Output::Directory && entries.len() != 1 {
+        return Err(i18n::t("cli.output_single"));
+    }
+
+    // probed once up front when reviewing, and reused for the downloads themselves
+    let mut planned = Vec::new();
+    if review != Review::None {
+        planned = plan(&settings, &client, &base, entries, output).await;
+        print!(
+            "{}",
+            format_plan(entries, &planned, Units::from_config(&settings.app))
+        );
+        let failed = planned.iter().filter(|p| p.is_err()).count();
+        if review == Review::DryRun {
+            return match failed {
+                0 => Ok(()),
+                count => Err(i18n::tf("cli.plan.failed", &[("count", &count)])),
+            };
+        }
+        if !confirm(planned.len() - failed) {
+            return Err(i18n::t("cli.confirm.aborted"));
+        }
+    }
+    // after the prompt, which a Ctrl+C should still just end
+    let mut quit = Quit {
+        signals: Signals::new().map_err(|e| e.to_string())?,
+        requested: false,
+    };
+    if *output == Output::Stdout {
+        let result = to_stdout(&settings, &client, &entries[0].url, &mut quit).await;
+        if quit.requested {
+            return Err(i18n::t("cli.interrupted"));
+        }
+        return result;
+    }
+
+    let mut planned = planned.into_iter();
+    let mut failed = 0;
+    for entry in entries {
+        let resolved = match planned.next() {
+            Some(resolved) => resolved,
+            None => tokio::select! {
+                resolved = resolve(&settings, &client, &base, entry, output) => resolved,
+                _ = quit.signals.recv() => return Err(i18n::t("cli.interrupted")),
+            },
+        };
+        let result = match resolved {
+            Ok(resolved) => download(&settings, &client, &entry.url, &resolved, &mut quit).await,
+            Err(e) => Err(e),
+        };
+        if quit.requested {
+            return Err(i18n::t("cli.interrupted"));
+        }
+        match result {
+            Ok(path) => eprintln!(
+                "{}",
+                i18n::tf("cli.download_saved", &[("path", &path.display())])
+            ),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    i18n::tf("cli.download_failed", &[("url", &entry.url), ("error", &e)])
+                );
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(i18n::tf("cli.downloads_failed", &[("count", &failed)]));
+    }
+    Ok(())
+}
+
+// Ctrl+C and friends stop the running download so it can save where it got to,
+// a second one exits right away
+struct Quit {
+    signals: Signals,
+    requested: bool,
+}
+
+impl Quit {
+    async fn guard<T>(&mut self, work: impl Future<Output = T>, transfer: &Transfer) -> T {
+        tokio::pin!(work);
+        tokio::select! {
+            result = &mut work => return result,
+            _ = self.signals.recv() => {}
+        }
+        self.requested = true;
+        transfer.stop();
+        tokio::select! {
+            result = work => result,
+            _ = self.signals.recv() => std::process::exit(130),
+        }
+    }
+}
+
+/// A url that's been probed, and where it would be saved
+#[derive(Debug, Clone)]
+struct Planned {
+    info: RemoteInfo,
+    /// `-` for stdout
+    path: PathBuf,
+    conflict: Option<Conflict>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Conflict {
+    /// a file is already there
+    Exists,
+    /// an earlier url in the batch is saved there too
+    Duplicate,
+}
+
+// every url probed at once, in the order given
+async fn plan(
+    settings: &AppSettings,
+    client: &HttpClient,
+    base: &Path,
+    entries: &[Entry],
+    output: &Output,
+) -> Vec<Result<Planned, String>> {
+    let mut planned = join_all(
+        entries
+            .iter()
+            .map(|entry| resolve(settings, client, base, entry, output)),
+    )
+    .await;
+    let mut seen = std::collections::HashSet::new();
+    for resolved in planned.iter_mut().flatten() {
+        if !seen.insert(resolved.path.clone()) && *output != Output::Stdout {
+            resolved.conflict = Some(Conflict::Duplicate);
+        }
+    }
+    planned
+}
+
+async fn resolve(
+    settings: &AppSettings,
+    client: &HttpClient,
+    base: &Path,
+    entry: &Entry,
+    output: &Output,
+) -> Result<Planned, String> {
+    let info = probe::probe(client, &entry.url).await?;
+    // a given name is used as is, relative to the current directory
+    let path = match (output, entry.filename.as_deref()) {
+        (Output::Stdout, _) => PathBuf::from("-"),
+        (Output::File(path), _) => base.join(path),
+        (Output::Directory, Some(name)) => base.join(name),
+        (Output::Directory, None) => template::planned(
+            settings,
+            base,
+            &entry.url,
+            &info.filename,
+            info.content_type.as_deref(),
+            None,
+        )?,
+    };
+    let conflict = (*output != Output::Stdout && path.exists()).then_some(Conflict::Exists);
+    Ok(Planned {
+        info,
+        path,
+        conflict,
+    })
+}
+
+// name, size, destination and any conflict, one url per row
+fn format_plan(entries: &[Entry], planned: &[Result<Planned, String>], units: Units) -> String {
+    let mut out = String::new();
+    for (entry, resolved) in entries.iter().zip(planned) {
+        let row = match resolved {
+            Ok(resolved) => {
+                let size = match resolved.info.size {
+                    Some(size) if size > 0 => units.size(size as u64),
+                    _ => "?".to_string(),
+                };
+                let name = resolved.path.file_name().map_or_else(
+                    || resolved.info.filename.clone(),
+                    |n| n.to_string_lossy().to_string(),
+                );
+                let note = match resolved.conflict {
+                    Some(Conflict::Exists) => i18n::t("cli.plan.exists"),
+                    Some(Conflict::Duplicate) => i18n::t("cli.plan.duplicate"),
+                    None => String::new(),
+                };
+                format!(
+                    "{:<32}  {:>10}  {}  {}",
+                    name,
+                    size,
+                    resolved.path.display(),
+                    note
+                )
+            }
+            Err(e) => i18n::tf("cli.download_failed", &[("url", &entry.url), ("error", e)]),
+        };
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+// asked on the terminal itself, stdin may be the url list
+fn confirm(count: usize) -> bool {
+    if count == 0 {
+        return false;
+    }
+    eprint!("{} ", i18n::tf("cli.confirm.prompt", &[("count", &count)]));
+    let _ = std::io::stderr().flush();
+    #[cfg(unix)]
+    let terminal = std::fs::File::open("/dev/tty");
+    #[cfg(windows)]
+    let terminal = std::fs::File::open("CONIN$");
+    #[cfg(not(any(unix, windows)))]
+    let terminal: std::io::Result<std::fs::File> = Err(std::io::ErrorKind::Unsupported.into());
+
+    let mut answer = String::new();
+    let read = match terminal {
+        Ok(tty) => std::io::BufReader::new(tty).read_line(&mut answer),
+        Err(_) => std::io::stdin().lock().read_line(&mut answer),
+    };
+    let answer = answer.trim().to_lowercase();
+    read.is_ok()
+        && i18n::t("cli.confirm.yes")
+            .split(',')
+            .any(|yes| yes == answer)
+}
+
+async fn download(
+    settings: &AppSettings,
+    client: &HttpClient,
+    url: &Url,
+    planned: &Planned,
+    quit: &mut Quit,
+) -> Result<PathBuf, String> {
+    let Planned { info, path, .. } = planned;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let threads = settings.download.num_threads;
+    let size = info.size.filter(|size| *size > 0).map(|size| size as u64);
+
+    let transfer = Arc::new(Transfer::default());
+    let progress = spawn_progress(transfer.clone(), size, Units::from_config(&settings.app));
+    let result = match size {
+        Some(size) if info.resume_supported => {
+            let state = state_path(path);
+            let config = &settings.download;
+            let strategy = Strategy::from_setting(&config.range_strategy, config.chunk_size);
+            let download = restore(&state, path, size)
+                .unwrap_or_else(|| Download::new(size as usize, threads, strategy));
+            transfer.add_downloaded(size - download.remaining() as u64);
+            let run = download.run(client.clone(), url.clone(), path, threads, transfer.clone());
+            match quit.guard(run, &transfer).await {
+                Ok(download) if !download.is_complete() => {
+                    save(&download, &state);
+                    Err(transfer
+                        .error()
+                        .unwrap_or_else(|| "Download stopped early".into()))
+                }
+                Ok(_) => {
+                    let _ = std::fs::remove_file(&state);
+                    Ok(())
+                }
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        _ => {
+            let stream = core::stream_single(client, url, path, &transfer, None);
+            quit.guard(stream, &transfer).await
+        }
+    };
+    progress.abort();
+    eprintln!();
+    result.map(|_| path.clone())
+}
+
+// ranges left of an interrupted download, next to the file so the same command picks them up
+fn state_path(path: &Path) -> PathBuf {
+    let mut state = path.as_os_str().to_owned();
+    state.push(".tur");
+    PathBuf::from(state)
+}
+
+// only while the partial file still has the size it was preallocated to
+fn restore(state: &Path, path: &Path, size: u64) -> Option<Download> {
+    let len = std::fs::metadata(path).ok()?.len();
+    if len != size {
+        return None;
+    }
+    let mut file = std::fs::File::open(state).ok()?;
+    Download::restore(&mut file, size as usize).ok()
+}
+
+fn save(download: &Download, state: &Path) {
+    let saved = std::fs::File::create(state)
+        .map_err(|e| e.to_string())
+        .and_then(|mut file| download.store(&mut file).map_err(|e| e.to_string()));
+    match saved {
+        Ok(()) => eprintln!(
+            "\n{}",
+            i18n::tf("cli.state_saved", &[("path", &state.display())])
+        ),
+        Err(e) => eprintln!("\nFailed to save {}: {}", state.display(), e),
+    }
+}
+
+// one connection written straight through, nothing to preallocate or seek in a pipe
+async fn to_stdout(
+    settings: &AppSettings,
+    client: &HttpClient,
+    url: &Url,
+    quit: &mut Quit,
+) -> Result<(), String> {
+    let response = client
+        .send(client.inner().get(url.clone()))
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    let size = response.content_length().filter(|size| *size > 0);
+
+    let transfer = Arc::new(Transfer::default());
+    let progress = spawn_progress(transfer.clone(), size, Units::from_config(&settings.app));
+    let mut stdout = std::io::stdout().lock();
+    let stream = core::stream_to(client, response, &mut stdout, &transfer, None);
+    let result = quit.guard(stream, &transfer).await;
+    progress.abort();
+    eprintln!();
+    result
+}
+
+// one line rewritten in place every half second
+fn spawn_progress(
+    transfer: Arc<Transfer>,
+    size: Option<u64>,
+    units: Units,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last = 0;
+        loop {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let done = transfer.downloaded();
+            let speed = units.speed(done.saturating_sub(last) * 2);
+            last = done;
+            let line = match size {
+                Some(size) => i18n::tf(
+                    "cli.download_progress",
+                    &[
+                        ("done", &units.size(done)),
+                        ("size", &units.size(size)),
+                        ("percent", &(done * 100).checked_div(size).unwrap_or(100)),
+                        ("speed", &speed),
+                    ],
+                ),
+                None => i18n::tf(
+                    "cli.download_progress_unknown",
+                    &[("done", &units.size(done)), ("speed", &speed)],
+                ),
+            };
+            eprint!("\r{}", line);
+            let _ = std::io::stderr().flush();
+        }
+    })
+}
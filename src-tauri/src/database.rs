@@ -1,14 +1,19 @@
 use rusqlite::{params, Connection, Result};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use uuid::Uuid;
-use tauri::Manager;
 
-#[derive(Debug, Clone)]
+use crate::downloads::client::Environment;
+use crate::downloads::crypt;
+use crate::downloads::thumbnail::Thumbnail;
+use crate::downloads::DownloadOptions;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, specta::Type)]
 pub struct Download {
     pub id: Uuid,
     pub filename: String,
-    pub status: Option<String>, // None = in-progress, Some("completed"|"paused"|"failed")
+    pub status: Option<String>, // None = in-progress, Some("completed"|"paused"|"failed"|"cancelled")
     pub size: Option<i64>,
     pub bytes_received: i64,
     pub url: String,
@@ -18,6 +23,25 @@ pub struct Download {
     pub destination: String,
     pub accept_ranges: bool,
     pub updated_at: i64,
+    pub options: DownloadOptions,
+    /// "download" or "upload", uploads read from `destination` and send to `url`
+    pub direction: String,
+    /// batch the download was added with, see the `jobs` table
+    pub job_id: Option<Uuid>,
+    /// "unscanned", "scanning" or "flagged" while a completed file is held back, see
+    /// `downloads::quarantine`. None once it's released or was never held.
+    #[serde(default)]
+    pub quarantine: Option<String>,
+    /// average speed over one connection's, kept when a multi-connection download completes
+    #[serde(default)]
+    pub acceleration: Option<f64>,
+    /// kept at the top of the history, for ones fetched again and again
+    #[serde(default)]
+    pub pinned: bool,
+    /// the device a sync brought this record from, its `destination` is a path there.
+    /// None once this device wrote its status, or when it never left it
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 impl Download {
@@ -31,6 +55,19 @@ impl Download {
         self.status.as_deref() == Some("completed")
     }
 
+    /// Check if this record is an upload
+    pub fn is_upload(&self) -> bool {
+        self.direction == "upload"
+    }
+
+    /// Point the record at `destination`, after its file was moved
+    pub fn relocated(&mut self, destination: &std::path::Path) {
+        if let Some(name) = destination.file_name() {
+            self.filename = name.to_string_lossy().to_string();
+        }
+        self.destination = destination.to_string_lossy().to_string();
+    }
+
     /// Check if download is in progress
     pub fn is_in_progress(&self) -> bool {
         self.status.is_none()
@@ -48,14 +85,76 @@ impl Download {
     }
 }
 
+/// Columns added after the table was first created, as (name, definition)
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("options", "TEXT"),
+    ("direction", "TEXT NOT NULL DEFAULT 'download'"),
+    ("job_id", "BLOB"),
+    ("environment", "TEXT"),
+    ("quarantine", "TEXT"),
+    ("acceleration", "REAL"),
+    ("pinned", "INTEGER NOT NULL DEFAULT 0"),
+    ("device", "TEXT"),
+];
+
+/// Columns of the downloads table, `status` None while it runs
+const DOWNLOADS_TABLE: &str = "
+    id             BLOB PRIMARY KEY,
+    filename       TEXT NOT NULL,
+    status         TEXT CHECK (status IN ('completed', 'paused', 'failed', 'cancelled')),
+    size           INTEGER,
+    bytes_received INTEGER NOT NULL DEFAULT 0,
+    url            TEXT NOT NULL,
+    etag           TEXT,
+    content_type   TEXT,
+    last_modified  TEXT,
+    destination    TEXT NOT NULL,
+    accept_ranges  INTEGER NOT NULL DEFAULT 0,
+    updated_at     INTEGER NOT NULL DEFAULT (unixepoch()),
+    options        TEXT,
+    direction      TEXT NOT NULL DEFAULT 'download',
+    job_id         BLOB,
+    environment    TEXT,
+    quarantine     TEXT,
+    acceleration   REAL,
+    pinned         INTEGER NOT NULL DEFAULT 0,
+    device         TEXT
+";
+
+/// First bytes of a plaintext database, an encrypted one starts with random salt
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
 pub struct Database {
     conn: Mutex<Connection>,
+    /// what the file is encrypted with, to open it again when `set_encrypted` fails
+    key: Mutex<Option<[u8; 32]>>,
 }
 
 impl Database {
+    /// Open or create the database at `db_path`, with the keyring's key when it's encrypted
     pub fn new(db_path: &Path) -> Result<Self> {
+        let key = match is_encrypted(db_path) {
+            true => Some(
+                crypt::history_key(false)
+                    .map_err(key_error)?
+                    .ok_or_else(|| key_error("The keyring has no key for the history".into()))?,
+            ),
+            false => None,
+        };
+        Self::open(db_path, key.as_ref())
+    }
+
+    fn open(db_path: &Path, key: Option<&[u8; 32]>) -> Result<Self> {
         let conn = Connection::open(db_path)?;
+        if let Some(key) = key {
+            conn.pragma_update(None, "key", raw_key(Some(key)))?;
+            // a wrong key only shows on the first read
+            conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+                .map_err(|_| key_error("The keyring's key doesn't open the history".into()))?;
+        }
 
+        // Only takes on a new file, older ones switch on their first `maintain`
+        conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
         // Enable WAL mode for better concurrent access
         conn.pragma_update(None, "journal_mode", "WAL")?;
         conn.pragma_update(None, "synchronous", "NORMAL")?;
@@ -64,23 +163,48 @@ impl Database {
 
         // Create table with improved schema
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS downloads (
-                id             BLOB PRIMARY KEY,
-                filename       TEXT NOT NULL,
-                status         TEXT CHECK (status IN ('completed', 'paused', 'failed')),
-                size           INTEGER,
-                bytes_received INTEGER NOT NULL DEFAULT 0,
-                url            TEXT NOT NULL,
-                etag           TEXT,
-                content_type   TEXT,
-                last_modified  TEXT,
-                destination    TEXT NOT NULL,
-                accept_ranges  INTEGER NOT NULL DEFAULT 0,
-                updated_at     INTEGER NOT NULL DEFAULT (unixepoch())
-            )",
+            &format!("CREATE TABLE IF NOT EXISTS downloads ({})", DOWNLOADS_TABLE),
             [],
         )?;
 
+        // Columns added later, databases from older versions get them here
+        for (column, definition) in ADDED_COLUMNS {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info('downloads') WHERE name = ?1")?
+                .exists(params![column])?;
+            if !exists {
+                conn.execute(
+                    &format!("ALTER TABLE downloads ADD COLUMN {} {}", column, definition),
+                    [],
+                )?;
+            }
+        }
+
+        // Older files only allow completed, paused and failed; SQLite can't change a CHECK
+        // in place, so the rows move to a table made with the current definition
+        let table: String = conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'downloads'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !table.contains("'cancelled'") {
+            let columns = conn
+                .prepare("SELECT name FROM pragma_table_info('downloads')")?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>>>()?
+                .join(", ");
+            conn.execute_batch(&format!(
+                "BEGIN;
+                 CREATE TABLE downloads_new ({table});
+                 INSERT INTO downloads_new ({columns}) SELECT {columns} FROM downloads;
+                 DROP TABLE downloads;
+                 ALTER TABLE downloads_new RENAME TO downloads;
+                 COMMIT;",
+                table = DOWNLOADS_TABLE,
+                columns = columns,
+            ))?;
+        }
+
         // Create indexes for better performance
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_downloads_status ON downloads(status)",
@@ -91,21 +215,99 @@ impl Database {
             [],
         )?;
 
+        // Batches added together, with the subfolder they were given
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id         BLOB PRIMARY KEY,
+                name       TEXT NOT NULL,
+                folder     TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (unixepoch())
+            )",
+            [],
+        )?;
+
+        // Downsampled speed series of finished downloads, samples as a JSON array
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS speed_history (
+                id       BLOB PRIMARY KEY,
+                interval INTEGER NOT NULL,
+                samples  TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Thumbnail file and extracted metadata of finished downloads, info as a JSON object
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS thumbnails (
+                id   BLOB PRIMARY KEY,
+                path TEXT,
+                info TEXT NOT NULL DEFAULT '{}'
+            )",
+            [],
+        )?;
+
+        // Content hashes of completed downloads, to spot the same file fetched twice
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS file_hashes (
+                id     BLOB PRIMARY KEY,
+                sha256 TEXT NOT NULL,
+                size   INTEGER NOT NULL,
+                path   TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_file_hashes_sha256 ON file_hashes(sha256)",
+            [],
+        )?;
+
+        // Timeline of what happened, kept when downloads are removed from the list
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS activity_log (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                at          INTEGER NOT NULL DEFAULT (unixepoch()),
+                kind        TEXT NOT NULL,
+                download_id BLOB,
+                detail      TEXT NOT NULL DEFAULT '{}'
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_activity_log_download ON activity_log(download_id)",
+            [],
+        )?;
+
+        // Downloads that were running when the app last quit, offered for restore on launch
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS session (
+                download_id BLOB PRIMARY KEY,
+                saved_at    INTEGER NOT NULL DEFAULT (unixepoch())
+            )",
+            [],
+        )?;
+
+        // Bytes received per UTC day ("YYYY-MM-DD"), what the monthly cap adds up
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transfer_stats (
+                day   TEXT PRIMARY KEY,
+                bytes INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
         Ok(Self {
             conn: Mutex::new(conn),
+            key: Mutex::new(key.copied()),
         })
     }
 
     /// Initialize database with proper app data directory path
     pub fn initialize(app_handle: &tauri::AppHandle) -> Result<Self, Box<dyn std::error::Error>> {
-        let app_data_dir = app_handle.path().app_data_dir()
+        // the active profile's folder, created if it's new
+        let data_dir = crate::profiles::data_dir(app_handle)
             .map_err(|e| format!("Failed to get app data directory: {}", e))?;
         
-        // Ensure directory exists
-        std::fs::create_dir_all(&app_data_dir)
-            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
-        
-        let db_path = app_data_dir.join("tur.db");
+        let db_path = data_dir.join("tur.db");
         Self::new(&db_path)
             .map_err(|e| format!("Failed to initialize database: {}", e).into())
     }
@@ -149,6 +351,124 @@ impl Database {
         Ok(())
     }
 
+    /// Insert a new upload record, `source` is the local file sent to `endpoint`
+    pub fn insert_upload(
+        &self,
+        id: &Uuid,
+        endpoint: &str,
+        filename: &str,
+        source: &str,
+        size: i64,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO downloads (
+                id, url, filename, destination, size, accept_ranges, direction, updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, 1, 'upload', unixepoch())",
+            params![id.as_bytes(), endpoint, filename, source, size],
+        )?;
+        Ok(())
+    }
+
+    /// Store per download overrides, applied again on every resume
+    pub fn set_options(&self, id: &Uuid, options: &DownloadOptions) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let options = serde_json::to_string(options).unwrap_or_default();
+        conn.execute(
+            "UPDATE downloads SET options = ?2 WHERE id = ?1",
+            params![id.as_bytes(), options],
+        )?;
+        Ok(())
+    }
+
+    /// Keep the settings the engine just started `id` with, replacing the last run's
+    pub fn set_environment(&self, id: &Uuid, environment: &Environment) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let environment = serde_json::to_string(environment).unwrap_or_default();
+        conn.execute(
+            "UPDATE downloads SET environment = ?2 WHERE id = ?1",
+            params![id.as_bytes(), environment],
+        )?;
+        Ok(())
+    }
+
+    /// Settings of the last run, None before the first one
+    pub fn get_environment(&self, id: &Uuid) -> Result<Option<Environment>> {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.query_row(
+            "SELECT environment FROM downloads WHERE id = ?1",
+            params![id.as_bytes()],
+            |row| row.get::<_, Option<String>>(0),
+        );
+        match result {
+            Ok(json) => Ok(json.and_then(|s| serde_json::from_str(&s).ok())),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Record a batch and the folder its downloads go to
+    pub fn insert_job(&self, id: &Uuid, name: &str, folder: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO jobs (id, name, folder) VALUES (?1, ?2, ?3)",
+            params![id.as_bytes(), name, folder],
+        )?;
+        Ok(())
+    }
+
+    /// Attach a download to the batch it came with
+    pub fn set_job(&self, id: &Uuid, job_id: &Uuid) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET job_id = ?2 WHERE id = ?1",
+            params![id.as_bytes(), job_id.as_bytes()],
+        )?;
+        Ok(())
+    }
+
+    /// Point a download at a fresh url, e.g. a re-signed presigned one
+    pub fn update_url(&self, id: &Uuid, url: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET url = ?2, updated_at = unixepoch() WHERE id = ?1",
+            params![id.as_bytes(), url],
+        )?;
+        Ok(())
+    }
+
+    /// Correct the size once the server turned out to have another one than it said
+    pub fn update_size(&self, id: &Uuid, size: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET size = ?2, updated_at = unixepoch() WHERE id = ?1",
+            params![id.as_bytes(), size],
+        )?;
+        Ok(())
+    }
+
+    /// Drop ranges and the size of a download the server compresses on the fly, `size` is
+    /// what it unpacked to once finished
+    pub fn set_encoded(&self, id: &Uuid, size: Option<i64>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET size = ?2, accept_ranges = 0, updated_at = unixepoch()
+             WHERE id = ?1",
+            params![id.as_bytes(), size],
+        )?;
+        Ok(())
+    }
+
+    /// Record where a download's file was moved to
+    pub fn update_destination(&self, id: &Uuid, filename: &str, destination: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET filename = ?2, destination = ?3, updated_at = unixepoch() WHERE id = ?1",
+            params![id.as_bytes(), filename, destination],
+        )?;
+        Ok(())
+    }
+
     /// Update headers for an existing download
     pub fn update_headers(
         &self,
@@ -201,6 +521,62 @@ impl Database {
         Ok(())
     }
 
+    /// Replace the session snapshot with `ids`, an empty list clears it
+    pub fn save_session(&self, ids: &[Uuid]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM session", [])?;
+        for id in ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO session (download_id) VALUES (?1)",
+                params![id.as_bytes()],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Downloads in the last session snapshot
+    pub fn get_session(&self) -> Result<Vec<Uuid>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT download_id FROM session ORDER BY saved_at")?;
+        let ids = stmt.query_map([], |row| {
+            let id_bytes: Vec<u8> = row.get(0)?;
+            Ok(Uuid::from_slice(&id_bytes).unwrap_or_default())
+        })?;
+        ids.collect()
+    }
+
+    /// Add `bytes` to what was received on `day`
+    pub fn add_received(&self, day: &str, bytes: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO transfer_stats (day, bytes) VALUES (?1, ?2)
+             ON CONFLICT(day) DO UPDATE SET bytes = bytes + excluded.bytes",
+            params![day, bytes as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Bytes received per day from `day` on, oldest first
+    pub fn get_received_since(&self, day: &str) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT day, bytes FROM transfer_stats WHERE day >= ?1 ORDER BY day")?;
+        let days = stmt.query_map(params![day], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        days.collect()
+    }
+
+    /// Where the newest `limit` downloads were saved and when they last changed, newest first
+    pub fn get_recent_destinations(&self, limit: i64) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT destination, updated_at FROM downloads WHERE direction = 'download'
+             ORDER BY updated_at DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
     /// Get all incomplete downloads (status is NULL)
     pub fn get_incomplete(&self) -> Result<Vec<(Uuid, String, i64)>> {
         let conn = self.conn.lock().unwrap();
@@ -222,7 +598,7 @@ impl Database {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, filename, status, size, bytes_received, url, etag, 
-                    content_type, last_modified, destination, accept_ranges, updated_at
+                    content_type, last_modified, destination, accept_ranges, updated_at, options, direction, job_id, quarantine, acceleration, pinned, device
              FROM downloads ORDER BY updated_at DESC"
         )?;
 
@@ -233,10 +609,90 @@ impl Database {
         downloads.collect()
     }
 
+    /// A page of finished, failed and paused downloads, pinned ones then newest first, and
+    /// how many there are
+    pub fn get_history(&self, limit: i64, offset: i64) -> Result<(Vec<Download>, i64)> {
+        let conn = self.conn.lock().unwrap();
+        let total = conn.query_row(
+            "SELECT COUNT(*) FROM downloads WHERE status IS NOT NULL",
+            [],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT id, filename, status, size, bytes_received, url, etag,
+                    content_type, last_modified, destination, accept_ranges, updated_at, options, direction, job_id, quarantine, acceleration, pinned, device
+             FROM downloads WHERE status IS NOT NULL
+             ORDER BY pinned DESC, updated_at DESC LIMIT ?1 OFFSET ?2"
+        )?;
+        let downloads = stmt
+            .query_map(params![limit, offset], |row| self.row_to_download(row))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok((downloads, total))
+    }
+
+    /// Records changed since `since` (unix seconds), what a sync snapshot carries
+    pub fn get_updated_since(&self, since: i64) -> Result<Vec<Download>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, filename, status, size, bytes_received, url, etag, 
+                    content_type, last_modified, destination, accept_ranges, updated_at, options, direction, job_id, quarantine, acceleration, pinned, device
+             FROM downloads WHERE updated_at >= ?1 ORDER BY updated_at"
+        )?;
+
+        let downloads = stmt.query_map([since], |row| {
+            self.row_to_download(row)
+        })?;
+
+        downloads.collect()
+    }
+
+    /// Insert or replace a record from another device, only when it's newer than ours
+    pub fn merge_download(&self, download: &Download) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let options = serde_json::to_string(&download.options).unwrap_or_default();
+        let changed = conn.execute(
+            "INSERT INTO downloads (
+                id, filename, status, size, bytes_received, url, etag, content_type,
+                last_modified, destination, accept_ranges, updated_at, options, direction, pinned,
+                device
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+            ON CONFLICT(id) DO UPDATE SET
+                filename = excluded.filename, status = excluded.status, size = excluded.size,
+                bytes_received = excluded.bytes_received, url = excluded.url, etag = excluded.etag,
+                content_type = excluded.content_type, last_modified = excluded.last_modified,
+                destination = excluded.destination, accept_ranges = excluded.accept_ranges,
+                updated_at = excluded.updated_at, options = excluded.options,
+                direction = excluded.direction, pinned = excluded.pinned, device = excluded.device
+            WHERE excluded.updated_at > downloads.updated_at",
+            params![
+                download.id.as_bytes(),
+                download.filename,
+                download.status,
+                download.size,
+                download.bytes_received,
+                download.url,
+                download.etag,
+                download.content_type,
+                download.last_modified,
+                download.destination,
+                download.accept_ranges as i32,
+                download.updated_at,
+                options,
+                download.direction,
+                download.pinned,
+                download.device
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
     /// Delete a single download record
     pub fn delete_download(&self, id: &Uuid) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM downloads WHERE id = ?1", params![id.as_bytes()])?;
+        conn.execute("DELETE FROM speed_history WHERE id = ?1", params![id.as_bytes()])?;
+        conn.execute("DELETE FROM thumbnails WHERE id = ?1", params![id.as_bytes()])?;
+        conn.execute("DELETE FROM file_hashes WHERE id = ?1", params![id.as_bytes()])?;
         Ok(())
     }
 
@@ -244,9 +700,174 @@ impl Database {
     pub fn purge(&self) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM downloads", [])?;
+        conn.execute("DELETE FROM speed_history", [])?;
+        conn.execute("DELETE FROM thumbnails", [])?;
+        conn.execute("DELETE FROM file_hashes", [])?;
+        conn.execute("DELETE FROM activity_log", [])?;
+        conn.execute("DELETE FROM jobs", [])?;
+        Ok(())
+    }
+
+    /// Store the speed series of a finished download
+    pub fn save_speed_history(&self, id: &Uuid, interval: u32, samples: &[u64]) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let samples = serde_json::to_string(samples).unwrap_or_else(|_| "[]".into());
+        conn.execute(
+            "INSERT OR REPLACE INTO speed_history (id, interval, samples) VALUES (?1, ?2, ?3)",
+            params![id.as_bytes(), interval, samples],
+        )?;
+        Ok(())
+    }
+
+    /// Get the stored speed series as (interval, samples)
+    pub fn get_speed_history(&self, id: &Uuid) -> Result<Option<(u32, Vec<u64>)>> {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.query_row(
+            "SELECT interval, samples FROM speed_history WHERE id = ?1",
+            params![id.as_bytes()],
+            |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)),
+        );
+
+        match result {
+            Ok((interval, samples)) => Ok(Some((
+                interval,
+                serde_json::from_str(&samples).unwrap_or_default(),
+            ))),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Store the thumbnail path and metadata of a finished download
+    pub fn save_thumbnail(
+        &self,
+        id: &Uuid,
+        path: Option<&Path>,
+        info: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let path = path.map(|p| p.to_string_lossy().to_string());
+        let info = serde_json::to_string(info).unwrap_or_else(|_| "{}".into());
+        conn.execute(
+            "INSERT OR REPLACE INTO thumbnails (id, path, info) VALUES (?1, ?2, ?3)",
+            params![id.as_bytes(), path, info],
+        )?;
         Ok(())
     }
 
+    /// Get the stored thumbnail
+    pub fn get_thumbnail(&self, id: &Uuid) -> Result<Option<Thumbnail>> {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.query_row(
+            "SELECT path, info FROM thumbnails WHERE id = ?1",
+            params![id.as_bytes()],
+            |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?)),
+        );
+
+        match result {
+            Ok((path, info)) => Ok(Some(Thumbnail {
+                path: path.map(Into::into),
+                info: serde_json::from_str(&info).unwrap_or_default(),
+            })),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Record the content hash of a completed download
+    pub fn save_hash(&self, id: &Uuid, sha256: &str, size: u64, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO file_hashes (id, sha256, size, path) VALUES (?1, ?2, ?3, ?4)",
+            params![id.as_bytes(), sha256, size as i64, path],
+        )?;
+        Ok(())
+    }
+
+    /// Other completed downloads with the same content, as (id, path), oldest first
+    pub fn find_by_hash(
+        &self,
+        sha256: &str,
+        size: u64,
+        except: &Uuid,
+    ) -> Result<Vec<(Uuid, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, path FROM file_hashes
+             WHERE sha256 = ?1 AND size = ?2 AND id != ?3 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![sha256, size as i64, except.as_bytes()], |row| {
+            let id: Vec<u8> = row.get(0)?;
+            Ok((
+                Uuid::from_slice(&id).unwrap_or_default(),
+                row.get::<_, String>(1)?,
+            ))
+        })?;
+        rows.collect()
+    }
+
+    /// Forget a hash whose file is gone
+    pub fn delete_hash(&self, id: &Uuid) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM file_hashes WHERE id = ?1", params![id.as_bytes()])?;
+        Ok(())
+    }
+
+    /// Append to the activity timeline, `detail` is free form per kind
+    pub fn log_activity(
+        &self,
+        kind: &str,
+        download_id: Option<&Uuid>,
+        detail: &serde_json::Value,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO activity_log (kind, download_id, detail) VALUES (?1, ?2, ?3)",
+            params![kind, download_id.map(|id| id.as_bytes().to_vec()), detail.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Activity matching `filter`, newest first
+    pub fn get_activity(&self, filter: &ActivityFilter) -> Result<Vec<Activity>> {
+        let conn = self.conn.lock().unwrap();
+        let mut sql = String::from(
+            "SELECT id, at, kind, download_id, detail FROM activity_log WHERE at >= ?1 AND at <= ?2",
+        );
+        let mut values: Vec<rusqlite::types::Value> = vec![
+            filter.since.unwrap_or(0).into(),
+            filter.until.unwrap_or(i64::MAX).into(),
+        ];
+        if let Some(id) = &filter.download_id {
+            values.push(id.as_bytes().to_vec().into());
+            sql.push_str(&format!(" AND download_id = ?{}", values.len()));
+        }
+        if !filter.kinds.is_empty() {
+            let mut placeholders = Vec::new();
+            for kind in &filter.kinds {
+                values.push(kind.clone().into());
+                placeholders.push(format!("?{}", values.len()));
+            }
+            sql.push_str(&format!(" AND kind IN ({})", placeholders.join(", ")));
+        }
+        values.push(filter.limit.unwrap_or(500).into());
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT ?{}", values.len()));
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            let download_id: Option<Vec<u8>> = row.get(3)?;
+            let detail: String = row.get(4)?;
+            Ok(Activity {
+                id: row.get(0)?,
+                at: row.get(1)?,
+                kind: row.get(2)?,
+                download_id: download_id.and_then(|bytes| Uuid::from_slice(&bytes).ok()),
+                detail: serde_json::from_str(&detail).unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
     /// Get a single download by ID
     pub fn get_download_by_id(&self, id: &Uuid) -> Result<Option<Download>> {
         let conn = self.conn.lock().unwrap();
@@ -257,7 +878,7 @@ impl Database {
     fn get_download_by_id_internal(&self, conn: &Connection, id: &Uuid) -> Result<Option<Download>> {
         let mut stmt = conn.prepare(
             "SELECT id, filename, status, size, bytes_received, url, etag, 
-                    content_type, last_modified, destination, accept_ranges, updated_at
+                    content_type, last_modified, destination, accept_ranges, updated_at, options, direction, job_id, quarantine, acceleration, pinned, device
              FROM downloads WHERE id = ?1"
         )?;
 
@@ -290,7 +911,7 @@ impl Database {
             Some(s) => {
                 let mut stmt = conn.prepare(
                     "SELECT id, filename, status, size, bytes_received, url, etag, 
-                            content_type, last_modified, destination, accept_ranges, updated_at
+                            content_type, last_modified, destination, accept_ranges, updated_at, options, direction, job_id, quarantine, acceleration, pinned, device
                      FROM downloads WHERE status = ?1 ORDER BY updated_at DESC"
                 )?;
                 let downloads = stmt.query_map([s], |row| {
@@ -301,7 +922,7 @@ impl Database {
             None => {
                 let mut stmt = conn.prepare(
                     "SELECT id, filename, status, size, bytes_received, url, etag, 
-                            content_type, last_modified, destination, accept_ranges, updated_at
+                            content_type, last_modified, destination, accept_ranges, updated_at, options, direction, job_id, quarantine, acceleration, pinned, device
                      FROM downloads WHERE status IS NULL ORDER BY updated_at DESC"
                 )?;
                 let downloads = stmt.query_map([], |row| {
@@ -312,16 +933,161 @@ impl Database {
         }
     }
 
-    /// Update download status (completed, paused, failed)
+    /// Hold a completed download back as `state`, None releases it
+    pub fn set_quarantine(&self, id: &Uuid, state: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET quarantine = ?2, updated_at = unixepoch() WHERE id = ?1",
+            params![id.as_bytes(), state],
+        )?;
+        Ok(())
+    }
+
+    /// Keep a download at the top of the history, or let it go back to its place
+    pub fn set_pinned(&self, id: &Uuid, pinned: bool) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET pinned = ?2, updated_at = unixepoch() WHERE id = ?1",
+            params![id.as_bytes(), pinned],
+        )?;
+        Ok(())
+    }
+
+    /// How many times one connection's speed a completed download averaged
+    pub fn set_acceleration(&self, id: &Uuid, factor: f64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE downloads SET acceleration = ?2 WHERE id = ?1",
+            params![id.as_bytes(), factor],
+        )?;
+        Ok(())
+    }
+
+    /// Update download status (completed, paused, failed, cancelled), a synced record
+    /// becomes this device's
     pub fn update_status(&self, id: &Uuid, status: Option<&str>) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE downloads SET status = ?2, updated_at = unixepoch() WHERE id = ?1",
+            "UPDATE downloads SET status = ?2, device = NULL, updated_at = unixepoch() WHERE id = ?1",
             params![id.as_bytes(), status],
         )?;
         Ok(())
     }
 
+    /// Whether the file is encrypted, see `set_encrypted`
+    pub fn is_encrypted(&self) -> bool {
+        let conn = self.conn.lock().unwrap();
+        conn.path().is_some_and(|path| is_encrypted(Path::new(path)))
+    }
+
+    /// Rewrite the database encrypted with `key`, or as plain SQLite with None. The copy
+    /// is made next to it and only replaces it once it's complete, the original is put
+    /// back and opened again when that fails.
+    pub fn set_encrypted(&self, key: Option<&[u8; 32]>) -> std::result::Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let path = PathBuf::from(conn.path().unwrap_or_default());
+        if path.as_os_str().is_empty() {
+            return Err("The history database isn't a file".into());
+        }
+        let mut copy = path.clone().into_os_string();
+        copy.push("-rekey");
+        let copy = PathBuf::from(copy);
+        let _ = std::fs::remove_file(&copy);
+
+        conn.execute(
+            "ATTACH DATABASE ?1 AS rekeyed KEY ?2",
+            params![copy.to_string_lossy(), raw_key(key)],
+        )
+        .map_err(|e| e.to_string())?;
+        let exported = conn
+            .execute_batch("PRAGMA rekeyed.auto_vacuum = INCREMENTAL")
+            .and_then(|_| conn.query_row("SELECT sqlcipher_export('rekeyed')", [], |_| Ok(())));
+        let detached = conn.execute_batch("DETACH DATABASE rekeyed");
+        if let Err(e) = exported.and(detached) {
+            let _ = std::fs::remove_file(&copy);
+            return Err(e.to_string());
+        }
+
+        // closing checkpoints the write-ahead log into the old file and removes it
+        let old = std::mem::replace(
+            &mut *conn,
+            Connection::open_in_memory().map_err(|e| e.to_string())?,
+        );
+        if let Err((old, e)) = old.close() {
+            *conn = old;
+            let _ = std::fs::remove_file(&copy);
+            return Err(e.to_string());
+        }
+        let mut backup = path.clone().into_os_string();
+        backup.push("-backup");
+        let backup = PathBuf::from(backup);
+        // Err with whether the original was moved aside already
+        let swapped = match std::fs::rename(&path, &backup) {
+            Ok(()) => std::fs::rename(&copy, &path)
+                .map_err(|e| e.to_string())
+                .and_then(|_| Self::open(&path, key).map_err(|e| e.to_string()))
+                .map_err(|e| (e, true)),
+            Err(e) => Err((e.to_string(), false)),
+        };
+        let mut current = self.key.lock().unwrap();
+        match swapped {
+            Ok(reopened) => {
+                *conn = reopened.conn.into_inner().unwrap();
+                *current = key.copied();
+                let _ = std::fs::remove_file(&backup);
+                Ok(())
+            }
+            Err((e, moved)) => {
+                let _ = std::fs::remove_file(&copy);
+                let original = match !moved || std::fs::rename(&backup, &path).is_ok() {
+                    true => &path,
+                    false => &backup,
+                };
+                *conn = Self::open(original, current.as_ref())
+                    .map_err(|reopen| {
+                        format!("{}, and the history can't be opened again: {}", e, reopen)
+                    })?
+                    .conn
+                    .into_inner()
+                    .unwrap();
+                Err(e)
+            }
+        }
+    }
+
+    /// Bytes the database takes on disk, its write-ahead log included
+    pub fn disk_size(&self) -> u64 {
+        let conn = self.conn.lock().unwrap();
+        let Some(path) = conn.path().filter(|p| !p.is_empty()) else {
+            return 0;
+        };
+        let len = |path: &str| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        len(path) + len(&format!("{}-wal", path))
+    }
+
+    /// Check the file for corruption, refresh the query planner's statistics, hand free
+    /// pages back to the filesystem and empty the write-ahead log. A database made before
+    /// incremental vacuum was on is rebuilt once with a full VACUUM to turn it on.
+    /// Returns what `integrity_check` found, `["ok"]` when nothing.
+    pub fn maintain(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let integrity = conn
+            .prepare("PRAGMA integrity_check")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        conn.execute_batch("PRAGMA optimize")?;
+        // 2 is INCREMENTAL
+        let auto_vacuum: i64 = conn.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+        if auto_vacuum == 2 {
+            conn.execute_batch("PRAGMA incremental_vacuum")?;
+        } else {
+            conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+            conn.execute_batch("VACUUM")?;
+        }
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(integrity)
+    }
+
     /// Helper to convert database row to Download struct
     fn row_to_download(&self, row: &rusqlite::Row) -> rusqlite::Result<Download> {
         let id_bytes: Vec<u8> = row.get(0)?;
@@ -340,10 +1106,69 @@ impl Database {
             destination: row.get(9)?,
             accept_ranges: row.get::<_, i32>(10)? != 0,
             updated_at: row.get(11)?,
+            options: row
+                .get::<_, Option<String>>(12)?
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default(),
+            direction: row.get(13)?,
+            job_id: row
+                .get::<_, Option<Vec<u8>>>(14)?
+                .and_then(|bytes| Uuid::from_slice(&bytes).ok()),
+            quarantine: row.get(15)?,
+            acceleration: row.get(16)?,
+            pinned: row.get::<_, i32>(17)? != 0,
+            device: row.get(18)?,
         })
     }
 }
 
+/// Whether the file at `path` is there and not plain SQLite
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+        .is_ok_and(|_| &header != SQLITE_HEADER)
+}
+
+// SQLCipher's raw key syntax, skipping its key derivation; empty for no encryption
+fn raw_key(key: Option<&[u8; 32]>) -> String {
+    key.map(|key| format!("x'{}'", hex::encode(key)))
+        .unwrap_or_default()
+}
+
+// a keyring problem reported as the file not opening
+fn key_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_NOTADB),
+        Some(message),
+    )
+}
+
+/// One entry of the activity timeline
+#[derive(Debug, Clone, serde::Serialize, specta::Type)]
+pub struct Activity {
+    pub id: i64,
+    /// unix seconds
+    pub at: i64,
+    /// added, started, paused, failed, cancelled, completed, settings_changed
+    pub kind: String,
+    pub download_id: Option<Uuid>,
+    pub detail: serde_json::Value,
+}
+
+/// What `get_activity` returns, every field narrows it down
+#[derive(Debug, Clone, Default, serde::Deserialize, specta::Type)]
+#[serde(default)]
+pub struct ActivityFilter {
+    pub kinds: Vec<String>,
+    pub download_id: Option<Uuid>,
+    /// unix seconds, inclusive
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// 500 when left out
+    pub limit: Option<i64>,
+}
+
 /// Extract created_at timestamp from UUID v7
 pub fn extract_timestamp_from_uuid_v7(id: &Uuid) -> Option<i64> {
     // UUID v7 has timestamp in first 48 bits (6 bytes)
@@ -1,32 +1,270 @@
+#[path = "downloads/actions.rs"]
+pub mod actions;
+#[path = "downloads/archive.rs"]
+pub mod archive;
+#[path = "downloads/backoff.rs"]
+pub mod backoff;
+#[path = "downloads/category.rs"]
+pub mod category;
+#[path = "downloads/cleanup.rs"]
+pub mod cleanup;
+#[path = "downloads/client.rs"]
+pub mod client;
 #[path = "downloads/core.rs"]
 pub mod core;
+#[path = "downloads/crypt.rs"]
+pub mod crypt;
+#[path = "downloads/dedup.rs"]
+pub mod dedup;
+#[path = "downloads/encoding.rs"]
+pub mod encoding;
+#[path = "downloads/existing.rs"]
+pub mod existing;
+#[path = "downloads/fair.rs"]
+pub mod fair;
+#[path = "downloads/folders.rs"]
+pub mod folders;
+#[path = "downloads/health.rs"]
+pub mod health;
+#[path = "downloads/io.rs"]
+pub mod io;
+#[path = "downloads/links.rs"]
+pub mod links;
+#[path = "downloads/maintenance.rs"]
+pub mod maintenance;
 #[path = "downloads/manager.rs"]
 pub mod manager;
+#[path = "downloads/mirrors.rs"]
+pub mod mirrors;
+#[path = "downloads/origin.rs"]
+pub mod origin;
+#[path = "downloads/permissions.rs"]
+pub mod permissions;
+#[path = "downloads/preview.rs"]
+pub mod preview;
+#[path = "downloads/process.rs"]
+pub mod process;
+#[path = "downloads/probe.rs"]
+pub mod probe;
+#[path = "downloads/quarantine.rs"]
+pub mod quarantine;
+#[path = "downloads/rewrite.rs"]
+pub mod rewrite;
+#[path = "downloads/robots.rs"]
+pub mod robots;
+#[path = "downloads/rules.rs"]
+pub mod rules;
+#[path = "downloads/safety.rs"]
+pub mod safety;
+#[path = "downloads/s3.rs"]
+pub mod s3;
+#[path = "downloads/speed.rs"]
+pub mod speed;
+#[path = "downloads/stats.rs"]
+pub mod stats;
+#[path = "downloads/template.rs"]
+pub mod template;
+#[path = "downloads/thumbnail.rs"]
+pub mod thumbnail;
+#[path = "downloads/units.rs"]
+pub mod units;
+#[path = "downloads/winpath.rs"]
+pub mod winpath;
+#[path = "downloads/zsync.rs"]
+pub mod zsync;
 
-use reqwest::Client;
 use serde_json::json;
-use std::path::Path;
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use tauri::{Emitter, Manager};
 use url::Url;
 use uuid::Uuid;
 
-use crate::database;
+use crate::events::{PrivateTargetHeld, QueueDownload, Queued};
+use crate::scripting;
 use crate::settings;
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, specta::Type)]
 #[serde(tag = "type", content = "data")]
 pub enum DownloadRequest {
     /// New downloads from external sources (browser extension, manual add, drag & drop)
-    New(Vec<Url>),
+    New {
+        urls: Vec<Url>,
+        #[serde(default)]
+        options: DownloadOptions,
+        /// put them all in one new subfolder, e.g. links from one crawl or list
+        #[serde(default)]
+        batch: Option<BatchOptions>,
+        #[serde(skip)]
+        source: RequestSource,
+        /// the user confirmed links to private addresses with the token of their
+        /// `private_target_held`, see `confirm_private_targets`
+        #[serde(skip)]
+        allow_private: bool,
+    },
     /// Resume existing downloads from history
     Resume(Vec<Uuid>),
     /// Deep link URLs (cold start, app fetches headers)
-    DeepLink(Vec<Url>),
+    DeepLink {
+        urls: Vec<Url>,
+        #[serde(default)]
+        options: DownloadOptions,
+        #[serde(skip)]
+        allow_private: bool,
+    },
+}
+
+/// Where a `New` request came from, told by the way it came in and never by the caller.
+/// All but the user's own adds go through `safety`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RequestSource {
+    /// anything the app can't vouch for, the extension, other programs or windows
+    #[default]
+    External,
+    /// add dialog, drag & drop, paste, in the app's own pages
+    Manual,
+    /// `queue` from an on_complete script
+    Script,
+}
+
+/// Subfolder for a batch, `tur-<date>-<name>` under the download location
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize, specta::Type)]
+#[serde(default)]
+pub struct BatchOptions {
+    /// "batch" when left out
+    pub name: Option<String>,
+    /// fetch every link even with `download.respect_robots` on
+    pub ignore_robots: bool,
+}
+
+/// Per download overrides, stored with the record so resumes use them too
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize, specta::Type)]
+#[serde(default)]
+pub struct DownloadOptions {
+    /// replaces the host rules and the global user agent
+    pub user_agent: Option<String>,
+    /// sent as is, wins over `page_url`
+    pub referer: Option<String>,
+    /// page the link was captured from (browser extension, deep link)
+    pub page_url: Option<Url>,
+    /// replaces `network.interfaces`, an empty list uses the default route
+    pub interfaces: Option<Vec<String>>,
+    /// replaces the `retry` settings for this download
+    pub retry: Option<settings::RetryConfig>,
+    /// replaces how workers retry a failing range, see `client::range_retry`
+    pub range_retry: Option<client::RangeRetry>,
+    /// retry ranges with `RangeRetry::AGGRESSIVE` when `range_retry` isn't set, for flaky
+    /// servers and mirrors
+    pub aggressive_retry: bool,
+    /// fetch front to back, for watching media while it downloads
+    pub sequential: bool,
+    /// let the file grow with the download instead of sizing it up front
+    pub skip_preallocation: bool,
+    /// first and last few MiB before the rest, for archive indexes and media headers
+    pub priority_pieces: bool,
+    /// encrypted while downloading, readable only after `decrypt_download`
+    pub encrypt: bool,
+    /// save under this name instead of the one from Content-Disposition or the url
+    pub filename: Option<String>,
+    /// replaces `download.num_threads`
+    pub connections: Option<u8>,
+    /// KiB/s, replaces `download.speed_limit`
+    pub speed_limit: Option<u64>,
+    /// one of `category::NAMES` instead of guessing from the name
+    pub category: Option<String>,
+    /// keeps running past the monthly transfer cap
+    pub priority: bool,
+    /// older copy of the file, only the blocks that changed are fetched when the server
+    /// has a `.zsync` file next to it
+    pub delta_from: Option<String>,
+    /// one connection at a time, still resuming by range, for servers whose session
+    /// tokens turn parallel requests away; set by itself once they do
+    pub single_session: bool,
+    /// other urls with the same file, ranges are spread over them and the url itself;
+    /// found when the download is added if none are given, see `mirrors`
+    pub mirrors: Vec<Url>,
+    /// files to take out of a ZIP instead of fetching all of it, unpacked into a folder
+    /// named after the archive, see `archive`
+    pub members: Vec<String>,
+    /// no `download_progress` events, only how it ends, for big batches nobody watches
+    pub quiet: bool,
+    /// added by a script, which skips `on_add` so scripts can't keep feeding themselves
+    #[serde(skip)]
+    pub scripted: bool,
+    /// came from outside the app and wasn't confirmed, every connection stays off private
+    /// addresses, see `safety`. Set by the app whatever the caller sends.
+    pub guarded: bool,
+}
+
+impl DownloadOptions {
+    /// Category set by hand or by a rule, when it's a known one
+    pub fn category(&self) -> Option<&'static str> {
+        self.category.as_deref().and_then(category::known)
+    }
+
+    /// Explicit name to save as, None leaves it to the server
+    pub fn filename(&self) -> Option<String> {
+        self.filename
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Manual referer, else the origin of the page the link came from.
+    /// Hotlink protection usually only checks the origin, so the page path isn't leaked.
+    pub fn referer(&self) -> Option<String> {
+        self.referer
+            .clone()
+            .filter(|r| !r.is_empty())
+            .or_else(|| {
+                self.page_url
+                    .as_ref()
+                    .map(|page| page.origin())
+                    .filter(|origin| origin.is_tuple())
+                    .map(|origin| format!("{}/", origin.ascii_serialization()))
+            })
+    }
+}
+
+/// Add `url` like the extension would, for downloads a script asked for
+pub fn queue_from_script(app: &tauri::AppHandle, url: Url) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let request = DownloadRequest::New {
+            urls: vec![url.clone()],
+            options: DownloadOptions {
+                scripted: true,
+                ..Default::default()
+            },
+            batch: None,
+            source: RequestSource::Script,
+            allow_private: false,
+        };
+        let manager = app.state::<manager::DownloadManager>();
+        if let Err(e) = process_request(app.clone(), manager, request).await {
+            eprintln!("Failed to add {} from a script: {}", url, e);
+        }
+    });
+}
+
+/// Configured download folder, the system one when it's unset
+pub fn download_location(
+    app: &tauri::AppHandle,
+    settings: &settings::AppSettings,
+) -> Result<PathBuf, String> {
+    if !settings.download.download_location.trim().is_empty() {
+        return Ok(PathBuf::from(&settings.download.download_location));
+    }
+    app.path()
+        .download_dir()
+        .map_err(|e| format!("Failed to get downloads directory: {}", e))
 }
 
 /// Handle deep link URL parsing and create download request
-pub fn parse_deep_link_url(url_str: &str) -> Option<(Url, Option<String>, Option<u64>)> {
+pub fn parse_deep_link_url(
+    url_str: &str,
+) -> Option<(Url, Option<String>, Option<u64>, DownloadOptions)> {
     let parsed = Url::parse(url_str).ok()?;
     
     let src_url_str = parsed.query_pairs().find(|(k, _)| k == "url")?.1.to_string();
@@ -40,142 +278,188 @@ pub fn parse_deep_link_url(url_str: &str) -> Option<(Url, Option<String>, Option
         .query_pairs()
         .find(|(k, _)| k == "size")
         .and_then(|(_, v)| v.parse::<u64>().ok());
+    let options = DownloadOptions {
+        referer: parsed
+            .query_pairs()
+            .find(|(k, _)| k == "referer")
+            .map(|(_, v)| v.to_string()),
+        page_url: parsed
+            .query_pairs()
+            .find(|(k, _)| k == "page")
+            .and_then(|(_, v)| Url::parse(&v).ok()),
+        filename: filename.clone(),
+        ..Default::default()
+    };
     
-    Some((src_url, filename, size_opt))
-}
-
-/// Create optimized HTTP client with settings-based configuration
-fn create_http_client(settings: &settings::config::AppSettings) -> Result<Client, String> {
-    let client = Client::builder()
-        // Timeouts based on settings or sensible defaults
-        .timeout(Duration::from_secs(300)) // 5min total timeout
-        .connect_timeout(Duration::from_secs(15)) // Slightly longer connection timeout
-        // Connection pooling for better performance
-        .pool_max_idle_per_host(settings.thread.total_connections as usize)
-        .pool_idle_timeout(Duration::from_secs(90))
-        .tcp_keepalive(Duration::from_secs(60))
-        // Compression is enabled by default in reqwest
-        // User agent and redirects
-        .user_agent("tur/1.0 (Download Manager)")
-        .redirect(reqwest::redirect::Policy::limited(10))
-        // Security settings
-        .danger_accept_invalid_certs(false)
-        .https_only(false) // Allow HTTP for compatibility
-        // HTTP/2 support
-        .http2_adaptive_window(true)
-        .http2_keep_alive_interval(Some(Duration::from_secs(30)))
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-    
-    Ok(client)
-}
-
-// Helper functions for extracting download metadata
-fn extract_filename_from_headers(headers: &reqwest::header::HeaderMap) -> Option<String> {
-    headers
-        .get(reqwest::header::CONTENT_DISPOSITION)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|cd| {
-            // Parse Content-Disposition header for filename
-            cd.split(';').find_map(|part| {
-                let part = part.trim();
-                if part.starts_with("filename=") {
-                    Some(part[9..].trim_matches('"').to_string())
-                } else if part.starts_with("filename*=") {
-                    // Handle RFC 5987 encoded filenames
-                    part[10..].split('\'').nth(2).map(|s| s.to_string())
-                } else {
-                    None
-                }
-            })
-        })
-}
-
-fn extract_filename_from_url(url: &str) -> String {
-    url.rsplit('/')
-        .next()
-        .and_then(|s| s.split('?').next()) // Remove query parameters
-        .and_then(|s| s.split('#').next()) // Remove fragments
-        .filter(|s| !s.is_empty())
-        .unwrap_or("download")
-        .to_string()
-}
-
-fn extract_content_length(headers: &reqwest::header::HeaderMap) -> Option<u64> {
-    headers
-        .get(reqwest::header::CONTENT_LENGTH)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.parse::<u64>().ok())
-}
-
-fn extract_etag(headers: &reqwest::header::HeaderMap) -> Option<String> {
-    headers
-        .get(reqwest::header::ETAG)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.trim_matches('"').to_string()) // Remove quotes if present
-}
-
-fn extract_last_modified(headers: &reqwest::header::HeaderMap) -> Option<String> {
-    headers
-        .get(reqwest::header::LAST_MODIFIED)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string())
-}
-
-fn extract_resume_support(headers: &reqwest::header::HeaderMap) -> bool {
-    headers
-        .get(reqwest::header::ACCEPT_RANGES)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.eq_ignore_ascii_case("bytes"))
-        .unwrap_or(false)
+    Some((src_url, filename, size_opt, options))
 }
 
 // for new instances
 // creating instance of Download push it's handle to DMan
 #[tauri::command]
+#[specta::specta]
 pub async fn handle_download_request(
     app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    mut request: DownloadRequest,
+) -> Result<(), String> {
+    if let DownloadRequest::New { source, .. } = &mut request {
+        *source = source_of(&webview);
+    }
+    process_request(app, manager, request).await
+}
+
+// the app's own pages are the user, any other page is external
+fn source_of(webview: &tauri::Webview) -> RequestSource {
+    match crate::security::own_page(webview) {
+        true => RequestSource::Manual,
+        false => RequestSource::External,
+    }
+}
+
+/// Add or resume what `request` asks for, with its source already told by the way it came
+/// in. Guarded links to private addresses are held back, see `hold_private`.
+pub async fn process_request(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
     request: DownloadRequest,
 ) -> Result<(), String> {
+    crate::lock::guard(&app)?;
     // Load fresh settings state
     let settings = settings::load_or_create(&app);
-    
-    // Create HTTP client
-    let client = match create_http_client(&settings) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Failed to create HTTP client: {}", e);
-            return Err(e);
-        }
-    };
 
     match request {
-        DownloadRequest::New(urls) => {
+        DownloadRequest::New { urls, options, batch, source, allow_private } => {
             // Get database instance
-            let db = database::Database::initialize(&app).map_err(|e| e.to_string())?;
+            let db = manager.db();
+            let mut base = download_location(&app, &settings)?;
+            let guarded = source != RequestSource::Manual && !allow_private;
+            let mut held = Vec::new();
+            let requested_batch = batch.clone();
+
+            // Several links at once can get a folder of their own
+            let batch = batch.or_else(|| {
+                (settings.download.batch_subfolders && urls.len() > 1).then(BatchOptions::default)
+            });
+            // a batch is likely a crawl, small sites get robots.txt honoured
+            let robots = settings.download.respect_robots
+                && urls.len() > 1
+                && !batch.as_ref().is_some_and(|b| b.ignore_robots);
+            let job = match batch {
+                Some(batch) => {
+                    let (job_id, folder) = manager.create_job(&base, batch.name.as_deref())?;
+                    base = folder;
+                    Some(job_id)
+                }
+                None => None,
+            };
             
             // Process each URL from browser extension
             for url in urls {
+                let requested = url.clone();
+                // rewrites first, everything after sees the url that's actually fetched
+                let url = rewrite::rewrite(&settings.download.rewrites, &url).rewritten;
                 let url_str = url.as_str();
+                // the first matching rule fills in what the request left open
+                let (mut options, rule) = rules::for_download(&settings.download.rules, &url, &options);
+                options.guarded = guarded;
+                let rule_folder = rule.as_ref().and_then(|r| r.destination.as_deref());
+                let mut base = match rule_folder.filter(|folder| !folder.trim().is_empty()) {
+                    Some(folder) if job.is_none() => PathBuf::from(folder),
+                    _ => base.clone(),
+                };
                 
-                // Fetch headers from server
-                let response = client.head(url_str).send().await.map_err(|e| e.to_string())?;
-                let headers = response.headers();
-                
-                let filename = extract_filename_from_headers(headers)
-                    .unwrap_or_else(|| extract_filename_from_url(url_str));
-                let size = extract_content_length(headers).map(|s| s as i64);
-                let etag = extract_etag(headers);
-                let last_modified = extract_last_modified(headers);
-                let resume_supported = extract_resume_support(headers);
+                // Fetch headers from server, with the headers this download will use
+                let (target, client) = client::for_target(&settings, &url, &options)?;
+                if guarded {
+                    if let Err(reason) = safety::check(&settings.network, &target).await {
+                        held.push((requested, reason));
+                        continue;
+                    }
+                }
+                if robots {
+                    let agent = client::user_agent(&settings, &url, &options);
+                    match manager.robots().visit(&client, &target, agent).await {
+                        // one connection at a time to hosts that ask to be crawled slowly
+                        Ok(rules) if rules.crawl_delay.is_some() => options.connections = Some(1),
+                        Ok(_) => {}
+                        Err(e) => {
+                            eprintln!("Skipping {}: {}", url_str, e);
+                            manager.log_activity("robots_skipped", None, json!({ "url": url_str }));
+                            continue;
+                        }
+                    }
+                }
+                let probe::RemoteInfo {
+                    filename,
+                    size,
+                    content_type,
+                    etag,
+                    last_modified,
+                    resume_supported,
+                } = probe::probe(&client, &target).await?;
+                let mut filename = options.filename().unwrap_or(filename);
 
                 // Generate unique ID for this download
                 let id = Uuid::now_v7();
 
-                // Determine destination path (use downloads directory + filename)
-                let downloads_dir = app.path().download_dir()
-                    .map_err(|e| format!("Failed to get downloads directory: {}", e))?;
-                let destination = downloads_dir.join(&filename).to_string_lossy().to_string();
+                // on_add scripts can still rename, move or recategorize it, nothing is stored yet
+                if !options.scripted {
+                    let draft = scripting::ScriptDownload {
+                        id,
+                        url: url_str.to_string(),
+                        filename: filename.clone(),
+                        folder: base.to_string_lossy().to_string(),
+                        size,
+                        content_type: content_type.clone(),
+                        category: options
+                            .category()
+                            .unwrap_or_else(|| category::category(&filename, content_type.as_deref()))
+                            .to_string(),
+                        ..Default::default()
+                    };
+                    let actions = scripting::run(&app, scripting::Hook::Add, draft).await;
+                    scripting::apply_added(&app, actions, &mut filename, &mut base, &mut options);
+                }
+                if settings.download.is_blocked(&filename) {
+                    return Err(format!("{} is a blocked file type", filename));
+                }
+
+                // Determine destination path (download location + filename template)
+                let destination = template::destination(
+                    &settings,
+                    &base,
+                    &url,
+                    &filename,
+                    content_type.as_deref(),
+                    options.category(),
+                )?
+                .to_string_lossy()
+                .to_string();
+                let planned = destination.clone();
+                let (destination, downloaded) =
+                    existing::resolve(&client, &target, destination, size, resume_supported).await;
+                // the file this one replaces is likely its previous version
+                if !downloaded
+                    && settings.download.delta_updates
+                    && options.delta_from.is_none()
+                    && options.members.is_empty()
+                    && Path::new(&planned).is_file()
+                {
+                    options.delta_from = Some(planned);
+                }
+                // other servers with the same file, its ranges get spread over them
+                if !downloaded
+                    && settings.download.discover_mirrors
+                    && resume_supported
+                    && options.mirrors.is_empty()
+                    && options.members.is_empty()
+                {
+                    if let Some(size) = size {
+                        options.mirrors = mirrors::discover(&client, &target, size).await;
+                    }
+                }
 
                 // Store to database
                 db.insert_download(
@@ -184,37 +468,64 @@ pub async fn handle_download_request(
                     &filename,
                     &destination,
                     size,
-                    headers.get(reqwest::header::CONTENT_TYPE)
-                        .and_then(|v| v.to_str().ok()),
+                    content_type.as_deref(),
                     etag.as_deref(),
                     last_modified.as_deref(),
                     resume_supported,
                 ).map_err(|e| e.to_string())?;
+                if options != DownloadOptions::default() {
+                    db.set_options(&id, &options).map_err(|e| e.to_string())?;
+                }
+                if let Some(job_id) = &job {
+                    db.set_job(&id, job_id).map_err(|e| e.to_string())?;
+                }
+                // nothing to fetch, the file already there is this one
+                let status = match downloaded {
+                    true => {
+                        db.update_progress(&id, size.unwrap_or(0))
+                            .and_then(|_| db.mark_completed(&id))
+                            .map_err(|e| e.to_string())?;
+                        "completed"
+                    }
+                    false => "queued",
+                };
 
                 // Emit download info to frontend
-                let payload = json!({
-                    "id": id,
-                    "url": url_str,
-                    "filename": filename,
-                    "size": size,
-                    "destination": destination,
-                    "resume_supported": resume_supported,
-                    "etag": etag,
-                    "last_modified": last_modified,
-                    "status": "queued",
-                    "type": "external"
+                let payload = QueueDownload::External(Queued {
+                    id,
+                    url: url_str.to_string(),
+                    filename,
+                    size,
+                    destination,
+                    job_id: job,
+                    resume_supported,
+                    etag,
+                    last_modified,
+                    status: status.into(),
+                    rule: rule.map(|r| r.name),
                 });
                 
+                manager.log_activity("added", Some(&id), json!(payload));
                 if let Err(e) = app.emit("queue_download", payload) {
                     eprintln!("Failed to emit queue_download event: {}", e);
                 }
 
-                // TODO: Start download work through download manager
-                // 1. Create Download instance with settings
-                // 2. Add to download manager
-                // 3. Start download process
+                if !downloaded {
+                    manager.start(&app, &id, false)?;
+                }
             }
 
+            if !held.is_empty() {
+                let (urls, reasons): (Vec<Url>, _) = held.into_iter().unzip();
+                let request = DownloadRequest::New {
+                    urls: urls.clone(),
+                    options,
+                    batch: requested_batch,
+                    source,
+                    allow_private: true,
+                };
+                hold_private(&app, &manager, request, urls, reasons);
+            }
             Ok(())
         }
         DownloadRequest::Resume(uuids) => {
@@ -225,7 +536,7 @@ pub async fn handle_download_request(
             // comments below this area are not part of instructions.
 
             // Get database instance
-            let db = database::Database::initialize(&app).map_err(|e| e.to_string())?;
+            let db = manager.db();
 
             // Get resume info for all requested UUIDs from database
             let uuid_refs: Vec<&Uuid> = uuids.iter().collect();
@@ -233,19 +544,26 @@ pub async fn handle_download_request(
 
             for download in downloads {
                 // 1st .emit("queue_work") - emit initial download info
-                let payload = json!({
-                    "id": download.id,
-                    "url": download.url,
-                    "filename": download.filename,
-                    "size": download.size,
-                    "type": "resume_check"
-                });
+                let payload = QueueDownload::ResumeCheck {
+                    id: download.id,
+                    url: download.url.clone(),
+                    filename: download.filename.clone(),
+                    size: download.size,
+                };
                 
                 if let Err(e) = app.emit("queue_download", payload) {
                     eprintln!("Failed to emit queue_download event: {}", e);
                     continue;
                 }
 
+                // uploads keep their own state, the server tells how far they got
+                if download.is_upload() {
+                    if let Err(e) = manager.start(&app, &download.id, true) {
+                        eprintln!("Failed to start upload: {}", e);
+                    }
+                    continue;
+                }
+
                 // Check file existence on destination
                 let file_path = Path::new(&download.destination);
                 let file_exists = file_path.exists();
@@ -256,36 +574,33 @@ pub async fn handle_download_request(
                 };
 
                 // Fetch current headers from server to check for changes
-                let response = match client.head(&download.url).send().await {
-                    Ok(resp) => resp,
+                let remote = match Url::parse(&download.url) {
+                    Ok(url) => match client::for_target(&settings, &url, &download.options) {
+                        Ok((target, client)) => probe::probe(&client, &target).await,
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                let remote = match remote {
+                    Ok(remote) => remote,
                     Err(e) => {
                         eprintln!("Failed to fetch headers for {}: {}", download.url, e);
                         continue;
                     }
                 };
 
-                let headers = response.headers();
-                let server_etag = extract_etag(headers);
-                let server_last_modified = extract_last_modified(headers);
-                let server_size = extract_content_length(headers).map(|s| s as i64);
-                let resume_supported = extract_resume_support(headers);
-
                 // Check for mismatches that require restart from scratch
-                let needs_restart = !file_exists ||
-                    (download.etag.is_some() && server_etag != download.etag) ||
-                    (download.last_modified.is_some() && server_last_modified != download.last_modified) ||
-                    (download.size.is_some() && server_size != download.size);
+                let needs_restart = probe::needs_restart(&download, &remote, file_exists);
 
                 if needs_restart {
                     // Update headers in database and reset progress
                     if let Err(e) = db.update_headers(
                         &download.id,
-                        server_size,
-                        headers.get(reqwest::header::CONTENT_TYPE)
-                            .and_then(|v| v.to_str().ok()),
-                        server_etag.as_deref(),
-                        server_last_modified.as_deref(),
-                        resume_supported,
+                        remote.size,
+                        remote.content_type.as_deref(),
+                        remote.etag.as_deref(),
+                        remote.last_modified.as_deref(),
+                        remote.resume_supported,
                     ) {
                         eprintln!("Failed to update headers: {}", e);
                         continue;
@@ -305,26 +620,27 @@ pub async fn handle_download_request(
                 }
 
                 // 2nd .emit("queue_work") - emit resume info with updated headers
-                let resume_payload = json!({
-                    "id": download.id,
-                    "url": download.url,
-                    "filename": download.filename,
-                    "size": server_size,
-                    "bytes_received": if needs_restart { 0 } else { current_file_size },
-                    "resume_supported": resume_supported,
-                    "etag": server_etag,
-                    "last_modified": server_last_modified,
-                    "needs_restart": needs_restart,
-                    "type": "resume_ready"
-                });
+                let resume_payload = QueueDownload::ResumeReady {
+                    id: download.id,
+                    url: download.url.clone(),
+                    filename: download.filename.clone(),
+                    size: remote.size,
+                    bytes_received: if needs_restart { 0 } else { current_file_size },
+                    resume_supported: remote.resume_supported,
+                    etag: remote.etag,
+                    last_modified: remote.last_modified,
+                    needs_restart,
+                };
                 
                 if let Err(e) = app.emit("queue_download", resume_payload) {
                     eprintln!("Failed to emit resume_ready event: {}", e);
                 }
 
-                // TODO: Start download work through download manager
                 // DMAN store to db
                 // Download() starts
+                if let Err(e) = manager.start(&app, &download.id, !needs_restart) {
+                    eprintln!("Failed to start download: {}", e);
+                }
             }
 
             // --- resume_instance (for resuming old instances, headers aren't available)
@@ -441,32 +757,102 @@ pub async fn handle_download_request(
             // start work
             Ok(())
         }
-        DownloadRequest::DeepLink(urls) => {
+        DownloadRequest::DeepLink { urls, options, allow_private } => {
             // Get database instance
-            let db = database::Database::initialize(&app).map_err(|e| e.to_string())?;
+            let db = manager.db();
+            let mut held = Vec::new();
             
             // Process each URL from deep link
             for url in urls {
+                let requested = url.clone();
+                // rewrites first, everything after sees the url that's actually fetched
+                let url = rewrite::rewrite(&settings.download.rewrites, &url).rewritten;
                 let url_str = url.as_str();
+                let (mut options, rule) = rules::for_download(&settings.download.rules, &url, &options);
+                options.guarded = !allow_private;
+                let mut base = match rule.as_ref().and_then(|r| r.destination.as_deref()) {
+                    Some(folder) if !folder.trim().is_empty() => PathBuf::from(folder),
+                    _ => download_location(&app, &settings)?,
+                };
                 
-                // Fetch headers from server
-                let response = client.head(url_str).send().await.map_err(|e| e.to_string())?;
-                let headers = response.headers();
-                
-                let filename = extract_filename_from_headers(headers)
-                    .unwrap_or_else(|| extract_filename_from_url(url_str));
-                let size = extract_content_length(headers).map(|s| s as i64);
-                let etag = extract_etag(headers);
-                let last_modified = extract_last_modified(headers);
-                let resume_supported = extract_resume_support(headers);
+                // Fetch headers from server, with the headers this download will use
+                let (target, client) = client::for_target(&settings, &url, &options)?;
+                // a web page can open a deep link, it doesn't get to pick local targets
+                if !allow_private {
+                    if let Err(reason) = safety::check(&settings.network, &target).await {
+                        held.push((requested, reason));
+                        continue;
+                    }
+                }
+                let probe::RemoteInfo {
+                    filename,
+                    size,
+                    content_type,
+                    etag,
+                    last_modified,
+                    resume_supported,
+                } = probe::probe(&client, &target).await?;
+                let mut filename = options.filename().unwrap_or(filename);
 
                 // Generate unique ID for this download
                 let id = Uuid::now_v7();
 
-                // Determine destination path (use downloads directory + filename)
-                let downloads_dir = app.path().download_dir()
-                    .map_err(|e| format!("Failed to get downloads directory: {}", e))?;
-                let destination = downloads_dir.join(&filename).to_string_lossy().to_string();
+                // on_add scripts can still rename, move or recategorize it, nothing is stored yet
+                if !options.scripted {
+                    let draft = scripting::ScriptDownload {
+                        id,
+                        url: url_str.to_string(),
+                        filename: filename.clone(),
+                        folder: base.to_string_lossy().to_string(),
+                        size,
+                        content_type: content_type.clone(),
+                        category: options
+                            .category()
+                            .unwrap_or_else(|| category::category(&filename, content_type.as_deref()))
+                            .to_string(),
+                        ..Default::default()
+                    };
+                    let actions = scripting::run(&app, scripting::Hook::Add, draft).await;
+                    scripting::apply_added(&app, actions, &mut filename, &mut base, &mut options);
+                }
+                if settings.download.is_blocked(&filename) {
+                    return Err(format!("{} is a blocked file type", filename));
+                }
+
+                // Determine destination path (download location + filename template)
+                let destination = template::destination(
+                    &settings,
+                    &base,
+                    &url,
+                    &filename,
+                    content_type.as_deref(),
+                    options.category(),
+                )?
+                .to_string_lossy()
+                .to_string();
+                let planned = destination.clone();
+                let (destination, downloaded) =
+                    existing::resolve(&client, &target, destination, size, resume_supported).await;
+                // the file this one replaces is likely its previous version
+                if !downloaded
+                    && settings.download.delta_updates
+                    && options.delta_from.is_none()
+                    && options.members.is_empty()
+                    && Path::new(&planned).is_file()
+                {
+                    options.delta_from = Some(planned);
+                }
+                // other servers with the same file, its ranges get spread over them
+                if !downloaded
+                    && settings.download.discover_mirrors
+                    && resume_supported
+                    && options.mirrors.is_empty()
+                    && options.members.is_empty()
+                {
+                    if let Some(size) = size {
+                        options.mirrors = mirrors::discover(&client, &target, size).await;
+                    }
+                }
 
                 // Store to database
                 db.insert_download(
@@ -475,35 +861,762 @@ pub async fn handle_download_request(
                     &filename,
                     &destination,
                     size,
-                    headers.get(reqwest::header::CONTENT_TYPE)
-                        .and_then(|v| v.to_str().ok()),
+                    content_type.as_deref(),
                     etag.as_deref(),
                     last_modified.as_deref(),
                     resume_supported,
                 ).map_err(|e| e.to_string())?;
+                if options != DownloadOptions::default() {
+                    db.set_options(&id, &options).map_err(|e| e.to_string())?;
+                }
+                // nothing to fetch, the file already there is this one
+                let status = match downloaded {
+                    true => {
+                        db.update_progress(&id, size.unwrap_or(0))
+                            .and_then(|_| db.mark_completed(&id))
+                            .map_err(|e| e.to_string())?;
+                        "completed"
+                    }
+                    false => "queued",
+                };
 
                 // Emit download info to frontend
-                let payload = json!({
-                    "id": id,
-                    "url": url_str,
-                    "filename": filename,
-                    "size": size,
-                    "destination": destination,
-                    "resume_supported": resume_supported,
-                    "etag": etag,
-                    "last_modified": last_modified,
-                    "status": "queued",
-                    "type": "deep_link"
+                let payload = QueueDownload::DeepLink(Queued {
+                    id,
+                    url: url_str.to_string(),
+                    filename,
+                    size,
+                    destination,
+                    job_id: None,
+                    resume_supported,
+                    etag,
+                    last_modified,
+                    status: status.into(),
+                    rule: rule.map(|r| r.name),
                 });
                 
+                manager.log_activity("added", Some(&id), json!(payload));
                 if let Err(e) = app.emit("queue_download", payload) {
                     eprintln!("Failed to emit queue_download event: {}", e);
                 }
 
-                // TODO: Start download work through download manager
+                if !downloaded {
+                    manager.start(&app, &id, false)?;
+                }
             }
 
+            if !held.is_empty() {
+                let (urls, reasons): (Vec<Url>, _) = held.into_iter().unzip();
+                let request = DownloadRequest::DeepLink {
+                    urls: urls.clone(),
+                    options,
+                    allow_private: true,
+                };
+                hold_private(&app, &manager, request, urls, reasons);
+            }
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+// links to private addresses wait for the user, `request` adds them once the token the
+// event carries comes back through `confirm_private_targets`
+fn hold_private(
+    app: &tauri::AppHandle,
+    manager: &manager::DownloadManager,
+    request: DownloadRequest,
+    urls: Vec<Url>,
+    reasons: Vec<String>,
+) {
+    for reason in &reasons {
+        eprintln!("Holding back a link: {}", reason);
+    }
+    manager.log_activity("private_target_held", None, json!({ "reasons": reasons }));
+    let token = manager.hold(request);
+    let payload = PrivateTargetHeld { token, urls, reasons };
+    if let Err(e) = app.emit("private_target_held", payload) {
+        eprintln!("Failed to emit private_target_held event: {}", e);
+    }
+}
+
+/// Add the links a `private_target_held` event held back, `token` is the one it carried.
+/// A token confirms once.
+#[tauri::command]
+#[specta::specta]
+pub async fn confirm_private_targets(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    token: Uuid,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "confirm_private_targets")?;
+    let request = manager
+        .take_held(&token)
+        .ok_or("Nothing is held back under this token")?;
+    process_request(app, manager, request).await
+}
+
+/// Speed samples for the graph, live while running and downsampled once completed
+#[tauri::command]
+#[specta::specta]
+pub fn get_speed_history(
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<speed::SpeedSeries, String> {
+    manager.speed_history(&id)
+}
+
+/// Thumbnail and file metadata of a finished download, None until one was made
+#[tauri::command]
+#[specta::specta]
+pub fn get_thumbnail(
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<Option<thumbnail::Thumbnail>, String> {
+    manager.db().get_thumbnail(&id).map_err(|e| e.to_string())
+}
+
+/// Decrypt a finished encrypted download into `target`, or in place when it's left out.
+/// Returns where the plaintext ended up.
+#[tauri::command]
+#[specta::specta]
+pub async fn decrypt_download(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+    target: Option<String>,
+) -> Result<String, String> {
+    crate::security::authorize(&app, &webview, "decrypt_download")?;
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    if !record.options.encrypt || !record.is_completed() {
+        return Err("Only finished encrypted downloads can be decrypted".into());
+    }
+    let path = PathBuf::from(&record.destination);
+    let target = target.map(PathBuf::from);
+    let decrypted = tauri::async_runtime::spawn_blocking(move || {
+        crypt::decrypt(&path, target.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    // decrypted in place, the record describes a plain file from now on
+    if decrypted == Path::new(&record.destination) {
+        let options = DownloadOptions {
+            encrypt: false,
+            ..record.options.clone()
+        };
+        manager
+            .db()
+            .set_options(&id, &options)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(decrypted.to_string_lossy().to_string())
+}
+
+/// Links in a finished HTML or PDF download, grouped by type, to queue follow-ups from
+#[tauri::command]
+#[specta::specta]
+pub async fn extract_links(
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<BTreeMap<&'static str, Vec<String>>, String> {
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    tauri::async_runtime::spawn_blocking(move || links::extract(&record))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Timeline of downloads and settings changes, newest first
+#[tauri::command]
+#[specta::specta]
+pub fn get_activity(
+    manager: tauri::State<'_, manager::DownloadManager>,
+    filter: Option<crate::database::ActivityFilter>,
+) -> Result<Vec<crate::database::Activity>, String> {
+    manager
+        .db()
+        .get_activity(&filter.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// The stored row, the settings `id` last ran with (threads, limits, UA, proxy) and,
+/// while it runs, segments, per worker speed and retries and the url after redirects
+#[tauri::command]
+#[specta::specta]
+pub fn get_download_details(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<manager::DownloadDetails, String> {
+    manager.details(&app, &id)
+}
+
+/// Resolves with the final row once `id` completed, or failed with no retry left pending,
+/// so a flow can chain something after one download
+#[tauri::command]
+#[specta::specta]
+pub async fn await_download(
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<crate::database::Download, String> {
+    manager.wait(&id).await
+}
+
+/// Running downloads with their progress, queued ones, the newest `history` (50 when left
+/// out) finished ones and the settings, in one call for a frontend that just (re)loaded
+#[tauri::command]
+#[specta::specta]
+pub fn get_app_state(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    history: Option<u32>,
+) -> Result<manager::AppState, String> {
+    manager.app_state(&app, history.unwrap_or(50) as i64)
+}
+
+/// Global speed, remaining bytes and queue ETA, with a per category breakdown
+#[tauri::command]
+#[specta::specta]
+pub fn get_dashboard_state(
+    manager: tauri::State<'_, manager::DownloadManager>,
+) -> manager::DashboardState {
+    manager.dashboard()
+}
+
+/// Writer pool, runtime latency and connection reuse counters, reset on every call
+#[tauri::command]
+#[specta::specta]
+pub fn get_io_metrics() -> io::IoMetrics {
+    io::take_metrics()
+}
+
+/// Restart running downloads on clients built from the current settings (proxy, TLS, UA)
+#[tauri::command]
+#[specta::specta]
+pub async fn reload_engine(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+) -> Result<Vec<Uuid>, String> {
+    crate::lock::guard(&app)?;
+    Ok(manager.reload(&app).await)
+}
+
+/// Resume what was running when the app last quit, see the `session_restore_available` event
+#[tauri::command]
+#[specta::specta]
+pub fn restore_session(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+) -> Result<Vec<Uuid>, String> {
+    crate::lock::guard(&app)?;
+    manager.restore_session(&app)
+}
+
+/// Folders downloads were saved to lately, the download location first, with the room
+/// left on their drives. `limit` is 8 when left out.
+#[tauri::command]
+#[specta::specta]
+pub fn get_recent_destinations(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    limit: Option<u32>,
+) -> Result<Vec<folders::RecentFolder>, String> {
+    let settings = settings::load_or_create(&app);
+    let default = download_location(&app, &settings)?;
+    folders::recent(manager.db(), &default, limit.unwrap_or(8) as usize)
+}
+
+/// Room left on the drive `path` is on, for a folder picked by hand
+#[tauri::command]
+#[specta::specta]
+pub fn get_free_space(path: PathBuf) -> Option<folders::Space> {
+    folders::space(&path)
+}
+
+/// Bytes received per day over the last `days` (31 when left out) and the billing month
+/// against `bandwidth.monthly_cap_mib`
+#[tauri::command]
+#[specta::specta]
+pub fn get_transfer_stats(
+    app: tauri::AppHandle,
+    days: Option<u32>,
+) -> Result<stats::TransferStats, String> {
+    stats::report(&app, days.unwrap_or(31))
+}
+
+/// Per download url, size, time spent, average speed and how it ended for the runs that
+/// started in `period`, as CSV or JSON, for reporting bandwidth use
+#[tauri::command]
+#[specta::specta]
+pub fn export_statistics(
+    app: tauri::AppHandle,
+    period: stats::Period,
+    format: stats::ExportFormat,
+) -> Result<String, String> {
+    stats::export(&app, &period, format)
+}
+
+/// Queue failed downloads again, `ids` or every failed one when left out, resuming those
+/// whose partial data still matches the server. Cancelled downloads are skipped.
+#[tauri::command]
+#[specta::specta]
+pub async fn retry_failed(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    ids: Option<Vec<Uuid>>,
+) -> Result<manager::RetrySummary, String> {
+    crate::lock::guard(&app)?;
+    manager.retry_failed(&app, ids).await
+}
+
+/// `url` after the rewrite rules, `rewrites` tries a list out before it's saved
+#[tauri::command]
+#[specta::specta]
+pub fn preview_rewrite(
+    app: tauri::AppHandle,
+    url: Url,
+    rewrites: Option<Vec<settings::RewriteRule>>,
+) -> Result<rewrite::Rewrite, String> {
+    let rewrites = rewrites.unwrap_or_else(|| settings::load_or_create(&app).download.rewrites);
+    rewrite::validate(&rewrites)?;
+    Ok(rewrite::rewrite(&rewrites, &url))
+}
+
+/// Run the send-to action called `action` for a download, e.g. "Open in VLC"
+#[tauri::command]
+#[specta::specta]
+pub fn run_action(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+    action: String,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "run_action")?;
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    if record.quarantine.is_some() {
+        return Err(format!("{} is in quarantine", record.filename));
+    }
+    let settings = settings::load_or_create(&app);
+    let action = settings
+        .actions
+        .iter()
+        .find(|a| a.name == action)
+        .ok_or_else(|| format!("No action called {}", action))?;
+    actions::run(&app, &record, action)
+}
+
+/// Let download `id` out of quarantine without waiting for the scanner
+#[tauri::command]
+#[specta::specta]
+pub fn release_quarantine(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    id: Uuid,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "release_quarantine")?;
+    quarantine::release(&app, &id, "user")
+}
+
+/// Which of the download rules a link to `url` would get, None when no rule matches
+#[tauri::command]
+#[specta::specta]
+pub fn test_rule(app: tauri::AppHandle, url: Url) -> Option<rules::RuleMatch> {
+    rules::find(&settings::load_or_create(&app).download.rules, &url)
+}
+
+/// First `bytes` of `url` in a temp file, so the GUI can show it before committing to it
+#[tauri::command]
+#[specta::specta]
+pub async fn fetch_preview(
+    app: tauri::AppHandle,
+    url: Url,
+    bytes: u64,
+    options: Option<DownloadOptions>,
+) -> Result<preview::Preview, String> {
+    let settings = settings::load_or_create(&app);
+    let (target, client) = client::for_target(&settings, &url, &options.unwrap_or_default())?;
+    preview::fetch(&client, &target, bytes).await
+}
+
+/// Files inside download `id`, a ZIP archive, read from its central directory without
+/// fetching the rest
+#[tauri::command]
+#[specta::specta]
+pub async fn list_contents(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<Vec<archive::Member>, String> {
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    list_members(&app, &record).await
+}
+
+/// Fetch only `files` out of download `id`, a ZIP archive, into a folder named after it.
+/// An empty list goes back to the whole archive. Not while it's running.
+#[tauri::command]
+#[specta::specta]
+pub async fn select_files(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+    files: Vec<String>,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    if manager.instance(&id).is_some() {
+        return Err(format!("Pause {} before picking its files", record.filename));
+    }
+    if record.is_completed() || record.is_upload() {
+        return Err(format!("{} can't have files picked anymore", record.filename));
+    }
+    if !files.is_empty() {
+        if record.options.encrypt {
+            return Err("Encrypted downloads are only fetched whole".into());
+        }
+        // the partial archive would be thrown away
+        if record.options.members.is_empty() && record.bytes_received > 0 {
+            return Err(format!(
+                "{} is already fetching the whole archive",
+                record.filename
+            ));
+        }
+        let members = list_members(&app, &record).await?;
+        for file in &files {
+            match members.iter().find(|m| &m.name == file) {
+                Some(member) if member.supported => {}
+                Some(_) => return Err(format!("{} can't be unpacked by tur", file)),
+                None => return Err(format!("{} isn't in the archive", file)),
+            }
+        }
+    }
+    let options = DownloadOptions {
+        members: files,
+        ..record.options.clone()
+    };
+    manager
+        .db()
+        .set_options(&id, &options)
+        .map_err(|e| e.to_string())?;
+    manager.log_activity(
+        "files_selected",
+        Some(&id),
+        json!({ "files": options.members }),
+    );
+    Ok(())
+}
+
+/// Files inside the ZIP at `url`, read from its central directory without fetching the rest
+#[tauri::command]
+#[specta::specta]
+pub async fn peek_archive(
+    app: tauri::AppHandle,
+    url: Url,
+    options: Option<DownloadOptions>,
+) -> Result<Vec<archive::Member>, String> {
+    let settings = settings::load_or_create(&app);
+    let (target, client) = client::for_target(&settings, &url, &options.unwrap_or_default())?;
+    let remote = probe::probe(&client, &target).await?;
+    match remote.size {
+        Some(size) if remote.resume_supported => archive::list(&client, &target, size as u64).await,
+        _ => Err(format!("{} can't be read in parts", url)),
+    }
+}
+
+/// Queue just `member` of the ZIP at `url`, unpacked into a folder named after the archive
+#[tauri::command]
+#[specta::specta]
+pub async fn download_archive_member(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    url: Url,
+    member: String,
+    options: Option<DownloadOptions>,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    let options = options.unwrap_or_default();
+    if options.encrypt {
+        return Err("Encrypted downloads are only fetched whole".into());
+    }
+    let members = peek_archive(app.clone(), url.clone(), Some(options.clone())).await?;
+    match members.iter().find(|m| m.name == member) {
+        Some(found) if found.supported => {}
+        Some(_) => return Err(format!("{} can't be unpacked by tur", member)),
+        None => return Err(format!("{} isn't in the archive", member)),
+    }
+    let request = DownloadRequest::New {
+        urls: vec![url],
+        options: DownloadOptions {
+            members: vec![member],
+            ..options
+        },
+        batch: None,
+        source: source_of(&webview),
+        allow_private: false,
+    };
+    process_request(app, manager, request).await
+}
+
+async fn list_members(
+    app: &tauri::AppHandle,
+    record: &crate::database::Download,
+) -> Result<Vec<archive::Member>, String> {
+    let size = match record.size {
+        Some(size) if record.accept_ranges && !record.is_upload() => size as u64,
+        _ => return Err(format!("{} can't be read in parts", record.filename)),
+    };
+    let settings = settings::load_or_create(app);
+    let url = Url::parse(&record.url).map_err(|e| e.to_string())?;
+    let (target, client) = client::for_target(&settings, &url, &record.options)?;
+    archive::list(&client, &target, size).await
+}
+
+/// Answer to `presigned_url_expired`: swap in the re-signed url and pick up where it stopped
+#[tauri::command]
+#[specta::specta]
+pub fn refresh_presigned_url(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+    url: Url,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    if !s3::is_presigned(&url) {
+        return Err("Not a presigned url".into());
+    }
+    manager
+        .db()
+        .update_url(&id, url.as_str())
+        .map_err(|e| e.to_string())?;
+    manager.start(&app, &id, true)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn pause_download(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    ids: Vec<Uuid>,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    for id in ids {
+        manager.pause(&id);
+    }
+    Ok(())
+}
+
+/// Keep `ids` at the top of the history, or unpin them
+#[tauri::command]
+#[specta::specta]
+pub fn pin_download(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    ids: Vec<Uuid>,
+    pinned: bool,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    for id in ids {
+        manager
+            .db()
+            .set_pinned(&id, pinned)
+            .map_err(|e| e.to_string())?;
+        manager.log_activity("pinned", Some(&id), json!({ "pinned": pinned }));
+    }
+    Ok(())
+}
+
+/// Fetch finished or failed download `id` again from the start, same file, same headers
+#[tauri::command]
+#[specta::specta]
+pub async fn download_again(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    manager.download_again(&app, &id).await
+}
+
+/// Add download `id` again as a new one with the same url, headers, category and limits.
+/// Its destination is worked out again by the rules and the filename template, and a file
+/// already there is handled like for any other add.
+#[tauri::command]
+#[specta::specta]
+pub async fn redownload(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<(), String> {
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    if record.is_upload() {
+        return Err("Uploads can't be added again".into());
+    }
+    let url = Url::parse(&record.url).map_err(|e| e.to_string())?;
+    // the seed and mirrors were found for the old one, the add looks for them again
+    let options = DownloadOptions {
+        delta_from: None,
+        mirrors: Vec::new(),
+        ..record.options
+    };
+    manager.log_activity("redownload", Some(&id), json!({ "url": record.url }));
+    let request = DownloadRequest::New {
+        urls: vec![url],
+        options,
+        batch: None,
+        source: source_of(&webview),
+        allow_private: false,
+    };
+    process_request(app, manager, request).await
+}
+
+/// How workers of download `id` retry a failing range: `policy`, else the aggressive
+/// preset when `aggressive` is set, else the default. Used from its next start on.
+#[tauri::command]
+#[specta::specta]
+pub fn set_retry_policy(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+    policy: Option<client::RangeRetry>,
+    aggressive: bool,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    let options = DownloadOptions {
+        range_retry: policy,
+        aggressive_retry: aggressive,
+        ..record.options
+    };
+    manager
+        .db()
+        .set_options(&id, &options)
+        .map_err(|e| e.to_string())?;
+    manager.log_activity(
+        "retry_policy",
+        Some(&id),
+        json!({ "policy": policy, "aggressive": aggressive }),
+    );
+    Ok(())
+}
+
+/// KiB/s for one download, None to follow the global limit again
+#[tauri::command]
+#[specta::specta]
+pub fn set_speed_limit(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+    limit: Option<u64>,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    manager.set_speed_limit(&app, &id, limit)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn cancel_download(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    ids: Vec<Uuid>,
+    keep_partial: Option<bool>,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    for id in ids {
+        manager.cancel(&id, keep_partial);
+    }
+    Ok(())
+}
+
+/// What cancelling a download would leave, for the keep or discard prompt
+#[tauri::command]
+#[specta::specta]
+pub fn get_salvageable(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, manager::DownloadManager>,
+    id: Uuid,
+) -> Result<cleanup::Salvage, String> {
+    let record = manager
+        .db()
+        .get_download_by_id(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    Ok(cleanup::salvage(&app, &record))
+}
+
+/// Check and compact the history database now, what the idle task does once a day. Refused
+/// while downloads run or wait, a full VACUUM would hold up their progress writes.
+#[tauri::command]
+#[specta::specta]
+pub async fn run_maintenance(app: tauri::AppHandle) -> Result<maintenance::Maintenance, String> {
+    crate::lock::guard(&app)?;
+    if !app.state::<manager::DownloadManager>().is_idle() {
+        return Err("Downloads are running, maintenance waits until they're done".into());
+    }
+    tauri::async_runtime::spawn_blocking(move || maintenance::run(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Whether the history database is encrypted with the keyring's key
+#[tauri::command]
+#[specta::specta]
+pub fn get_history_encryption(manager: tauri::State<'_, manager::DownloadManager>) -> bool {
+    manager.db().is_encrypted()
+}
+
+/// Encrypt the history database with SQLCipher, its key kept in the OS keyring, or turn
+/// it back into plain SQLite. The key stays in the keyring for a later encrypt.
+#[tauri::command]
+#[specta::specta]
+pub async fn set_history_encryption(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    enabled: bool,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "set_history_encryption")?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let manager = app.state::<manager::DownloadManager>();
+        if manager.db().is_encrypted() == enabled {
+            return Ok(());
+        }
+        let key = match enabled {
+            true => crypt::history_key(true)?,
+            false => None,
+        };
+        manager.db().set_encrypted(key.as_ref())?;
+        manager.log_activity("history_encryption", None, json!({ "enabled": enabled }));
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
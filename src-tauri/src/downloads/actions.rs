@@ -0,0 +1,188 @@
+use serde_json::json;
+use std::path::Path;
+use std::process::Command;
+use tauri::{AppHandle, Manager};
+
+use super::manager::DownloadManager;
+use super::process;
+use crate::database::Download;
+use crate::settings::SendAction;
+
+/// What a command template can use, each filled in from the download it runs for
+pub const PLACEHOLDERS: [&str; 5] = ["{path}", "{url}", "{folder}", "{filename}", "{id}"];
+
+/// Refuse actions that can't run as written, before they're saved
+pub fn validate(actions: &[SendAction]) -> Result<(), String> {
+    for (i, action) in actions.iter().enumerate() {
+        let name = action.name.trim();
+        if name.is_empty() {
+            return Err("Every action needs a name".into());
+        }
+        if actions[..i].iter().any(|a| a.name.trim() == name) {
+            return Err(format!("There are two actions called {}", name));
+        }
+        let words = split(&action.command).map_err(|e| format!("Action {}: {}", name, e))?;
+        match words.first() {
+            None => return Err(format!("Action {}: no command", name)),
+            // the program is always one the user typed, never a name from a server
+            Some(program) if program.contains('{') => {
+                return Err(format!(
+                    "Action {}: the program can't be a placeholder",
+                    name
+                ))
+            }
+            Some(_) => {}
+        }
+        for word in &words {
+            check(word).map_err(|e| format!("Action {}: {}", name, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Start `action` for `download` and return, its exit code and output go to the activity
+/// log once it's done. No shell is involved, each word is one argument however the
+/// placeholders in it expand.
+pub fn run(app: &AppHandle, download: &Download, action: &SendAction) -> Result<(), String> {
+    validate(std::slice::from_ref(action))?;
+    let words = expand(&split(&action.command)?, download);
+    let mut command = Command::new(&words[0]);
+    command.args(&words[1..]);
+    if let Some(dir) = Path::new(&download.destination)
+        .parent()
+        .filter(|d| d.is_dir())
+    {
+        command.current_dir(dir);
+    }
+
+    let manager = app.state::<DownloadManager>();
+    let started = process::start(manager.children(), command)?;
+    manager.log_activity(
+        "action_started",
+        Some(&download.id),
+        json!({ "action": action.name, "command": words }),
+    );
+    let app = app.clone();
+    let id = download.id;
+    let name = action.name.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let manager = app.state::<DownloadManager>();
+        let detail = match process::finish(manager.children(), started) {
+            Ok((code, stdout, stderr)) => json!({
+                "action": name,
+                "exit_code": code,
+                "stdout": stdout,
+                "stderr": stderr,
+            }),
+            Err(e) => json!({ "action": name, "error": e }),
+        };
+        manager.log_activity("action_finished", Some(&id), detail);
+    });
+    Ok(())
+}
+
+// words like a shell splits them: whitespace between, quotes group, a backslash escapes the
+// next character outside single quotes. Nothing is expanded.
+fn split(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut started = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                started = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed '".into()),
+                    }
+                }
+            }
+            '"' => {
+                started = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => return Err("unclosed \"".into()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed \"".into()),
+                    }
+                }
+            }
+            '\\' => {
+                started = true;
+                word.push(chars.next().ok_or("trailing \\")?);
+            }
+            c if c.is_whitespace() => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                    started = false;
+                }
+            }
+            c => {
+                started = true;
+                word.push(c);
+            }
+        }
+    }
+    if started {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// every `{...}` in a word has to be a known placeholder
+fn check(word: &str) -> Result<(), String> {
+    let mut rest = word;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed {{ in {}", word))?;
+        let placeholder = &rest[open..open + close + 1];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder {}, one of {}",
+                placeholder,
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[open + close + 1..];
+    }
+    Ok(())
+}
+
+// one pass, so a filename with `{url}` in it stays as it is
+fn expand(words: &[String], download: &Download) -> Vec<String> {
+    let folder = Path::new(&download.destination)
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let id = download.id.to_string();
+    let value = |placeholder: &str| match placeholder {
+        "{path}" => download.destination.as_str(),
+        "{url}" => download.url.as_str(),
+        "{folder}" => folder.as_str(),
+        "{filename}" => download.filename.as_str(),
+        _ => id.as_str(),
+    };
+    words
+        .iter()
+        .map(|word| {
+            let mut expanded = String::new();
+            let mut rest = word.as_str();
+            while let Some(open) = rest.find('{') {
+                let close = open + rest[open..].find('}').unwrap_or(rest.len() - open - 1);
+                expanded.push_str(&rest[..open]);
+                expanded.push_str(value(&rest[open..=close]));
+                rest = &rest[close + 1..];
+            }
+            expanded.push_str(rest);
+            expanded
+        })
+        .collect()
+}
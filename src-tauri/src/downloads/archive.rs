@@ -0,0 +1,358 @@
+use flate2::write::DeflateDecoder;
+use flate2::CrcWriter;
+use reqwest::{header, StatusCode};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use super::client::HttpClient;
+use super::core::Transfer;
+
+/// The end of central directory record sits in this many bytes at the end, comment included
+const TAIL: u64 = 22 + 0xFFFF;
+/// Central directories bigger than this are refused, they're read whole
+const MAX_DIRECTORY: u64 = 64 << 20;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_END_OF_DIRECTORY: u32 = 0x0606_4b50;
+/// Extra field holding the 64-bit sizes and offset of a ZIP64 member
+const ZIP64_EXTRA: u16 = 0x0001;
+/// Smallest central directory record, the most entries `length` bytes can hold
+const CENTRAL_RECORD: u64 = 46;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// A file or folder inside a remote ZIP archive
+#[derive(Debug, Clone, PartialEq, Serialize, specta::Type)]
+pub struct Member {
+    /// path inside the archive, `/` separated, folders end in `/`
+    pub name: String,
+    /// bytes once extracted
+    pub size: u64,
+    /// bytes it takes in the archive, what fetching it costs
+    pub compressed_size: u64,
+    pub is_dir: bool,
+    /// stored or deflated and not encrypted, the only kinds that can be fetched
+    pub supported: bool,
+    #[serde(skip)]
+    method: u16,
+    #[serde(skip)]
+    crc32: u32,
+    /// where its local header starts
+    #[serde(skip)]
+    offset: u64,
+}
+
+/// Members of the `size` byte ZIP at `url`, read from its central directory with a few
+/// ranged GETs: the tail, then the ZIP64 end record and the directory when the tail
+/// doesn't hold them
+pub async fn list(client: &HttpClient, url: &Url, size: u64) -> Result<Vec<Member>, String> {
+    if size < 22 {
+        return Err("Not a ZIP archive, it's too small".into());
+    }
+    let tail_start = size.saturating_sub(TAIL);
+    let tail = read(client, url, tail_start, size).await?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| le32(&tail, at) == END_OF_DIRECTORY)
+        .ok_or("Not a ZIP archive, it has no central directory")?;
+    let mut entries = le16(&tail, end + 10) as u64;
+    let mut length = le32(&tail, end + 12) as u64;
+    let mut offset = le32(&tail, end + 16) as u64;
+    // saturated fields, the real ones are in the ZIP64 record the locator before it points at
+    if entries == 0xFFFF || length == 0xFFFF_FFFF || offset == 0xFFFF_FFFF {
+        if end < 20 || le32(&tail, end - 20) != ZIP64_LOCATOR {
+            return Err("The archive's central directory is damaged".into());
+        }
+        let at = le64(&tail, end - 12);
+        if at.saturating_add(56) > size {
+            return Err("The archive's central directory is damaged".into());
+        }
+        let record = match at.checked_sub(tail_start) {
+            Some(from) => tail[from as usize..from as usize + 56].to_vec(),
+            None => read(client, url, at, at + 56).await?,
+        };
+        if le32(&record, 0) != ZIP64_END_OF_DIRECTORY {
+            return Err("The archive's central directory is damaged".into());
+        }
+        entries = le64(&record, 32);
+        length = le64(&record, 40);
+        offset = le64(&record, 48);
+    }
+    if length > MAX_DIRECTORY
+        || offset.saturating_add(length) > size
+        || entries > length / CENTRAL_RECORD
+    {
+        return Err("The archive's central directory is damaged".into());
+    }
+    let directory = if offset >= tail_start {
+        let from = (offset - tail_start) as usize;
+        tail[from..from + length as usize].to_vec()
+    } else {
+        read(client, url, offset, offset + length).await?
+    };
+    parse(&directory, entries as usize)
+}
+
+/// `entries` central directory records from the start of `data`
+pub fn parse(data: &[u8], entries: usize) -> Result<Vec<Member>, String> {
+    let damaged = || "The archive's central directory is damaged".to_string();
+    let mut members = Vec::with_capacity(entries);
+    let mut at = 0;
+    while members.len() < entries {
+        if at + 46 > data.len() || le32(data, at) != CENTRAL_HEADER {
+            return Err(damaged());
+        }
+        let flags = le16(data, at + 8);
+        let method = le16(data, at + 10);
+        let name_end = at + 46 + le16(data, at + 28) as usize;
+        let name = data.get(at + 46..name_end).ok_or_else(damaged)?;
+        // flag 11 marks UTF-8, the rest is usually ASCII anyway
+        let name = String::from_utf8_lossy(name).replace('\\', "/");
+        let extra_end = name_end + le16(data, at + 30) as usize;
+        let extra = data.get(name_end..extra_end).ok_or_else(damaged)?;
+        let mut size = le32(data, at + 24) as u64;
+        let mut compressed_size = le32(data, at + 20) as u64;
+        let mut offset = le32(data, at + 42) as u64;
+        // saturated fields come from the ZIP64 extra field, in this order
+        let mut wide = zip64_extra(extra).ok_or_else(damaged)?.into_iter();
+        for field in [&mut size, &mut compressed_size, &mut offset] {
+            if *field == 0xFFFF_FFFF {
+                *field = wide.next().ok_or_else(damaged)?;
+            }
+        }
+        members.push(Member {
+            is_dir: name.ends_with('/'),
+            supported: flags & 1 == 0 && matches!(method, STORED | DEFLATED),
+            name,
+            size,
+            compressed_size,
+            method,
+            crc32: le32(data, at + 16),
+            offset,
+        });
+        at = extra_end + le16(data, at + 32) as usize;
+    }
+    Ok(members)
+}
+
+// the 64-bit values of a ZIP64 extra field among `extra`, None when the fields are cut short
+fn zip64_extra(extra: &[u8]) -> Option<Vec<u64>> {
+    let mut at = 0;
+    while at + 4 <= extra.len() {
+        let id = le16(extra, at);
+        let end = at + 4 + le16(extra, at + 2) as usize;
+        let field = extra.get(at + 4..end)?;
+        if id == ZIP64_EXTRA {
+            return Some(field.chunks_exact(8).map(|v| le64(v, 0)).collect());
+        }
+        at = end;
+    }
+    Some(Vec::new())
+}
+
+/// Where the members of an archive saved as `destination` go: a folder named after it
+pub fn folder(destination: &Path) -> PathBuf {
+    destination.with_extension("")
+}
+
+/// Fetch the members called `names` from the ZIP at `url` into `folder`, keeping their
+/// paths. Members already there at their full size are kept from an earlier run. The
+/// parts of the archive that aren't fetched count as done in `transfer`.
+pub async fn extract(
+    client: &HttpClient,
+    url: &Url,
+    size: u64,
+    names: &[String],
+    folder: &Path,
+    transfer: &Transfer,
+) -> Result<(), String> {
+    let members = list(client, url, size).await?;
+    let mut wanted = Vec::with_capacity(names.len());
+    for name in names {
+        let member = members
+            .iter()
+            .find(|m| &m.name == name)
+            .ok_or_else(|| format!("{} isn't in the archive", name))?;
+        if !member.supported {
+            return Err(format!(
+                "{} is encrypted or packed in a way tur can't unpack",
+                name
+            ));
+        }
+        let path =
+            target(folder, name).ok_or_else(|| format!("{} points outside the folder", name))?;
+        wanted.push((member, path));
+    }
+    let fetched: u64 = wanted.iter().map(|(m, _)| m.compressed_size).sum();
+    transfer.add_downloaded(size.saturating_sub(fetched));
+
+    for (member, path) in wanted {
+        if transfer.is_stopped() {
+            return Ok(());
+        }
+        if member.is_dir {
+            std::fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+            continue;
+        }
+        let done = std::fs::metadata(&path).is_ok_and(|m| m.len() == member.size);
+        if done {
+            transfer.add_downloaded(member.compressed_size);
+            continue;
+        }
+        fetch(client, url, member, &path, transfer).await?;
+    }
+    Ok(())
+}
+
+// `name` under `folder`, None for absolute paths and ones climbing out with `..`
+fn target(folder: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = folder.to_path_buf();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            part if part.contains(':') => return None,
+            part => path.push(part),
+        }
+    }
+    (path != folder && !name.starts_with('/')).then_some(path)
+}
+
+// unpack one member into `path`, checked against its CRC; a stop leaves no partial file
+async fn fetch(
+    client: &HttpClient,
+    url: &Url,
+    member: &Member,
+    path: &Path,
+    transfer: &Transfer,
+) -> Result<(), String> {
+    let header = read(client, url, member.offset, member.offset + 30).await?;
+    if le32(&header, 0) != LOCAL_HEADER {
+        return Err(format!(
+            "The archive's entry for {} is damaged",
+            member.name
+        ));
+    }
+    // the local extra field can differ from the central one
+    let start = member.offset + 30 + le16(&header, 26) as u64 + le16(&header, 28) as u64;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut out = Unpack::new(member.method, file);
+
+    let mut left = member.compressed_size;
+    if left > 0 {
+        let mut response = range(client, url, start, start + left).await?;
+        while let Some(chunk) = client.chunk(&mut response).await? {
+            if transfer.is_stopped() {
+                drop(out);
+                std::fs::remove_file(path).map_err(|e| e.to_string())?;
+                return Ok(());
+            }
+            let n = chunk.len().min(left as usize);
+            out.write_all(&chunk[..n]).map_err(|e| e.to_string())?;
+            transfer.add_downloaded(n as u64);
+            left -= n as u64;
+            if left == 0 {
+                break;
+            }
+        }
+    }
+    if left > 0 {
+        return Err(format!("Connection closed before {} ended", member.name));
+    }
+    let (crc, len) = out.finish().map_err(|e| e.to_string())?;
+    if crc != member.crc32 || len != member.size {
+        return Err(format!(
+            "{} came out corrupt, its checksum doesn't match",
+            member.name
+        ));
+    }
+    Ok(())
+}
+
+// what a member's bytes go through on their way to the file, with a running CRC-32
+enum Unpack {
+    Stored(CrcWriter<BufWriter<File>>),
+    Deflated(DeflateDecoder<CrcWriter<BufWriter<File>>>),
+}
+
+impl Unpack {
+    fn new(method: u16, file: File) -> Self {
+        let out = CrcWriter::new(BufWriter::new(file));
+        match method {
+            DEFLATED => Unpack::Deflated(DeflateDecoder::new(out)),
+            _ => Unpack::Stored(out),
+        }
+    }
+
+    // CRC and length of what was written
+    fn finish(self) -> std::io::Result<(u32, u64)> {
+        let mut out = match self {
+            Unpack::Stored(out) => out,
+            Unpack::Deflated(decoder) => decoder.finish()?,
+        };
+        out.flush()?;
+        let crc = out.crc();
+        Ok((crc.sum(), crc.amount() as u64))
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Unpack::Stored(out) => out.write_all(data),
+            Unpack::Deflated(decoder) => decoder.write_all(data),
+        }
+    }
+}
+
+async fn range(
+    client: &HttpClient,
+    url: &Url,
+    start: u64,
+    end: u64,
+) -> Result<reqwest::Response, String> {
+    let request = client
+        .inner()
+        .get(url.clone())
+        .header(header::RANGE, format!("bytes={}-{}", start, end - 1));
+    let response = client.send(request).await?;
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => Ok(response),
+        s if s.is_success() => Err("The server doesn't send parts of the archive".into()),
+        s => Err(format!("Unexpected status {}", s)),
+    }
+}
+
+// bytes `start..end` of the file, whole
+async fn read(client: &HttpClient, url: &Url, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    let mut response = range(client, url, start, end).await?;
+    let mut data = Vec::with_capacity((end - start) as usize);
+    while let Some(chunk) = client.chunk(&mut response).await? {
+        data.extend_from_slice(&chunk);
+    }
+    if (data.len() as u64) < end - start {
+        return Err("The server sent less of the archive than asked for".into());
+    }
+    data.truncate((end - start) as usize);
+    Ok(data)
+}
+
+fn le16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn le32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn le64(data: &[u8], at: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[at..at + 8]);
+    u64::from_le_bytes(bytes)
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// calm time needed before one more connection is let through
+const RAMP_EVERY: Duration = Duration::from_secs(5);
+const MAX_PAUSE: Duration = Duration::from_secs(60);
+
+/// Shared backoff per host, so every worker on it slows down together
+#[derive(Default)]
+pub struct HostBackoff {
+    hosts: Mutex<HashMap<String, Arc<HostGate>>>,
+}
+
+impl HostBackoff {
+    pub fn gate(&self, host: &str) -> Arc<HostGate> {
+        let mut hosts = self.hosts.lock().unwrap();
+        // nobody else holds it and it's not throttled, no state worth keeping
+        hosts.retain(|_, gate| Arc::strong_count(gate) > 1 || gate.is_throttled());
+        hosts
+            .entry(host.to_ascii_lowercase())
+            .or_insert_with(|| Arc::new(HostGate::default()))
+            .clone()
+    }
+}
+
+struct GateState {
+    until: Instant,
+    /// connections allowed right now, None while the host never pushed back
+    cap: Option<usize>,
+    active: usize,
+    /// most connections seen at once, what ramping climbs back to
+    peak: usize,
+    strikes: u32,
+    calm_since: Instant,
+}
+
+pub struct HostGate {
+    state: Mutex<GateState>,
+    released: Notify,
+}
+
+impl Default for HostGate {
+    fn default() -> Self {
+        let now = Instant::now();
+        HostGate {
+            state: Mutex::new(GateState {
+                until: now,
+                cap: None,
+                active: 0,
+                peak: 0,
+                strikes: 0,
+                calm_since: now,
+            }),
+            released: Notify::new(),
+        }
+    }
+}
+
+impl HostGate {
+    /// Wait out a pause and for a free connection slot
+    pub async fn acquire(self: &Arc<Self>) -> HostPermit {
+        loop {
+            let wait = {
+                let mut s = self.state.lock().unwrap();
+                let now = Instant::now();
+                if now < s.until {
+                    Some(s.until - now)
+                } else {
+                    if let Some(cap) = s.cap {
+                        if now.duration_since(s.calm_since) >= RAMP_EVERY {
+                            s.calm_since = now;
+                            s.cap = (cap < s.peak).then_some(cap + 1);
+                            if s.cap.is_none() {
+                                s.strikes = 0; // back to full speed
+                            }
+                        }
+                    }
+                    if s.cap.is_none_or(|cap| s.active < cap) {
+                        s.active += 1;
+                        s.peak = s.peak.max(s.active);
+                        return HostPermit { gate: self.clone() };
+                    }
+                    None
+                }
+            };
+            match wait {
+                Some(pause) => tokio::time::sleep(pause).await,
+                // ramping is time based, so don't rely on a release alone
+                None => {
+                    let _ = tokio::time::timeout(RAMP_EVERY, self.released.notified()).await;
+                }
+            }
+        }
+    }
+
+    /// Host answered 429/503: pause everyone and halve the connections
+    pub fn throttle(&self, retry_after: Option<Duration>) {
+        let mut s = self.state.lock().unwrap();
+        s.strikes += 1;
+        let backoff = Duration::from_millis(500 << s.strikes.min(10)).min(MAX_PAUSE);
+        let now = Instant::now();
+        s.until = s.until.max(now + retry_after.unwrap_or(backoff).min(MAX_PAUSE));
+        s.cap = Some((s.cap.unwrap_or(s.active).min(s.active) / 2).max(1));
+        s.calm_since = s.until;
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.state.lock().unwrap().cap.is_some()
+    }
+}
+
+/// A connection slot, given back on drop
+pub struct HostPermit {
+    gate: Arc<HostGate>,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        self.gate.state.lock().unwrap().active -= 1;
+        self.gate.released.notify_one();
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+const VIDEO: &[&str] = &["mp4", "mkv", "webm", "avi", "mov", "wmv", "flv", "m4v", "mpg", "mpeg"];
+const AUDIO: &[&str] = &["mp3", "flac", "wav", "ogg", "m4a", "aac", "opus", "wma"];
+const IMAGE: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "svg", "tiff", "heic"];
+const ARCHIVE: &[&str] = &["zip", "rar", "7z", "tar", "gz", "bz2", "xz", "zst", "tgz"];
+const DOCUMENT: &[&str] = &["pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "txt", "epub"];
+const PROGRAM: &[&str] = &["exe", "msi", "dmg", "pkg", "deb", "rpm", "appimage", "apk", "iso"];
+
+/// Every bucket `category` can return
+pub const NAMES: &[&str] = &["video", "audio", "image", "archive", "document", "program", "other"];
+
+/// `name` as one of the known buckets, for categories set by hand
+pub fn known(name: &str) -> Option<&'static str> {
+    NAMES.iter().copied().find(|known| known.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Bucket a download by extension, content type as fallback
+pub fn category(filename: &str, content_type: Option<&str>) -> &'static str {
+    let ext = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    for (name, list) in [
+        ("video", VIDEO),
+        ("audio", AUDIO),
+        ("image", IMAGE),
+        ("archive", ARCHIVE),
+        ("document", DOCUMENT),
+        ("program", PROGRAM),
+    ] {
+        if list.contains(&ext.as_str()) {
+            return name;
+        }
+    }
+
+    match content_type.and_then(|c| c.split('/').next()) {
+        Some("video") => "video",
+        Some("audio") => "audio",
+        Some("image") => "image",
+        Some("text") => "document",
+        _ => "other",
+    }
+}
@@ -0,0 +1,220 @@
+use serde::Serialize;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::core::Download;
+use super::crypt;
+use super::manager::DownloadManager;
+use crate::database::{self, Database};
+use crate::events::PartialFile;
+use crate::settings;
+
+/// How often failed downloads are checked for partials old enough to go
+const SWEEP_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Delete the file failed download `record` left behind and forget its saved ranges, the
+/// record stays in history. Logged as `partial_deleted` with the bytes freed.
+pub fn partial(app: &AppHandle, record: &database::Download) -> Result<(), String> {
+    // an upload's destination is the user's own file
+    if record.is_upload() {
+        return Ok(());
+    }
+    let manager = app.state::<DownloadManager>();
+    let path = Path::new(&record.destination);
+    let size = match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() => meta.len(),
+        Ok(_) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    std::fs::remove_file(path).map_err(|e| e.to_string())?;
+    Download::remove_meta(app, &record.id);
+    manager
+        .db()
+        .update_progress(&record.id, 0)
+        .map_err(|e| e.to_string())?;
+    manager.log_activity(
+        "partial_deleted",
+        Some(&record.id),
+        json!({ "path": record.destination, "size": size }),
+    );
+    Ok(())
+}
+
+/// What cancelled download `record` leaves: with `keep` the file and its saved ranges
+/// for a later resume, without it neither. None leaves it to `download.on_cancel`, which
+/// deletes the file or cuts it back to the bytes it got in one piece from the start.
+/// None when there's no file.
+pub fn cancelled(
+    app: &AppHandle,
+    record: &database::Download,
+    keep: Option<bool>,
+) -> Result<Option<PartialFile>, String> {
+    let path = Path::new(&record.destination);
+    if keep == Some(true) {
+        let size = std::fs::metadata(path).map(|m| m.len()).ok();
+        return Ok(size.map(|size| PartialFile {
+            action: "kept".into(),
+            size,
+        }));
+    }
+    // where the first gap is, read before it's dropped
+    let saved = record
+        .size
+        .filter(|size| *size > 0)
+        .and_then(|size| Download::load(app, record.id, size as usize).ok());
+    Download::remove_meta(app, &record.id);
+    // a pick of archive members goes to a folder, the archive itself is never written
+    if record.is_upload() || !record.options.members.is_empty() {
+        return Ok(None);
+    }
+    let len = match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() => meta.len(),
+        Ok(_) => return Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    let manager = app.state::<DownloadManager>();
+    let delete =
+        keep == Some(false) || settings::load_or_create(app).download.on_cancel == "delete";
+    let (action, size) = if delete {
+        std::fs::remove_file(path).map_err(|e| e.to_string())?;
+        if record.options.encrypt {
+            let _ = std::fs::remove_file(crypt::sidecar_path(path));
+        }
+        ("deleted", 0)
+    } else {
+        // without saved ranges it was written front to back
+        let size = saved.map_or(len, |d| (d.contiguous() as u64).min(len));
+        if size < len {
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(size))
+                .map_err(|e| e.to_string())?;
+        }
+        ("truncated", size)
+    };
+    manager
+        .db()
+        .update_progress(&record.id, size as i64)
+        .map_err(|e| e.to_string())?;
+    Ok(Some(PartialFile {
+        action: action.into(),
+        size,
+    }))
+}
+
+/// What cancelling a download could keep of it, for asking before it's thrown away
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Salvage {
+    /// bytes on disk, all of them kept when it's cancelled for a later resume
+    pub downloaded: u64,
+    /// bytes in one piece from the start, what a truncated file keeps
+    pub contiguous: u64,
+    /// a later resume picks up where it stopped instead of starting over
+    pub resumable: bool,
+}
+
+/// [`Salvage`] of `record`, from the running transfer or else from its saved ranges
+pub fn salvage(app: &AppHandle, record: &database::Download) -> Salvage {
+    let manager = app.state::<DownloadManager>();
+    let ranged = record.accept_ranges && record.size.is_some_and(|size| size > 0);
+    if let Some(instance) = manager.instance(&record.id) {
+        let transfer = &instance.transfer;
+        let downloaded = transfer.downloaded();
+        // ranges are handed out front to back, nothing before the lowest one is missing
+        let first_gap = transfer.segments().iter().map(|s| s.start).min();
+        return Salvage {
+            downloaded,
+            contiguous: first_gap.unwrap_or(downloaded).min(downloaded),
+            resumable: ranged && !transfer.is_encoded() && !record.is_upload(),
+        };
+    }
+    let len = std::fs::metadata(&record.destination)
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let saved = record
+        .size
+        .filter(|_| ranged)
+        .and_then(|size| Download::load(app, record.id, size as usize).ok());
+    match saved {
+        Some(saved) => {
+            let size = record.size.unwrap_or(0) as u64;
+            Salvage {
+                downloaded: (size - saved.remaining() as u64).min(len),
+                contiguous: (saved.contiguous() as u64).min(len),
+                resumable: true,
+            }
+        }
+        None => Salvage {
+            downloaded: len,
+            contiguous: len,
+            resumable: false,
+        },
+    }
+}
+
+/// Failed downloads untouched for `download.cleanup_partials_days`, without a retry
+/// pending, lose their partial files
+pub fn sweep(app: &AppHandle) {
+    let Some(days) = settings::load_or_create(app).download.cleanup_partials_days else {
+        return;
+    };
+    let manager = app.state::<DownloadManager>();
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let expired = match expired(manager.db(), days, now, |id| manager.retry_pending(id)) {
+        Ok(expired) => expired,
+        Err(e) => {
+            eprintln!("Failed to list failed downloads: {}", e);
+            return;
+        }
+    };
+    for record in expired {
+        if let Err(e) = partial(app, &record) {
+            eprintln!("Failed to delete partial of {}: {}", record.id, e);
+        }
+    }
+}
+
+/// What a sweep at `now` deletes the partials of: failed downloads untouched for `days`
+/// that left a file and aren't `retrying`. Cancelled ones are left alone, as the cleanup
+/// right after a failure does, a cancel either removed the file already or kept it on
+/// purpose. So are records synced from another device, their destination is a path there.
+pub fn expired(
+    db: &Database,
+    days: u32,
+    now: i64,
+    retrying: impl Fn(&Uuid) -> bool,
+) -> Result<Vec<database::Download>, String> {
+    let failed = db
+        .get_downloads_by_status(Some("failed"))
+        .map_err(|e| e.to_string())?;
+    let cutoff = now - days as i64 * 24 * 60 * 60;
+    Ok(failed
+        .into_iter()
+        .filter(|record| {
+            record.updated_at <= cutoff
+                && record.device.is_none()
+                && !retrying(&record.id)
+                && Path::new(&record.destination).exists()
+        })
+        .collect())
+}
+
+/// Sweep at startup and every hour after, the setting is read each time
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_EVERY);
+        loop {
+            interval.tick().await;
+            let app = app.clone();
+            let _ = tokio::task::spawn_blocking(move || sweep(&app)).await;
+        }
+    });
+}
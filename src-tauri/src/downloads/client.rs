@@ -0,0 +1,703 @@
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderValue, REFERER};
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower_layer::Layer;
+use tower_service::Service;
+use url::Url;
+
+use super::backoff::{HostGate, HostPermit};
+use super::s3;
+use super::safety;
+use super::DownloadOptions;
+use crate::settings::{AppSettings, ChaosConfig};
+
+/// Longest wait between two tries of a range
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How workers retry a range that failed, per download
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct RangeRetry {
+    /// tries after the first before the download fails
+    pub retries: u32,
+    /// ms before the first retry, doubled for each one after it up to 30 s
+    pub backoff_ms: u64,
+    /// seconds a connection may go without data before the range is tried again,
+    /// 0 leaves it to `network.read_timeout`
+    pub stall_timeout: u64,
+}
+
+impl Default for RangeRetry {
+    fn default() -> Self {
+        RangeRetry {
+            retries: 5,
+            backoff_ms: 1000,
+            stall_timeout: 0,
+        }
+    }
+}
+
+impl RangeRetry {
+    /// For flaky servers and mirrors: many quick retries, stalled connections dropped early
+    pub const AGGRESSIVE: RangeRetry = RangeRetry {
+        retries: 20,
+        backoff_ms: 250,
+        stall_timeout: 10,
+    };
+
+    /// Wait before retry `attempt`, counting from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(16);
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor)).min(MAX_BACKOFF)
+    }
+}
+
+/// The download's own policy, `RangeRetry::AGGRESSIVE` when it asks for that, else the
+/// default one, with `network.read_timeout` filling in an unset stall timeout
+pub fn range_retry(settings: &AppSettings, options: &DownloadOptions) -> RangeRetry {
+    let mut policy = match (options.range_retry, options.aggressive_retry) {
+        (Some(policy), _) => policy,
+        (None, true) => RangeRetry::AGGRESSIVE,
+        (None, false) => RangeRetry::default(),
+    };
+    if policy.stall_timeout == 0 {
+        policy.stall_timeout = settings.network.read_timeout;
+    }
+    policy
+}
+
+/// Create optimized HTTP client with settings-based configuration
+pub fn create(settings: &AppSettings) -> Result<Client, String> {
+    build(
+        settings,
+        &settings.download.user_agent,
+        None,
+        None,
+        false,
+        settings.download.num_threads,
+    )
+}
+
+/// Client for one download, headers picked from its options and the host rules, kept off
+/// private addresses when it's guarded
+pub fn for_download(
+    settings: &AppSettings,
+    url: &Url,
+    options: &DownloadOptions,
+) -> Result<Client, String> {
+    build(
+        settings,
+        user_agent(settings, url, options),
+        options.referer().as_deref(),
+        None,
+        options.guarded,
+        threads(settings, options),
+    )
+}
+
+/// Same as `for_download` with sockets bound to `interface`, an address or an interface name
+fn for_interface(
+    settings: &AppSettings,
+    url: &Url,
+    options: &DownloadOptions,
+    interface: &str,
+) -> Result<Client, String> {
+    build(
+        settings,
+        user_agent(settings, url, options),
+        options.referer().as_deref(),
+        Some(interface),
+        options.guarded,
+        threads(settings, options),
+    )
+}
+
+/// Workers the download runs with, one in single session mode
+pub fn threads(settings: &AppSettings, options: &DownloadOptions) -> u8 {
+    match options.single_session {
+        true => 1,
+        false => options.connections.unwrap_or(settings.download.num_threads),
+    }
+}
+
+/// Per download override, then the first matching host rule, then the global one
+pub fn user_agent<'a>(settings: &'a AppSettings, url: &Url, options: &'a DownloadOptions) -> &'a str {
+    if let Some(ua) = options.user_agent.as_deref().filter(|ua| !ua.is_empty()) {
+        return ua;
+    }
+    let host = url.host_str().unwrap_or_default();
+    settings
+        .download
+        .user_agent_rules
+        .iter()
+        .find(|rule| host_matches(&rule.host, host))
+        .map(|rule| rule.user_agent.as_str())
+        .unwrap_or(&settings.download.user_agent)
+}
+
+/// `*.example.com` covers example.com and every subdomain, anything else is exact
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+        None => host == pattern,
+    }
+}
+
+fn build(
+    settings: &AppSettings,
+    user_agent: &str,
+    referer: Option<&str>,
+    interface: Option<&str>,
+    guarded: bool,
+    threads: u8,
+) -> Result<Client, String> {
+    let network = &settings.network;
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(15)) // Slightly longer connection timeout
+        // keep one idle connection per worker, so every range after the first reuses one
+        .pool_max_idle_per_host(threads.max(1) as usize)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .connector_layer(CountConnects)
+        // Socket tuning, see NetworkConfig
+        .tcp_nodelay(network.tcp_nodelay)
+        .tcp_keepalive((network.tcp_keepalive > 0).then(|| Duration::from_secs(network.tcp_keepalive)))
+        // Compression is enabled by default in reqwest
+        // Redirects
+        .redirect(reqwest::redirect::Policy::limited(10))
+        // Security settings
+        .danger_accept_invalid_certs(network.allow_insecure)
+        .https_only(false) // Allow HTTP for compatibility
+        // HTTP/2 support
+        .http2_adaptive_window(true)
+        .http2_keep_alive_interval(Some(Duration::from_secs(30)));
+    // a total timeout cuts off any download that takes longer, idle reads are what hang
+    if network.request_timeout > 0 {
+        builder = builder.timeout(Duration::from_secs(network.request_timeout));
+    }
+    if network.read_timeout > 0 {
+        builder = builder.read_timeout(Duration::from_secs(network.read_timeout));
+    }
+    if network.socket_buffer_size > 0 {
+        // reqwest has no SO_RCVBUF knob, the HTTP/2 windows are what it lets us size
+        let window = network.socket_buffer_size * 1024;
+        builder = builder
+            .http2_adaptive_window(false)
+            .http2_initial_stream_window_size(window)
+            .http2_initial_connection_window_size(window);
+    }
+    if !network.ca_certificate.is_empty() {
+        let pem = std::fs::read(&network.ca_certificate)
+            .map_err(|e| format!("Failed to read {}: {}", network.ca_certificate, e))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid certificate {}: {}", network.ca_certificate, e))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    match network.proxy.as_str() {
+        "" => {}
+        "none" => builder = builder.no_proxy(),
+        proxy => {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|e| format!("Invalid proxy: {}", e))?;
+            builder = builder.proxy(proxy);
+        }
+    }
+    if !user_agent.is_empty() {
+        builder = builder.user_agent(user_agent);
+    }
+    // hosts with hotlink protection 403 without it, set once so every request carries it
+    if let Some(referer) = referer {
+        let value = HeaderValue::from_str(referer).map_err(|e| format!("Invalid referer: {}", e))?;
+        builder = builder.default_headers(HeaderMap::from_iter([(REFERER, value)]));
+    }
+    if let Some(interface) = interface {
+        builder = bind(builder, interface)?;
+    }
+    // checked on every connection and redirect, not just for the link that was added
+    if guarded && network.block_private_targets {
+        builder = builder
+            .dns_resolver(Arc::new(safety::Resolver::new(network)))
+            .redirect(safety::redirects(network));
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn bind(builder: ClientBuilder, interface: &str) -> Result<ClientBuilder, String> {
+    if let Ok(addr) = interface.parse::<IpAddr>() {
+        return Ok(builder.local_address(addr));
+    }
+    bind_name(builder, interface)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn bind_name(builder: ClientBuilder, interface: &str) -> Result<ClientBuilder, String> {
+    Ok(builder.interface(interface))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn bind_name(_builder: ClientBuilder, interface: &str) -> Result<ClientBuilder, String> {
+    Err(format!(
+        "Binding by interface name isn't supported on this platform, use its address instead of {}",
+        interface
+    ))
+}
+
+/// Url to actually fetch and a client for it, `s3://` goes to the bucket endpoint with signing
+pub fn for_target(
+    settings: &AppSettings,
+    url: &Url,
+    options: &DownloadOptions,
+) -> Result<(Url, HttpClient), String> {
+    let client = HttpClient::from(for_download(settings, url, options)?);
+    if url.scheme() == "s3" {
+        let (endpoint, signer) = s3::resolve(&settings.s3, url)?;
+        return Ok((endpoint, client.with_signer(signer)));
+    }
+    Ok((url.clone(), client))
+}
+
+/// Client the engine runs on, chaos is on when the setting or `--chaos` says so.
+/// With interfaces configured, workers are spread across one client per interface.
+pub fn create_engine(
+    settings: &AppSettings,
+    url: &Url,
+    options: &DownloadOptions,
+    force_chaos: bool,
+) -> Result<(Url, HttpClient), String> {
+    let (target, mut client) = for_target(settings, url, options)?;
+    let interfaces = options
+        .interfaces
+        .as_ref()
+        .unwrap_or(&settings.network.interfaces);
+    if !interfaces.is_empty() {
+        let bound = interfaces
+            .iter()
+            .map(|i| for_interface(settings, url, options, i))
+            .collect::<Result<Vec<_>, _>>()?;
+        client.bound = bound.into();
+    }
+    if settings.chaos.enabled || force_chaos {
+        client = client.with_chaos(&settings.chaos);
+    }
+    let limit = options.speed_limit.unwrap_or(settings.download.speed_limit);
+    if limit > 0 {
+        client = client.with_speed_limit(limit * 1024);
+    }
+    Ok((target, client.with_retry(range_retry(settings, options))))
+}
+
+/// The settings a download ran with, kept so a slow or broken download can be explained
+/// after the fact. Taken every time the engine starts it.
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+pub struct Environment {
+    /// unix seconds
+    pub started_at: i64,
+    pub version: String,
+    pub threads: u8,
+    /// KiB/s, 0 is unlimited
+    pub speed_limit: u64,
+    pub user_agent: String,
+    /// without credentials, empty follows the environment's proxy variables
+    pub proxy: String,
+    pub interfaces: Vec<String>,
+    pub request_timeout: u64,
+    pub read_timeout: u64,
+    pub socket_buffer_size: u32,
+    pub tcp_nodelay: bool,
+    pub tcp_keepalive: u64,
+    pub allow_insecure: bool,
+    pub ca_certificate: String,
+    pub chaos: bool,
+    /// how failing ranges were retried
+    #[serde(default)]
+    pub range_retry: RangeRetry,
+}
+
+/// What `create_engine` builds from the same arguments, plus the worker count
+pub fn environment(
+    settings: &AppSettings,
+    url: &Url,
+    options: &DownloadOptions,
+    threads: u8,
+    force_chaos: bool,
+) -> Environment {
+    let network = &settings.network;
+    Environment {
+        started_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        threads,
+        speed_limit: options.speed_limit.unwrap_or(settings.download.speed_limit),
+        user_agent: user_agent(settings, url, options).to_string(),
+        proxy: redact(&network.proxy),
+        interfaces: options
+            .interfaces
+            .clone()
+            .unwrap_or_else(|| network.interfaces.clone()),
+        request_timeout: network.request_timeout,
+        read_timeout: network.read_timeout,
+        socket_buffer_size: network.socket_buffer_size,
+        tcp_nodelay: network.tcp_nodelay,
+        tcp_keepalive: network.tcp_keepalive,
+        allow_insecure: network.allow_insecure,
+        ca_certificate: network.ca_certificate.clone(),
+        chaos: settings.chaos.enabled || force_chaos,
+        range_retry: range_retry(settings, options),
+    }
+}
+
+// proxy url without user and password, those don't belong in the database
+fn redact(proxy: &str) -> String {
+    match Url::parse(proxy) {
+        Ok(mut url) if !url.username().is_empty() || url.password().is_some() => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.to_string()
+        }
+        _ => proxy.to_string(),
+    }
+}
+
+/// reqwest client plus optional fault injection and host backoff, what workers talk through
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    /// one client per bound interface, empty when not binding
+    bound: Arc<[Client]>,
+    chaos: Option<Arc<Chaos>>,
+    gate: Option<Arc<HostGate>>,
+    signer: Option<Arc<s3::Signer>>,
+    /// shared by every worker, so it caps the whole download
+    limit: Option<Arc<RateLimit>>,
+    retry: RangeRetry,
+}
+
+impl From<Client> for HttpClient {
+    fn from(client: Client) -> Self {
+        HttpClient {
+            client,
+            bound: Arc::new([]),
+            chaos: None,
+            gate: None,
+            signer: None,
+            limit: None,
+            retry: RangeRetry::default(),
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn with_chaos(mut self, config: &ChaosConfig) -> Self {
+        self.chaos = Some(Arc::new(Chaos::new(config)));
+        self
+    }
+
+    /// Sign every request, for `s3://` urls
+    pub fn with_signer(mut self, signer: s3::Signer) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Read at most `bytes_per_second` over all workers
+    pub fn with_speed_limit(mut self, bytes_per_second: u64) -> Self {
+        self.limit = Some(Arc::new(RateLimit::new(bytes_per_second)));
+        self
+    }
+
+    /// Read through `limit`, which can change while the download runs
+    pub fn with_rate_limit(mut self, limit: Arc<RateLimit>) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Retry failing ranges and cut off stalled reads per `policy`
+    pub fn with_retry(mut self, policy: RangeRetry) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    pub fn retry(&self) -> RangeRetry {
+        self.retry
+    }
+
+    /// Share backoff with every other download on the same host
+    pub fn with_gate(mut self, gate: Arc<HostGate>) -> Self {
+        self.gate = Some(gate);
+        self
+    }
+
+    /// Connection slot on the host, waits while it's backing off
+    pub async fn permit(&self) -> Option<HostPermit> {
+        match &self.gate {
+            Some(gate) => Some(gate.acquire().await),
+            None => None,
+        }
+    }
+
+    /// Report a 429/503, false when there's no shared gate to handle the wait
+    pub fn throttle(&self, retry_after: Option<Duration>) -> bool {
+        match &self.gate {
+            Some(gate) => {
+                gate.throttle(retry_after);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Client for worker `n`, round robin over the bound interfaces
+    pub fn for_worker(&self, n: usize) -> HttpClient {
+        let mut client = self.clone();
+        if !self.bound.is_empty() {
+            client.client = self.bound[n % self.bound.len()].clone();
+        }
+        client
+    }
+
+    pub fn inner(&self) -> &Client {
+        &self.client
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let (client, request) = request.build_split();
+        let mut request = request.map_err(|e| e.to_string())?;
+        if let Some(chaos) = &self.chaos {
+            // answered like a real server would, so its status and Retry-After go the
+            // same way through the retries and the host backoff
+            if chaos.roll(chaos.config.error_rate) {
+                return Ok(unavailable(request.url()));
+            }
+            if chaos.roll(chaos.config.reset_rate) {
+                return Err("chaos: connection reset before response".into());
+            }
+        }
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut request)?;
+        }
+        // counted once it actually goes out, the chaos answers above never left
+        CONNECTIONS.requests.fetch_add(1, Ordering::Relaxed);
+        client.execute(request).await.map_err(|e| e.to_string())
+    }
+
+    pub async fn chunk(&self, response: &mut Response) -> Result<Option<Bytes>, String> {
+        if let Some(chaos) = &self.chaos {
+            if chaos.roll(chaos.config.reset_rate) {
+                return Err("chaos: connection reset mid-stream".into());
+            }
+            if chaos.roll(chaos.config.slow_rate) {
+                tokio::time::sleep(Duration::from_millis(chaos.config.slow_ms)).await;
+            }
+        }
+        let read = response.chunk();
+        let chunk = match self.retry.stall_timeout {
+            0 => read.await,
+            secs => tokio::time::timeout(Duration::from_secs(secs), read)
+                .await
+                .map_err(|_| format!("No data for {} s, the connection stalled", secs))?,
+        }
+        .map_err(|e| e.to_string())?;
+        if let Some(chunk) = &chunk {
+            RECEIVED.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+        if let (Some(limit), Some(chunk)) = (&self.limit, &chunk) {
+            limit.take(chunk.len()).await;
+        }
+        Ok(chunk)
+    }
+}
+
+// the 503 chaos injects, with a short Retry-After
+fn unavailable(url: &Url) -> Response {
+    let response = hyper::Response::builder()
+        .status(reqwest::StatusCode::SERVICE_UNAVAILABLE)
+        .header(reqwest::header::RETRY_AFTER, "1")
+        .url(url.clone())
+        .body("chaos: injected 503 Service Unavailable")
+        .expect("static response parts");
+    Response::from(response)
+}
+
+/// Bytes per second over everything reading through it, paced so reads can run a
+/// little ahead of the rate but never by more than `RateLimit::BURST`
+pub struct RateLimit {
+    /// 0 is unlimited
+    rate: AtomicU64,
+    /// what `fair` gives it of the link, 0 when it isn't held back
+    share: AtomicU64,
+    /// when the bytes taken so far are paid for
+    next: Mutex<Instant>,
+}
+
+impl RateLimit {
+    const BURST: Duration = Duration::from_millis(250);
+
+    pub fn new(bytes_per_second: u64) -> Self {
+        RateLimit {
+            rate: AtomicU64::new(bytes_per_second),
+            share: AtomicU64::new(0),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn set(&self, bytes_per_second: u64) {
+        self.rate.store(bytes_per_second, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    /// Cap from the bandwidth balancer, the lower of it and the rate counts
+    pub fn set_share(&self, bytes_per_second: u64) {
+        self.share.store(bytes_per_second, Ordering::Relaxed);
+    }
+
+    pub fn share(&self) -> u64 {
+        self.share.load(Ordering::Relaxed)
+    }
+
+    /// Wait until `n` more bytes fit under the rate
+    pub async fn take(&self, n: usize) {
+        let rate = match (self.get(), self.share()) {
+            (0, share) => share,
+            (rate, 0) => rate,
+            (rate, share) => rate.min(share),
+        };
+        if rate == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            *next = (*next).max(now) + Duration::from_secs_f64(n as f64 / rate as f64);
+            next.saturating_duration_since(now + Self::BURST)
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+static CONNECTIONS: ConnectionCounters = ConnectionCounters {
+    requests: AtomicU64::new(0),
+    opened: AtomicU64::new(0),
+};
+
+struct ConnectionCounters {
+    requests: AtomicU64,
+    opened: AtomicU64,
+}
+
+/// Requests against new connections since the last `take_metrics`.
+/// Every request past `opened` went out on a pooled connection.
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct ConnectionMetrics {
+    pub requests: u64,
+    /// connection attempts, failed ones included
+    pub opened: u64,
+    pub reused: u64,
+    pub reuse_percent: f64,
+}
+
+pub fn take_metrics() -> ConnectionMetrics {
+    let requests = CONNECTIONS.requests.swap(0, Ordering::Relaxed);
+    let opened = CONNECTIONS.opened.swap(0, Ordering::Relaxed);
+    let reused = requests.saturating_sub(opened);
+    ConnectionMetrics {
+        requests,
+        opened,
+        reused,
+        reuse_percent: if requests > 0 {
+            reused as f64 * 100.0 / requests as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+/// body bytes read through `HttpClient::chunk`, see `take_received`
+static RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+/// Body bytes received since the last call, every download, probe and preview included
+pub fn take_received() -> u64 {
+    RECEIVED.swap(0, Ordering::Relaxed)
+}
+
+// sits in front of reqwest's connector, which only runs when the pool had nothing idle
+#[derive(Clone)]
+struct CountConnects;
+
+impl<S> Layer<S> for CountConnects {
+    type Service = CountedConnector<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CountedConnector(inner)
+    }
+}
+
+#[derive(Clone)]
+struct CountedConnector<S>(S);
+
+impl<S: Service<R>, R> Service<R> for CountedConnector<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        CONNECTIONS.opened.fetch_add(1, Ordering::Relaxed);
+        self.0.call(request)
+    }
+}
+
+/// Seeded dice so a failing run can be replayed
+struct Chaos {
+    config: ChaosConfig,
+    state: AtomicU64,
+}
+
+impl Chaos {
+    fn new(config: &ChaosConfig) -> Self {
+        Chaos {
+            config: config.clone(),
+            state: AtomicU64::new(config.seed.max(1)),
+        }
+    }
+
+    fn roll(&self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // xorshift64*
+        let mut x = self.state.load(Ordering::Relaxed);
+        loop {
+            let mut next = x;
+            next ^= next >> 12;
+            next ^= next << 25;
+            next ^= next >> 27;
+            match self
+                .state
+                .compare_exchange_weak(x, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    let r = next.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+                    return (r as f64 / (1u64 << 53) as f64) < probability;
+                }
+                Err(current) => x = current,
+            }
+        }
+    }
+}
@@ -1,15 +1,26 @@
 use bincode::{config, error::DecodeError, error::EncodeError, Decode, Encode};
+use bytes::Bytes;
+use futures_util::future::join_all;
+use reqwest::{header, StatusCode};
+use serde::Serialize;
 use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
 use std::ops::Range;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use tauri::path::BaseDirectory;
-use tauri::Manager;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, oneshot};
-use tokio::task::JoinHandle;
+use tokio::task::JoinSet;
+use url::Url;
 use uuid::Uuid;
 
-use crate::settings::AppSettings;
+use super::client::HttpClient;
+use super::crypt::Cipher;
+use super::encoding::{self, Decoder};
+use super::io;
+use super::mirrors;
+use super::winpath;
 
 const PHI: f32 = 1.618033988749895;
 // 2504730781958 to 2199023255552 for 64 bit limit
@@ -76,11 +87,189 @@ const RANGE: [std::ops::Range<usize>; 59] = [
     1548008755918..2199023255552,
 ];
 
+// RANGE entries are counted in 8 MiB units, see get_index
+const UNIT: usize = 1 << 23;
+// a range is only split when the victim has at least this much left
+const MIN_STEAL: usize = 2 << 20;
+// nor when its worker ends it sooner than this, the extra request would cost more than
+// the split saves and the tail would crumble into tiny ranges
+const TAIL: Duration = Duration::from_millis(500);
+// head and tail fetched first in priority mode, enough for headers, indexes and trailers
+const PRIORITY_PIECE: usize = 4 << 20;
+// sequential mode keeps ranges short so the written prefix grows steadily
+const SEQUENTIAL_RANGE: usize = 4 << 20;
+// adaptive ranges: the first ones, before anything was measured, and the bounds
+const ADAPTIVE_START: usize = 4 << 20;
+const ADAPTIVE_MIN: usize = 1 << 20;
+const ADAPTIVE_MAX: usize = 1 << 30;
+// an adaptive range runs at least this long, and at least ROUND_TRIPS round trips so the
+// request in front of it costs a few percent
+const ADAPTIVE_TIME: Duration = Duration::from_secs(2);
+const ROUND_TRIPS: u32 = 20;
+// how often the mirrors' speeds are taken again from the workers on them
+const RERANK: Duration = Duration::from_secs(2);
+// metadata starts with this, the unversioned format with the first range byte (0..=59)
+const META_MAGIC: [u8; 3] = *b"TUR";
+// 1 was unversioned, 2 added the size, strategy and sequential mode
+const META_VERSION: u8 = 2;
+
 struct Index {
     start: AtomicUsize,
     end: AtomicUsize,
 }
 
+impl Index {
+    fn new(range: Range<usize>) -> Self {
+        Index {
+            start: AtomicUsize::new(range.start),
+            end: AtomicUsize::new(range.end),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.end
+            .load(Ordering::Acquire)
+            .saturating_sub(self.start.load(Ordering::Acquire))
+    }
+
+    fn is_done(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn segment(&self) -> Segment {
+        Segment {
+            start: self.start.load(Ordering::Acquire) as u64,
+            end: self.end.load(Ordering::Acquire) as u64,
+        }
+    }
+}
+
+/// A range still to fetch, `start` moves up as bytes land
+#[derive(Debug, Clone, Copy, Serialize, specta::Type)]
+pub struct Segment {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// One worker as the details pane shows it
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct WorkerState {
+    /// None while it waits for a range
+    pub range: Option<Segment>,
+    pub downloaded: u64,
+    /// bytes/s over the last second or so
+    pub speed: u64,
+    /// failed requests it retried
+    pub retries: u32,
+    /// ms to open its connection ahead of the first range, None when it didn't
+    pub setup_ms: Option<u64>,
+}
+
+/// What a worker or the coordinator just did, for `download_debug` in debug mode
+#[derive(Debug, Clone, Serialize, specta::Type)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WorkerEvent {
+    /// `worker` fetches `range` from `url`, again from another source after a mirror failed
+    Assigned {
+        worker: usize,
+        range: Segment,
+        url: String,
+    },
+    /// once a second while it runs
+    Speed {
+        worker: usize,
+        speed: u64,
+        downloaded: u64,
+    },
+    Retry {
+        worker: usize,
+        attempt: u32,
+        retries: u32,
+        wait_ms: u64,
+        error: String,
+    },
+    /// the busiest range was cut at `range.start`, the worker asking next takes `range`
+    Stolen { kept: Segment, range: Segment },
+}
+
+// live counters of one worker, the engine writes and `Transfer::workers` reads
+#[derive(Default)]
+struct WorkerStats {
+    range: Mutex<Weak<Index>>,
+    downloaded: AtomicU64,
+    retries: AtomicU32,
+    requests: AtomicU32,
+    /// until the response headers, smoothed, in microseconds
+    rtt: AtomicU64,
+    /// warm-up request until its response headers, microseconds, 0 without one
+    setup: AtomicU64,
+    /// which of `Sources::urls` it fetches from, 0 is the download's own url
+    source: AtomicUsize,
+    rate: Mutex<Rate>,
+}
+
+// bytes in the current window and the speed of the last full one
+#[derive(Default)]
+struct Rate {
+    since: Option<Instant>,
+    bytes: u64,
+    speed: u64,
+}
+
+impl WorkerStats {
+    // a response came back `rtt` after its request went out
+    fn answered(&self, rtt: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let sample = rtt.as_micros() as u64;
+        let smoothed = match self.rtt.load(Ordering::Relaxed) {
+            0 => sample,
+            old => (old * 7 + sample) / 8,
+        };
+        self.rtt.store(smoothed, Ordering::Relaxed);
+    }
+
+    fn record(&self, n: u64) {
+        self.downloaded.fetch_add(n, Ordering::Relaxed);
+        let mut rate = self.rate.lock().unwrap();
+        let since = *rate.since.get_or_insert_with(Instant::now);
+        rate.bytes += n;
+        let elapsed = since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            rate.speed = (rate.bytes as f64 / elapsed.as_secs_f64()) as u64;
+            rate.since = Some(Instant::now());
+            rate.bytes = 0;
+        }
+    }
+
+    // bytes per second, from the window still open before the first one closes
+    fn speed(&self) -> u64 {
+        let rate = self.rate.lock().unwrap();
+        match rate.since {
+            Some(since) if rate.speed == 0 => {
+                (rate.bytes as f64 / since.elapsed().as_secs_f64().max(1e-3)) as u64
+            }
+            _ => rate.speed,
+        }
+    }
+
+    fn state(&self) -> WorkerState {
+        let rate = self.rate.lock().unwrap();
+        // a stalled worker never closes its window, so don't report the old speed forever
+        let stalled = rate.since.is_some_and(|t| t.elapsed() >= Duration::from_secs(2));
+        let range = self.range.lock().unwrap().upgrade().map(|i| i.segment());
+        WorkerState {
+            speed: if stalled || range.is_none() { 0 } else { rate.speed },
+            range,
+            downloaded: self.downloaded.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            setup_ms: match self.setup.load(Ordering::Relaxed) {
+                0 => None,
+                us => Some(us / 1000),
+            },
+        }
+    }
+}
+
 impl Encode for Index {
     fn encode<E: bincode::enc::Encoder>(&self, e: &mut E) -> Result<(), EncodeError> {
         self.start.load(Ordering::Relaxed).encode(e)?;
@@ -97,68 +286,629 @@ impl<Context> Decode<Context> for Index {
     }
 }
 
-#[derive(Encode, Decode)]
+/// How the coordinator sizes fresh ranges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// growing ranges following the RANGE table
+    Fibonacci,
+    /// every range has the same size, in MiB
+    Fixed(u32),
+    /// sized from the speed and round trip measured on the connections so far, small on
+    /// slow or flaky links and huge on a LAN
+    Adaptive,
+}
+
+impl Strategy {
+    /// `download.range_strategy`, with `chunk_mib` for the fixed one
+    pub fn from_setting(name: &str, chunk_mib: u32) -> Self {
+        match name {
+            "fixed" => Strategy::Fixed(chunk_mib),
+            "adaptive" => Strategy::Adaptive,
+            _ => Strategy::Fibonacci,
+        }
+    }
+}
+
+impl Encode for Strategy {
+    fn encode<E: bincode::enc::Encoder>(&self, e: &mut E) -> Result<(), EncodeError> {
+        match self {
+            Strategy::Fibonacci => 0u8.encode(e),
+            Strategy::Fixed(mib) => {
+                1u8.encode(e)?;
+                mib.encode(e)
+            }
+            Strategy::Adaptive => 2u8.encode(e),
+        }
+    }
+}
+
+impl<Context> Decode<Context> for Strategy {
+    fn decode<D: bincode::de::Decoder<Context = Context>>(d: &mut D) -> Result<Self, DecodeError> {
+        match u8::decode(d)? {
+            0 => Ok(Strategy::Fibonacci),
+            1 => Ok(Strategy::Fixed(u32::decode(d)?)),
+            2 => Ok(Strategy::Adaptive),
+            tag => Err(DecodeError::OtherString(format!("unknown strategy {}", tag))),
+        }
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Fibonacci => write!(f, "fibonacci"),
+            Strategy::Fixed(mib) => write!(f, "fixed-{}m", mib),
+            Strategy::Adaptive => write!(f, "adaptive"),
+        }
+    }
+}
+
 struct Coordinator {
     range_byte: Range<u8>, // start moves ahead and we know when to stop
-    // steal_ptr: u8, 
+    offset: usize,         // first byte not handed out yet
+    steal_ptr: u8,
+    total_size: usize, // 0 when loaded from unversioned metadata
+    strategy: Strategy,
+    sequential: bool,
+    reserved: Option<usize>, // start of a tail handed out up front, new ranges stop there
+}
+
+impl Encode for Coordinator {
+    fn encode<E: bincode::enc::Encoder>(&self, e: &mut E) -> Result<(), EncodeError> {
+        self.range_byte.encode(e)?;
+        self.offset.encode(e)?;
+        self.steal_ptr.encode(e)
+    }
 }
+
+impl<Context> Decode<Context> for Coordinator {
+    fn decode<D: bincode::de::Decoder<Context = Context>>(d: &mut D) -> Result<Self, DecodeError> {
+        Ok(Coordinator::from_parts(
+            Range::<u8>::decode(d)?,
+            usize::decode(d)?,
+            u8::decode(d)?,
+        ))
+    }
+}
+
 impl Coordinator {
-    fn new(max_index: u8) -> Self {
+    fn new(max_index: u8, total_size: usize, strategy: Strategy) -> Self {
         Coordinator {
             range_byte: 0..max_index,
-            // steal_ptr: 0,
+            offset: 0,
+            steal_ptr: 0,
+            total_size,
+            strategy,
+            sequential: false,
+            reserved: None,
         }
     }
+
+    fn from_parts(range_byte: Range<u8>, offset: usize, steal_ptr: u8) -> Self {
+        Coordinator {
+            range_byte,
+            offset,
+            steal_ptr,
+            total_size: 0,
+            strategy: Strategy::Fibonacci,
+            sequential: false,
+            reserved: None,
+        }
+    }
+
+    // where fresh ranges end, the reserved tail is already in the range list
+    fn limit(&self) -> usize {
+        self.reserved.unwrap_or(self.total_size).min(self.total_size)
+    }
+
     // ask from coordinator, return a range
-    fn new_range(&self) -> Range<usize> {
-        if self.range_byte.start < self.range_byte.end {
-        } else if self.range_byte.start == self.range_byte.end {
-            // TODO for the case of index 364..609 but if we need till 512 or something that's less than index value than select total size helps decide
+    fn new_range(&mut self, transfer: &Transfer) -> Option<Range<usize>> {
+        if self.offset >= self.limit() {
+            return None;
+        }
+        let len = match self.strategy {
+            _ if self.sequential => SEQUENTIAL_RANGE,
+            Strategy::Fibonacci => {
+                // past the last index we keep reusing its length
+                let i = self.range_byte.start as usize;
+                if self.range_byte.start < self.range_byte.end {
+                    self.range_byte.start += 1;
+                }
+                RANGE[i.min(RANGE.len() - 1)].len() * UNIT
+            }
+            Strategy::Fixed(mib) => (mib.max(1) as usize) << 20,
+            Strategy::Adaptive => match transfer.link() {
+                Some(link) => link.range(self.total_size),
+                None => ADAPTIVE_START,
+            },
+        };
+        let start = self.offset;
+        // TODO for the case of index 364..609 but if we need till 512 or something that's less than index value than select total size helps decide
+        self.offset = (start + len).min(self.limit());
+        Some(start..self.offset)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.offset >= self.limit()
+    }
+}
+
+/// Counters and flags shared between a running download and whoever drives it
+#[derive(Default)]
+pub struct Transfer {
+    downloaded: AtomicU64,
+    stopped: AtomicBool,
+    error: Mutex<Option<String>>,
+    workers: Mutex<Vec<Arc<WorkerStats>>>,
+    // weak so leftovers still count as unowned in `next_index`
+    segments: Mutex<Vec<Weak<Index>>>,
+    url: Mutex<Option<Url>>,
+    /// size the download started with, 0 when unknown
+    expected: AtomicU64,
+    /// size the server's responses gave when it isn't `expected`, 0 when they agree
+    reported: AtomicU64,
+    /// the server compresses the body on the fly, its length and ranges aren't the file's
+    encoded: AtomicBool,
+    /// the server turned parallel ranges away, one connection goes on alone
+    single_session: AtomicBool,
+    /// bytes/s one connection got before the others started, 0 when it wasn't measured
+    baseline: AtomicU64,
+    /// events not handed to `download_debug` yet, None unless debug mode asked for them
+    trace: Mutex<Option<Vec<WorkerEvent>>>,
+}
+
+impl Transfer {
+    /// `downloaded` is what's already on disk when resuming
+    pub fn new(downloaded: u64) -> Self {
+        Transfer {
+            downloaded: AtomicU64::new(downloaded),
+            ..Default::default()
+        }
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded.load(Ordering::Relaxed)
+    }
+
+    /// Count bytes that were already on disk before this run
+    pub fn add_downloaded(&self, n: u64) {
+        self.downloaded.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Start the count over, for a delta update that gave up before the full fetch
+    pub fn reset_downloaded(&self) {
+        self.downloaded.store(0, Ordering::Relaxed);
+    }
+
+    /// Ask workers to wrap up, ranges they didn't finish stay in the metadata
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// First fatal worker error, if any
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    fn fail(&self, e: String) {
+        self.error.lock().unwrap().get_or_insert(e);
+        self.stop();
+    }
+
+    /// Ranges not fetched yet, in the order they were handed out
+    pub fn segments(&self) -> Vec<Segment> {
+        self.segments
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|i| i.segment())
+            .filter(|s| s.start < s.end)
+            .collect()
+    }
+
+    pub fn workers(&self) -> Vec<WorkerState> {
+        self.workers.lock().unwrap().iter().map(|w| w.state()).collect()
+    }
+
+    /// Where the last response came from, after redirects
+    pub fn url(&self) -> Option<Url> {
+        self.url.lock().unwrap().clone()
+    }
+
+    /// The size the file turned out to have, when it's not the one it started with
+    pub fn resized(&self) -> Option<u64> {
+        Some(self.reported.load(Ordering::Acquire)).filter(|&size| size > 0)
+    }
+
+    pub fn expect_size(&self, size: u64) {
+        self.expected.store(size, Ordering::Release);
+    }
+
+    /// Note the size a response gave for the whole file
+    pub fn report_size(&self, size: u64) {
+        let expected = self.expected.load(Ordering::Acquire);
+        if expected > 0 && size != expected {
+            self.reported.store(size, Ordering::Release);
+        }
+    }
+
+    /// The body came with a Content-Encoding, the file only comes whole through one stream
+    pub fn is_encoded(&self) -> bool {
+        self.encoded.load(Ordering::Acquire)
+    }
+
+    /// Parallel ranges were rejected, the server takes one connection at a time
+    pub fn is_single_session(&self) -> bool {
+        self.single_session.load(Ordering::Acquire)
+    }
+
+    /// What one connection fetched per second at the start of a multi-connection run,
+    /// what its average speed is compared against
+    pub fn baseline(&self) -> Option<u64> {
+        Some(self.baseline.load(Ordering::Relaxed)).filter(|&speed| speed > 0)
+    }
+
+    /// Collect what the workers and coordinator do from now on, see [`WorkerEvent`]
+    pub fn enable_trace(&self) {
+        self.trace.lock().unwrap().get_or_insert_with(Vec::new);
+    }
+
+    /// Note an event when tracing, `event` isn't built otherwise
+    pub fn trace(&self, event: impl FnOnce() -> WorkerEvent) {
+        if self.trace.lock().unwrap().is_none() {
+            return;
+        }
+        // built outside the lock, it may look at the workers
+        let event = event();
+        if let Some(events) = self.trace.lock().unwrap().as_mut() {
+            events.push(event);
+        }
+    }
+
+    /// Events since the last call, empty when not tracing
+    pub fn take_trace(&self) -> Vec<WorkerEvent> {
+        self.trace
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // where `stats` is among the workers, what trace events call it
+    fn worker_number(&self, stats: &WorkerStats) -> usize {
+        let workers = self.workers.lock().unwrap();
+        workers
+            .iter()
+            .position(|w| std::ptr::eq(w.as_ref(), stats))
+            .unwrap_or_default()
+    }
+
+    // workers holding a range right now
+    fn busy(&self) -> usize {
+        let workers = self.workers.lock().unwrap();
+        workers
+            .iter()
+            .filter(|w| w.range.lock().unwrap().strong_count() > 0)
+            .count()
+    }
+
+    // what the connections measured so far, None before any of them has a speed
+    fn link(&self) -> Option<Link> {
+        let workers = self.workers.lock().unwrap();
+        let speeds: Vec<u64> = workers.iter().map(|w| w.speed()).filter(|&s| s > 0).collect();
+        if speeds.is_empty() {
+            return None;
+        }
+        let rtts: Vec<u64> = workers
+            .iter()
+            .map(|w| w.rtt.load(Ordering::Relaxed))
+            .filter(|&r| r > 0)
+            .collect();
+        let requests: u32 = workers.iter().map(|w| w.requests.load(Ordering::Relaxed)).sum();
+        let retries: u32 = workers.iter().map(|w| w.retries.load(Ordering::Relaxed)).sum();
+        Some(Link {
+            speed: speeds.iter().sum::<u64>() / speeds.len() as u64,
+            rtt: Duration::from_micros(rtts.iter().sum::<u64>() / rtts.len().max(1) as u64),
+            retry_rate: retries as f64 / requests.max(1) as f64,
+            connections: workers.len(),
+        })
+    }
+
+    // speed of the worker fetching `index`, 0 when none is or it hasn't measured yet
+    fn speed_on(&self, index: &Arc<Index>) -> u64 {
+        let workers = self.workers.lock().unwrap();
+        workers
+            .iter()
+            .find(|w| w.range.lock().unwrap().upgrade().is_some_and(|i| Arc::ptr_eq(&i, index)))
+            .map_or(0, |w| w.speed())
+    }
+
+    fn set_url(&self, url: &Url) {
+        let mut current = self.url.lock().unwrap();
+        if current.as_ref() != Some(url) {
+            *current = Some(url.clone());
+        }
+    }
+
+    fn publish(&self, range: &VecDeque<Arc<Index>>) {
+        *self.segments.lock().unwrap() = range.iter().map(Arc::downgrade).collect();
+    }
+}
+
+// one connection's share of the link, as the adaptive strategy sizes ranges for it
+struct Link {
+    /// bytes per second
+    speed: u64,
+    rtt: Duration,
+    /// retries per request
+    retry_rate: f64,
+    connections: usize,
+}
+
+impl Link {
+    // long enough to be worth its request, short enough that a retry loses little and
+    // no connection takes more than its part of a `total` byte file
+    fn range(&self, total: usize) -> usize {
+        let rtt = self.rtt.as_secs_f64() * ROUND_TRIPS as f64;
+        let secs = ADAPTIVE_TIME.as_secs_f64().max(rtt);
+        let len = self.speed as f64 * secs / (1.0 + 4.0 * self.retry_rate);
+        let share = total / self.connections.max(1);
+        (len as usize).min(share).clamp(ADAPTIVE_MIN, ADAPTIVE_MAX)
+    }
+}
+
+// the download's url first, then its mirrors, with how fast a connection to each is
+struct Sources {
+    urls: Vec<Url>,
+    state: Mutex<SourceState>,
+}
+
+struct SourceState {
+    /// bytes/s one connection gets, measured up front and then from the workers on it
+    speed: Vec<u64>,
+    /// turned a range away, it gets no workers anymore
+    failed: Vec<bool>,
+    ranked: Instant,
+}
+
+impl Sources {
+    // each measured with a small ranged GET, when there's more than one or `measure` asks
+    // for the url's own speed anyway
+    async fn rank(client: &HttpClient, urls: Vec<Url>, measure: bool) -> Self {
+        let speed = match urls.len() {
+            1 if !measure => vec![1],
+            _ => join_all(urls.iter().map(|url| mirrors::measure(client, url)))
+                .await
+                .into_iter()
+                .map(|speed| speed.unwrap_or(0))
+                .collect(),
+        };
+        let state = SourceState {
+            failed: vec![false; urls.len()],
+            speed,
+            ranked: Instant::now(),
+        };
+        Sources {
+            urls,
+            state: Mutex::new(state),
+        }
+    }
+
+    // workers in proportion to the measured speeds
+    fn spread(&self, workers: &[Arc<WorkerStats>]) {
+        let shares = mirrors::shares(&self.state.lock().unwrap().speed, workers.len());
+        let mut on = shares
+            .iter()
+            .enumerate()
+            .flat_map(|(source, &n)| std::iter::repeat_n(source, n));
+        for worker in workers {
+            worker.source.store(on.next().unwrap_or(0), Ordering::Relaxed);
         }
-        0..0
+    }
+
+    // where `worker` fetches its next range: where it is, unless that has more workers
+    // than its share now, then the source furthest below its share
+    fn url(&self, worker: &WorkerStats, transfer: &Transfer) -> (usize, Url) {
+        let current = worker.source.load(Ordering::Relaxed);
+        if self.urls.len() == 1 {
+            return (current, self.urls[current].clone());
+        }
+        let mut state = self.state.lock().unwrap();
+        let workers = transfer.workers.lock().unwrap();
+        let mut on = vec![0; self.urls.len()];
+        for w in workers.iter() {
+            on[w.source.load(Ordering::Relaxed)] += 1;
+        }
+        // a mirror that slowed down loses workers to the others
+        if state.ranked.elapsed() >= RERANK {
+            state.ranked = Instant::now();
+            for source in 0..self.urls.len() {
+                let speeds: Vec<u64> = workers
+                    .iter()
+                    .filter(|w| w.source.load(Ordering::Relaxed) == source)
+                    .map(|w| w.speed())
+                    .filter(|&s| s > 0)
+                    .collect();
+                if !state.failed[source] && !speeds.is_empty() {
+                    state.speed[source] = speeds.iter().sum::<u64>() / speeds.len() as u64;
+                }
+            }
+        }
+        let shares = mirrors::shares(&state.speed, workers.len());
+        if on[current] > shares[current] {
+            let short = (0..self.urls.len())
+                .filter(|&s| on[s] < shares[s])
+                .max_by_key(|&s| shares[s] - on[s]);
+            if let Some(to) = short {
+                worker.source.store(to, Ordering::Relaxed);
+                return (to, self.urls[to].clone());
+            }
+        }
+        (current, self.urls[current].clone())
+    }
+
+    // a mirror that failed a range is dropped, false for the download's own url
+    fn fail(&self, source: usize) -> bool {
+        if source == 0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failed[source] = true;
+        state.speed[source] = 0;
+        true
     }
 }
 
+enum WorkerError {
+    Retry(Option<Duration>, String),
+    /// 429/503, the whole host should back off
+    Throttled(Option<Duration>, String),
+    Fatal(String),
+}
+
 pub struct Download {
     // id: Uuid,
     coordinator: Coordinator,
     range: VecDeque<Arc<Index>>,
+    preallocate: bool,      // not serialized, comes from the download's options
+    cipher: Option<Cipher>, // same, the key lives in the download's sidecar
+    mirrors: Vec<Url>,      // same, other urls for the file
 }
 
 impl Encode for Download {
     fn encode<E: bincode::enc::Encoder>(&self, e: &mut E) -> Result<(), EncodeError> {
+        META_MAGIC.encode(e)?;
+        META_VERSION.encode(e)?;
+        (self.coordinator.total_size as u64).encode(e)?;
+        self.coordinator.strategy.encode(e)?;
+        self.coordinator.sequential.encode(e)?;
         self.coordinator.encode(e)?;
         self.range.len().encode(e)?;
         for i in &self.range {
             i.encode(e)?
         }
-        Ok(())
+        // appended last so metadata saved before priority mode still decodes
+        self.coordinator.reserved.encode(e)
     }
 }
 
 impl<Context> Decode<Context> for Download {
     fn decode<D: bincode::de::Decoder<Context = Context>>(d: &mut D) -> Result<Self, DecodeError> {
-        let coordinator = Coordinator::decode(d)?;
+        let first = u8::decode(d)?;
+        let mut coordinator = if first == META_MAGIC[0] {
+            if <[u8; 2]>::decode(d)? != META_MAGIC[1..] {
+                return Err(DecodeError::Other("not a tur metadata file"));
+            }
+            let version = u8::decode(d)?;
+            if version > META_VERSION {
+                return Err(DecodeError::OtherString(format!(
+                    "metadata version {} is newer than this build reads",
+                    version
+                )));
+            }
+            let total_size = u64::decode(d)? as usize;
+            let strategy = Strategy::decode(d)?;
+            let sequential = bool::decode(d)?;
+            Coordinator {
+                total_size,
+                strategy,
+                sequential,
+                ..Coordinator::decode(d)?
+            }
+        } else {
+            // unversioned, `first` starts the range byte
+            let range_byte = first..u8::decode(d)?;
+            Coordinator::from_parts(range_byte, usize::decode(d)?, u8::decode(d)?)
+        };
         let len = usize::decode(d)?;
         let mut range = VecDeque::with_capacity(len);
         for _ in 0..len {
             range.push_back(Arc::new(Index::decode(d)?));
         }
+        coordinator.reserved = match Option::<usize>::decode(d) {
+            Ok(reserved) => reserved,
+            Err(DecodeError::UnexpectedEnd { .. }) => None,
+            Err(DecodeError::Io { inner, .. })
+                if inner.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                None
+            }
+            Err(e) => return Err(e),
+        };
         Ok(Download {
             // id: Uuid::nil(),
             coordinator,
             range,
+            preallocate: true,
+            cipher: None,
+            mirrors: Vec::new(),
         })
     }
 }
 
 impl Download {
-    pub fn new(id: Uuid, size: usize, num_conn: u8) -> Self {
+    pub fn new(size: usize, num_conn: u8, strategy: Strategy) -> Self {
         Download {
             range: VecDeque::with_capacity((PHI * num_conn as f32).round() as usize),
-            coordinator: Coordinator::new(Self::get_index(size >> 23).unwrap()),
+            coordinator: Coordinator::new(
+                Self::get_index(size >> 23).unwrap(),
+                size,
+                strategy,
+            ),
+            preallocate: true,
+            cipher: None,
+            mirrors: Vec::new(),
         }
     }
+
+    /// Hand out ranges strictly in order and never split them, so the file fills front
+    /// to back and media plays while it's still downloading
+    pub fn with_sequential(mut self, sequential: bool) -> Self {
+        self.coordinator.sequential = sequential;
+        self
+    }
+
+    /// Queue the first and last few MiB ahead of everything else, so archive indexes
+    /// and media headers are there early. Only a fresh download can be reordered.
+    pub fn with_priority_pieces(mut self, enabled: bool) -> Self {
+        let total = self.coordinator.total_size;
+        let fresh = self.coordinator.offset == 0 && self.range.is_empty();
+        if enabled && fresh && total >= 4 * PRIORITY_PIECE {
+            let tail = total - PRIORITY_PIECE;
+            // unheld ranges go out before fresh ones
+            self.range.push_back(Arc::new(Index::new(0..PRIORITY_PIECE)));
+            self.range.push_back(Arc::new(Index::new(tail..total)));
+            self.coordinator.offset = PRIORITY_PIECE;
+            self.coordinator.reserved = Some(tail);
+        }
+        self
+    }
+
+    /// Off leaves the file growing with the writes instead of sized up front,
+    /// players that trust the file length then don't run into a zeroed tail
+    pub fn with_preallocation(mut self, preallocate: bool) -> Self {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Encrypt every write, see `crypt`
+    pub fn with_cipher(mut self, cipher: Option<Cipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Other urls with the same file. Connections are split between them and the url
+    /// `run` is given by how fast each answers, and again as that changes, see `mirrors`
+    pub fn with_mirrors(mut self, mirrors: Vec<Url>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
     // pass value as (value/2^20/8) or simply (value >> 23)
     pub fn get_index(v: usize) -> Option<u8> {
         let mut lo = if v <= RANGE[13].start { 0 } else { 13 };
@@ -178,10 +928,11 @@ impl Download {
 
     /// frontend req. from History to start instance
     /// Load self from the given UUID, used when started from History
-    /// let mut a = A::load(&handle, uuid).unwrap();
+    /// let mut a = A::load(&handle, uuid, size).unwrap();
     pub fn load<R: tauri::Runtime>(
         handle: &tauri::AppHandle<R>,
         id: Uuid,
+        total_size: usize,
     ) -> Result<Self, bincode::error::DecodeError> {
         let mut file = std::fs::File::open(Self::meta_path(handle, &id)).map_err(|e| {
             bincode::error::DecodeError::Io {
@@ -189,8 +940,28 @@ impl Download {
                 additional: 0,
             }
         })?;
-        let instance: Download = bincode::decode_from_std_read(&mut file, config::standard())?;
         // instance.id = id;
+        Self::restore(&mut file, total_size)
+    }
+
+    /// Decode saved state for a file of `total_size`, which wins over the size it was saved
+    /// with
+    pub fn restore<Rd: std::io::Read>(
+        reader: &mut Rd,
+        total_size: usize,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        let mut instance: Download = bincode::decode_from_std_read(reader, config::standard())?;
+        if instance.coordinator.total_size == 0 {
+            // unversioned, whatever size it was saved for, nothing past this one goes out
+            for index in &instance.range {
+                index.end.fetch_min(total_size, Ordering::AcqRel);
+            }
+            instance.coordinator.offset = instance.coordinator.offset.min(total_size);
+            instance.coordinator.total_size = total_size;
+        } else {
+            instance.resize(total_size);
+        }
+        instance.range.retain(|i| !i.is_done());
         Ok(instance)
     }
 
@@ -199,10 +970,9 @@ impl Download {
         handle: &tauri::AppHandle<R>,
         uuid: &Uuid,
     ) -> std::path::PathBuf {
-        let mut p = handle
-            .path()
-            .resolve("metadata", BaseDirectory::AppData)
-            .expect("cannot resolve AppData/metadata");
+        let mut p = crate::profiles::data_dir(handle)
+            .expect("cannot resolve AppData/metadata")
+            .join("metadata");
         std::fs::create_dir_all(&p).ok();
         p.push(format!("{}.tur", uuid.as_simple()));
         p
@@ -216,44 +986,848 @@ impl Download {
     ) -> Result<(), bincode::error::EncodeError> {
         let mut file = std::fs::File::create(Self::meta_path(handle, id))
             .map_err(|e| bincode::error::EncodeError::Io { inner: e, index: 0 })?;
-        bincode::encode_into_std_write(self, &mut file, config::standard()).map(|_| ())
+        self.store(&mut file)
     }
 
-    pub async fn work(){
-        let client = reqwest::Client::new();
-        let res = client.get("https://example.com").send();
-        let resp = res.await.unwrap();
+    /// Encode state for `restore`
+    pub fn store<W: std::io::Write>(&self, writer: &mut W) -> Result<(), EncodeError> {
+        bincode::encode_into_std_write(self, writer, config::standard()).map(|_| ())
     }
-    
-    fn run_instance<R: tauri::Runtime>(handle: &tauri::AppHandle<R>, config: AppSettings) -> Vec<JoinHandle<()>> {
-        // TODO: Read from tauri store instead of hardcoding
-        // the solution: InstanceConfig is available
 
-        // Create coordination channel
-        // TODO do we need onshot or could we emit/listen to same ID
-        let (tx, _rx) = mpsc::channel::<oneshot::Sender<Arc<Index>>>(config.download.socket_buffer_size as usize); // Increased buffer for better throughput
+    /// Drop saved state, used once finished or cancelled
+    pub fn remove_meta<R: tauri::Runtime>(handle: &tauri::AppHandle<R>, id: &Uuid) {
+        let _ = std::fs::remove_file(Self::meta_path(handle, id));
+    }
+
+    /// Every byte has been handed out and written
+    pub fn is_complete(&self) -> bool {
+        self.coordinator.is_exhausted() && self.range.iter().all(|i| i.is_done())
+    }
+
+    /// Bytes still missing, handed out or not
+    pub fn remaining(&self) -> usize {
+        let pending: usize = self.range.iter().map(|i| i.remaining()).sum();
+        pending + self.coordinator.limit().saturating_sub(self.coordinator.offset)
+    }
+
+    /// Bytes written in one piece from the start of the file, where the first gap begins
+    pub fn contiguous(&self) -> usize {
+        self.pending()
+            .first()
+            .map_or(self.coordinator.total_size, |r| r.start)
+    }
+
+    // ranges still missing, handed out or not, in file order
+    fn pending(&self) -> Vec<Range<usize>> {
+        let mut pending: Vec<Range<usize>> = self
+            .range
+            .iter()
+            .filter(|i| !i.is_done())
+            .map(|i| i.start.load(Ordering::Acquire)..i.end.load(Ordering::Acquire))
+            .collect();
+        if !self.coordinator.is_exhausted() {
+            pending.push(self.coordinator.offset..self.coordinator.limit());
+        }
+        pending.sort_by_key(|r| r.start);
+        pending
+    }
 
-        let mut handles = Vec::new();
+    /// Fit saved state to a `file_len` byte file before resuming into it: what the state
+    /// counts as written past the end of the file is queued again, the bytes that adds
+    /// come back. Err when the state contradicts itself and can't be trusted at all.
+    pub fn reconcile(&mut self, file_len: u64) -> Result<Option<usize>, String> {
+        let total = self.coordinator.total_size;
+        let pending = self.pending();
+        if let Some(pair) = pending.windows(2).find(|pair| pair[0].end > pair[1].start) {
+            return Err(format!("ranges {:?} and {:?} overlap", pair[0], pair[1]));
+        }
+        if let Some(range) = pending.iter().find(|r| r.end > total) {
+            return Err(format!("range {:?} ends past {} bytes", range, total));
+        }
 
-        // Spawn coordinator task
-        // TODO creating steal_ptr in thread start and the keep using that with modulo
-        let coordinator_handle = handle.clone();
-        handles.push(tokio::spawn(async move {
-            // TODO: Implement coordinator logic with rx and coordinator_handle
-        }));
+        let cut = (file_len as usize).min(total);
+        let missing: usize = pending.iter().map(|r| r.end - r.start.max(cut).min(r.end)).sum();
+        if missing == total - cut {
+            return Ok(None);
+        }
+        let before = self.remaining();
+        self.resize(cut);
+        self.resize(total);
+        self.range.retain(|i| !i.is_done());
+        Ok(Some(self.remaining() - before))
+    }
 
+    /// Fetch into `path` with `num_conn` workers until done or stopped through `transfer`.
+    /// Hands back the state so it can be saved when the download didn't finish.
+    pub async fn run(
+        self,
+        client: impl Into<HttpClient>,
+        url: Url,
+        path: &Path,
+        num_conn: u8,
+        transfer: Arc<Transfer>,
+    ) -> std::io::Result<Download> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(winpath::long_path(path))?;
+        // preallocate so workers can write anywhere, dropping leftovers of a larger old file
+        let len = file.metadata()?.len();
+        let total = self.coordinator.total_size as u64;
+        if len > total || (self.preallocate && len != total) {
+            file.set_len(total)?;
+        }
+        let file = Arc::new(file);
+        let client = client.into();
+        transfer.expect_size(total);
+
+        // Create coordination channel, workers send a oneshot and get a range back
+        // TODO do we need onshot or could we emit/listen to same ID
+        let num_conn = num_conn.max(1);
+        let (tx, rx) = mpsc::channel::<oneshot::Sender<Arc<Index>>>(num_conn as usize);
+
+        let stats: Vec<_> = (0..num_conn).map(|_| Arc::new(WorkerStats::default())).collect();
+        *transfer.workers.lock().unwrap() = stats.clone();
+        transfer.publish(&self.range);
+        let urls = std::iter::once(url).chain(self.mirrors.clone()).collect();
+        // one connection's speed first, what the download's average is compared against
+        let sources = Arc::new(Sources::rank(&client, urls, num_conn > 1).await);
+        if num_conn > 1 {
+            let baseline = sources.state.lock().unwrap().speed[0];
+            transfer.baseline.store(baseline, Ordering::Relaxed);
+        }
+        sources.spread(&stats);
         // Spawn worker tasks
-        for i in 0..config.download.num_threads {
-            let worker_tx = tx.clone();
-            let worker_handle = handle.clone();
-            handles.push(tokio::spawn(async move {
-                // TODO: Implement worker logic with worker_tx and worker_handle
-                // Worker creates oneshot, keeps rx, sends tx via mpsc when needed
-                // emit progress as well
-            }));
+        let mut workers = JoinSet::new();
+        for (n, stats) in stats.into_iter().enumerate() {
+            let client = client.for_worker(n);
+            let (sources, file, tx) = (sources.clone(), file.clone(), tx.clone());
+            let (transfer, cipher) = (transfer.clone(), self.cipher.clone());
+            workers.spawn(async move {
+                // every connection does its handshakes at once instead of in the way of
+                // its first range
+                if num_conn > 1 {
+                    let source = stats.source.load(Ordering::Relaxed);
+                    warm_up(&client, &sources.urls[source], &stats).await;
+                }
+                worker(client, sources, file, tx, transfer, stats, cipher).await
+            });
+        }
+        drop(tx);
+
+        // coordinator returns once every worker dropped its sender
+        let download = self.coordinate(rx, &transfer).await;
+        while workers.join_next().await.is_some() {}
+
+        // the server's file was another size, cut off or extend what was preallocated
+        let resized = download.coordinator.total_size as u64;
+        if resized != total {
+            file.set_len(resized)?;
+        }
+        file.sync_all()?;
+        Ok(download)
+    }
+
+    async fn coordinate(
+        mut self,
+        mut rx: mpsc::Receiver<oneshot::Sender<Arc<Index>>>,
+        transfer: &Transfer,
+    ) -> Download {
+        while let Some(reply) = rx.recv().await {
+            if transfer.is_stopped() || transfer.is_encoded() {
+                continue; // dropping reply tells the worker to quit
+            }
+            // one range out at a time, the other workers quit
+            let held = self.range.iter().any(|i| Arc::strong_count(i) > 1);
+            if transfer.is_single_session() && held {
+                continue;
+            }
+            if let Some(size) = transfer.resized() {
+                self.resize(size as usize);
+            }
+            if let Some(index) = self.next_index(transfer) {
+                transfer.publish(&self.range);
+                let _ = reply.send(index);
+            }
+        }
+        self.range.retain(|i| !i.is_done());
+        transfer.publish(&self.range);
+        self
+    }
+
+    // the server has `size` bytes, not what HEAD said: ranges past it go, a larger file
+    // gets the part beyond the old end handed out too
+    fn resize(&mut self, size: usize) {
+        let total = self.coordinator.total_size;
+        if size == total {
+            return;
+        }
+        if size < total {
+            for index in &self.range {
+                index.end.fetch_min(size, Ordering::AcqRel);
+            }
+            self.coordinator.offset = self.coordinator.offset.min(size);
+            // or growing again later would hand out what gets queued past the new end
+            if let Some(reserved) = &mut self.coordinator.reserved {
+                *reserved = (*reserved).min(size);
+            }
+        } else if self.coordinator.reserved.is_some() {
+            // fresh ranges stop at the reserved tail, the new part has to be queued
+            self.range.push_back(Arc::new(Index::new(total..size)));
+        }
+        self.coordinator.total_size = size;
+    }
+
+    fn next_index(&mut self, transfer: &Transfer) -> Option<Arc<Index>> {
+        self.range.retain(|i| !i.is_done());
+
+        // leftovers from a previous session aren't held by any worker
+        let mut free = self.range.iter().filter(|i| Arc::strong_count(i) == 1);
+        let leftover = if self.coordinator.sequential {
+            free.min_by_key(|i| i.start.load(Ordering::Acquire))
+        } else {
+            free.next()
+        };
+        if let Some(i) = leftover {
+            return Some(i.clone());
+        }
+
+        if let Some(r) = self.coordinator.new_range(transfer) {
+            let index = Arc::new(Index::new(r));
+            self.range.push_back(index.clone());
+            return Some(index);
+        }
+
+        // a stolen upper half would be written ahead of the part still missing below it
+        if self.coordinator.sequential {
+            return None;
+        }
+        self.steal_range(transfer)
+    }
+
+    // split the busiest range in half, the upper part goes to the asking worker
+    fn steal_range(&mut self, transfer: &Transfer) -> Option<Arc<Index>> {
+        let len = self.range.len();
+        if len == 0 {
+            return None;
         }
+        let from = self.coordinator.steal_ptr as usize % len;
+        let victim = (0..len)
+            .map(|n| (from + n) % len)
+            .max_by_key(|&n| self.range[n].remaining())?;
+        self.coordinator.steal_ptr = ((victim + 1) % len) as u8;
 
-        handles
+        let v = &self.range[victim];
+        let start = v.start.load(Ordering::Acquire);
+        let end = v.end.load(Ordering::Acquire);
+        if end.saturating_sub(start) < MIN_STEAL {
+            return None;
+        }
+        // the asking worker quits and the rest finish the tail on their own
+        let speed = transfer.speed_on(v);
+        if speed > 0 && ((end - start) as f64 / speed as f64) < TAIL.as_secs_f64() {
+            return None;
+        }
+        let mid = start + (end - start) / 2;
+        v.end.store(mid, Ordering::Release);
+
+        let index = Arc::new(Index::new(mid..end));
+        transfer.trace(|| WorkerEvent::Stolen {
+            kept: Segment {
+                start: start as u64,
+                end: mid as u64,
+            },
+            range: index.segment(),
+        });
+        self.range.push_back(index.clone());
+        Some(index)
     }
     // db conn is on DM, it save the necessary info, DState goes to file-dl.tur
 }
+
+// a HEAD that leaves an open, handshaken connection in the pool for the first range; a
+// failure here shows again on the range request, which retries it
+async fn warm_up(client: &HttpClient, url: &Url, stats: &WorkerStats) {
+    let _permit = client.permit().await;
+    let started = Instant::now();
+    if client.send(client.inner().head(url.clone())).await.is_ok() {
+        stats.setup.store(started.elapsed().as_micros().max(1) as u64, Ordering::Relaxed);
+    }
+}
+
+async fn worker(
+    client: HttpClient,
+    sources: Arc<Sources>,
+    file: Arc<File>,
+    tx: mpsc::Sender<oneshot::Sender<Arc<Index>>>,
+    transfer: Arc<Transfer>,
+    stats: Arc<WorkerStats>,
+    cipher: Option<Cipher>,
+) {
+    loop {
+        if transfer.is_stopped() {
+            return;
+        }
+        // Worker creates oneshot, keeps rx, sends tx via mpsc when needed
+        // emit progress as well
+        let (reply, range) = oneshot::channel();
+        if tx.send(reply).await.is_err() {
+            return;
+        }
+        // coordinator dropping the reply means there's nothing left
+        let Ok(index) = range.await else {
+            *stats.range.lock().unwrap() = Weak::new();
+            return;
+        };
+        *stats.range.lock().unwrap() = Arc::downgrade(&index);
+        // a mirror that fails leaves the range to another source
+        let result = loop {
+            let (source, url) = sources.url(&stats, &transfer);
+            transfer.trace(|| WorkerEvent::Assigned {
+                worker: transfer.worker_number(&stats),
+                range: index.segment(),
+                url: url.to_string(),
+            });
+            match fetch_range(&client, &url, &file, &index, &transfer, &stats, cipher.as_ref()).await {
+                Err(e) if sources.fail(source) => eprintln!("Dropping mirror {}: {}", url, e),
+                result => break result,
+            }
+        };
+        if let Err(e) = result {
+            transfer.fail(e);
+            return;
+        }
+    }
+}
+
+async fn fetch_range(
+    client: &HttpClient,
+    url: &Url,
+    file: &Arc<File>,
+    index: &Index,
+    transfer: &Transfer,
+    stats: &WorkerStats,
+    cipher: Option<&Cipher>,
+) -> Result<(), String> {
+    let policy = client.retry();
+    let mut attempt = 0;
+    loop {
+        let permit = client.permit().await;
+        let result = stream_range(client, url, file, index, transfer, stats, cipher).await;
+        drop(permit);
+
+        let (after, e) = match result {
+            Ok(()) => return Ok(()),
+            Err(WorkerError::Fatal(e)) => return Err(e),
+            // the gate holds everyone back, next permit() does the waiting
+            Err(WorkerError::Throttled(after, e)) if client.throttle(after) => {
+                (Some(Duration::ZERO), e)
+            }
+            Err(WorkerError::Retry(after, e)) | Err(WorkerError::Throttled(after, e)) => (after, e),
+        };
+        if attempt >= policy.retries {
+            return Err(e);
+        }
+        attempt += 1;
+        stats.retries.fetch_add(1, Ordering::Relaxed);
+        let wait = after.unwrap_or(policy.backoff(attempt));
+        transfer.trace(|| WorkerEvent::Retry {
+            worker: transfer.worker_number(stats),
+            attempt,
+            retries: policy.retries,
+            wait_ms: wait.as_millis() as u64,
+            error: e.clone(),
+        });
+        tokio::time::sleep(wait).await;
+        if transfer.is_stopped() {
+            return Ok(());
+        }
+        eprintln!("Retrying range ({}/{}): {}", attempt, policy.retries, e);
+    }
+}
+
+async fn stream_range(
+    client: &HttpClient,
+    url: &Url,
+    file: &Arc<File>,
+    index: &Index,
+    transfer: &Transfer,
+    stats: &WorkerStats,
+    cipher: Option<&Cipher>,
+) -> Result<(), WorkerError> {
+    let start = index.start.load(Ordering::Acquire);
+    let end = index.end.load(Ordering::Acquire);
+    if start >= end {
+        return Ok(());
+    }
+
+    let request = client
+        .inner()
+        .get(url.clone())
+        .header(header::RANGE, format!("bytes={}-{}", start, end - 1));
+    let sent = Instant::now();
+    let mut response = client
+        .send(request)
+        .await
+        .map_err(|e| WorkerError::Retry(None, e))?;
+    stats.answered(sent.elapsed());
+    transfer.set_url(response.url());
+
+    // compressed on the fly, nothing of it fits the ranges; the manager starts over
+    let compressed = encoding::of(response.headers(), response.url()).is_some();
+    if compressed && response.status().is_success() {
+        transfer.encoded.store(true, Ordering::Release);
+        return Ok(());
+    }
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {}
+        // server ignored the range, fine as long as we wanted the beginning
+        StatusCode::OK if start == 0 => {}
+        // the file ends before this range, the coordinator drops what's past it
+        StatusCode::RANGE_NOT_SATISFIABLE => match full_size(&response) {
+            Some(size) => {
+                transfer.report_size(size);
+                index.end.fetch_min(size as usize, Ordering::AcqRel);
+                return Ok(());
+            }
+            None => return Err(WorkerError::Fatal("Range not satisfiable".into())),
+        },
+        // a session token good for one connection at a time, the range waits for the
+        // worker that's still let in
+        StatusCode::FORBIDDEN | StatusCode::CONFLICT if transfer.busy() > 1 => {
+            transfer.single_session.store(true, Ordering::Release);
+            return Ok(());
+        }
+        // the last connection's session may not have ended yet on the server's side
+        s @ (StatusCode::FORBIDDEN | StatusCode::CONFLICT) if transfer.is_single_session() => {
+            return Err(WorkerError::Retry(None, s.to_string()))
+        }
+        s @ (StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) => {
+            return Err(WorkerError::Throttled(retry_after(response.headers()), s.to_string()))
+        }
+        s if s.is_server_error() => return Err(WorkerError::Retry(None, s.to_string())),
+        s => return Err(WorkerError::Fatal(format!("Unexpected status {}", s))),
+    }
+    // a HEAD that got the size wrong shows here, the range then ends where the file does
+    if let Some(size) = full_size(&response) {
+        transfer.report_size(size);
+        index.end.fetch_min(size as usize, Ordering::AcqRel);
+    }
+
+    while let Some(chunk) = client
+        .chunk(&mut response)
+        .await
+        .map_err(|e| WorkerError::Retry(None, e))?
+    {
+        if transfer.is_stopped() {
+            return Ok(());
+        }
+        // end can shrink under us when the coordinator steals
+        let offset = index.start.load(Ordering::Acquire);
+        let end = index.end.load(Ordering::Acquire);
+        if offset >= end {
+            return Ok(());
+        }
+        let n = chunk.len().min(end - offset);
+        let data = match cipher {
+            Some(cipher) => {
+                let mut data = chunk[..n].to_vec();
+                cipher.apply(&mut data, offset as u64);
+                Bytes::from(data)
+            }
+            None => chunk.slice(..n),
+        };
+        io::write_at(file, data, offset as u64)
+            .await
+            .map_err(|e| WorkerError::Fatal(e.to_string()))?;
+        index.start.store(offset + n, Ordering::Release);
+
+        // don't count bytes a thief is going to fetch again
+        let counted = n.min(index.end.load(Ordering::Acquire).saturating_sub(offset));
+        transfer.downloaded.fetch_add(counted as u64, Ordering::Relaxed);
+        stats.record(counted as u64);
+        if offset + n >= end {
+            return Ok(());
+        }
+    }
+
+    if index.is_done() {
+        Ok(())
+    } else {
+        Err(WorkerError::Retry(None, "Connection closed before range end".into()))
+    }
+}
+
+// size of the whole file: the total of Content-Range, or the length of a full response
+fn full_size(response: &reqwest::Response) -> Option<u64> {
+    if response.status() == StatusCode::OK {
+        return response.content_length();
+    }
+    let range = response.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    range.rsplit_once('/')?.1.trim().parse().ok()
+}
+
+fn retry_after(headers: &header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Single connection fallback for servers without ranges or a known size
+pub async fn stream_single(
+    client: &HttpClient,
+    url: &Url,
+    path: &Path,
+    transfer: &Transfer,
+    cipher: Option<&Cipher>,
+) -> Result<(), String> {
+    let response = client
+        .send(client.inner().get(url.clone()))
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    transfer.set_url(response.url());
+    let mut file = File::create(winpath::long_path(path)).map_err(|e| e.to_string())?;
+    match encoding::of(response.headers(), response.url()) {
+        None => stream_to(client, response, &mut file, transfer, cipher).await?,
+        Some(encoding) => {
+            transfer.encoded.store(true, Ordering::Release);
+            let mut decoder = Decoder::new(&encoding, &mut file, cipher.cloned())?;
+            stream_to(client, response, &mut decoder, transfer, None).await?;
+            if !transfer.is_stopped() {
+                // the count was of compressed bytes, the file is what's on disk
+                let written = decoder.finish().map_err(|e| e.to_string())?;
+                transfer.reset_downloaded();
+                transfer.add_downloaded(written);
+            }
+        }
+    }
+    file.sync_all().map_err(|e| e.to_string())
+}
+
+/// Body of `response` front to back into `out`, which doesn't have to be seekable
+/// (stdout for `tur -O -`)
+pub async fn stream_to(
+    client: &HttpClient,
+    mut response: reqwest::Response,
+    out: &mut impl std::io::Write,
+    transfer: &Transfer,
+    cipher: Option<&Cipher>,
+) -> Result<(), String> {
+    let mut offset = 0;
+    while let Some(chunk) = client.chunk(&mut response).await? {
+        if transfer.is_stopped() {
+            break;
+        }
+        if let Some(cipher) = cipher {
+            let mut data = chunk.to_vec();
+            cipher.apply(&mut data, offset);
+            out.write_all(&data).map_err(|e| e.to_string())?;
+        } else {
+            out.write_all(&chunk).map_err(|e| e.to_string())?;
+        }
+        offset += chunk.len() as u64;
+        transfer
+            .downloaded
+            .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+    out.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const CASES: u64 = 400;
+
+    // xorshift, a failing case comes back from the seed in its message
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n.max(1)
+        }
+
+        fn chance(&mut self, percent: u64) -> bool {
+            self.below(100) < percent
+        }
+    }
+
+    // workers fetching from the coordinator without a network, pausing and resuming
+    // through the saved state at random
+    struct Sim {
+        download: Download,
+        total: usize,
+        sequential: bool,
+        held: Vec<Option<Arc<Index>>>,
+        quit: Vec<bool>,
+        transfer: Transfer,
+        /// start -> end of every write, no byte may be written twice
+        written: BTreeMap<usize, usize>,
+        seed: u64,
+    }
+
+    impl Sim {
+        fn new(rng: &mut Rng, seed: u64, total: usize) -> Self {
+            let threads = 1 + rng.below(16) as u8;
+            let strategy = match rng.below(3) {
+                0 => Strategy::Fibonacci,
+                1 => Strategy::Fixed(1 + rng.below(8) as u32),
+                _ => Strategy::Adaptive,
+            };
+            let sequential = rng.chance(20);
+            let download = Download::new(total, threads, strategy)
+                .with_sequential(sequential)
+                .with_priority_pieces(rng.chance(30));
+            Sim {
+                download,
+                total,
+                sequential,
+                held: vec![None; threads as usize],
+                quit: vec![false; threads as usize],
+                transfer: Transfer::default(),
+                written: BTreeMap::new(),
+                seed,
+            }
+        }
+
+        // false once every worker quit
+        fn step(&mut self, rng: &mut Rng) -> bool {
+            let open: Vec<usize> = (0..self.held.len()).filter(|&w| !self.quit[w]).collect();
+            if open.is_empty() {
+                return false;
+            }
+            let w = open[rng.below(open.len() as u64) as usize];
+            match self.held[w].clone() {
+                Some(index) if !index.is_done() => {
+                    let start = index.start.load(Ordering::Acquire);
+                    let end = index.end.load(Ordering::Acquire);
+                    let n = (1 + rng.below(3 << 20) as usize).min(end - start);
+                    self.write(start..start + n);
+                    index.start.store(start + n, Ordering::Release);
+                }
+                _ => {
+                    self.held[w] = self.download.next_index(&self.transfer);
+                    self.quit[w] = self.held[w].is_none();
+                }
+            }
+            true
+        }
+
+        fn write(&mut self, range: Range<usize>) {
+            let seed = self.seed;
+            assert!(
+                range.end <= self.total,
+                "seed {}: {:?} past the end",
+                seed,
+                range
+            );
+            if let Some((_, &end)) = self.written.range(..range.end).next_back() {
+                assert!(
+                    end <= range.start,
+                    "seed {}: {:?} written twice",
+                    seed,
+                    range
+                );
+            }
+            self.written.insert(range.start, range.end);
+        }
+
+        // workers stop, the state goes through bincode and comes back for `total` bytes
+        fn pause(&mut self, total: usize) {
+            self.held.iter_mut().for_each(|h| *h = None);
+            self.quit.iter_mut().for_each(|q| *q = false);
+            self.download.range.retain(|i| !i.is_done());
+            let mut saved = Vec::new();
+            self.download.store(&mut saved).unwrap();
+            self.download = Download::restore(&mut saved.as_slice(), total)
+                .unwrap()
+                .with_sequential(self.sequential)
+                .with_priority_pieces(true);
+            if total < self.total {
+                self.written = self
+                    .written
+                    .iter()
+                    .filter(|(&start, _)| start < total)
+                    .map(|(&start, &end)| (start, end.min(total)))
+                    .collect();
+            }
+            self.total = total;
+        }
+
+        fn assert_covered(&self) {
+            let mut at = 0;
+            for (&start, &end) in &self.written {
+                assert_eq!(
+                    start, at,
+                    "seed {}: {}..{} never written",
+                    self.seed, at, start
+                );
+                at = end;
+            }
+            assert_eq!(at, self.total, "seed {}: tail never written", self.seed);
+            assert!(
+                self.download.is_complete(),
+                "seed {}: not complete",
+                self.seed
+            );
+            assert_eq!(self.download.remaining(), 0, "seed {}", self.seed);
+        }
+    }
+
+    fn size(rng: &mut Rng) -> usize {
+        match rng.below(4) {
+            0 => rng.below(64) as usize,
+            1 => rng.below(MIN_STEAL as u64 * 4) as usize,
+            _ => rng.below(96 << 20) as usize,
+        }
+    }
+
+    #[test]
+    fn ranges_never_overlap_and_cover_the_file() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let total = size(&mut rng);
+            let mut sim = Sim::new(&mut rng, seed, total);
+            while sim.step(&mut rng) {
+                if rng.chance(1) {
+                    sim.pause(total);
+                }
+            }
+            sim.assert_covered();
+        }
+    }
+
+    #[test]
+    fn restore_fits_a_resized_file() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let total = size(&mut rng);
+            let mut sim = Sim::new(&mut rng, seed, total);
+            for _ in 0..rng.below(200) {
+                sim.step(&mut rng);
+            }
+            sim.pause(rng.below(2 * total as u64 + 1) as usize);
+            while sim.step(&mut rng) {}
+            sim.assert_covered();
+        }
+    }
+
+    #[test]
+    fn fresh_ranges_stay_within_the_size() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let total = size(&mut rng);
+            let mut coordinator = Download::new(total, 1, Strategy::Fibonacci).coordinator;
+            let mut next = 0;
+            while let Some(range) = coordinator.new_range(&Transfer::default()) {
+                assert_eq!(range.start, next, "seed {}", seed);
+                assert!(
+                    range.start < range.end && range.end <= total,
+                    "seed {}",
+                    seed
+                );
+                next = range.end;
+            }
+            assert_eq!(next, total, "seed {}", seed);
+            assert!(coordinator.is_exhausted());
+        }
+    }
+
+    #[test]
+    fn reconcile_queues_what_the_file_lost() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let total = size(&mut rng);
+            let mut sim = Sim::new(&mut rng, seed, total);
+            for _ in 0..rng.below(200) {
+                sim.step(&mut rng);
+            }
+            sim.pause(total);
+            // the file got cut short while the download was paused
+            let len = rng.below(total as u64 + 1) as usize;
+            let before = sim.download.remaining();
+            let requeued = sim.download.reconcile(len as u64).unwrap();
+            let lost = sim.written.values().any(|&end| end > len);
+            sim.written = sim
+                .written
+                .iter()
+                .filter(|(&start, _)| start < len)
+                .map(|(&start, &end)| (start, end.min(len)))
+                .collect();
+            assert_eq!(requeued.unwrap_or(0), sim.download.remaining() - before);
+            assert_eq!(requeued.is_some(), lost, "seed {}", seed);
+            while sim.step(&mut rng) {}
+            sim.assert_covered();
+        }
+    }
+
+    #[test]
+    fn reconcile_refuses_overlapping_ranges() {
+        let mut download = Download::new(16 << 20, 2, Strategy::Fixed(4));
+        download.range.push_back(Arc::new(Index::new(0..(4 << 20))));
+        download.range.push_back(Arc::new(Index::new((2 << 20)..(6 << 20))));
+        download.coordinator.offset = 6 << 20;
+        assert!(download.reconcile(16 << 20).is_err());
+    }
+
+    #[test]
+    fn metadata_keeps_size_and_strategy() {
+        let mut download = Download::new(40 << 20, 4, Strategy::Fixed(3)).with_sequential(true);
+        let transfer = Transfer::default();
+        let first = download.next_index(&transfer).unwrap();
+        first.start.store(1 << 20, Ordering::Release);
+        download.next_index(&transfer).unwrap();
+
+        let mut saved = Vec::new();
+        download.store(&mut saved).unwrap();
+        assert_eq!(saved[..4], *b"TUR\x02");
+        let restored = Download::restore(&mut saved.as_slice(), 40 << 20).unwrap();
+        assert_eq!(restored.coordinator.total_size, 40 << 20);
+        assert_eq!(restored.coordinator.strategy, Strategy::Fixed(3));
+        assert!(restored.coordinator.sequential);
+        assert_eq!(restored.coordinator.offset, 2 * SEQUENTIAL_RANGE);
+        assert_eq!(restored.remaining(), download.remaining());
+    }
+
+    #[test]
+    fn unversioned_metadata_still_loads() {
+        // coordinator, ranges, reserved tail, as saved before the header
+        let legacy = (
+            0u8..4u8,
+            24usize << 20,
+            1u8,
+            1usize,
+            (2usize << 20, 8usize << 20),
+            None::<usize>,
+        );
+        let saved = bincode::encode_to_vec(legacy, config::standard()).unwrap();
+
+        let restored = Download::restore(&mut saved.as_slice(), 20 << 20).unwrap();
+        assert_eq!(restored.coordinator.total_size, 20 << 20);
+        assert_eq!(restored.coordinator.strategy, Strategy::Fibonacci);
+        assert_eq!(restored.coordinator.offset, 20 << 20);
+        assert_eq!(restored.remaining(), 6 << 20);
+    }
+
+    #[test]
+    fn newer_metadata_is_refused() {
+        let mut saved = Vec::new();
+        Download::new(1 << 20, 1, Strategy::Fibonacci)
+            .store(&mut saved)
+            .unwrap();
+        saved[3] = META_VERSION + 1;
+        assert!(Download::restore(&mut saved.as_slice(), 1 << 20).is_err());
+    }
+}
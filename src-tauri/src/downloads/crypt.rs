@@ -0,0 +1,523 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ctr::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+type Aes256Ctr = ctr::Ctr128BE<aes_gcm::aes::Aes256>;
+
+/// Keyring entries holding the master key and the history database's key, the same on
+/// every platform
+const KEYRING_SERVICE: &str = "tur";
+const KEYRING_ACCOUNT: &str = "download-encryption";
+const HISTORY_ACCOUNT: &str = "history-encryption";
+
+const SIDECAR_VERSION: u32 = 1;
+
+/// AES-256-CTR over the whole file, seekable so ranges can be written in any order
+/// and the ciphertext keeps the plaintext's offsets
+#[derive(Clone)]
+pub struct Cipher {
+    key: [u8; 32],
+    iv: [u8; 16],
+}
+
+impl Cipher {
+    /// Encrypt or decrypt `data` that sits at `offset` in the file
+    pub fn apply(&self, data: &mut [u8], offset: u64) {
+        let mut stream = Aes256Ctr::new(&self.key.into(), &self.iv.into());
+        stream.seek(offset);
+        stream.apply_keystream(data);
+    }
+}
+
+/// `<file>.turenc` next to an encrypted download: its own key wrapped with the
+/// keyring's master key, plus a sealed digest of the finished ciphertext
+#[derive(Debug, Serialize, Deserialize)]
+struct Sidecar {
+    version: u32,
+    /// nonce || AES-GCM(master, key || iv)
+    key: String,
+    /// nonce || AES-GCM(master, sha256(ciphertext)), set once the download completes
+    digest: Option<String>,
+}
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".turenc");
+    PathBuf::from(sidecar)
+}
+
+/// Cipher for a download about to be written to `path`. A resume keeps the key
+/// it started with, a fresh start gets a new one. A resume whose sidecar is gone fails
+/// when bytes were already written, those can't be decrypted with any other key.
+pub fn prepare(path: &Path, resume: bool) -> Result<Cipher, String> {
+    prepare_with(&master_key()?, path, resume)
+}
+
+fn prepare_with(master: &[u8; 32], path: &Path, resume: bool) -> Result<Cipher, String> {
+    let sidecar = sidecar_path(path);
+    if resume {
+        if sidecar.exists() {
+            return unwrap_key(master, &read_sidecar(&sidecar)?);
+        }
+        if std::fs::metadata(path).is_ok_and(|m| m.len() > 0) {
+            return Err(format!(
+                "The key of {} is gone, it has to be downloaded again",
+                path.display()
+            ));
+        }
+    }
+
+    // key || iv
+    let mut secret = [0u8; 48];
+    OsRng.fill_bytes(&mut secret);
+    let sidecar_data = Sidecar {
+        version: SIDECAR_VERSION,
+        key: seal(master, &secret)?,
+        digest: None,
+    };
+    write_sidecar(&sidecar, &sidecar_data)?;
+    unwrap_key(master, &sidecar_data)
+}
+
+/// Seal the digest of the completed ciphertext, so tampering or truncation shows up on decrypt
+pub fn finish(path: &Path) -> Result<(), String> {
+    finish_with(&master_key()?, path)
+}
+
+fn finish_with(master: &[u8; 32], path: &Path) -> Result<(), String> {
+    let sidecar = sidecar_path(path);
+    let mut sidecar_data = read_sidecar(&sidecar)?;
+    let digest = ciphertext_digest(path).map_err(|e| e.to_string())?;
+    sidecar_data.digest = Some(seal(master, &digest)?);
+    write_sidecar(&sidecar, &sidecar_data)
+}
+
+/// Decrypt the completed download at `path` into `target`, or in place when there's
+/// no target, which also drops the sidecar. Nothing is written if the digest is off.
+pub fn decrypt(path: &Path, target: Option<&Path>) -> Result<PathBuf, String> {
+    decrypt_with(&master_key()?, path, target)
+}
+
+fn decrypt_with(master: &[u8; 32], path: &Path, target: Option<&Path>) -> Result<PathBuf, String> {
+    let sidecar = sidecar_path(path);
+    let sidecar_data = read_sidecar(&sidecar)?;
+    let cipher = unwrap_key(master, &sidecar_data)?;
+    let expected = sidecar_data
+        .digest
+        .as_deref()
+        .ok_or_else(|| "Download hasn't finished, nothing to decrypt yet".to_string())
+        .and_then(|d| open(master, d))?;
+
+    let target = target.unwrap_or(path).to_path_buf();
+    let mut temp = target.as_os_str().to_owned();
+    temp.push(".tur-decrypt");
+    let temp = PathBuf::from(temp);
+    let result = decrypt_into(&cipher, path, &temp).and_then(|digest| {
+        if digest[..] == expected[..] {
+            Ok(())
+        } else {
+            Err("Encrypted file was modified or is incomplete".to_string())
+        }
+    });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    std::fs::rename(&temp, &target).map_err(|e| e.to_string())?;
+    if target == path {
+        let _ = std::fs::remove_file(&sidecar);
+    }
+    Ok(target)
+}
+
+// plaintext to `temp`, returns the digest of the ciphertext it read
+fn decrypt_into(cipher: &Cipher, path: &Path, temp: &Path) -> Result<Vec<u8>, String> {
+    let mut input = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut output = std::fs::File::create(temp).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    let mut offset = 0u64;
+    loop {
+        let n = input.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        cipher.apply(&mut buf[..n], offset);
+        output.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        offset += n as u64;
+    }
+    output.sync_all().map_err(|e| e.to_string())?;
+    Ok(hasher.finalize().to_vec())
+}
+
+fn ciphertext_digest(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+fn read_sidecar(path: &Path) -> Result<Sidecar, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Not an encrypted download: {}", e))?;
+    let sidecar: Sidecar = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+    if sidecar.version > SIDECAR_VERSION {
+        return Err(format!(
+            "Unsupported encryption version {}",
+            sidecar.version
+        ));
+    }
+    Ok(sidecar)
+}
+
+fn write_sidecar(path: &Path, sidecar: &Sidecar) -> Result<(), String> {
+    let data = serde_json::to_vec_pretty(sidecar).map_err(|e| e.to_string())?;
+    std::fs::write(path, data).map_err(|e| e.to_string())
+}
+
+fn unwrap_key(master: &[u8; 32], sidecar: &Sidecar) -> Result<Cipher, String> {
+    let secret = open(master, &sidecar.key)?;
+    if secret.len() != 48 {
+        return Err("Broken encryption key".into());
+    }
+    let mut cipher = Cipher {
+        key: [0; 32],
+        iv: [0; 16],
+    };
+    cipher.key.copy_from_slice(&secret[..32]);
+    cipher.iv.copy_from_slice(&secret[32..]);
+    Ok(cipher)
+}
+
+fn seal(master: &[u8; 32], plaintext: &[u8]) -> Result<String, String> {
+    let aead = Aes256Gcm::new(master.into());
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(aead.encrypt(&nonce, plaintext).map_err(|e| e.to_string())?);
+    Ok(BASE64.encode(sealed))
+}
+
+fn open(master: &[u8; 32], sealed: &str) -> Result<Vec<u8>, String> {
+    let sealed = BASE64.decode(sealed).map_err(|e| e.to_string())?;
+    if sealed.len() < 12 {
+        return Err("Broken encryption data".into());
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    Aes256Gcm::new(master.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        // wrong master key, e.g. the keyring entry was recreated
+        .map_err(|_| "Can't unlock this download with the keyring's key".to_string())
+}
+
+// read from the keyring once per run, created on first use
+fn master_key() -> Result<[u8; 32], String> {
+    static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(*key);
+    }
+    let key = stored_key(KEYRING_ACCOUNT, true)?.ok_or("Keyring didn't keep the encryption key")?;
+    Ok(*KEY.get_or_init(|| key))
+}
+
+/// Key the history database is encrypted with, see `Database::set_encrypted`. With
+/// `create` a new one is stored when the keyring has none, without it that's None.
+pub fn history_key(create: bool) -> Result<Option<[u8; 32]>, String> {
+    stored_key(HISTORY_ACCOUNT, create)
+}
+
+// Two first uses at once would each store a key of their own and the later one would
+// win, files sealed with the other couldn't be opened anymore. One creates at a time,
+// and what's returned is read back from the keyring, not the key that was generated.
+fn stored_key(account: &str, create: bool) -> Result<Option<[u8; 32]>, String> {
+    static CREATING: Mutex<()> = Mutex::new(());
+    let _creating = CREATING.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = read_key(account)? {
+        return Ok(Some(key));
+    }
+    if !create {
+        return Ok(None);
+    }
+    let key: [u8; 32] = Aes256Gcm::generate_key(&mut OsRng).into();
+    keyring::set(account, &BASE64.encode(key))?;
+    read_key(account)?
+        .map(Some)
+        .ok_or_else(|| "Keyring didn't keep the encryption key".to_string())
+}
+
+fn read_key(account: &str) -> Result<Option<[u8; 32]>, String> {
+    let Some(encoded) = keyring::get(account)? else {
+        return Ok(None);
+    };
+    let bytes = BASE64.decode(encoded.trim()).map_err(|e| e.to_string())?;
+    <[u8; 32]>::try_from(bytes.as_slice())
+        .map(Some)
+        .map_err(|_| "Keyring holds a broken encryption key".to_string())
+}
+
+/// Secret Service through `secret-tool`
+#[cfg(target_os = "linux")]
+mod keyring {
+    use super::KEYRING_SERVICE;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    pub fn get(account: &str) -> Result<Option<String>, String> {
+        let out = Command::new("secret-tool")
+            .args(["lookup", "service", KEYRING_SERVICE, "account", account])
+            .output()
+            .map_err(|e| format!("secret-tool not available: {}", e))?;
+        // exits 1 with no output when there's no such entry
+        let secret = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        Ok((out.status.success() && !secret.is_empty()).then_some(secret))
+    }
+
+    pub fn set(account: &str, secret: &str) -> Result<(), String> {
+        let label = format!("--label=tur {}", account.replace('-', " "));
+        let mut child = Command::new("secret-tool")
+            .args([
+                "store",
+                label.as_str(),
+                "service",
+                KEYRING_SERVICE,
+                "account",
+                account,
+            ])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("secret-tool not available: {}", e))?;
+        // stdin rather than an argument, so it never shows up in the process list
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(secret.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err("Failed to store the encryption key in the keyring".into())
+        }
+    }
+}
+
+/// The login keychain through `security`
+#[cfg(target_os = "macos")]
+mod keyring {
+    use super::KEYRING_SERVICE;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    pub fn get(account: &str) -> Result<Option<String>, String> {
+        let out = Command::new("security")
+            .args([
+                "find-generic-password",
+                "-s",
+                KEYRING_SERVICE,
+                "-a",
+                account,
+                "-w",
+            ])
+            .output()
+            .map_err(|e| e.to_string())?;
+        let secret = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        Ok((out.status.success() && !secret.is_empty()).then_some(secret))
+    }
+
+    // `-i` reads the command from stdin, as an argument the key would show up in the
+    // process list. Its exit status doesn't say whether the command worked, `stored_key`
+    // reads the entry back for that.
+    pub fn set(account: &str, secret: &str) -> Result<(), String> {
+        let mut child = Command::new("security")
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        let command = format!(
+            "add-generic-password -U -s {} -a {} -w \"{}\"\n",
+            KEYRING_SERVICE, account, secret
+        );
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(command.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err("Failed to store the encryption key in the keychain".into())
+        }
+    }
+}
+
+/// Credential Manager, a generic credential for the current user
+#[cfg(windows)]
+mod keyring {
+    use super::KEYRING_SERVICE;
+    use std::ffi::c_void;
+
+    const CRED_TYPE_GENERIC: u32 = 1;
+    const CRED_PERSIST_LOCAL_MACHINE: u32 = 2;
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    struct CREDENTIALW {
+        Flags: u32,
+        Type: u32,
+        TargetName: *mut u16,
+        Comment: *mut u16,
+        LastWritten: [u32; 2],
+        CredentialBlobSize: u32,
+        CredentialBlob: *mut u8,
+        Persist: u32,
+        AttributeCount: u32,
+        Attributes: *mut c_void,
+        TargetAlias: *mut u16,
+        UserName: *mut u16,
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn CredReadW(target: *const u16, kind: u32, flags: u32, out: *mut *mut CREDENTIALW) -> i32;
+        fn CredWriteW(credential: *const CREDENTIALW, flags: u32) -> i32;
+        fn CredFree(buffer: *mut c_void);
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn target(account: &str) -> Vec<u16> {
+        wide(&format!("{}/{}", KEYRING_SERVICE, account))
+    }
+
+    pub fn get(account: &str) -> Result<Option<String>, String> {
+        let target = target(account);
+        let mut credential = std::ptr::null_mut();
+        // SAFETY: target is nul terminated, the out pointer is only read after success
+        // and freed with CredFree
+        unsafe {
+            if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+                return Ok(None);
+            }
+            let blob = std::slice::from_raw_parts(
+                (*credential).CredentialBlob,
+                (*credential).CredentialBlobSize as usize,
+            );
+            let secret = String::from_utf8_lossy(blob).to_string();
+            CredFree(credential.cast());
+            Ok(Some(secret))
+        }
+    }
+
+    pub fn set(account: &str, secret: &str) -> Result<(), String> {
+        let mut target = target(account);
+        let mut user = wide(account);
+        let mut blob = secret.as_bytes().to_vec();
+        let credential = CREDENTIALW {
+            Flags: 0,
+            Type: CRED_TYPE_GENERIC,
+            TargetName: target.as_mut_ptr(),
+            Comment: std::ptr::null_mut(),
+            LastWritten: [0; 2],
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            AttributeCount: 0,
+            Attributes: std::ptr::null_mut(),
+            TargetAlias: std::ptr::null_mut(),
+            UserName: user.as_mut_ptr(),
+        };
+        // SAFETY: every pointer in the struct outlives the call
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            Err(std::io::Error::last_os_error().to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod keyring {
+    pub fn get(_account: &str) -> Result<Option<String>, String> {
+        Err("No keyring on this platform".into())
+    }
+
+    pub fn set(_account: &str, _secret: &str) -> Result<(), String> {
+        Err("No keyring on this platform".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        let name = format!("tur-crypt-{}.bin", uuid::Uuid::now_v7().as_simple());
+        std::env::temp_dir().join(name)
+    }
+
+    #[test]
+    fn sealed_download_decrypts_back() {
+        let master: [u8; 32] = Aes256Gcm::generate_key(&mut OsRng).into();
+        let path = temp_path();
+        let plain: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+
+        // written in two ranges, the second one after a resume
+        let cipher = prepare_with(&master, &path, false).unwrap();
+        let (head, tail) = plain.split_at(40_000);
+        let mut first = head.to_vec();
+        cipher.apply(&mut first, 0);
+        std::fs::write(&path, &first).unwrap();
+        let cipher = prepare_with(&master, &path, true).unwrap();
+        let mut second = tail.to_vec();
+        cipher.apply(&mut second, head.len() as u64);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&second)
+            .unwrap();
+        assert_ne!(std::fs::read(&path).unwrap(), plain);
+        finish_with(&master, &path).unwrap();
+
+        let target = temp_path();
+        assert!(decrypt_with(&master, &path, Some(&target)).is_ok());
+        assert_eq!(std::fs::read(&target).unwrap(), plain);
+        // another master key can't unwrap it
+        let other: [u8; 32] = Aes256Gcm::generate_key(&mut OsRng).into();
+        assert!(decrypt_with(&other, &path, Some(&target)).is_err());
+
+        // a flipped byte fails the digest and leaves the file as it was
+        let mut tampered = std::fs::read(&path).unwrap();
+        tampered[1234] ^= 1;
+        std::fs::write(&path, &tampered).unwrap();
+        let _ = std::fs::remove_file(&target);
+        assert!(decrypt_with(&master, &path, Some(&target)).is_err());
+        assert!(!target.exists());
+
+        // without its sidecar a started file can't resume under a new key
+        std::fs::remove_file(sidecar_path(&path)).unwrap();
+        assert!(prepare_with(&master, &path, true).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(prepare_with(&master, &path, true).is_ok());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(sidecar_path(&path));
+    }
+}
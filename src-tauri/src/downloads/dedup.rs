@@ -0,0 +1,115 @@
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+use super::manager::DownloadManager;
+use crate::events::DuplicateDownload;
+use crate::settings;
+
+/// Hash a completed download in the background and compare it against earlier ones
+pub fn queue(app: &AppHandle, id: Uuid, path: PathBuf) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = check(&app, &id, &path) {
+            eprintln!("Duplicate check of {} failed: {}", id, e);
+        }
+    });
+}
+
+fn check(app: &AppHandle, id: &Uuid, path: &Path) -> Result<(), String> {
+    let manager = app.state::<DownloadManager>();
+    let db = manager.db();
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let hash = sha256(path).map_err(|e| e.to_string())?;
+
+    // the index outlives files the user deleted or moved, drop those as we go
+    let matches = db.find_by_hash(&hash, size, id).map_err(|e| e.to_string())?;
+    let mut existing = None;
+    for (other, other_path) in matches {
+        let other_path = PathBuf::from(other_path);
+        if std::fs::metadata(&other_path).is_ok_and(|m| m.len() == size) {
+            existing = Some((other, other_path));
+            break;
+        }
+        if let Err(e) = db.delete_hash(&other) {
+            eprintln!("Failed to drop stale hash of {}: {}", other, e);
+        }
+    }
+    db.save_hash(id, &hash, size, &path.to_string_lossy())
+        .map_err(|e| e.to_string())?;
+
+    let Some((other, other_path)) = existing else {
+        return Ok(());
+    };
+    if same_file(path, &other_path) {
+        return Ok(());
+    }
+    let linked = settings::load_or_create(app).download.hardlink_duplicates
+        && match hard_link(&other_path, path) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Failed to hard link {}: {}", path.display(), e);
+                false
+            }
+        };
+
+    let payload = DuplicateDownload {
+        id: *id,
+        path: path.to_path_buf(),
+        existing_id: other,
+        existing_path: other_path,
+        size,
+        linked,
+    };
+    if let Err(e) = app.emit("duplicate_download", payload) {
+        eprintln!("Failed to emit duplicate_download event: {}", e);
+    }
+    Ok(())
+}
+
+/// Hex sha256 of a file's content
+pub fn sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// swap `path` for a link to `original`, through a temp name so `path` never goes missing
+fn hard_link(original: &Path, path: &Path) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tur-link");
+    let temp = PathBuf::from(temp);
+    let _ = std::fs::remove_file(&temp);
+    std::fs::hard_link(original, &temp)?;
+    std::fs::rename(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+// already linked, or the same path twice
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
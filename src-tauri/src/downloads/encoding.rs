@@ -0,0 +1,97 @@
+use flate2::write::{MultiGzDecoder, ZlibDecoder};
+use reqwest::header::{self, HeaderMap};
+use std::io::{self, Write};
+use url::Url;
+
+use super::crypt::Cipher;
+
+/// Content-Encoding a server put on the body of `url`, None for identity. Such a body is
+/// compressed on the fly, so its Content-Length and ranges are not the file's. A `.gz`
+/// labelled gzip is the file itself and passes as identity.
+pub fn of(headers: &HeaderMap, url: &Url) -> Option<String> {
+    let value = headers.get(header::CONTENT_ENCODING)?.to_str().ok()?;
+    let encoding = value.trim().to_ascii_lowercase();
+    if encoding.is_empty() || encoding == "identity" {
+        return None;
+    }
+    let path = url.path().to_ascii_lowercase();
+    let packed = [".gz", ".tgz", ".gzip"]
+        .iter()
+        .any(|ext| path.ends_with(ext));
+    if packed && matches!(encoding.as_str(), "gzip" | "x-gzip") {
+        return None;
+    }
+    Some(encoding)
+}
+
+/// Unpacks a body into a file, encrypting what comes out when there's a cipher
+pub enum Decoder<W: Write> {
+    Gzip(MultiGzDecoder<Sink<W>>),
+    Deflate(ZlibDecoder<Sink<W>>),
+}
+
+impl<W: Write> Decoder<W> {
+    pub fn new(encoding: &str, out: W, cipher: Option<Cipher>) -> Result<Self, String> {
+        let sink = Sink {
+            out,
+            cipher,
+            written: 0,
+        };
+        match encoding {
+            "gzip" | "x-gzip" => Ok(Decoder::Gzip(MultiGzDecoder::new(sink))),
+            "deflate" => Ok(Decoder::Deflate(ZlibDecoder::new(sink))),
+            other => Err(format!("Unsupported Content-Encoding {}", other)),
+        }
+    }
+
+    /// Check the end of the stream, gives back the bytes written
+    pub fn finish(self) -> io::Result<u64> {
+        let sink = match self {
+            Decoder::Gzip(decoder) => decoder.finish()?,
+            Decoder::Deflate(decoder) => decoder.finish()?,
+        };
+        Ok(sink.written)
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.write(buf),
+            Decoder::Deflate(decoder) => decoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.flush(),
+            Decoder::Deflate(decoder) => decoder.flush(),
+        }
+    }
+}
+
+// the cipher has to see decoded offsets, not the ones in the compressed body
+pub struct Sink<W> {
+    out: W,
+    cipher: Option<Cipher>,
+    written: u64,
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.cipher {
+            Some(cipher) => {
+                let mut data = buf.to_vec();
+                cipher.apply(&mut data, self.written);
+                self.out.write_all(&data)?;
+            }
+            None => self.out.write_all(buf)?,
+        }
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
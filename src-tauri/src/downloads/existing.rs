@@ -0,0 +1,137 @@
+use reqwest::{header, StatusCode};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use super::client::HttpClient;
+
+/// Bytes compared at each end of the file
+const BLOCK: u64 = 64 << 10;
+
+/// How a file already at the destination compares to the remote one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    /// same size, first and last blocks, taken as already downloaded
+    Same,
+    /// same size but other content
+    Different,
+}
+
+/// Where a new download goes and whether the file there already is it. A file of the
+/// same size is compared by its ends instead of fetched again, one that differs moves
+/// the download to a free `name (n).ext`.
+pub async fn resolve(
+    client: &HttpClient,
+    url: &Url,
+    destination: String,
+    size: Option<i64>,
+    resume_supported: bool,
+) -> (String, bool) {
+    let size = match size {
+        Some(size) if size > 0 && resume_supported => size as u64,
+        _ => return (destination, false),
+    };
+    match compare(client, url, Path::new(&destination), size).await {
+        Some(Existing::Same) => (destination, true),
+        Some(Existing::Different) => {
+            let free = free_name(Path::new(&destination));
+            (free.to_string_lossy().to_string(), false)
+        }
+        None => (destination, false),
+    }
+}
+
+/// Compare `path` with `size` bytes at `url` by hashing both ends of each. None when
+/// there's nothing to compare: no file, another size or a server ignoring ranges.
+pub async fn compare(client: &HttpClient, url: &Url, path: &Path, size: u64) -> Option<Existing> {
+    if std::fs::metadata(path).map(|m| m.len()).ok() != Some(size) {
+        return None;
+    }
+    let mut blocks = vec![(0, BLOCK.min(size))];
+    if size > BLOCK {
+        let start = (size - BLOCK).max(BLOCK);
+        blocks.push((start, size - start));
+    }
+    for (start, len) in blocks {
+        let local = match local_hash(path, start, len) {
+            Ok(hash) => hash,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let remote = match remote_hash(client, url, start, len).await {
+            Ok(hash) => hash?,
+            Err(e) => {
+                eprintln!("Failed to fetch a block of {}: {}", url, e);
+                return None;
+            }
+        };
+        if local != remote {
+            return Some(Existing::Different);
+        }
+    }
+    Some(Existing::Same)
+}
+
+/// `path` if nothing is there, otherwise the first free `name (n).ext` next to it
+pub fn free_name(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path.extension().map(|e| e.to_string_lossy().to_string());
+    (1..)
+        .map(|n| {
+            let name = match &extension {
+                Some(ext) => format!("{} ({}).{}", stem, n, ext),
+                None => format!("{} ({})", stem, n),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+fn local_hash(path: &Path, start: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut block = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut block)?;
+    Ok(Sha256::digest(&block).to_vec())
+}
+
+// None when the server doesn't answer with exactly that range
+async fn remote_hash(
+    client: &HttpClient,
+    url: &Url,
+    start: u64,
+    len: u64,
+) -> Result<Option<Vec<u8>>, String> {
+    let request = client.inner().get(url.clone()).header(
+        header::RANGE,
+        format!("bytes={}-{}", start, start + len - 1),
+    );
+    let mut response = client
+        .send(request)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Ok(None);
+    }
+    let mut hasher = Sha256::new();
+    let mut read = 0;
+    while let Some(chunk) = client.chunk(&mut response).await? {
+        read += chunk.len() as u64;
+        if read > len {
+            return Ok(None);
+        }
+        hasher.update(&chunk);
+    }
+    Ok((read == len).then(|| hasher.finalize().to_vec()))
+}
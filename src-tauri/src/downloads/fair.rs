@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::client::RateLimit;
+use super::core::Transfer;
+use super::manager::DownloadManager;
+use crate::settings::{self, BandwidthConfig};
+
+/// How often the shares are worked out again
+pub const INTERVAL: Duration = Duration::from_millis(500);
+/// Shares of the link a priority download gets against a regular one
+pub const PRIORITY_WEIGHT: u64 = 2;
+/// Room over what was measured, so shares keep growing while the link has more to give
+const HEADROOM: f64 = 1.25;
+/// A download reading this close to its share was held back by it
+const HELD: f64 = 0.9;
+/// No share goes below this, a download squeezed to nothing can't show it wants more
+const MIN_SHARE: u64 = 16 << 10;
+
+/// A running download as the balancer sees it
+pub struct Flow {
+    pub id: Uuid,
+    /// shares of the link, 0 leaves it out (uploads, which don't compete for it)
+    pub weight: u64,
+    pub limit: Arc<RateLimit>,
+    pub transfer: Arc<Transfer>,
+}
+
+/// Splits the link between running downloads by weight instead of by connection count,
+/// so one with 16 connections doesn't starve another with 2. Each tick it measures what
+/// every download got, hands the downloads that took less than their part exactly that
+/// and splits the rest evenly by weight, within `bandwidth.total_limit` or a little over
+/// the measured total when there's no limit.
+#[derive(Default)]
+pub struct Balancer {
+    /// bytes each download had at the last tick
+    seen: HashMap<Uuid, u64>,
+    last: Option<Instant>,
+}
+
+impl Balancer {
+    pub fn rebalance(&mut self, config: &BandwidthConfig, flows: &[Flow]) {
+        let elapsed = self.last.replace(Instant::now()).map(|last| last.elapsed());
+        let flows: Vec<&Flow> = flows.iter().filter(|f| f.weight > 0).collect();
+        let mut seen = HashMap::with_capacity(flows.len());
+        let mut rates = Vec::with_capacity(flows.len());
+        for flow in &flows {
+            let downloaded = flow.transfer.downloaded();
+            let before = self.seen.get(&flow.id).copied();
+            seen.insert(flow.id, downloaded);
+            // resumes and copies count bytes that never crossed the link, take a tick to settle
+            let rate = match (before, elapsed) {
+                (Some(before), Some(elapsed)) if downloaded >= before => {
+                    Some(((downloaded - before) as f64 / elapsed.as_secs_f64()) as u64)
+                }
+                _ => None,
+            };
+            rates.push(rate);
+        }
+        self.seen = seen;
+
+        let total_limit = config.total_limit * 1024;
+        let capacity = if total_limit > 0 {
+            total_limit
+        } else if config.balance && flows.len() > 1 && rates.iter().all(Option::is_some) {
+            (rates.iter().flatten().sum::<u64>() as f64 * HEADROOM) as u64
+        } else {
+            0
+        };
+        if capacity == 0 {
+            for flow in &flows {
+                flow.limit.set_share(0);
+            }
+            return;
+        }
+
+        let demands: Vec<(u64, u64)> = flows
+            .iter()
+            .zip(&rates)
+            .map(|(flow, rate)| {
+                let share = flow.limit.share();
+                let demand = match rate {
+                    Some(rate) if share > 0 && (*rate as f64) < share as f64 * HELD => {
+                        (*rate as f64 * HEADROOM) as u64
+                    }
+                    // held back by its share or not measured yet, it could take any amount
+                    _ => u64::MAX,
+                };
+                let demand = match flow.limit.get() {
+                    0 => demand,
+                    own => demand.min(own),
+                };
+                (flow.weight, demand)
+            })
+            .collect();
+        for (flow, share) in flows.iter().zip(shares(capacity, &demands)) {
+            flow.limit.set_share(share.max(MIN_SHARE));
+        }
+    }
+}
+
+/// Weighted max-min split of `capacity` between `(weight, demand)` flows: the ones
+/// wanting less than their part get what they want, the rest is split again by weight
+pub fn shares(capacity: u64, flows: &[(u64, u64)]) -> Vec<u64> {
+    let mut shares = vec![0; flows.len()];
+    let mut open: Vec<usize> = (0..flows.len()).collect();
+    let mut left = capacity as f64;
+    while !open.is_empty() && left > 0.0 {
+        let weights: u64 = open.iter().map(|&i| flows[i].0).sum();
+        let unit = left / weights as f64;
+        let (satisfied, rest): (Vec<usize>, Vec<usize>) = open
+            .iter()
+            .partition(|&&i| (flows[i].1 as f64) <= unit * flows[i].0 as f64);
+        if satisfied.is_empty() {
+            for i in rest {
+                shares[i] = (unit * flows[i].0 as f64) as u64;
+            }
+            break;
+        }
+        for i in satisfied {
+            shares[i] = flows[i].1;
+            left -= flows[i].1 as f64;
+        }
+        open = rest;
+    }
+    shares
+}
+
+/// Rebalance the running downloads every `INTERVAL`
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut balancer = Balancer::default();
+        let mut interval = tokio::time::interval(INTERVAL);
+        loop {
+            interval.tick().await;
+            let flows = app.state::<DownloadManager>().flows();
+            if flows.is_empty() {
+                balancer = Balancer::default();
+                continue;
+            }
+            let config = settings::load_or_create(&app).bandwidth;
+            balancer.rebalance(&config, &flows);
+        }
+    });
+}
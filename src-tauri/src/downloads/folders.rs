@@ -0,0 +1,123 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::database::Database;
+
+/// Downloads looked at to find the recent folders, newest first
+const LOOK_BACK: i64 = 500;
+
+/// A folder downloads were saved to, for the add dialog to suggest
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct RecentFolder {
+    pub path: String,
+    /// unix seconds of the newest download saved there, None for the download location
+    /// when nothing went there yet
+    pub last_used: Option<i64>,
+    /// downloads saved there among the recent ones
+    pub downloads: u32,
+    /// the configured download location
+    pub default: bool,
+    /// false once it was moved or deleted, a download there creates it again
+    pub exists: bool,
+    pub space: Option<Space>,
+}
+
+/// Room on the drive a folder is on
+#[derive(Debug, Clone, Copy, Serialize, specta::Type)]
+pub struct Space {
+    /// bytes this user can still write
+    pub free: u64,
+    pub total: u64,
+}
+
+/// The `limit` folders downloads went to most recently, the download location first
+pub fn recent(db: &Database, default: &Path, limit: usize) -> Result<Vec<RecentFolder>, String> {
+    let destinations = db
+        .get_recent_destinations(LOOK_BACK)
+        .map_err(|e| e.to_string())?;
+    let mut folders: Vec<RecentFolder> = vec![folder(default.to_path_buf(), None, true)];
+    for (destination, updated_at) in destinations {
+        let Some(parent) = Path::new(&destination).parent() else {
+            continue;
+        };
+        match folders.iter_mut().find(|f| Path::new(&f.path) == parent) {
+            Some(known) => {
+                known.downloads += 1;
+                known.last_used.get_or_insert(updated_at);
+            }
+            None => {
+                let mut recent = folder(parent.to_path_buf(), Some(updated_at), false);
+                recent.downloads = 1;
+                folders.push(recent);
+            }
+        }
+    }
+    folders.truncate(limit.max(1));
+    for folder in &mut folders {
+        folder.space = space(Path::new(&folder.path));
+    }
+    Ok(folders)
+}
+
+fn folder(path: PathBuf, last_used: Option<i64>, default: bool) -> RecentFolder {
+    RecentFolder {
+        exists: path.is_dir(),
+        path: path.to_string_lossy().to_string(),
+        last_used,
+        downloads: 0,
+        default,
+        space: None,
+    }
+}
+
+/// Room on the drive `path` is on, or would be on: a folder that doesn't exist yet is
+/// looked up through the closest one above it that does
+pub fn space(path: &Path) -> Option<Space> {
+    let existing = path.ancestors().find(|p| p.is_dir())?;
+    drive_space(existing)
+}
+
+#[cfg(unix)]
+fn drive_space(path: &Path) -> Option<Space> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only writes the zeroed struct it's given, the path is nul terminated
+    unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        let block = stat.f_frsize as u64;
+        Some(Space {
+            free: stat.f_bavail as u64 * block,
+            total: stat.f_blocks as u64 * block,
+        })
+    }
+}
+
+#[cfg(windows)]
+fn drive_space(path: &Path) -> Option<Space> {
+    use std::os::windows::ffi::OsStrExt;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            available: *mut u64,
+            total: *mut u64,
+            free: *mut u64,
+        ) -> i32;
+    }
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let (mut available, mut total, mut free) = (0, 0, 0);
+    // SAFETY: the path is nul terminated and the three outputs are valid u64s
+    let ok = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, &mut total, &mut free) };
+    (ok != 0).then_some(Space {
+        free: available,
+        total,
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn drive_space(_path: &Path) -> Option<Space> {
+    None
+}
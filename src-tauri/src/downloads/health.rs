@@ -0,0 +1,169 @@
+use reqwest::header;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use url::Url;
+use uuid::Uuid;
+
+use super::client::{self, HttpClient};
+use super::manager::DownloadManager;
+use super::probe::{self, RemoteInfo};
+use crate::database;
+use crate::events::UrlHealth;
+use crate::i18n;
+use crate::notifications;
+use crate::settings;
+
+/// How often the setting is looked at again while checks are off
+const IDLE: Duration = Duration::from_secs(60);
+/// Answers that mean the file won't come back at this url
+const GONE: [StatusCode; 2] = [StatusCode::NOT_FOUND, StatusCode::GONE];
+
+/// What a check of a waiting download found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Health {
+    Alive,
+    /// the server answered 404 or 410
+    Gone(u16),
+    /// the etag, last modified date or size isn't the one stored when it was added
+    Changed,
+}
+
+impl Health {
+    fn name(&self) -> &'static str {
+        match self {
+            Health::Alive => "alive",
+            Health::Gone(_) => "gone",
+            Health::Changed => "changed",
+        }
+    }
+}
+
+/// HEAD `url`, or a one byte GET when the server refuses HEAD. Err when it couldn't be
+/// reached or answered something else, that says nothing about the file.
+pub async fn check(
+    client: &HttpClient,
+    url: &Url,
+    stored: &database::Download,
+) -> Result<Health, String> {
+    let mut response = client.send(client.inner().head(url.clone())).await?;
+    if !response.status().is_success() && !GONE.contains(&response.status()) {
+        let request = client
+            .inner()
+            .get(url.clone())
+            .header(header::RANGE, "bytes=0-0");
+        response = client.send(request).await?;
+    }
+    let status = response.status();
+    if GONE.contains(&status) {
+        return Ok(Health::Gone(status.as_u16()));
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+    let remote = RemoteInfo::from_response(url, &response);
+    Ok(match probe::needs_restart(stored, &remote, true) {
+        true => Health::Changed,
+        false => Health::Alive,
+    })
+}
+
+/// Check every queued download, and failed ones waiting on a retry, each
+/// `download.health_check_minutes` so a link that died shows up before it's started.
+/// Each finding is told once, again only when it changes.
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut reported: HashMap<Uuid, Health> = HashMap::new();
+        loop {
+            let minutes = settings::load_or_create(&app).download.health_check_minutes;
+            if minutes == 0 {
+                tokio::time::sleep(IDLE).await;
+                continue;
+            }
+            tokio::time::sleep(Duration::from_secs(minutes as u64 * 60)).await;
+            sweep(&app, &mut reported).await;
+        }
+    });
+}
+
+async fn sweep(app: &AppHandle, reported: &mut HashMap<Uuid, Health>) {
+    let manager = app.state::<DownloadManager>();
+    let pending = match manager.pending() {
+        Ok(pending) => pending,
+        Err(e) => {
+            eprintln!("Failed to list queued downloads: {}", e);
+            return;
+        }
+    };
+    // started or removed since, nothing to tell about them anymore
+    reported.retain(|id, _| pending.iter().any(|d| d.id == *id));
+
+    let settings = settings::load_or_create(app);
+    for record in pending.into_iter().filter(|d| !d.is_upload()) {
+        let health = match Url::parse(&record.url) {
+            Ok(url) => match client::for_target(&settings, &url, &record.options) {
+                Ok((target, client)) => check(&client, &target, &record).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        let health = match health {
+            Ok(health) => health,
+            // offline or a server hiccup, the next sweep tries again
+            Err(e) => {
+                eprintln!("Failed to check {}: {}", record.url, e);
+                continue;
+            }
+        };
+        let before = reported.get(&record.id).unwrap_or(&Health::Alive);
+        if *before == health {
+            continue;
+        }
+        report(app, &record, &health);
+        match health {
+            Health::Alive => reported.remove(&record.id),
+            health => reported.insert(record.id, health),
+        };
+    }
+}
+
+fn report(app: &AppHandle, record: &database::Download, health: &Health) {
+    let status = match health {
+        Health::Gone(status) => Some(*status),
+        _ => None,
+    };
+    let manager = app.state::<DownloadManager>();
+    manager.log_activity(
+        "url_checked",
+        Some(&record.id),
+        json!({ "health": health.name(), "status": status }),
+    );
+    let payload = UrlHealth {
+        id: record.id,
+        health: health.name().into(),
+        status,
+    };
+    if let Err(e) = app.emit("url_health", payload) {
+        eprintln!("Failed to emit url_health event: {}", e);
+    }
+    let message = match health {
+        Health::Alive => return,
+        Health::Gone(status) => i18n::tf(
+            "notify.url_gone",
+            &[
+                ("filename", &record.filename),
+                ("status", &status.to_string()),
+            ],
+        ),
+        Health::Changed => i18n::tf("notify.url_changed", &[("filename", &record.filename)]),
+    };
+    notifications::notify(
+        app,
+        &format!("url_{}", health.name()),
+        Some(record.id),
+        message,
+    );
+}
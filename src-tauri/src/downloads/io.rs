@@ -0,0 +1,208 @@
+use bytes::Bytes;
+use serde::Serialize;
+use std::fs::File;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+use super::client;
+
+/// Dedicated writer threads, so chunk writes stay off tokio's shared blocking pool
+static POOL: OnceLock<WritePool> = OnceLock::new();
+static METRICS: Metrics = Metrics::new();
+
+struct Job {
+    file: Arc<File>,
+    data: Bytes,
+    offset: u64,
+    queued: Instant,
+    done: oneshot::Sender<std::io::Result<()>>,
+}
+
+struct WritePool {
+    tx: mpsc::Sender<Job>,
+}
+
+/// Start `threads` writer threads, 0 keeps using `spawn_blocking`. Only the first call counts.
+pub fn init(threads: u8) {
+    if threads == 0 {
+        return;
+    }
+    POOL.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for n in 0..threads {
+            let rx = rx.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("tur-io-{}", n))
+                .spawn(move || loop {
+                    let job = match rx.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let _ = job.done.send(timed_write(&job.file, &job.data, job.offset, job.queued));
+                });
+            if let Err(e) = spawned {
+                eprintln!("Failed to start writer thread: {}", e);
+            }
+        }
+        WritePool { tx }
+    });
+}
+
+/// Positional write through the writer pool, or the blocking pool when there is none
+pub async fn write_at(file: &Arc<File>, data: Bytes, offset: u64) -> std::io::Result<()> {
+    let queued = Instant::now();
+    let Some(pool) = POOL.get() else {
+        let file = file.clone();
+        return tokio::task::spawn_blocking(move || timed_write(&file, &data, offset, queued))
+            .await
+            .map_err(std::io::Error::other)?;
+    };
+
+    let (done, result) = oneshot::channel();
+    let job = Job {
+        file: file.clone(),
+        data,
+        offset,
+        queued,
+        done,
+    };
+    pool.tx
+        .send(job)
+        .map_err(|_| std::io::Error::other("writer threads are gone"))?;
+    result
+        .await
+        .map_err(|_| std::io::Error::other("writer thread dropped the write"))?
+}
+
+/// Positional read of up to `len` bytes on the blocking pool, short only at end of file
+pub async fn read_at(file: &Arc<File>, offset: u64, len: usize) -> std::io::Result<Bytes> {
+    let file = file.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut buf = vec![0; len];
+        let mut read = 0;
+        while read < len {
+            match read_some_at(&file, &mut buf[read..], offset + read as u64)? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        buf.truncate(read);
+        Ok(Bytes::from(buf))
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+fn read_some_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.read_at(buf, offset)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        file.seek_read(buf, offset)
+    }
+}
+
+fn timed_write(file: &File, buf: &[u8], offset: u64, queued: Instant) -> std::io::Result<()> {
+    let started = Instant::now();
+    let result = write_all_at(file, buf, offset);
+    METRICS.record_write(started - queued, started.elapsed());
+    result
+}
+
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.write_all_at(buf, offset)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        let mut written = 0;
+        while written < buf.len() {
+            let n = file.seek_write(&buf[written..], offset + written as u64)?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            written += n;
+        }
+        Ok(())
+    }
+}
+
+struct Metrics {
+    writes: AtomicU64,
+    queue_us: AtomicU64,
+    max_queue_us: AtomicU64,
+    write_us: AtomicU64,
+    max_lag_us: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            writes: AtomicU64::new(0),
+            queue_us: AtomicU64::new(0),
+            max_queue_us: AtomicU64::new(0),
+            write_us: AtomicU64::new(0),
+            max_lag_us: AtomicU64::new(0),
+        }
+    }
+
+    fn record_write(&self, queued: Duration, took: Duration) {
+        let queued = queued.as_micros() as u64;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.queue_us.fetch_add(queued, Ordering::Relaxed);
+        self.max_queue_us.fetch_max(queued, Ordering::Relaxed);
+        self.write_us
+            .fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Write and scheduling latencies since the last `take_metrics`
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct IoMetrics {
+    pub writer_threads: bool,
+    pub writes: u64,
+    /// time a chunk waited for a thread before its write started
+    pub avg_queue_us: u64,
+    pub max_queue_us: u64,
+    pub avg_write_us: u64,
+    /// worst delay seen by the lag probe, i.e. how late a UI command could start
+    pub max_runtime_lag_us: u64,
+    /// whether workers got pooled connections or opened new ones
+    pub connections: client::ConnectionMetrics,
+}
+
+pub fn take_metrics() -> IoMetrics {
+    let writes = METRICS.writes.swap(0, Ordering::Relaxed);
+    let avg = |total: u64| total.checked_div(writes).unwrap_or(0);
+    IoMetrics {
+        writer_threads: POOL.get().is_some(),
+        writes,
+        avg_queue_us: avg(METRICS.queue_us.swap(0, Ordering::Relaxed)),
+        max_queue_us: METRICS.max_queue_us.swap(0, Ordering::Relaxed),
+        avg_write_us: avg(METRICS.write_us.swap(0, Ordering::Relaxed)),
+        max_runtime_lag_us: METRICS.max_lag_us.swap(0, Ordering::Relaxed),
+        connections: client::take_metrics(),
+    }
+}
+
+/// Tick on the current runtime and record how late each tick wakes up
+pub async fn lag_probe(period: Duration) {
+    loop {
+        let started = Instant::now();
+        tokio::time::sleep(period).await;
+        let lag = started.elapsed().saturating_sub(period);
+        METRICS
+            .max_lag_us
+            .fetch_max(lag.as_micros() as u64, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,174 @@
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
+use url::Url;
+
+use super::category;
+use crate::database::Download;
+
+/// Bigger documents are read up to here
+const MAX_READ: u64 = 64 << 20;
+/// extensions of web pages rather than files
+const PAGES: &[&str] = &["html", "htm", "xhtml", "shtml", "php", "asp", "aspx"];
+
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap()
+});
+static BASE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<base\b[^>]*\bhref\s*=\s*["']([^"']+)["']"#).unwrap());
+static BARE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s"'<>()\[\]{}\\]+"#).unwrap());
+static PDF_URI: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"/URI\s*\(((?:\\.|[^\\)])*)\)").unwrap());
+static PDF_STREAM: LazyLock<regex::bytes::Regex> = LazyLock::new(|| {
+    regex::bytes::Regex::new(r"(?s-u)/FlateDecode.*?stream\r?\n(.*?)endstream").unwrap()
+});
+
+enum Kind {
+    Html,
+    Pdf,
+}
+
+/// Urls in a finished HTML or PDF download, grouped by the category their filename
+/// falls in, or "page" for web pages. Relative links are resolved against the page,
+/// each url shows up once.
+pub fn extract(download: &Download) -> Result<BTreeMap<&'static str, Vec<String>>, String> {
+    if !download.is_completed() {
+        return Err("Only finished downloads can be scanned for links".into());
+    }
+    if download.options.encrypt {
+        return Err("Decrypt the download before scanning it for links".into());
+    }
+    let path = Path::new(&download.destination);
+    let mut data = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(MAX_READ).read_to_end(&mut data))
+        .map_err(|e| e.to_string())?;
+
+    let base = Url::parse(&download.url).ok();
+    let found = match kind(download, &data) {
+        Some(Kind::Html) => html(&String::from_utf8_lossy(&data), base),
+        Some(Kind::Pdf) => pdf(&data),
+        None => return Err("Only HTML and PDF files have links to extract".into()),
+    };
+
+    let mut seen = HashSet::new();
+    let mut groups: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+    for url in found {
+        if !matches!(url.scheme(), "http" | "https" | "ftp") || !seen.insert(url.clone()) {
+            continue;
+        }
+        let name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default();
+        let extension = Path::new(name)
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let group = match extension.as_deref() {
+            None => "page",
+            Some(ext) if PAGES.contains(&ext) => "page",
+            Some(_) => category::category(name, None),
+        };
+        groups.entry(group).or_default().push(url.to_string());
+    }
+    Ok(groups)
+}
+
+fn kind(download: &Download, data: &[u8]) -> Option<Kind> {
+    if data.starts_with(b"%PDF") {
+        return Some(Kind::Pdf);
+    }
+    let content_type = download.content_type.as_deref().unwrap_or_default();
+    let extension = Path::new(&download.destination)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let html = content_type.contains("html") || PAGES.contains(&extension.as_str());
+    html.then_some(Kind::Html)
+}
+
+/// href and src attributes against <base> or the page url, then urls in the text
+pub fn html(text: &str, page: Option<Url>) -> Vec<Url> {
+    let base = BASE
+        .captures(text)
+        .and_then(|c| match &page {
+            Some(page) => page.join(&unescape_html(&c[1])).ok(),
+            None => Url::parse(&unescape_html(&c[1])).ok(),
+        })
+        .or(page);
+    let attributes = ATTRIBUTE.captures_iter(text).filter_map(|c| {
+        let value = c.get(1).or(c.get(2)).or(c.get(3))?.as_str();
+        let value = unescape_html(value.trim());
+        match &base {
+            Some(base) => base.join(&value).ok(),
+            None => Url::parse(&value).ok(),
+        }
+    });
+    let bare = BARE
+        .find_iter(text)
+        .filter_map(|m| Url::parse(&unescape_html(trim_end(m.as_str()))).ok());
+    attributes.chain(bare).collect()
+}
+
+// /URI actions of link annotations, then urls in the text, in plain and deflated streams
+fn pdf(data: &[u8]) -> Vec<Url> {
+    let mut texts = vec![String::from_utf8_lossy(data).into_owned()];
+    for stream in PDF_STREAM.captures_iter(data) {
+        let mut inflated = Vec::new();
+        let mut decoder = flate2::read::ZlibDecoder::new(&stream[1]).take(MAX_READ);
+        // streams often end early or carry a trailing newline, keep what came out
+        let _ = decoder.read_to_end(&mut inflated);
+        if !inflated.is_empty() {
+            texts.push(String::from_utf8_lossy(&inflated).into_owned());
+        }
+    }
+
+    let mut urls = Vec::new();
+    for text in &texts {
+        for c in PDF_URI.captures_iter(text) {
+            if let Ok(url) = Url::parse(&unescape_pdf(&c[1])) {
+                urls.push(url);
+            }
+        }
+        // what's left once the /URI strings are out, so they aren't found twice
+        let rest = PDF_URI.replace_all(text, "");
+        urls.extend(
+            BARE.find_iter(&rest)
+                .filter_map(|m| Url::parse(trim_end(m.as_str())).ok()),
+        );
+    }
+    urls
+}
+
+// sentence punctuation right after a url in running text isn't part of it
+fn trim_end(url: &str) -> &str {
+    url.trim_end_matches(['.', ',', ';', ':', '!', '?'])
+}
+
+fn unescape_html(value: &str) -> String {
+    value
+        .replace("&amp;", "&")
+        .replace("&#38;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+}
+
+// backslash escapes in a PDF literal string
+fn unescape_pdf(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
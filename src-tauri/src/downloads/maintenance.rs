@@ -0,0 +1,107 @@
+use serde::Serialize;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use super::manager::DownloadManager;
+use crate::database::ActivityFilter;
+use crate::i18n;
+use crate::notifications;
+
+/// How often the task looks at whether the app is idle
+const CHECK_EVERY: Duration = Duration::from_secs(5 * 60);
+/// Nothing running or waiting for this long counts as idle
+const IDLE_FOR: Duration = Duration::from_secs(15 * 60);
+/// Seconds between two idle runs, counted from the last logged one
+const RUN_EVERY: i64 = 24 * 60 * 60;
+
+/// What a maintenance run did to the history database
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Maintenance {
+    /// bytes on disk, write-ahead log included
+    pub size_before: u64,
+    pub size_after: u64,
+    /// false when `integrity_check` found damage, `problems` says where
+    pub ok: bool,
+    pub problems: Vec<String>,
+    pub took_ms: u64,
+}
+
+/// Check and compact the database, logged as `db_maintenance`. Damage found is also
+/// notified, it isn't repaired.
+pub fn run(app: &AppHandle) -> Result<Maintenance, String> {
+    let manager = app.state::<DownloadManager>();
+    let started = Instant::now();
+    let size_before = manager.db().disk_size();
+    let integrity = manager.db().maintain().map_err(|e| e.to_string())?;
+    let ok = integrity.iter().all(|line| line == "ok");
+    let report = Maintenance {
+        size_before,
+        size_after: manager.db().disk_size(),
+        ok,
+        problems: if ok { Vec::new() } else { integrity },
+        took_ms: started.elapsed().as_millis() as u64,
+    };
+    manager.log_activity(
+        "db_maintenance",
+        None,
+        json!({
+            "size_before": report.size_before,
+            "size_after": report.size_after,
+            "ok": report.ok,
+            "problems": report.problems.len(),
+        }),
+    );
+    if !report.ok {
+        eprintln!("Database integrity check failed: {:?}", report.problems);
+        notifications::notify(
+            app,
+            "database_damaged",
+            None,
+            i18n::t("notify.database_damaged"),
+        );
+    }
+    Ok(report)
+}
+
+/// Run maintenance once a day, when no download has been running or waiting for
+/// `IDLE_FOR`, so the database lock it holds never stalls one
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut idle_since: Option<Instant> = None;
+        let mut interval = tokio::time::interval(CHECK_EVERY);
+        loop {
+            interval.tick().await;
+            if !app.state::<DownloadManager>().is_idle() {
+                idle_since = None;
+                continue;
+            }
+            let since = *idle_since.get_or_insert_with(Instant::now);
+            if since.elapsed() < IDLE_FOR || !due(&app) {
+                continue;
+            }
+            let app = app.clone();
+            let done = tokio::task::spawn_blocking(move || run(&app)).await;
+            if let Ok(Err(e)) = done {
+                eprintln!("Failed to run database maintenance: {}", e);
+            }
+        }
+    });
+}
+
+// no run logged in the last `RUN_EVERY`
+fn due(app: &AppHandle) -> bool {
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let filter = ActivityFilter {
+        kinds: vec!["db_maintenance".into()],
+        since: Some(now - RUN_EVERY),
+        limit: Some(1),
+        ..Default::default()
+    };
+    let manager = app.state::<DownloadManager>();
+    manager
+        .db()
+        .get_activity(&filter)
+        .is_ok_and(|runs| runs.is_empty())
+}
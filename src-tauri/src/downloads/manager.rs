@@ -1,11 +1,53 @@
-use std::sync::Mutex;
-use tauri::Manager;
-use tokio::task::JoinSet;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::watch;
+use url::Url;
+use uuid::Uuid;
 
-#[cfg(unix)]
-use tokio::signal::{self, unix::SignalKind};
 
-use crate::database::Database;
+use super::archive;
+use super::backoff::HostBackoff;
+use super::category;
+use super::cleanup;
+use super::client::{self, RateLimit};
+use super::core::{self, Download, Segment, Strategy, Transfer, WorkerEvent, WorkerState};
+use super::crypt;
+use super::dedup;
+use super::fair;
+use super::origin;
+use super::permissions;
+use super::probe;
+use super::process::{self, Children};
+use super::quarantine;
+use super::robots::RobotsCache;
+use super::s3;
+use super::speed::{SpeedHistory, SpeedSeries};
+use super::stats;
+use super::units::{self, Units};
+use super::thumbnail;
+use super::zsync;
+use super::{DownloadOptions, DownloadRequest};
+use crate::args::AppArgs;
+use crate::database::{self, Database};
+use crate::events::{
+    DownloadDebug, DownloadFinished, DownloadProgress, RetryScheduled, SizeMismatch,
+};
+use crate::i18n;
+use crate::notifications;
+use crate::scripting::{self, Hook};
+use crate::settings;
+use crate::signals::Signals;
+use crate::uploads::{self, UploadState};
+use crate::webhooks;
+
 enum _ControlCommand {
     Resume,
     Pause,
@@ -13,49 +55,1522 @@ enum _ControlCommand {
     SpeedLimit(usize),
 }
 
+/// How long quitting waits for running downloads to save their ranges
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A running download as the manager sees it
+pub struct Instance {
+    pub transfer: Arc<Transfer>,
+    pub size: Option<u64>,
+    pub speed: Mutex<SpeedHistory>,
+    pub category: &'static str,
+    /// lowercase, what `thread.max_concurrent_per_host` counts by
+    pub host: String,
+    /// what the client reads through, `set_speed_limit` changes it in place
+    pub limit: Arc<RateLimit>,
+    /// its part of the link next to other downloads, see `fair`
+    pub weight: u64,
+    cancelled: AtomicBool,
+    /// what the cancel asked for the file: keep it with its ranges, discard both, or
+    /// None for `download.on_cancel`
+    keep_partial: Mutex<Option<bool>>,
+    /// flips to true once the drive task has saved state and emitted its final event
+    done: watch::Sender<bool>,
+}
+
+impl Instance {
+    /// What the server actually has, once it turned out not to be what HEAD said. Unknown
+    /// for a body compressed on the fly, its length isn't the file's.
+    pub fn size(&self) -> Option<u64> {
+        if self.transfer.is_encoded() {
+            return None;
+        }
+        self.transfer.resized().or(self.size)
+    }
+
+    /// Bytes left, None while the size is unknown
+    pub fn remaining(&self) -> Option<u64> {
+        self.size().map(|s| s.saturating_sub(self.transfer.downloaded()))
+    }
+
+    /// Resolves once the download stopped for whatever reason
+    pub async fn finished(&self) {
+        let _ = self.done.subscribe().wait_for(|done| *done).await;
+    }
+
+    /// What `download_progress` carries, raw numbers for the frontend and text in the
+    /// user's units for everything else
+    pub fn progress(&self, id: Uuid, units: &Units) -> DownloadProgress {
+        let speed = self.speed.lock().unwrap().current();
+        let eta = self
+            .remaining()
+            .and_then(|remaining| remaining.checked_div(speed));
+        DownloadProgress {
+            id,
+            downloaded: self.transfer.downloaded(),
+            size: self.size(),
+            speed,
+            eta,
+            speed_text: units.speed(speed),
+            eta_text: eta.map(units::eta),
+        }
+    }
+}
+
+/// Totals over every running download
+#[derive(Debug, Default, Serialize, specta::Type)]
+pub struct DashboardState {
+    pub active: usize,
+    /// bytes/s over the last second
+    pub speed: u64,
+    pub downloaded: u64,
+    pub remaining: u64,
+    /// downloads that don't report a size, left out of `remaining`
+    pub unknown_size: usize,
+    /// seconds until everything sized is done at the current speed
+    pub eta: Option<u64>,
+    pub categories: BTreeMap<&'static str, CategoryState>,
+}
+
+#[derive(Debug, Default, Serialize, specta::Type)]
+pub struct CategoryState {
+    pub active: usize,
+    pub speed: u64,
+    pub remaining: u64,
+}
+
+/// A stored download with what it last ran with, for the details pane
+#[derive(Debug, Serialize, specta::Type)]
+pub struct DownloadDetails {
+    #[serde(flatten)]
+    pub download: database::Download,
+    /// None until it has been started once
+    pub environment: Option<client::Environment>,
+    /// automatic retries of the whole download so far
+    pub attempts: u32,
+    /// how its next run retries a failing range, from its options and the settings
+    pub retry_policy: client::RangeRetry,
+    /// None unless it's running
+    pub live: Option<LiveState>,
+}
+
+/// Everything the frontend shows, so a reload mid-download doesn't have to rebuild it
+/// from events
+#[derive(Debug, Serialize, specta::Type)]
+pub struct AppState {
+    /// running right now, oldest first
+    pub active: Vec<ActiveDownload>,
+    /// stored as in progress but not running, and failed ones with a retry scheduled
+    pub queued: Vec<database::Download>,
+    pub history: HistoryPage,
+    pub dashboard: DashboardState,
+    pub settings: settings::AppSettings,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct ActiveDownload {
+    #[serde(flatten)]
+    pub download: database::Download,
+    /// the last `download_progress` as of now
+    pub progress: DownloadProgress,
+}
+
+/// Newest first
+#[derive(Debug, Serialize, specta::Type)]
+pub struct HistoryPage {
+    pub downloads: Vec<database::Download>,
+    /// finished, failed and paused downloads in all, for paging
+    pub total: i64,
+}
+
+/// What the engine is doing right now
+#[derive(Debug, Serialize, specta::Type)]
+pub struct LiveState {
+    pub downloaded: u64,
+    /// bytes/s over the last second
+    pub speed: u64,
+    /// after redirects, None before the first response
+    pub url: Option<String>,
+    /// empty for single connection downloads
+    pub segments: Vec<Segment>,
+    pub workers: Vec<WorkerState>,
+    /// bytes/s it's capped at, 0 is unlimited
+    pub speed_limit: u64,
+}
+
+/// How `retry_failed` put each download back in
+#[derive(Debug, Default, Serialize, specta::Type)]
+pub struct RetrySummary {
+    /// continuing from their partial data
+    pub resumed: Vec<Uuid>,
+    /// changed on the server or the partial file is gone, started over
+    pub restarted: Vec<Uuid>,
+    /// not failed, or the server couldn't be reached
+    pub skipped: Vec<Uuid>,
+}
+
 //  TODO tauri store read to memory and push new changes design
 pub struct DownloadManager {
     db: Database, // if it's needed or not, check with our db implementation
-    instances: Mutex<JoinSet<()>>, // uuid ain't needed if joinset auto drop on finish
+    instances: Mutex<HashMap<Uuid, Arc<Instance>>>,
+    /// held back by the per host cap, oldest first, with whether to resume
+    waiting: Mutex<VecDeque<(Uuid, bool)>>,
+    backoff: HostBackoff,
+    robots: RobotsCache,
+    retries: Mutex<HashMap<Uuid, ScheduledRetry>>,
+    shutting_down: AtomicBool,
+    children: Children,
+    /// bumped whenever a download stops running or loses its pending retry, `wait` checks again
+    settled: watch::Sender<u64>,
+    /// links to private addresses waiting for the user, by the token they were announced with
+    held: Mutex<HashMap<Uuid, DownloadRequest>>,
+}
+
+/// Automatic retries so far and the timer for the next one
+struct ScheduledRetry {
+    attempts: u32,
+    timer: Option<tauri::async_runtime::JoinHandle<()>>,
 }
 
 impl DownloadManager {
     pub fn new(app_handle: &tauri::AppHandle) -> anyhow::Result<Self> {
-        let db_path = app_handle.path().app_data_dir()?.join("tur.db");
+        let data_dir = crate::profiles::data_dir(app_handle).map_err(anyhow::Error::msg)?;
         Ok(Self {
-            db: Database::new(&db_path)?,
-            instances: Mutex::new(JoinSet::new()),
+            db: Database::new(&data_dir.join("tur.db"))?,
+            instances: Mutex::new(HashMap::new()),
+            waiting: Mutex::new(VecDeque::new()),
+            backoff: HostBackoff::default(),
+            robots: RobotsCache::default(),
+            retries: Mutex::new(HashMap::new()),
+            shutting_down: AtomicBool::new(false),
+            children: Children::default(),
+            settled: watch::channel(0).0,
+            held: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    /// Commands started for finished downloads that are still running
+    pub fn children(&self) -> &Children {
+        &self.children
+    }
+
+    /// robots.txt of every host a batch went to
+    pub fn robots(&self) -> &RobotsCache {
+        &self.robots
+    }
+
+    /// Keep `request` until the user confirms it, with the token that does
+    pub fn hold(&self, request: DownloadRequest) -> Uuid {
+        let mut token = [0u8; 16];
+        OsRng.fill_bytes(&mut token);
+        let token = Uuid::from_bytes(token);
+        self.held.lock().unwrap().insert(token, request);
+        token
+    }
+
+    /// The request held under `token`, only once
+    pub fn take_held(&self, token: &Uuid) -> Option<DownloadRequest> {
+        self.held.lock().unwrap().remove(token)
+    }
+
+    pub fn instance(&self, id: &Uuid) -> Option<Arc<Instance>> {
+        self.instances.lock().unwrap().get(id).cloned()
+    }
+
+    /// Nothing running and nothing waiting on a host
+    pub fn is_idle(&self) -> bool {
+        self.instances.lock().unwrap().is_empty() && self.waiting.lock().unwrap().is_empty()
+    }
+
+    /// What the bandwidth balancer works on, one per running download
+    pub fn flows(&self) -> Vec<fair::Flow> {
+        self.instances
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, instance)| fair::Flow {
+                id: *id,
+                weight: instance.weight,
+                limit: instance.limit.clone(),
+                transfer: instance.transfer.clone(),
+            })
+            .collect()
+    }
+
+    /// Run the stored download `id`, picking up saved ranges when `resume` is set. With
+    /// its host at `thread.max_concurrent_per_host` it waits for one of those to stop.
+    pub fn start(&self, app: &AppHandle, id: &Uuid, resume: bool) -> Result<(), String> {
+        if self.shutting_down.load(Ordering::Acquire) {
+            return Err("Shutting down".into());
+        }
+        let record = self
+            .db
+            .get_download_by_id(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Download {} not found", id))?;
+        let host = Url::parse(&record.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+            .unwrap_or_default();
+        let per_host = settings::load_or_create(app).thread.max_concurrent_per_host as usize;
+        // over the monthly cap only priority downloads go, the rest wait for the user
+        if stats::cap_reached() && !record.options.priority && !record.is_upload() {
+            if let Err(e) = self.db.update_status(id, Some("paused")) {
+                eprintln!("Failed to update status: {}", e);
+            }
+            self.log_activity("cap_paused", Some(id), json!({}));
+            return Ok(());
+        }
+
+        let instance = {
+            let mut instances = self.instances.lock().unwrap();
+            if instances.contains_key(id) {
+                return Ok(()); // already running
+            }
+            let on_host = instances.values().filter(|i| i.host == host).count();
+            if per_host > 0 && on_host >= per_host {
+                let mut waiting = self.waiting.lock().unwrap();
+                if !waiting.iter().any(|(w, _)| w == id) {
+                    waiting.push_back((*id, resume));
+                    drop(waiting);
+                    if let Err(e) = self.db.update_status(id, None) {
+                        eprintln!("Failed to update status: {}", e);
+                    }
+                    self.log_activity("waiting", Some(id), json!({ "host": host }));
+                }
+                return Ok(());
+            }
+            self.waiting.lock().unwrap().retain(|(w, _)| w != id);
+            let instance = Arc::new(Instance {
+                transfer: Arc::new(Transfer::new(0)),
+                size: record.size.map(|s| s as u64),
+                speed: Mutex::new(SpeedHistory::default()),
+                category: record.options.category().unwrap_or_else(|| {
+                    category::category(&record.filename, record.content_type.as_deref())
+                }),
+                host,
+                limit: Arc::new(RateLimit::new(0)),
+                weight: if record.is_upload() {
+                    0
+                } else if record.options.priority {
+                    fair::PRIORITY_WEIGHT
+                } else {
+                    1
+                },
+                cancelled: AtomicBool::new(false),
+                keep_partial: Mutex::new(None),
+                done: watch::channel(false).0,
+            });
+            instances.insert(*id, instance.clone());
+            instance
+        };
+        if let Err(e) = self.db.update_status(id, None) {
+            eprintln!("Failed to update status: {}", e);
+        }
+        // where it picks up, so the journal counts only what this run receives
+        let from = if resume { record.bytes_received } else { 0 };
+        self.log_activity("started", Some(id), json!({ "resume": resume, "from": from }));
+
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let manager = app.state::<DownloadManager>();
+            manager.drive(&app, record, instance, resume).await;
+        });
+        Ok(())
+    }
+
+    /// Start the oldest download waiting on `host`, once one of its downloads stopped
+    fn promote(&self, app: &AppHandle, host: &str) {
+        let next = {
+            let mut waiting = self.waiting.lock().unwrap();
+            let at = waiting.iter().position(|(id, _)| {
+                matches!(
+                    self.db.get_download_by_id(id),
+                    Ok(Some(record)) if Url::parse(&record.url)
+                        .is_ok_and(|url| url.host_str().is_some_and(|h| h.eq_ignore_ascii_case(host)))
+                )
+            });
+            at.and_then(|at| waiting.remove(at))
+        };
+        if let Some((id, resume)) = next {
+            if let Err(e) = self.start(app, &id, resume) {
+                eprintln!("Failed to start {}: {}", id, e);
+            }
+        }
+    }
+
+    // drop `id` from the per host queue, stored as `status`, false when it wasn't waiting
+    fn unqueue(&self, id: &Uuid, status: &str) -> bool {
+        let mut waiting = self.waiting.lock().unwrap();
+        let before = waiting.len();
+        waiting.retain(|(w, _)| w != id);
+        if waiting.len() == before {
+            return false;
+        }
+        drop(waiting);
+        if let Err(e) = self.db.update_status(id, Some(status)) {
+            eprintln!("Failed to update status: {}", e);
+        }
+        true
+    }
+
+    /// Append to the activity timeline, a failed write only gets logged
+    pub fn log_activity(&self, kind: &str, id: Option<&Uuid>, detail: serde_json::Value) {
+        if let Err(e) = self.db.log_activity(kind, id, &detail) {
+            eprintln!("Failed to log {} activity: {}", kind, e);
+        }
+    }
+
+    /// Subfolder of `base` for one batch, `tur-<date>-<name>` with a number added on collision
+    pub fn create_job(&self, base: &Path, name: Option<&str>) -> Result<(Uuid, PathBuf), String> {
+        let today = time::OffsetDateTime::now_utc().date();
+        let name = name
+            .map(|n| n.trim().replace(['/', '\\', ':'], "_"))
+            .filter(|n| !n.is_empty() && n != "." && n != "..")
+            .unwrap_or_else(|| "batch".into());
+        let stem = format!(
+            "tur-{}-{:02}-{:02}-{}",
+            today.year(),
+            today.month() as u8,
+            today.day(),
+            name
+        );
+
+        std::fs::create_dir_all(base).map_err(|e| e.to_string())?;
+        let mut folder = base.join(&stem);
+        let mut n = 1;
+        // create_dir fails on existing folders, so two batches never share one
+        while let Err(e) = std::fs::create_dir(&folder) {
+            if e.kind() != std::io::ErrorKind::AlreadyExists {
+                return Err(e.to_string());
+            }
+            n += 1;
+            folder = base.join(format!("{}-{}", stem, n));
+        }
+
+        let id = Uuid::now_v7();
+        self.db
+            .insert_job(&id, &name, &folder.to_string_lossy())
+            .map_err(|e| e.to_string())?;
+        Ok((id, folder))
+    }
+
+    /// Stop workers, ranges left are saved for a later resume
+    pub fn pause(&self, id: &Uuid) -> bool {
+        self.clear_retry(id);
+        if self.unqueue(id, "paused") {
+            return true;
+        }
+        match self.instance(id) {
+            Some(instance) => {
+                instance.transfer.stop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cap `id` at `limit` KiB/s, None goes back to the global limit. It's kept with the
+    /// download's options so it holds over pauses and restarts, a running one slows down
+    /// right away.
+    pub fn set_speed_limit(
+        &self,
+        app: &AppHandle,
+        id: &Uuid,
+        limit: Option<u64>,
+    ) -> Result<(), String> {
+        let mut record = self
+            .db
+            .get_download_by_id(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Download {} not found", id))?;
+        record.options.speed_limit = limit;
+        self.db
+            .set_options(id, &record.options)
+            .map_err(|e| e.to_string())?;
+        if let Some(instance) = self.instance(id) {
+            let global = settings::load_or_create(app).download.speed_limit;
+            instance.limit.set(limit.unwrap_or(global) * 1024);
+        }
+        self.log_activity("speed_limit", Some(id), json!({ "limit": limit }));
+        Ok(())
+    }
+
+    /// Pause every running download that isn't a priority one or an upload, for the
+    /// monthly cap. Returns how many stopped.
+    pub fn pause_unprioritized(&self) -> usize {
+        let running: Vec<Uuid> = self.instances.lock().unwrap().keys().copied().collect();
+        let mut paused = 0;
+        for id in running {
+            let skip = matches!(
+                self.db.get_download_by_id(&id),
+                Ok(Some(record)) if record.options.priority || record.is_upload()
+            );
+            if !skip && self.pause(&id) {
+                paused += 1;
+            }
+        }
+        // waiting ones would start as soon as a slot frees up
+        let waiting: Vec<Uuid> = self.waiting.lock().unwrap().iter().map(|(id, _)| *id).collect();
+        for id in waiting {
+            let skip = matches!(
+                self.db.get_download_by_id(&id),
+                Ok(Some(record)) if record.options.priority
+            );
+            if !skip && self.unqueue(&id, "paused") {
+                paused += 1;
+            }
+        }
+        paused
+    }
+
+    /// Stop workers, then keep the file with its saved ranges for a later resume, discard
+    /// both, or leave it to `download.on_cancel` when `keep_partial` is None
+    pub fn cancel(&self, id: &Uuid, keep_partial: Option<bool>) -> bool {
+        self.clear_retry(id);
+        if self.unqueue(id, "cancelled") {
+            return true;
+        }
+        match self.instance(id) {
+            Some(instance) => {
+                *instance.keep_partial.lock().unwrap() = keep_partial;
+                instance.cancelled.store(true, Ordering::Release);
+                instance.transfer.stop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// An automatic retry of `id` is waiting on its timer
+    pub fn retry_pending(&self, id: &Uuid) -> bool {
+        self.retries
+            .lock()
+            .unwrap()
+            .get(id)
+            .is_some_and(|retry| retry.timer.is_some())
+    }
+
+    /// Forget the attempt count and drop a pending retry
+    pub fn clear_retry(&self, id: &Uuid) {
+        if let Some(retry) = self.retries.lock().unwrap().remove(id) {
+            if let Some(timer) = retry.timer {
+                timer.abort();
+                self.settled.send_modify(|n| *n += 1);
+            }
+        }
+    }
+
+    /// Resolve with the stored row once `id` completed, was cancelled, or failed without a retry
+    /// pending. Paused downloads keep it waiting until they're resumed and finish.
+    pub async fn wait(&self, id: &Uuid) -> Result<database::Download, String> {
+        let mut settled = self.settled.subscribe();
+        loop {
+            settled.borrow_and_update();
+            if let Some(record) = self.finished(id)? {
+                return Ok(record);
+            }
+            settled.changed().await.map_err(|e| e.to_string())?;
+        }
+    }
+
+    // the row when `id` is done for good, None while it runs, waits for a retry or is paused
+    fn finished(&self, id: &Uuid) -> Result<Option<database::Download>, String> {
+        if self.instance(id).is_some() {
+            return Ok(None);
+        }
+        let record = self
+            .db
+            .get_download_by_id(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Download {} not found", id))?;
+        let retrying = self
+            .retries
+            .lock()
+            .unwrap()
+            .get(id)
+            .is_some_and(|r| r.timer.is_some());
+        Ok(match record.status.as_deref() {
+            Some("completed") | Some("cancelled") => Some(record),
+            Some("failed") if !retrying => Some(record),
+            _ => None,
         })
     }
 
+    // failed download: come back after the policy's delay until attempts run out
+    fn schedule_retry(&self, app: &AppHandle, record: &database::Download) {
+        let settings = settings::load_or_create(app);
+        let policy = record.options.retry.as_ref().unwrap_or(&settings.retry);
+        let mut retries = self.retries.lock().unwrap();
+        let retry = retries.entry(record.id).or_insert(ScheduledRetry {
+            attempts: 0,
+            timer: None,
+        });
+        if !policy.enabled || retry.attempts >= policy.max_attempts {
+            retries.remove(&record.id);
+            return;
+        }
+        retry.attempts += 1;
+
+        let id = record.id;
+        let delay = Duration::from_secs(policy.delay_minutes as u64 * 60);
+        let retry_at = std::time::SystemTime::now() + delay;
+        let payload = RetryScheduled {
+            id,
+            attempt: retry.attempts,
+            max_attempts: policy.max_attempts,
+            retry_in: delay.as_secs(),
+            retry_at: retry_at
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        };
+        if let Err(e) = app.emit("download_retry_scheduled", payload) {
+            eprintln!("Failed to emit download_retry_scheduled event: {}", e);
+        }
+
+        let app = app.clone();
+        retry.timer = Some(tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let manager = app.state::<DownloadManager>();
+            // started before the timer is cleared, so `wait` never sees a failed download
+            // with nothing pending in between
+            if let Err(e) = manager.start(&app, &id, true) {
+                eprintln!("Scheduled retry of {} failed: {}", id, e);
+            }
+            if let Some(retry) = manager.retries.lock().unwrap().get_mut(&id) {
+                retry.timer = None;
+            }
+            manager.settled.send_modify(|n| *n += 1);
+        }));
+    }
+
+    /// Live samples for running downloads, the stored series otherwise
+    pub fn speed_history(&self, id: &Uuid) -> Result<SpeedSeries, String> {
+        if let Some(instance) = self.instance(id) {
+            return Ok(instance.speed.lock().unwrap().recent());
+        }
+        Ok(self
+            .db
+            .get_speed_history(id)
+            .map_err(|e| e.to_string())?
+            .map(|(interval, samples)| SpeedSeries { interval, samples })
+            .unwrap_or_default())
+    }
+
+    /// Pause everything running, wait for the saved state, then resume it all.
+    /// Clients are built per run, so the resumed downloads pick up new settings.
+    pub async fn reload(&self, app: &AppHandle) -> Vec<Uuid> {
+        let running = self.stop_all();
+        let mut resumed = Vec::new();
+        for (id, instance) in running {
+            instance.finished().await;
+            // finished, failed or cancelled while draining, leave those alone
+            let paused = matches!(
+                self.db.get_download_by_id(&id),
+                Ok(Some(record)) if record.status.as_deref() == Some("paused")
+            );
+            if !paused {
+                continue;
+            }
+            match self.start(app, &id, true) {
+                Ok(()) => resumed.push(id),
+                Err(e) => eprintln!("Failed to resume {} after reload: {}", id, e),
+            }
+        }
+        resumed
+    }
+
+    // stop every worker with pause semantics, ranges get saved as the drive tasks wind down
+    fn stop_all(&self) -> Vec<(Uuid, Arc<Instance>)> {
+        let running: Vec<(Uuid, Arc<Instance>)> = self
+            .instances
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, instance)| (*id, instance.clone()))
+            .collect();
+        for (_, instance) in &running {
+            instance.transfer.stop();
+        }
+        running
+    }
+
     // replace shutdown_all() with Drop trait
-    async fn _start_signal_handler(&self) {
-        #[cfg(unix)]
-        {
-            let mut _sigterm = signal::unix::signal(SignalKind::terminate()).unwrap();
-            // let mut sigint = signal::unix::signal(SignalKind::interrupt()).unwrap();
+    /// Pause everything and wait until each download saved its state, at most `timeout`.
+    /// False when some were still going when time ran out.
+    pub async fn shutdown_all(&self, timeout: Duration) -> bool {
+        for (_, retry) in self.retries.lock().unwrap().drain() {
+            if let Some(timer) = retry.timer {
+                timer.abort();
+            }
+        }
+        let running = self.stop_all();
+        let drained = futures_util::future::join_all(
+            running.iter().map(|(_, instance)| instance.finished()),
+        );
+        tokio::time::timeout(timeout, drained).await.is_ok()
+    }
+
+    /// Quit without losing progress: record the session, drain, then exit for real.
+    /// False when a shutdown is already underway.
+    pub fn shutdown(&self, app: &AppHandle, restart: bool) -> bool {
+        if self.shutting_down.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.save_session();
+        if settings::load_or_create(app).download.stop_commands_on_quit {
+            self.children.terminate_all();
+        }
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let manager = app.state::<DownloadManager>();
+            if !manager.shutdown_all(SHUTDOWN_TIMEOUT).await {
+                eprintln!("Some downloads didn't save their state before quitting");
+            }
+            if restart {
+                app.request_restart();
+            } else {
+                app.exit(0);
+            }
+        });
+        true
+    }
+
+    /// Remember what's running at quit, the next launch offers to pick it up again.
+    /// Unlike the rest of the unfinished history, these were running when the user left.
+    pub fn save_session(&self) {
+        let mut running: Vec<Uuid> = self.instances.lock().unwrap().keys().copied().collect();
+        running.extend(self.waiting.lock().unwrap().iter().map(|(id, _)| *id));
+        if let Err(e) = self.db.save_session(&running) {
+            eprintln!("Failed to save session: {}", e);
+        }
+    }
+
+    /// Downloads of the last session that still aren't done
+    pub fn pending_session(&self) -> Vec<Uuid> {
+        let ids = match self.db.get_session() {
+            Ok(ids) => ids,
+            Err(e) => {
+                eprintln!("Failed to read session: {}", e);
+                return Vec::new();
+            }
+        };
+        ids.into_iter()
+            .filter(|id| {
+                matches!(
+                    self.db.get_download_by_id(id),
+                    Ok(Some(record)) if !record.is_completed()
+                )
+            })
+            .collect()
+    }
+
+    /// Resume the last session and forget it
+    pub fn restore_session(&self, app: &AppHandle) -> Result<Vec<Uuid>, String> {
+        let mut resumed = Vec::new();
+        for id in self.pending_session() {
+            match self.start(app, &id, true) {
+                Ok(()) => resumed.push(id),
+                Err(e) => eprintln!("Failed to restore {}: {}", id, e),
+            }
+        }
+        self.db.save_session(&[]).map_err(|e| e.to_string())?;
+        Ok(resumed)
+    }
+
+    /// Probe failed downloads again, `ids` or every failed one, and queue them. Partial data
+    /// is picked up while the validators still match, otherwise they start over. Cancelled
+    /// ones were stopped on purpose and are skipped, named in `ids` or not.
+    pub async fn retry_failed(
+        &self,
+        app: &AppHandle,
+        ids: Option<Vec<Uuid>>,
+    ) -> Result<RetrySummary, String> {
+        let records = match ids {
+            Some(ids) => ids
+                .into_iter()
+                .map(|id| (id, self.db.get_download_by_id(&id).ok().flatten()))
+                .collect(),
+            None => self
+                .db
+                .get_downloads_by_status(Some("failed"))
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|record| (record.id, Some(record)))
+                .collect::<Vec<_>>(),
+        };
+        let settings = settings::load_or_create(app);
+        let mut summary = RetrySummary::default();
+        for (id, record) in records {
+            let record = match record {
+                Some(record) if record.status.as_deref() == Some("failed") => record,
+                _ => {
+                    summary.skipped.push(id);
+                    continue;
+                }
+            };
+            self.clear_retry(&id);
+
+            // uploads keep their own state, the server tells how far they got
+            let restart = if record.is_upload() {
+                false
+            } else {
+                let remote = match Url::parse(&record.url) {
+                    Ok(url) => match client::for_target(&settings, &url, &record.options) {
+                        Ok((target, client)) => probe::probe(&client, &target).await,
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                let remote = match remote {
+                    Ok(remote) => remote,
+                    Err(e) => {
+                        eprintln!("Failed to fetch headers for {}: {}", record.url, e);
+                        summary.skipped.push(id);
+                        continue;
+                    }
+                };
+                let exists = Path::new(&record.destination).exists();
+                let restart =
+                    probe::needs_restart(&record, &remote, exists) || !remote.resume_supported;
+                if restart {
+                    let reset = self
+                        .db
+                        .update_headers(
+                            &id,
+                            remote.size,
+                            remote.content_type.as_deref(),
+                            remote.etag.as_deref(),
+                            remote.last_modified.as_deref(),
+                            remote.resume_supported,
+                        )
+                        .and_then(|_| self.db.update_progress(&id, 0));
+                    if let Err(e) = reset {
+                        eprintln!("Failed to reset {}: {}", id, e);
+                        summary.skipped.push(id);
+                        continue;
+                    }
+                }
+                restart
+            };
+
+            match self.start(app, &id, !restart) {
+                Ok(()) if restart => summary.restarted.push(id),
+                Ok(()) => summary.resumed.push(id),
+                Err(e) => {
+                    eprintln!("Failed to retry {}: {}", id, e);
+                    summary.skipped.push(id);
+                }
+            }
+        }
+        self.log_activity(
+            "retried",
+            None,
+            json!({
+                "resumed": summary.resumed.len(),
+                "restarted": summary.restarted.len(),
+                "skipped": summary.skipped.len(),
+            }),
+        );
+        Ok(summary)
+    }
+
+    /// Fetch a finished or failed download once more from the start, into the same file with
+    /// the headers it was added with, for files that change at a fixed url like nightly builds
+    pub async fn download_again(&self, app: &AppHandle, id: &Uuid) -> Result<(), String> {
+        let record = self
+            .db
+            .get_download_by_id(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Download {} not found", id))?;
+        if record.status.is_none() || self.instance(id).is_some() {
+            return Err(format!("{} is still in the queue", record.filename));
+        }
+        if record.is_upload() {
+            return Err("Uploads can't be fetched again".into());
+        }
+        let settings = settings::load_or_create(app);
+        let url = Url::parse(&record.url).map_err(|e| e.to_string())?;
+        let (target, client) = client::for_target(&settings, &url, &record.options)?;
+        let remote = probe::probe(&client, &target).await?;
+        self.clear_retry(id);
+        self.db
+            .update_headers(
+                id,
+                remote.size,
+                remote.content_type.as_deref(),
+                remote.etag.as_deref(),
+                remote.last_modified.as_deref(),
+                remote.resume_supported,
+            )
+            .and_then(|_| self.db.update_progress(id, 0))
+            .and_then(|_| self.db.set_quarantine(id, None))
+            .map_err(|e| e.to_string())?;
+        Download::remove_meta(app, id);
+        self.log_activity(
+            "downloaded_again",
+            Some(id),
+            json!({ "etag": remote.etag, "size": remote.size }),
+        );
+        self.start(app, id, false)
+    }
+
+    /// Row of `id`, the settings snapshot of its last run and the engine's state while it runs
+    pub fn details(&self, app: &AppHandle, id: &Uuid) -> Result<DownloadDetails, String> {
+        let download = self
+            .db
+            .get_download_by_id(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Download {} not found", id))?;
+        let environment = self.db.get_environment(id).map_err(|e| e.to_string())?;
+        let attempts = self
+            .retries
+            .lock()
+            .unwrap()
+            .get(id)
+            .map_or(0, |r| r.attempts);
+        let live = self.instance(id).map(|instance| {
+            let transfer = &instance.transfer;
+            LiveState {
+                downloaded: transfer.downloaded(),
+                speed: instance.speed.lock().unwrap().current(),
+                url: transfer.url().map(String::from),
+                segments: transfer.segments(),
+                workers: transfer.workers(),
+                speed_limit: instance.limit.get(),
+            }
+        });
+        let settings = settings::load_or_create(app);
+        let retry_policy = client::range_retry(&settings, &download.options);
+        Ok(DownloadDetails {
+            download,
+            environment,
+            attempts,
+            retry_policy,
+            live,
+        })
+    }
+
+    /// Aggregate straight from the transfer counters
+    pub fn dashboard(&self) -> DashboardState {
+        let mut state = DashboardState::default();
+        for instance in self.instances.lock().unwrap().values() {
+            let speed = instance.speed.lock().unwrap().current();
+            let remaining = instance.remaining();
+
+            state.active += 1;
+            state.speed += speed;
+            state.downloaded += instance.transfer.downloaded();
+            match remaining {
+                Some(r) => state.remaining += r,
+                None => state.unknown_size += 1,
+            }
+
+            let category = state.categories.entry(instance.category).or_default();
+            category.active += 1;
+            category.speed += speed;
+            category.remaining += remaining.unwrap_or(0);
+        }
+        state.eta = (state.speed > 0).then(|| state.remaining.div_ceil(state.speed));
+        state
+    }
+
+    /// Stored as in progress but not running, and failed ones with a retry scheduled
+    pub fn pending(&self) -> Result<Vec<database::Download>, String> {
+        let mut queued: Vec<database::Download> = self
+            .db
+            .get_downloads_by_status(None)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|d| self.instance(&d.id).is_none())
+            .collect();
+        let retrying: Vec<Uuid> = self
+            .retries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, retry)| retry.timer.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in retrying {
+            if let Some(download) = self.db.get_download_by_id(&id).map_err(|e| e.to_string())? {
+                queued.push(download);
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Running and queued downloads, the first `history` finished ones and the settings
+    pub fn app_state(&self, app: &AppHandle, history: i64) -> Result<AppState, String> {
+        let settings = settings::load_or_create(app);
+        let units = Units::from_config(&settings.app);
+        let mut instances: Vec<(Uuid, Arc<Instance>)> = self
+            .instances
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, instance)| (*id, instance.clone()))
+            .collect();
+        // v7 ids sort by when they were added
+        instances.sort_by_key(|(id, _)| *id);
+
+        let mut active = Vec::new();
+        for (id, instance) in instances {
+            // gone from the database while it runs, nothing to show it with
+            let Some(download) = self.db.get_download_by_id(&id).map_err(|e| e.to_string())? else {
+                continue;
+            };
+            active.push(ActiveDownload {
+                download,
+                progress: instance.progress(id, &units),
+            });
+        }
+
+        let queued = self.pending()?;
+        let (downloads, total) = self
+            .db
+            .get_history(history, 0)
+            .map_err(|e| e.to_string())?;
+        Ok(AppState {
+            active,
+            queued,
+            history: HistoryPage { downloads, total },
+            dashboard: self.dashboard(),
+            settings,
+        })
+    }
 
-            // TODO select! issue
-            // tokio::select! {
-            //     _ = signal::ctrl_c() => {
-            //         self.shutdown_all();
-            //     },
-            //     _ = sigterm.recv() => {
-            //         self.shutdown_all();
-            //     },
-            // }
+    async fn drive(
+        &self,
+        app: &AppHandle,
+        mut record: database::Download,
+        instance: Arc<Instance>,
+        resume: bool,
+    ) {
+        let id = record.id;
+        let transfer = instance.transfer.clone();
+        let result = self
+            .run_engine(app, &record, &transfer, &instance.limit, resume)
+            .await;
+
+        let idle = {
+            let mut instances = self.instances.lock().unwrap();
+            instances.remove(&id);
+            instances.is_empty() && self.waiting.lock().unwrap().is_empty()
+        };
+        self.promote(app, &instance.host);
+        let downloaded = transfer.downloaded() as i64;
+        if let Err(e) = self.db.update_progress(&id, downloaded) {
+            eprintln!("Failed to update progress: {}", e);
         }
 
-        #[cfg(not(unix))]
+        let error = result.err().or_else(|| transfer.error());
+        let complete = error.is_none() && !transfer.is_stopped();
+        let mut partial = None;
+        let (status, event) = if instance.cancelled.load(Ordering::Acquire) {
+            let keep = *instance.keep_partial.lock().unwrap();
+            match cleanup::cancelled(app, &record, keep) {
+                Ok(left) => partial = left,
+                Err(e) => eprintln!("Failed to clean up after cancelling {}: {}", id, e),
+            }
+            if record.is_upload() {
+                uploads::discard(app, &record).await;
+            }
+            ("cancelled", "download_cancelled")
+        } else if complete {
+            Download::remove_meta(app, &id);
+            UploadState::remove(app, &id);
+            let series = instance.speed.lock().unwrap().downsampled();
+            if let Err(e) = self.db.save_speed_history(&id, series.interval, &series.samples) {
+                eprintln!("Failed to save speed history: {}", e);
+            }
+            if !record.options.members.is_empty() {
+                // the folder the files were unpacked into is what was downloaded
+                let folder = archive::folder(Path::new(&record.destination));
+                let name = folder.file_name().map(|n| n.to_string_lossy().to_string());
+                let moved = self.db.update_destination(
+                    &id,
+                    name.as_deref().unwrap_or(&record.filename),
+                    &folder.to_string_lossy(),
+                );
+                if let Err(e) = moved {
+                    eprintln!("Failed to update destination: {}", e);
+                }
+                record.relocated(&folder);
+            }
+            if !record.is_upload() {
+                // scripts may move or rename it, everything below works on where it ends up
+                let destination =
+                    scripting::finished(app, Hook::Complete, &record, instance.category, None).await;
+                record.relocated(&destination);
+            }
+            let held = quarantine::hold(app, &record);
+            if !record.is_upload() && record.options.encrypt {
+                // ciphertext, nothing to hash, preview or tag until it's decrypted
+                let path = PathBuf::from(&record.destination);
+                let sealed = tauri::async_runtime::spawn_blocking({
+                    let path = path.clone();
+                    move || crypt::finish(&path)
+                })
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r);
+                if let Err(e) = sealed {
+                    eprintln!("Failed to seal encrypted download {}: {}", path.display(), e);
+                }
+            } else if !record.is_upload() && record.options.members.is_empty() {
+                let path = Path::new(&record.destination);
+                let mut config = settings::load_or_create(app).download;
+                // executable bits, the post command and thumbnails wait for the release
+                config.mark_executable &= !held;
+                if let Err(e) = permissions::apply(&config, path, instance.size()) {
+                    eprintln!("Failed to set permissions of {}: {}", path.display(), e);
+                }
+                // a held file was tagged already
+                if config.record_origin && !held {
+                    let referrer = record.options.referer();
+                    if let Err(e) = Url::parse(&record.url)
+                        .map_err(|e| e.to_string())
+                        .and_then(|url| origin::record(path, &url, referrer.as_deref()))
+                    {
+                        eprintln!("Failed to record origin of {}: {}", path.display(), e);
+                    }
+                }
+                dedup::queue(app, id, PathBuf::from(&record.destination));
+                if !held {
+                    thumbnail::queue(app, id, PathBuf::from(&record.destination));
+                    process::queue(app, id, record.url.clone(), PathBuf::from(&record.destination));
+                }
+            }
+            ("completed", "download_completed")
+        } else if error.as_deref().is_some_and(|e| e.contains("403"))
+            && Url::parse(&record.url).is_ok_and(|url| s3::is_presigned(&url))
+        {
+            // a retry would hit the same expired signature, the frontend has to hand us a new url
+            ("failed", "presigned_url_expired")
+        } else if error.is_some() {
+            ("failed", "download_failed")
+        } else {
+            ("paused", "download_paused")
+        };
+
+        if let Err(e) = self.db.update_status(&id, Some(status)) {
+            eprintln!("Failed to update status: {}", e);
+        }
+        if status == "failed" && !record.is_upload() {
+            let destination =
+                scripting::finished(app, Hook::Fail, &record, instance.category, error.as_deref()).await;
+            record.relocated(&destination);
+        }
+        // notification text in the user's language
+        let message_key = match event {
+            "download_completed" => "notify.completed",
+            "download_cancelled" => "notify.cancelled",
+            "presigned_url_expired" => "notify.url_expired",
+            "download_failed" => "notify.failed",
+            _ => "notify.paused",
+        };
+        let message = i18n::tf(
+            message_key,
+            &[
+                ("filename", &record.filename),
+                ("error", &error.as_deref().unwrap_or_default()),
+            ],
+        );
+        let average_speed = instance.speed.lock().unwrap().average();
+        // how much the extra connections bought, two decimals
+        let acceleration = transfer
+            .baseline()
+            .filter(|_| status == "completed" && average_speed > 0)
+            .map(|baseline| (average_speed as f64 / baseline as f64 * 100.0).round() / 100.0);
+        if let Some(factor) = acceleration {
+            if let Err(e) = self.db.set_acceleration(&id, factor) {
+                eprintln!("Failed to save acceleration: {}", e);
+            }
+        }
+        let units = Units::from_config(&settings::load_or_create(app).app);
+        let payload = DownloadFinished {
+            id,
+            url: record.url.clone(),
+            downloaded,
+            error: error.clone(),
+            message: message.clone(),
+            average_speed,
+            average_speed_text: units.speed(average_speed),
+            acceleration,
+            partial: partial.clone(),
+        };
+        if let Err(e) = app.emit(event, payload) {
+            eprintln!("Failed to emit {} event: {}", event, e);
+        }
+        let kind = match event {
+            "download_completed" => "completed",
+            "download_cancelled" => "cancelled",
+            "download_paused" => "paused",
+            _ => "failed",
+        };
+        self.log_activity(
+            kind,
+            Some(&id),
+            json!({
+                "downloaded": downloaded,
+                "error": error,
+                "event": event,
+                "acceleration": acceleration,
+                "partial": partial,
+            }),
+        );
+        let hook = match event {
+            "download_completed" => Some("download_completed"),
+            "download_failed" | "presigned_url_expired" => Some("download_failed"),
+            _ => None,
+        };
+        if let Some(hook) = hook {
+            let record = database::Download {
+                bytes_received: downloaded,
+                status: Some(status.to_string()),
+                ..record.clone()
+            };
+            webhooks::fire(app, hook, Some(&record), error.as_deref(), &message);
+        }
+        notifications::notify(app, event, Some(id), message);
+        match event {
+            "download_failed" => self.schedule_retry(app, &record),
+            "download_completed" => self.clear_retry(&id),
+            _ => {}
+        }
+        // nothing will pick it up again, a cleanup set to 0 days deletes what's left now
+        if status == "failed"
+            && !self.retry_pending(&id)
+            && settings::load_or_create(app).download.cleanup_partials_days == Some(0)
         {
-            signal::ctrl_c().await?;
-            tracing::info!("Ctrl-C");
-            self.shutdown_all().await;
+            if let Err(e) = cleanup::partial(app, &record) {
+                eprintln!("Failed to delete partial of {}: {}", id, e);
+            }
+        }
+        instance.done.send_replace(true);
+        self.settled.send_modify(|n| *n += 1);
+        // the last one ending by itself, not a pause or cancel
+        if idle && hook.is_some() && !self.shutting_down.load(Ordering::Acquire) {
+            webhooks::fire(app, "queue_empty", None, None, &i18n::t("notify.queue_empty"));
+        }
+    }
+
+    // Ok(()) covers finished and paused, the transfer tells them apart
+    async fn run_engine(
+        &self,
+        app: &AppHandle,
+        record: &database::Download,
+        transfer: &Arc<Transfer>,
+        limit: &Arc<RateLimit>,
+        resume: bool,
+    ) -> Result<(), String> {
+        let settings = settings::load_or_create(app);
+        let chaos = app.try_state::<AppArgs>().map(|a| a.chaos).unwrap_or(false);
+        let debug = app.try_state::<AppArgs>().is_some_and(|a| a.debug);
+        if debug || settings.app.debug_events {
+            transfer.enable_trace();
+        }
+        let source = Url::parse(&record.url).map_err(|e| e.to_string())?;
+        let (url, client) = client::create_engine(&settings, &source, &record.options, chaos)?;
+        limit.set(record.options.speed_limit.unwrap_or(settings.download.speed_limit) * 1024);
+        let client = client
+            .with_gate(self.backoff.gate(url.host_str().unwrap_or_default()))
+            .with_rate_limit(limit.clone());
+        let path = PathBuf::from(&record.destination);
+        let threads = client::threads(&settings, &record.options);
+        let environment = client::environment(&settings, &source, &record.options, threads, chaos);
+        if let Err(e) = self.db.set_environment(&record.id, &environment) {
+            eprintln!("Failed to save environment: {}", e);
+        }
+        let cipher = if record.options.encrypt && !record.is_upload() {
+            Some(crypt::prepare(&path, resume)?)
+        } else {
+            None
+        };
+
+        let ticker = self.spawn_ticker(app, record.id, record.options.quiet);
+        let result = match record.size {
+            // uploads send `destination` to `url`, resuming from the server's side
+            _ if record.is_upload() => {
+                let mut state = resume
+                    .then(|| UploadState::load(app, &record.id))
+                    .flatten()
+                    .unwrap_or_default();
+                let protocol = uploads::Protocol::of(&source);
+                let result = uploads::run(&client, protocol, &url, &path, transfer, &mut state).await;
+                if result.is_err() || transfer.is_stopped() {
+                    state.save(app, &record.id).and(result)
+                } else {
+                    result
+                }
+            }
+            // a pick of files out of a ZIP, unpacked into a folder named after it
+            Some(size) if !record.options.members.is_empty() => {
+                let folder = archive::folder(&path);
+                let members = &record.options.members;
+                archive::extract(&client, &url, size as u64, members, &folder, transfer).await
+            }
+            Some(size) if record.accept_ranges && size > 0 => {
+                let size = size as usize;
+                let saved = resume.then(|| self.load_state(app, record, size, &path)).flatten();
+                let seed = record.options.delta_from.as_deref().map(Path::new);
+                let patched = match (seed, &saved, &cipher) {
+                    (Some(seed), None, None) => {
+                        self.delta_update(record, &client, &url, seed, &path, transfer).await
+                    }
+                    _ => false,
+                };
+                if patched {
+                    Ok(())
+                } else {
+                    let config = &settings.download;
+                    let strategy = Strategy::from_setting(&config.range_strategy, config.chunk_size);
+                    let download = saved
+                        .unwrap_or_else(|| Download::new(size, threads, strategy))
+                        .with_sequential(record.options.sequential)
+                        .with_priority_pieces(record.options.priority_pieces)
+                        .with_preallocation(!record.options.skip_preallocation)
+                        .with_cipher(cipher.clone())
+                        .with_mirrors(record.options.mirrors.clone());
+                    transfer.add_downloaded((size - download.remaining()) as u64);
+
+                    let run = download.run(
+                        client.clone(),
+                        url.clone(),
+                        &path,
+                        threads,
+                        transfer.clone(),
+                    );
+                    match run.await {
+                        // compressed on the fly, the ranges were no good
+                        Ok(_) if transfer.is_encoded() => {
+                            Download::remove_meta(app, &record.id);
+                            transfer.reset_downloaded();
+                            if transfer.is_stopped() {
+                                Ok(())
+                            } else {
+                                core::stream_single(&client, &url, &path, transfer, cipher.as_ref())
+                                    .await
+                            }
+                        }
+                        Ok(download) if !download.is_complete() => {
+                            download.save(app, &record.id).map_err(|e| e.to_string())
+                        }
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e.to_string()),
+                    }
+                }
+            }
+            // no ranges or unknown size, one connection from the start
+            _ => {
+                let result =
+                    core::stream_single(&client, &url, &path, transfer, cipher.as_ref()).await;
+                // the body is the file, however long HEAD said it was
+                if let (Ok(()), Some(size)) = (&result, record.size.filter(|s| *s > 0)) {
+                    if !transfer.is_stopped() && !transfer.is_encoded() {
+                        transfer.expect_size(size as u64);
+                        transfer.report_size(transfer.downloaded());
+                    }
+                }
+                result
+            },
+        };
+        ticker.abort();
+        // what happened since its last tick
+        emit_trace(app, record.id, transfer);
+        if transfer.is_encoded() {
+            let finished = result.is_ok() && !transfer.is_stopped();
+            self.encoded(record, finished.then(|| transfer.downloaded()));
+        } else if let Some(actual) = transfer.resized() {
+            self.size_mismatch(app, record, actual);
+        }
+        if transfer.is_single_session() && !record.options.single_session {
+            self.single_session(record);
+        }
+        result
+    }
+
+    // kept for the next run, which starts with one connection right away
+    fn single_session(&self, record: &database::Download) {
+        eprintln!(
+            "{} rejects parallel requests, going on with one connection",
+            record.url
+        );
+        let options = DownloadOptions {
+            single_session: true,
+            ..record.options.clone()
+        };
+        if let Err(e) = self.db.set_options(&record.id, &options) {
+            eprintln!("Failed to save options: {}", e);
         }
+        self.log_activity("single_session", Some(&record.id), json!({}));
     }
 
-    // TODO: remove shutdown after shutdown logic is done
-    fn _shutdown_all(&self) {} // cancel each instance, not .abort()
+    // the server compresses it on the fly: no ranges from now on, and the size is only
+    // known once the whole body was unpacked
+    fn encoded(&self, record: &database::Download, size: Option<u64>) {
+        if record.accept_ranges || record.size.is_some() {
+            eprintln!(
+                "{} is compressed in transit, fetching it as one stream of unknown size",
+                record.url
+            );
+        }
+        if let Err(e) = self.db.set_encoded(&record.id, size.map(|s| s as i64)) {
+            eprintln!("Failed to update size: {}", e);
+        }
+    }
+
+    // saved ranges of `record` checked against its file and progress, None starts over
+    fn load_state(
+        &self,
+        app: &AppHandle,
+        record: &database::Download,
+        size: usize,
+        path: &Path,
+    ) -> Option<Download> {
+        let mut download = Download::load(app, record.id, size).ok()?;
+        let done = size - download.remaining();
+        let len = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        let problem = match download.reconcile(len) {
+            Err(e) => e,
+            // a restart zeroed it, the ranges are from the attempt before
+            Ok(_) if done > 0 && record.bytes_received == 0 => {
+                "progress was reset after it was saved".to_string()
+            }
+            Ok(Some(requeued)) => {
+                eprintln!(
+                    "{} is shorter than its saved state, fetching {} bytes again",
+                    path.display(),
+                    requeued
+                );
+                self.log_activity(
+                    "resume_repaired",
+                    Some(&record.id),
+                    json!({ "file_len": len, "requeued": requeued }),
+                );
+                return Some(download);
+            }
+            Ok(None) => return Some(download),
+        };
+        eprintln!("Saved state of {} doesn't fit, starting over: {}", record.filename, problem);
+        self.log_activity("resume_restarted", Some(&record.id), json!({ "reason": problem }));
+        Download::remove_meta(app, &record.id);
+        None
+    }
+
+    // stored before a resume reads it back, the saved ranges only fit the new size
+    fn size_mismatch(&self, app: &AppHandle, record: &database::Download, actual: u64) {
+        let expected = record.size.unwrap_or(0) as u64;
+        eprintln!(
+            "{} has {} bytes, not the {} it was announced with",
+            record.url, actual, expected
+        );
+        if let Err(e) = self.db.update_size(&record.id, actual as i64) {
+            eprintln!("Failed to update size: {}", e);
+        }
+        let payload = SizeMismatch {
+            id: record.id,
+            expected,
+            actual,
+        };
+        self.log_activity("size_mismatch", Some(&record.id), json!(payload));
+        if let Err(e) = app.emit("size_mismatch", payload) {
+            eprintln!("Failed to emit size_mismatch event: {}", e);
+        }
+    }
+
+    // true when `seed` and the server's `.zsync` file took care of it, or it was paused
+    // partway and is tried again on resume; false leaves it to the engine
+    async fn delta_update(
+        &self,
+        record: &database::Download,
+        client: &client::HttpClient,
+        url: &Url,
+        seed: &Path,
+        path: &Path,
+        transfer: &Transfer,
+    ) -> bool {
+        let size = record.size.unwrap_or(0) as u64;
+        match zsync::update(client, url, seed, path, size, transfer).await {
+            _ if transfer.is_stopped() => true,
+            Ok(Some(report)) => {
+                self.log_activity("delta_update", Some(&record.id), json!(report));
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                eprintln!("Delta update of {} failed, fetching all of it: {}", record.filename, e);
+                transfer.reset_downloaded();
+                false
+            }
+        }
+    }
+
+    // progress events every `app.progress_interval_ms` unless `quiet`, a speed sample
+    // every second either way
+    fn spawn_ticker(
+        &self,
+        app: &AppHandle,
+        id: Uuid,
+        quiet: bool,
+    ) -> tauri::async_runtime::JoinHandle<()> {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let Some(instance) = app.state::<DownloadManager>().instance(&id) else {
+                return;
+            };
+            let config = settings::load_or_create(&app).app;
+            let units = Units::from_config(&config);
+            // intervals over a second are counted in one second ticks
+            let tick = config.progress_interval_ms.min(1000);
+            let ticks_per_event = config.progress_interval_ms.div_ceil(tick);
+            let mut interval = tokio::time::interval(Duration::from_millis(tick));
+            let mut last = (Instant::now(), instance.transfer.downloaded());
+            for n in 0.. {
+                interval.tick().await;
+                let downloaded = instance.transfer.downloaded();
+                let elapsed = last.0.elapsed();
+                if elapsed >= Duration::from_secs(1) {
+                    let speed = (downloaded.saturating_sub(last.1) as f64 / elapsed.as_secs_f64()) as u64;
+                    instance.speed.lock().unwrap().push(speed);
+                    last = (Instant::now(), downloaded);
+                    for (worker, state) in instance.transfer.workers().into_iter().enumerate() {
+                        instance.transfer.trace(|| WorkerEvent::Speed {
+                            worker,
+                            speed: state.speed,
+                            downloaded: state.downloaded,
+                        });
+                    }
+                }
+
+                emit_trace(&app, id, &instance.transfer);
+                if quiet || n % ticks_per_event != 0 {
+                    continue;
+                }
+                let payload = instance.progress(id, &units);
+                if let Err(e) = app.emit("download_progress", payload) {
+                    eprintln!("Failed to emit download_progress event: {}", e);
+                }
+            }
+        })
+    }
+}
+
+// `download_debug` with the events `transfer` collected, nothing when it doesn't trace
+fn emit_trace(app: &AppHandle, id: Uuid, transfer: &Transfer) {
+    let events = transfer.take_trace();
+    if events.is_empty() {
+        return;
+    }
+    if let Err(e) = app.emit("download_debug", DownloadDebug { id, events }) {
+        eprintln!("Failed to emit download_debug event: {}", e);
+    }
 }
 
 // impl Drop for DownloadManager {
@@ -63,3 +1578,21 @@ impl DownloadManager {
 //         // cancel all, so they save progress and close db conn
 //     }
 // }
+
+/// Ctrl+C, SIGTERM and the console being closed quit the way closing the window does,
+/// a second one doesn't wait
+pub async fn start_signal_handler(app: AppHandle) {
+    let mut signals = match Signals::new() {
+        Ok(signals) => signals,
+        Err(e) => {
+            eprintln!("Failed to listen for signals: {}", e);
+            return;
+        }
+    };
+    loop {
+        signals.recv().await;
+        if !app.state::<DownloadManager>().shutdown(&app, false) {
+            std::process::exit(130);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use futures_util::future::join_all;
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use super::client::HttpClient;
+use super::{links, probe};
+
+/// Mirrors kept for one download, the closest ones
+pub const MAX_MIRRORS: usize = 8;
+/// A mirror answering slower than this is left out
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Mirror list pages are read up to this much
+const MAX_PAGE: usize = 1 << 20;
+/// RFC 6249 default for a `Link` without `pri`
+const DEFAULT_PRI: u32 = 999_999;
+/// Bytes of the ranged GET a source is measured with
+const SAMPLE: u64 = 128 << 10;
+/// Range length the measured round trip and throughput are weighed for
+const WEIGH_FOR: f64 = (4 << 20) as f64;
+
+/// Other urls with the same file as `url`, closest first. Taken from RFC 6249
+/// `Link: <...>; rel=duplicate` headers, or from the `?mirrorlist` page MirrorBrain and
+/// MirrorBits redirectors serve. Only mirrors that answer with `size` bytes and take
+/// ranges are kept, ordered by how long they took to answer.
+pub async fn discover(client: &HttpClient, url: &Url, size: i64) -> Vec<Url> {
+    let response = match client.send(client.inner().head(url.clone())).await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return Vec::new(),
+    };
+    let mut candidates = from_links(response.headers(), response.url());
+    // a redirector's own Link headers went with the redirect, its mirror list still
+    // names them
+    let redirected = response.url().host_str() != url.host_str();
+    if candidates.is_empty() && (redirected || is_mirrorbrain(response.headers())) {
+        candidates = mirror_list(client, url).await;
+    }
+    let mut seen = HashSet::new();
+    candidates.retain(|c| c != url && c != response.url() && seen.insert(c.clone()));
+    rank(client, candidates, size).await
+}
+
+/// `rel=duplicate` targets of every `Link` header, lowest `pri` first
+pub fn from_links(headers: &HeaderMap, base: &Url) -> Vec<Url> {
+    let mut links: Vec<(u32, Url)> = headers
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(parse_link)
+        .filter(|(_, params)| {
+            params.iter().any(|(k, v)| {
+                k == "rel"
+                    && v.split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("duplicate"))
+            })
+        })
+        .filter_map(|(target, params)| {
+            let url = base
+                .join(&target)
+                .ok()
+                .filter(|u| matches!(u.scheme(), "http" | "https"))?;
+            let pri = params
+                .iter()
+                .find(|(k, _)| k == "pri")
+                .and_then(|(_, v)| v.parse().ok())
+                .unwrap_or(DEFAULT_PRI);
+            Some((pri, url))
+        })
+        .collect();
+    links.sort_by_key(|(pri, _)| *pri);
+    links.into_iter().map(|(_, url)| url).collect()
+}
+
+// `<target>; key=value; key="value", <target>; ...` into targets and lowercased params
+fn parse_link(value: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut links = Vec::new();
+    let mut rest = value;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let target = rest[open + 1..open + close].trim().to_string();
+        rest = &rest[open + close + 1..];
+        let params_end = rest.find('<').unwrap_or(rest.len());
+        let params = rest[..params_end]
+            .split(';')
+            .filter_map(|param| {
+                let (key, value) = param.trim().trim_end_matches(',').split_once('=')?;
+                let value = value.trim().trim_end_matches(',').trim_matches('"');
+                Some((key.trim().to_ascii_lowercase(), value.to_string()))
+            })
+            .collect();
+        links.push((target, params));
+        rest = &rest[params_end..];
+    }
+    links
+}
+
+/// Links on a mirror list page to a file with the same name as `url`, one per host
+pub fn from_page(html: &str, url: &Url) -> Vec<Url> {
+    let Some(name) = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .filter(|n| !n.is_empty())
+    else {
+        return Vec::new();
+    };
+    let mut found: Vec<Url> = Vec::new();
+    for link in links::html(html, Some(url.clone())) {
+        let same_name = link.path_segments().and_then(|mut s| s.next_back()) == Some(name);
+        let host = link.host_str();
+        if matches!(link.scheme(), "http" | "https")
+            && same_name
+            && host != url.host_str()
+            && !found.iter().any(|f| f.host_str() == host)
+        {
+            found.push(link);
+        }
+    }
+    found
+}
+
+fn is_mirrorbrain(headers: &HeaderMap) -> bool {
+    headers
+        .keys()
+        .any(|k| k.as_str().starts_with("x-mirrorbrain"))
+}
+
+// only read when it is a page, a server without one may answer with the file itself
+async fn mirror_list(client: &HttpClient, url: &Url) -> Vec<Url> {
+    let mut page = url.clone();
+    page.set_query(Some("mirrorlist"));
+    let is_html = |headers: &HeaderMap| {
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|t| t.starts_with("text/html"))
+    };
+    match client.send(client.inner().head(page.clone())).await {
+        Ok(response) if response.status().is_success() && is_html(response.headers()) => {}
+        _ => return Vec::new(),
+    }
+    let Ok(mut response) = client.send(client.inner().get(page)).await else {
+        return Vec::new();
+    };
+    if !response.status().is_success() || !is_html(response.headers()) {
+        return Vec::new();
+    }
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_PAGE {
+            break;
+        }
+    }
+    from_page(&String::from_utf8_lossy(&body), url)
+}
+
+// the ones serving the same size with ranges, quickest to answer first
+async fn rank(client: &HttpClient, candidates: Vec<Url>, size: i64) -> Vec<Url> {
+    let checks = candidates.into_iter().map(|url| async move {
+        let started = Instant::now();
+        let remote = tokio::time::timeout(TIMEOUT, probe::probe(client, &url)).await;
+        match remote {
+            Ok(Ok(remote)) if remote.resume_supported && remote.size == Some(size) => {
+                Some((started.elapsed(), url))
+            }
+            _ => None,
+        }
+    });
+    let mut answered: Vec<(Duration, Url)> = join_all(checks).await.into_iter().flatten().collect();
+    answered.sort_by_key(|(latency, _)| *latency);
+    answered
+        .into_iter()
+        .take(MAX_MIRRORS)
+        .map(|(_, url)| url)
+        .collect()
+}
+
+/// What one connection to `url` would fetch per second, from a ranged GET of the first
+/// `SAMPLE` bytes. None when it doesn't answer that with a range in time.
+pub async fn measure(client: &HttpClient, url: &Url) -> Option<u64> {
+    let sample = async {
+        let started = Instant::now();
+        let request = client
+            .inner()
+            .get(url.clone())
+            .header(header::RANGE, format!("bytes=0-{}", SAMPLE - 1));
+        let mut response = client.send(request).await.ok()?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return None;
+        }
+        let rtt = started.elapsed();
+        let mut received = 0;
+        while let Some(chunk) = response.chunk().await.ok()? {
+            received += chunk.len() as u64;
+        }
+        Some(score(rtt, received, started.elapsed() - rtt))
+    };
+    tokio::time::timeout(TIMEOUT, sample).await.ok().flatten()
+}
+
+/// Bytes/s over a `WEIGH_FOR` byte range that `bytes` taking `took` after a `rtt` round
+/// trip point to, so a near mirror wins over a far one of the same throughput
+pub fn score(rtt: Duration, bytes: u64, took: Duration) -> u64 {
+    let throughput = (bytes as f64 / took.as_secs_f64().max(1e-3)).max(1.0);
+    (WEIGH_FOR / (rtt.as_secs_f64() + WEIGH_FOR / throughput)) as u64
+}
+
+/// `workers` connections split between sources by their `speeds`, the largest
+/// remainders rounding up. Sources at 0 get none, unless all are, then the first gets all.
+pub fn shares(speeds: &[u64], workers: usize) -> Vec<usize> {
+    let total: u64 = speeds.iter().sum();
+    let mut shares = vec![0; speeds.len()];
+    if total == 0 {
+        if let Some(first) = shares.first_mut() {
+            *first = workers;
+        }
+        return shares;
+    }
+    let exact: Vec<f64> = speeds
+        .iter()
+        .map(|&s| s as f64 * workers as f64 / total as f64)
+        .collect();
+    for (share, exact) in shares.iter_mut().zip(&exact) {
+        *share = *exact as usize;
+    }
+    let mut order: Vec<usize> = (0..speeds.len()).filter(|&i| speeds[i] > 0).collect();
+    order.sort_by(|&a, &b| (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor())));
+    let left = workers.saturating_sub(shares.iter().sum::<usize>());
+    for i in order.into_iter().take(left) {
+        shares[i] += 1;
+    }
+    shares
+}
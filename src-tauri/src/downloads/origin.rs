@@ -0,0 +1,101 @@
+use std::path::Path;
+use url::Url;
+
+/// Tag a completed file with where it came from, the way browsers do:
+/// `user.xdg.origin.url` / `user.xdg.referrer.url` xattrs on Linux and macOS,
+/// the `Zone.Identifier` stream on Windows so SmartScreen knows it's from the internet
+pub fn record(path: &Path, url: &Url, referrer: Option<&str>) -> Result<(), String> {
+    let url = strip_credentials(url);
+    let referrer = referrer
+        .and_then(|r| Url::parse(r).ok())
+        .map(|r| strip_credentials(&r));
+    write(path, &url, referrer.as_deref())
+}
+
+/// Set `com.apple.quarantine` the way browsers do, so Gatekeeper checks the file before
+/// it's first opened. Other systems have no such flag, the tag `record` writes is the mark.
+#[cfg(target_os = "macos")]
+pub fn flag_quarantined(path: &Path) -> Result<(), String> {
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    // 0081: downloaded, not yet approved by the user
+    set_xattr(path, "com.apple.quarantine", &format!("0081;{:08x};tur;", now))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn flag_quarantined(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+// user:password@ never ends up on disk
+fn strip_credentials(url: &Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.to_string()
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn write(path: &Path, url: &str, referrer: Option<&str>) -> Result<(), String> {
+    set_xattr(path, "user.xdg.origin.url", url)?;
+    if let Some(referrer) = referrer {
+        set_xattr(path, "user.xdg.referrer.url", referrer)?;
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn set_xattr(path: &Path, name: &str, value: &str) -> Result<(), String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let c_name = CString::new(name).map_err(|e| e.to_string())?;
+    let value = value.as_bytes();
+    // SAFETY: both strings are nul terminated and outlive the call, value is a valid slice
+    let result = unsafe {
+        #[cfg(target_os = "linux")]
+        {
+            libc::setxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        }
+        #[cfg(target_os = "macos")]
+        {
+            libc::setxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+                0,
+            )
+        }
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(windows)]
+fn write(path: &Path, url: &str, referrer: Option<&str>) -> Result<(), String> {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(":Zone.Identifier");
+    // zone 3 is the internet zone
+    let mut content = String::from("[ZoneTransfer]\r\nZoneId=3\r\n");
+    if let Some(referrer) = referrer {
+        content.push_str(&format!("ReferrerUrl={}\r\n", referrer));
+    }
+    content.push_str(&format!("HostUrl={}\r\n", url));
+    std::fs::write(stream, content).map_err(|e| e.to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn write(_path: &Path, _url: &str, _referrer: Option<&str>) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use crate::settings::DownloadConfig;
+
+/// Apply `file_mode` and `mark_executable` to a completed download. Only files that
+/// came out at the size the server announced get the executable bits.
+#[cfg(unix)]
+pub fn apply(
+    config: &DownloadConfig,
+    path: &Path,
+    expected_size: Option<u64>,
+) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(path).map_err(|e| e.to_string())?;
+    let mut mode = config
+        .file_mode_bits()
+        .unwrap_or(metadata.permissions().mode() & 0o7777);
+
+    let verified = expected_size.is_none_or(|size| size == metadata.len());
+    if config.mark_executable && verified && is_executable_type(config, path) {
+        // executable for whoever can read it
+        mode |= (mode & 0o444) >> 2;
+    }
+    if mode != metadata.permissions().mode() & 0o7777 {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(
+    _config: &DownloadConfig,
+    _path: &Path,
+    _expected_size: Option<u64>,
+) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable_type(config: &DownloadConfig, path: &Path) -> bool {
+    let Some(extension) = path.extension().map(|e| e.to_string_lossy()) else {
+        return false;
+    };
+    config
+        .executable_extensions
+        .iter()
+        .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+}
@@ -0,0 +1,92 @@
+use reqwest::header;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+use url::Url;
+use uuid::Uuid;
+
+use super::category;
+use super::client::HttpClient;
+use super::probe::RemoteInfo;
+
+/// Largest preview a caller can ask for
+pub const MAX_PREVIEW: u64 = 16 << 20;
+
+/// The start of a remote file on disk, with what it looks like
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Preview {
+    /// temp file holding the fetched bytes
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// the file goes on past `bytes`
+    pub partial: bool,
+    pub filename: String,
+    /// full size, when the server said
+    pub size: Option<i64>,
+    /// what the server claims
+    pub content_type: Option<String>,
+    /// sniffed from the magic bytes, None when unrecognised
+    pub mime: Option<String>,
+    pub extension: Option<String>,
+    pub category: &'static str,
+}
+
+/// Fetch up to `limit` bytes of `url` into a temp file
+pub async fn fetch(client: &HttpClient, url: &Url, limit: u64) -> Result<Preview, String> {
+    let limit = limit.clamp(1, MAX_PREVIEW);
+    let request = client
+        .inner()
+        .get(url.clone())
+        .header(header::RANGE, format!("bytes=0-{}", limit - 1));
+    let mut response = client
+        .send(request)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    let info = RemoteInfo::from_response(url, &response);
+
+    // servers ignoring the range send it all, stop reading at the limit
+    let mut data = Vec::with_capacity(limit as usize);
+    while let Some(chunk) = client.chunk(&mut response).await? {
+        let take = chunk.len().min(limit as usize - data.len());
+        data.extend_from_slice(&chunk[..take]);
+        if data.len() as u64 >= limit {
+            break;
+        }
+    }
+
+    let kind = infer::get(&data);
+    let extension = kind
+        .map(|k| k.extension().to_string())
+        .or_else(|| {
+            std::path::Path::new(&info.filename)
+                .extension()
+                .map(|e| e.to_string_lossy().to_string())
+        });
+    let dir = std::env::temp_dir().join("tur-previews");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let mut name = Uuid::now_v7().as_simple().to_string();
+    if let Some(ext) = &extension {
+        name = format!("{}.{}", name, ext);
+    }
+    let path = dir.join(name);
+    std::fs::File::create(&path)
+        .and_then(|mut file| file.write_all(&data))
+        .map_err(|e| e.to_string())?;
+
+    let bytes = data.len() as u64;
+    Ok(Preview {
+        path,
+        bytes,
+        partial: info.size.map_or(bytes >= limit, |size| size as u64 > bytes),
+        category: category::category(
+            &info.filename,
+            kind.map(|k| k.mime_type()).or(info.content_type.as_deref()),
+        ),
+        filename: info.filename,
+        size: info.size,
+        content_type: info.content_type,
+        mime: kind.map(|k| k.mime_type().to_string()),
+        extension,
+    })
+}
@@ -0,0 +1,191 @@
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Response, StatusCode};
+use url::Url;
+
+use super::client::HttpClient;
+use super::encoding;
+use crate::database;
+
+/// What the server told us about a url before downloading it
+#[derive(Debug, Clone)]
+pub struct RemoteInfo {
+    pub filename: String,
+    pub size: Option<i64>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub resume_supported: bool,
+}
+
+/// Fetch headers for `url`, falling back to a one byte GET for servers that refuse HEAD
+pub async fn probe(client: &HttpClient, url: &Url) -> Result<RemoteInfo, String> {
+    let response = client.send(client.inner().head(url.clone())).await?;
+    if response.status().is_success() {
+        return Ok(RemoteInfo::from_response(url, &response));
+    }
+
+    let request = client
+        .inner()
+        .get(url.clone())
+        .header(header::RANGE, "bytes=0-0");
+    let response = client
+        .send(request)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    Ok(RemoteInfo::from_response(url, &response))
+}
+
+impl RemoteInfo {
+    /// Read from a HEAD, ranged GET or plain GET response
+    pub fn from_response(url: &Url, response: &Response) -> Self {
+        let headers = response.headers();
+        // a 206 to our one byte probe carries the real size in Content-Range
+        let (size, resume_supported) = if response.status() == StatusCode::PARTIAL_CONTENT {
+            (extract_content_range_total(headers).map(|s| s as i64), true)
+        } else {
+            (
+                extract_content_length(headers).map(|s| s as i64),
+                extract_resume_support(headers),
+            )
+        };
+        // compressed on the fly, neither the length nor ranges are the file's
+        let (size, resume_supported) = match encoding::of(headers, response.url()) {
+            Some(_) => (None, false),
+            None => (size, resume_supported),
+        };
+
+        RemoteInfo {
+            filename: extract_filename_from_headers(headers)
+                .unwrap_or_else(|| extract_filename_from_url(url.as_str())),
+            size,
+            content_type: headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string()),
+            etag: extract_etag(headers),
+            last_modified: extract_last_modified(headers),
+            resume_supported,
+        }
+    }
+}
+
+/// Stored validators no longer match the server, or the partial file is gone
+pub fn needs_restart(stored: &database::Download, remote: &RemoteInfo, file_exists: bool) -> bool {
+    !file_exists
+        || (stored.etag.is_some() && remote.etag != stored.etag)
+        || (stored.last_modified.is_some() && remote.last_modified != stored.last_modified)
+        || (stored.size.is_some() && remote.size != stored.size)
+}
+
+// Helper functions for extracting download metadata
+/// Name from Content-Disposition, `filename*` (RFC 5987) winning over `filename` as
+/// RFC 6266 asks, without any folders the server put in front of it
+pub fn extract_filename_from_headers(headers: &HeaderMap) -> Option<String> {
+    let cd = headers
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())?;
+    let mut plain = None;
+    let mut extended = None;
+    for part in cd.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename" => plain = Some(value.trim().trim_matches('"').to_string()),
+            "filename*" => extended = decode_extended(value.trim().trim_matches('"')),
+            _ => {}
+        }
+    }
+    extended.or(plain).and_then(|name| basename(&name))
+}
+
+// `charset'language'percent-encoded`, UTF-8 or ISO-8859-1
+fn decode_extended(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let encoded = parts.nth(1)?;
+    let bytes = percent_decode(encoded);
+    if charset.eq_ignore_ascii_case("iso-8859-1") {
+        return Some(bytes.into_iter().map(char::from).collect());
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Last path segment of `url` percent-decoded, "download" when there's none
+pub fn extract_filename_from_url(url: &str) -> String {
+    url.split(['?', '#'])
+        .next()
+        .and_then(|s| s.rsplit('/').next())
+        .map(|s| String::from_utf8_lossy(&percent_decode(s)).into_owned())
+        .and_then(|s| basename(&s))
+        .unwrap_or_else(|| "download".to_string())
+}
+
+// what follows the last `/` or `\`, a decoded `%2F` or a `C:\...` from the server
+// doesn't get to pick the folder
+fn basename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next()?.trim();
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.to_string()),
+    }
+}
+
+/// `%XX` escapes of `s` as the bytes they stand for, anything else kept as is
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+pub fn extract_content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.parse::<u64>().ok())
+}
+
+// "bytes 0-0/1234" -> 1234
+pub fn extract_content_range_total(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.rsplit('/').next())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+}
+
+pub fn extract_etag(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.trim_matches('"').to_string()) // Remove quotes if present
+}
+
+pub fn extract_last_modified(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
+pub fn extract_resume_support(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.eq_ignore_ascii_case("bytes"))
+        .unwrap_or(false)
+}
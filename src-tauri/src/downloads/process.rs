@@ -0,0 +1,236 @@
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::manager::DownloadManager;
+use crate::settings;
+
+/// Output kept per stream in the activity log, the end is what explains a failure
+const OUTPUT_LIMIT: usize = 4096;
+
+/// Commands tur started that are still running, each in its own process group (a job
+/// object on Windows) so quitting can end them together with whatever they started
+#[derive(Default)]
+pub struct Children {
+    running: Mutex<HashMap<u32, Group>>,
+}
+
+impl Children {
+    /// End every command still running
+    pub fn terminate_all(&self) {
+        for (_, group) in self.running.lock().unwrap().drain() {
+            group.terminate();
+        }
+    }
+}
+
+/// Run `download.post_command` for a completed download in the background, its exit code
+/// and output go to the activity log
+pub fn queue(app: &AppHandle, id: Uuid, url: String, path: PathBuf) {
+    let command = settings::load_or_create(app).download.post_command;
+    if command.trim().is_empty() {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let manager = app.state::<DownloadManager>();
+        // the shell only sees the command the user typed, names from servers go in the environment
+        let mut shell = shell(&command);
+        shell
+            .env("TUR_ID", id.to_string())
+            .env("TUR_URL", &url)
+            .env("TUR_PATH", &path);
+        if let Some(dir) = path.parent() {
+            shell.current_dir(dir);
+        }
+        let detail = match run(manager.children(), shell) {
+            Ok((code, stdout, stderr)) => json!({
+                "command": command,
+                "exit_code": code,
+                "stdout": stdout,
+                "stderr": stderr,
+            }),
+            Err(e) => json!({ "command": command, "error": e }),
+        };
+        manager.log_activity("command_finished", Some(&id), detail);
+    });
+}
+
+pub(super) fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        let mut shell = Command::new("cmd");
+        shell
+            .arg("/C")
+            .arg(command)
+            .creation_flags(CREATE_NO_WINDOW);
+        shell
+    }
+    #[cfg(not(windows))]
+    {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+// spawn, register the group until it exits, collect both streams
+pub(super) fn run(children: &Children, command: Command) -> Result<(Option<i32>, String, String), String> {
+    let started = start(children, command)?;
+    finish(children, started)
+}
+
+/// A command that's running, in `Children` until `finish` collects it
+pub(super) struct Started {
+    pid: u32,
+    child: Child,
+}
+
+/// Spawn `command` in its own group with both streams piped
+pub(super) fn start(children: &Children, mut command: Command) -> Result<Started, String> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let pid = child.id();
+    match Group::of(&child) {
+        Ok(group) => {
+            children.running.lock().unwrap().insert(pid, group);
+        }
+        Err(e) => eprintln!("Failed to group command {}: {}", pid, e),
+    }
+    Ok(Started { pid, child })
+}
+
+/// Wait for a started command, returns its exit code and the end of both streams
+pub(super) fn finish(
+    children: &Children,
+    started: Started,
+) -> Result<(Option<i32>, String, String), String> {
+    let Started { pid, mut child } = started;
+    // stderr on its own thread, a full pipe would block the child otherwise
+    let stderr = child.stderr.take().map(|mut pipe| {
+        std::thread::spawn(move || {
+            let mut out = Vec::new();
+            let _ = pipe.read_to_end(&mut out);
+            out
+        })
+    });
+    let mut stdout = Vec::new();
+    if let Some(mut pipe) = child.stdout.take() {
+        let _ = pipe.read_to_end(&mut stdout);
+    }
+    let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+    let status = child.wait();
+    children.running.lock().unwrap().remove(&pid);
+    let status = status.map_err(|e| e.to_string())?;
+    Ok((status.code(), tail(&stdout), tail(&stderr)))
+}
+
+fn tail(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    let text = text.trim_end();
+    let mut start = text.len().saturating_sub(OUTPUT_LIMIT);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
+
+/// The process group a command leads
+#[cfg(unix)]
+struct Group(i32);
+
+#[cfg(unix)]
+impl Group {
+    fn of(child: &std::process::Child) -> std::io::Result<Self> {
+        Ok(Group(child.id() as i32))
+    }
+
+    fn terminate(self) {
+        // SAFETY: killpg only signals, a group that's gone returns ESRCH
+        unsafe {
+            libc::killpg(self.0, libc::SIGTERM);
+        }
+    }
+}
+
+/// A job object holding the command and everything it starts
+#[cfg(windows)]
+struct Group(*mut std::ffi::c_void);
+
+// SAFETY: job handles can be used and closed from any thread
+#[cfg(windows)]
+unsafe impl Send for Group {}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn CreateJobObjectW(
+        attributes: *mut std::ffi::c_void,
+        name: *const u16,
+    ) -> *mut std::ffi::c_void;
+    fn AssignProcessToJobObject(job: *mut std::ffi::c_void, process: *mut std::ffi::c_void) -> i32;
+    fn TerminateJobObject(job: *mut std::ffi::c_void, exit_code: u32) -> i32;
+    fn CloseHandle(handle: *mut std::ffi::c_void) -> i32;
+}
+
+#[cfg(windows)]
+impl Group {
+    fn of(child: &std::process::Child) -> std::io::Result<Self> {
+        use std::os::windows::io::AsRawHandle;
+        // SAFETY: a fresh unnamed job, and the child's handle stays open while `child` lives
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null_mut(), std::ptr::null());
+            if job.is_null() {
+                return Err(std::io::Error::last_os_error());
+            }
+            if AssignProcessToJobObject(job, child.as_raw_handle()) == 0 {
+                let e = std::io::Error::last_os_error();
+                CloseHandle(job);
+                return Err(e);
+            }
+            Ok(Group(job))
+        }
+    }
+
+    fn terminate(self) {
+        // SAFETY: the handle is ours until drop
+        unsafe {
+            TerminateJobObject(self.0, 1);
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Group {
+    fn drop(&mut self) {
+        // SAFETY: closed exactly once, here
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+struct Group;
+
+#[cfg(not(any(unix, windows)))]
+impl Group {
+    fn of(_child: &std::process::Child) -> std::io::Result<Self> {
+        Ok(Group)
+    }
+
+    fn terminate(self) {}
+}
@@ -0,0 +1,138 @@
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+use url::Url;
+use uuid::Uuid;
+
+use super::manager::DownloadManager;
+use super::{origin, permissions, process, thumbnail};
+use crate::database;
+use crate::events::QuarantineChanged;
+use crate::i18n;
+use crate::notifications;
+use crate::settings;
+
+/// Held until the scanner passes or the user releases it
+pub const UNSCANNED: &str = "unscanned";
+pub const SCANNING: &str = "scanning";
+/// the scanner failed it, only the user can release it now
+pub const FLAGGED: &str = "flagged";
+
+/// Hold completed download `record` back when `download.quarantine` is on. It's tagged
+/// as from the internet whatever `record_origin` says and `scan_command` runs on it.
+/// Executable bits, the post command and thumbnails wait for `release`.
+pub fn hold(app: &AppHandle, record: &database::Download) -> bool {
+    let config = settings::load_or_create(app).download;
+    if !config.quarantine || record.is_upload() {
+        return false;
+    }
+    let path = Path::new(&record.destination);
+    let tagged = Url::parse(&record.url)
+        .map_err(|e| e.to_string())
+        .and_then(|url| origin::record(path, &url, record.options.referer().as_deref()))
+        .and_then(|_| origin::flag_quarantined(path));
+    if let Err(e) = tagged {
+        eprintln!("Failed to mark {} as downloaded: {}", path.display(), e);
+    }
+    set(app, &record.id, Some(UNSCANNED));
+    // ciphertext can't be scanned, it waits for the user
+    if !config.scan_command.trim().is_empty() && !record.options.encrypt {
+        scan(app, record.clone(), config.scan_command);
+    }
+    true
+}
+
+/// Let download `id` out of quarantine, `by` is "user" or "scanner" in the activity log
+pub fn release(app: &AppHandle, id: &Uuid, by: &str) -> Result<(), String> {
+    let manager = app.state::<DownloadManager>();
+    let record = manager
+        .db()
+        .get_download_by_id(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Download {} not found", id))?;
+    if record.quarantine.is_none() {
+        return Ok(());
+    }
+    set(app, id, None);
+    manager.log_activity("quarantine_released", Some(id), json!({ "by": by }));
+    if record.options.encrypt {
+        return Ok(());
+    }
+    let path = PathBuf::from(&record.destination);
+    let config = settings::load_or_create(app).download;
+    if let Err(e) = permissions::apply(&config, &path, record.size.map(|s| s as u64)) {
+        eprintln!("Failed to set permissions of {}: {}", path.display(), e);
+    }
+    thumbnail::queue(app, *id, path.clone());
+    process::queue(app, *id, record.url, path);
+    Ok(())
+}
+
+fn set(app: &AppHandle, id: &Uuid, state: Option<&str>) {
+    let manager = app.state::<DownloadManager>();
+    if let Err(e) = manager.db().set_quarantine(id, state) {
+        eprintln!("Failed to update quarantine of {}: {}", id, e);
+        return;
+    }
+    let payload = QuarantineChanged {
+        id: *id,
+        state: state.map(String::from),
+    };
+    if let Err(e) = app.emit("quarantine_changed", payload) {
+        eprintln!("Failed to emit quarantine_changed event: {}", e);
+    }
+}
+
+// exit code 0 releases it, anything else flags it, a scanner that can't start leaves it
+// unscanned
+fn scan(app: &AppHandle, record: database::Download, command: String) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let manager = app.state::<DownloadManager>();
+        set(&app, &record.id, Some(SCANNING));
+        let path = PathBuf::from(&record.destination);
+        let mut shell = process::shell(&command);
+        shell
+            .env("TUR_ID", record.id.to_string())
+            .env("TUR_URL", &record.url)
+            .env("TUR_PATH", &path);
+        if let Some(dir) = path.parent() {
+            shell.current_dir(dir);
+        }
+        let (code, stdout, stderr) = match process::run(manager.children(), shell) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Failed to run scanner on {}: {}", path.display(), e);
+                manager.log_activity(
+                    "scan_finished",
+                    Some(&record.id),
+                    json!({ "command": command, "error": e }),
+                );
+                set(&app, &record.id, Some(UNSCANNED));
+                return;
+            }
+        };
+        manager.log_activity(
+            "scan_finished",
+            Some(&record.id),
+            json!({
+                "command": command,
+                "exit_code": code,
+                "stdout": stdout,
+                "stderr": stderr,
+            }),
+        );
+        if code == Some(0) {
+            if let Err(e) = release(&app, &record.id, "scanner") {
+                eprintln!("Failed to release {}: {}", record.id, e);
+            }
+            return;
+        }
+        set(&app, &record.id, Some(FLAGGED));
+        let message = i18n::tf(
+            "notify.quarantine_flagged",
+            &[("filename", &record.filename)],
+        );
+        notifications::notify(&app, "quarantine_flagged", Some(record.id), message);
+    });
+}
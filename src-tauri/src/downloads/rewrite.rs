@@ -0,0 +1,86 @@
+use regex::Regex;
+use serde::Serialize;
+use url::Url;
+
+use super::client::host_matches;
+use crate::settings::RewriteRule;
+
+/// A url before and after the rewrite rules, with the ones that changed it
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Rewrite {
+    pub original: Url,
+    pub rewritten: Url,
+    /// names of the rules that changed something, in the order they ran
+    pub applied: Vec<String>,
+}
+
+/// Run `url` through every matching rule in order. A rule whose result doesn't parse
+/// as a url is skipped.
+pub fn rewrite(rules: &[RewriteRule], url: &Url) -> Rewrite {
+    let mut rewritten = url.clone();
+    let mut applied = Vec::new();
+    for rule in rules {
+        match apply(rule, &rewritten) {
+            Ok(Some(next)) if next != rewritten => {
+                rewritten = next;
+                applied.push(rule.name.clone());
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Rewrite rule {} skipped: {}", rule.name, e),
+        }
+    }
+    Rewrite {
+        original: url.clone(),
+        rewritten,
+        applied,
+    }
+}
+
+/// The url after `rule`, None when the rule doesn't match it
+fn apply(rule: &RewriteRule, url: &Url) -> Result<Option<Url>, String> {
+    let host = rule.host.trim();
+    if !host.is_empty() && !host_matches(host, url.host_str().unwrap_or_default()) {
+        return Ok(None);
+    }
+    let mut url = if rule.pattern.is_empty() {
+        url.clone()
+    } else {
+        let pattern = Regex::new(&rule.pattern).map_err(|e| e.to_string())?;
+        if !pattern.is_match(url.as_str()) {
+            return Ok(None);
+        }
+        let replaced = pattern.replace_all(url.as_str(), rule.replacement.as_str());
+        Url::parse(&replaced).map_err(|e| format!("{}: {}", replaced, e))?
+    };
+    if !rule.strip_params.is_empty() && url.query().is_some() {
+        let kept: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| !rule.strip_params.iter().any(|p| param_matches(p, name)))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+    Ok(Some(url))
+}
+
+// `utm_*` is a prefix, anything else the exact name
+fn param_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Refuse patterns that don't compile, before they're saved
+pub fn validate(rules: &[RewriteRule]) -> Result<(), String> {
+    for rule in rules {
+        if !rule.pattern.is_empty() {
+            Regex::new(&rule.pattern).map_err(|e| format!("Rewrite {}: {}", rule.name, e))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+use super::client::HttpClient;
+
+/// robots.txt is fetched again after this long
+const REFRESH: Duration = Duration::from_secs(24 * 60 * 60);
+/// longer crawl-delays are cut down to this, a batch shouldn't stall for hours
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// anything past this is ignored, like crawlers do
+const MAX_SIZE: usize = 500 << 10;
+
+/// What one host's robots.txt says for us
+#[derive(Debug, Default)]
+pub struct Robots {
+    /// (allow, path pattern), in file order
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Rules of the group naming `agent`'s product token, or of `*` without one
+    pub fn parse(text: &str, agent: &str) -> Self {
+        let product = agent
+            .split(['/', ' '])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut named = Robots::default();
+        let mut any = Robots::default();
+        let mut has_named = false;
+        // agents of the group being read, a rule after them closes the list
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let key = key.trim().to_ascii_lowercase();
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_rules = true;
+            let is_named = agents.iter().any(|a| !product.is_empty() && *a == product);
+            has_named |= is_named;
+            let target = match (is_named, agents.iter().any(|a| a == "*")) {
+                (true, _) => &mut named,
+                (false, true) => &mut any,
+                (false, false) => continue,
+            };
+            match key.as_str() {
+                // an empty Disallow allows everything, nothing to keep
+                "disallow" if !value.is_empty() => target.rules.push((false, value.to_string())),
+                "allow" if !value.is_empty() => target.rules.push((true, value.to_string())),
+                "crawl-delay" => {
+                    target.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|d| d.is_finite() && *d > 0.0)
+                        .map(|d| Duration::from_secs_f64(d).min(MAX_DELAY));
+                }
+                _ => {}
+            }
+        }
+        if has_named {
+            named
+        } else {
+            any
+        }
+    }
+
+    /// Longest matching pattern wins, Allow on a tie
+    pub fn allows(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, &path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+// `*` is any run of characters, a trailing `$` anchors the end
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut at = first.len();
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return path[at..].ends_with(part);
+        }
+        match path[at..].find(part) {
+            Some(found) => at += found + part.len(),
+            None => return false,
+        }
+    }
+    !anchored || at == path.len()
+}
+
+struct Host {
+    robots: Arc<Robots>,
+    fetched: Instant,
+    /// last time a link on it was let through
+    visited: Option<Instant>,
+}
+
+/// robots.txt per host, shared by every batch
+#[derive(Default)]
+pub struct RobotsCache {
+    hosts: Mutex<HashMap<String, Host>>,
+}
+
+impl RobotsCache {
+    /// Err when robots.txt disallows `url` for `agent`. Otherwise waits until the host's
+    /// crawl-delay has passed since the last link to it, and returns its rules.
+    pub async fn visit(
+        &self,
+        client: &HttpClient,
+        url: &Url,
+        agent: &str,
+    ) -> Result<Arc<Robots>, String> {
+        let Some(origin) = url.host_str().map(|_| url.origin().ascii_serialization()) else {
+            return Ok(Arc::default());
+        };
+        let cached = self
+            .hosts
+            .lock()
+            .unwrap()
+            .get(&origin)
+            .filter(|host| host.fetched.elapsed() < REFRESH)
+            .map(|host| host.robots.clone());
+        let robots = match cached {
+            Some(robots) => robots,
+            None => {
+                let robots = Arc::new(fetch(client, &origin, agent).await);
+                let mut hosts = self.hosts.lock().unwrap();
+                let host = hosts.entry(origin.clone()).or_insert_with(|| Host {
+                    robots: robots.clone(),
+                    fetched: Instant::now(),
+                    visited: None,
+                });
+                host.robots = robots.clone();
+                host.fetched = Instant::now();
+                robots
+            }
+        };
+        if !robots.allows(url) {
+            return Err(format!("robots.txt of {} disallows {}", origin, url));
+        }
+
+        let Some(delay) = robots.crawl_delay else {
+            return Ok(robots);
+        };
+        // claim the next slot before sleeping, so links to one host queue up behind it
+        let wait = {
+            let mut hosts = self.hosts.lock().unwrap();
+            let now = Instant::now();
+            match hosts.get_mut(&origin) {
+                Some(host) => {
+                    let next = host.visited.map_or(now, |last| (last + delay).max(now));
+                    host.visited = Some(next);
+                    next - now
+                }
+                None => Duration::ZERO,
+            }
+        };
+        tokio::time::sleep(wait).await;
+        Ok(robots)
+    }
+}
+
+// a missing file or a client error allows everything, so does a server that's down
+async fn fetch(client: &HttpClient, origin: &str, agent: &str) -> Robots {
+    let url = format!("{}/robots.txt", origin);
+    let response = match client.send(client.inner().get(&url)).await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Failed to fetch {}: {}", url, e);
+            return Robots::default();
+        }
+    };
+    if response.status() != StatusCode::OK {
+        return Robots::default();
+    }
+    match response.text().await {
+        Ok(text) => {
+            let mut end = text.len().min(MAX_SIZE);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            Robots::parse(&text[..end], agent)
+        }
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", url, e);
+            Robots::default()
+        }
+    }
+}
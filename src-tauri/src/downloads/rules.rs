@@ -0,0 +1,82 @@
+use regex::Regex;
+use serde::Serialize;
+use url::Url;
+
+use super::category;
+use super::client::host_matches;
+use super::DownloadOptions;
+use crate::settings::DownloadRule;
+
+/// The rule a url gets and where it sits in the list
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct RuleMatch {
+    pub index: usize,
+    pub rule: DownloadRule,
+}
+
+/// First of `rules` matching `url`
+pub fn find(rules: &[DownloadRule], url: &Url) -> Option<RuleMatch> {
+    rules
+        .iter()
+        .position(|rule| matches(rule, url))
+        .map(|index| RuleMatch {
+            index,
+            rule: rules[index].clone(),
+        })
+}
+
+/// Host and pattern both fit, a pattern that doesn't compile never does
+pub fn matches(rule: &DownloadRule, url: &Url) -> bool {
+    let host = rule.host.trim();
+    let host_fits = host.is_empty() || host_matches(host, url.host_str().unwrap_or_default());
+    let pattern_fits = rule.pattern.is_empty()
+        || Regex::new(&rule.pattern).is_ok_and(|pattern| pattern.is_match(url.as_str()));
+    host_fits && pattern_fits
+}
+
+/// `options` with the first matching rule filled in, and that rule
+pub fn for_download(
+    rules: &[DownloadRule],
+    url: &Url,
+    options: &DownloadOptions,
+) -> (DownloadOptions, Option<DownloadRule>) {
+    let mut options = options.clone();
+    let rule = find(rules, url).map(|found| found.rule);
+    if let Some(rule) = &rule {
+        apply(rule, &mut options);
+    }
+    (options, rule)
+}
+
+/// Fill in what the download didn't set itself
+pub fn apply(rule: &DownloadRule, options: &mut DownloadOptions) {
+    options.connections = options.connections.or(rule.connections);
+    options.speed_limit = options.speed_limit.or(rule.speed_limit);
+    if options.user_agent.as_deref().is_none_or(str::is_empty) {
+        options.user_agent = rule.user_agent.clone().filter(|ua| !ua.is_empty());
+    }
+    if options.category.is_none() {
+        options.category = rule.category.clone();
+    }
+}
+
+/// Refuse rules that can't work as written, before they're saved
+pub fn validate(rules: &[DownloadRule]) -> Result<(), String> {
+    for rule in rules {
+        if !rule.pattern.is_empty() {
+            Regex::new(&rule.pattern).map_err(|e| format!("Rule {}: {}", rule.name, e))?;
+        }
+        if rule.connections == Some(0) {
+            return Err(format!(
+                "Rule {}: connections must be at least 1",
+                rule.name
+            ));
+        }
+        if let Some(name) = &rule.category {
+            if category::known(name).is_none() {
+                return Err(format!("Rule {}: unknown category {}", rule.name, name));
+            }
+        }
+    }
+    Ok(())
+}
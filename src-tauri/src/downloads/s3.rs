@@ -0,0 +1,199 @@
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::probe::percent_decode;
+use crate::settings::S3Config;
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// https endpoint for `s3://bucket/key` plus the signer its requests need
+pub fn resolve(config: &S3Config, url: &Url) -> Result<(Url, Signer), String> {
+    let bucket = url.host_str().ok_or("s3 url without a bucket")?;
+    let key = url.path().trim_start_matches('/');
+    if config.access_key_id.is_empty() || config.secret_access_key.is_empty() {
+        return Err("S3 credentials aren't configured".into());
+    }
+
+    let endpoint = if !config.endpoint.is_empty() {
+        // custom endpoints (MinIO, R2, ...) are path style
+        format!("{}/{}/{}", config.endpoint.trim_end_matches('/'), bucket, key)
+    } else if bucket.contains('.') {
+        // dotted buckets break the wildcard certificate of virtual hosts
+        format!("https://s3.{}.amazonaws.com/{}/{}", config.region, bucket, key)
+    } else {
+        format!("https://{}.s3.{}.amazonaws.com/{}", bucket, config.region, key)
+    };
+    let endpoint = Url::parse(&endpoint).map_err(|e| e.to_string())?;
+
+    Ok((
+        endpoint,
+        Signer {
+            access_key_id: config.access_key_id.clone(),
+            secret_access_key: config.secret_access_key.clone(),
+            session_token: Some(config.session_token.clone()).filter(|t| !t.is_empty()),
+            region: config.region.clone(),
+            requester_pays: config.requester_pays,
+        },
+    ))
+}
+
+/// Presigned S3 urls carry their signature in the query and expire
+pub fn is_presigned(url: &Url) -> bool {
+    url.query_pairs()
+        .any(|(k, _)| k.eq_ignore_ascii_case("X-Amz-Signature") || k == "Signature")
+}
+
+/// AWS Signature Version 4 for S3 requests
+pub struct Signer {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    region: String,
+    requester_pays: bool,
+}
+
+impl Signer {
+    /// Add the x-amz headers and Authorization, right before sending since it's time bound
+    pub fn sign(&self, request: &mut reqwest::Request) -> Result<(), String> {
+        let now = time::OffsetDateTime::now_utc();
+        let amz_date = format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            now.year(),
+            now.month() as u8,
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+
+        let mut headers = vec![
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if self.requester_pays {
+            headers.push(("x-amz-request-payer", "requester".into()));
+        }
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        for (name, value) in &headers {
+            let value = HeaderValue::from_str(value).map_err(|e| e.to_string())?;
+            request
+                .headers_mut()
+                .insert(HeaderName::from_static(name), value);
+        }
+
+        let host = match request.url().port() {
+            Some(port) => format!("{}:{}", request.url().host_str().unwrap_or_default(), port),
+            None => request.url().host_str().unwrap_or_default().to_string(),
+        };
+        headers.push(("host", host));
+        let authorization = self.authorization(
+            request.method().as_str(),
+            request.url(),
+            &mut headers,
+            &amz_date,
+            UNSIGNED_PAYLOAD,
+        );
+        let value = HeaderValue::from_str(&authorization).map_err(|e| e.to_string())?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+        Ok(())
+    }
+
+    /// `headers` are lowercase name/value pairs, every one of them gets signed
+    fn authorization(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &mut [(&str, String)],
+        amz_date: &str,
+        payload_hash: &str,
+    ) -> String {
+        headers.sort_by(|a, b| a.0.cmp(b.0));
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| (uri_encode(k.as_bytes()), uri_encode(v.as_bytes())))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        let canonical_uri = url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    // they come percent-encoded from Url, decode before encoding the AWS way
+                    .map(|s| uri_encode(&percent_decode(s)))
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
+
+        let canonical_request = format!(
+            "{}\n/{}\n{}\n{}\n{}\n{}",
+            method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = hmac(format!("AWS4{}", self.secret_access_key).as_bytes(), date.as_bytes());
+        let key = hmac(&key, self.region.as_bytes());
+        let key = hmac(&key, b"s3");
+        let key = hmac(&key, b"aws4_request");
+        let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        )
+    }
+}
+
+/// HMAC-SHA256, webhooks sign their bodies with it too
+pub(crate) fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+// RFC 3986 unreserved characters stay, everything else becomes %XX
+fn uri_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
@@ -0,0 +1,199 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use url::{Host, Url};
+
+use crate::settings::NetworkConfig;
+
+/// Redirects a download follows before it fails
+const MAX_REDIRECTS: usize = 10;
+
+/// Err when `url` points at this machine, the local network or a cloud metadata
+/// service and `network.block_private_targets` is on. Hosts in
+/// `network.allowed_private_hosts` pass, so does a name that doesn't resolve, the probe
+/// fails on it anyway.
+pub async fn check(config: &NetworkConfig, url: &Url) -> Result<(), String> {
+    if !config.block_private_targets {
+        return Ok(());
+    }
+    let Some(host) = url.host() else {
+        return Ok(());
+    };
+    let name = host.to_string();
+    if allowed(&config.allowed_private_hosts, &name) {
+        return Ok(());
+    }
+    let addresses: Vec<IpAddr> = match host {
+        Host::Ipv4(ip) => vec![IpAddr::V4(ip)],
+        Host::Ipv6(ip) => vec![IpAddr::V6(ip)],
+        Host::Domain(domain) => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            match tokio::net::lookup_host((domain, port)).await {
+                Ok(found) => found.map(|a| a.ip()).collect(),
+                Err(_) => return Ok(()),
+            }
+        }
+    };
+    // any private answer counts, a name can resolve to both kinds
+    match addresses.into_iter().find(|ip| private(*ip)) {
+        Some(ip) => Err(format!("{} points at the private address {}", name, ip)),
+        None => Ok(()),
+    }
+}
+
+/// DNS for guarded downloads: private answers are dropped, a name left without any
+/// doesn't connect. It runs for every connection, so a name that passed `check` can't
+/// switch to a private address later. A proxy resolves names itself, this doesn't see those.
+pub struct Resolver {
+    allowed: Vec<String>,
+}
+
+impl Resolver {
+    pub fn new(config: &NetworkConfig) -> Self {
+        Resolver {
+            allowed: config.allowed_private_hosts.clone(),
+        }
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let name = name.as_str().to_string();
+        let open = allowed(&self.allowed, &name);
+        Box::pin(async move {
+            let found: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| open || !private(address.ip()))
+                .collect();
+            if found.is_empty() {
+                return Err(format!("{} only resolves to private addresses", name).into());
+            }
+            Ok(Box::new(found.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Redirects for guarded downloads, `MAX_REDIRECTS` of them and none to a private
+/// address written out in the url. Names are left to `Resolver`.
+pub fn redirects(config: &NetworkConfig) -> Policy {
+    let hosts = config.allowed_private_hosts.clone();
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        let literal = match attempt.url().host() {
+            Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+            _ => None,
+        };
+        match literal {
+            Some(ip) if private(ip) && !allowed(&hosts, &ip.to_string()) => {
+                let reason = format!("redirected to the private address {}", ip);
+                attempt.error(reason)
+            }
+            _ => attempt.follow(),
+        }
+    })
+}
+
+// listed in `network.allowed_private_hosts`, v6 literals with or without brackets
+fn allowed(hosts: &[String], name: &str) -> bool {
+    let name = name.trim_matches(['[', ']']);
+    hosts
+        .iter()
+        .any(|allowed| allowed.trim().eq_ignore_ascii_case(name))
+}
+
+/// Loopback, private ranges, link-local (which holds 169.254.169.254, the metadata
+/// service of most clouds), shared CGNAT space and unspecified addresses
+pub fn private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => private_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => private_v4(ip),
+            None => private_v6(ip),
+        },
+    }
+}
+
+fn private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // 0.0.0.0/8 reaches the local host on some systems
+        || a == 0
+}
+
+fn private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // fc00::/7 unique local, fe80::/10 link-local
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn private_ranges() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            // cloud metadata, link-local
+            "169.254.169.254",
+            // CGNAT
+            "100.64.0.1",
+            "100.127.255.254",
+            // 0.0.0.0/8
+            "0.0.0.0",
+            "0.1.2.3",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "febf::1",
+            // v4 mapped into v6
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(private(ip(address)), "{} counts as private", address);
+        }
+        for address in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "100.63.255.255",
+            "100.128.0.1",
+            "169.255.0.1",
+            "2606:4700::1111",
+            "fec0::1",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(!private(ip(address)), "{} counts as public", address);
+        }
+    }
+
+    #[test]
+    fn allowed_hosts_ignore_brackets_and_case() {
+        let hosts = vec![" NAS.local ".to_string(), "::1".to_string()];
+        assert!(allowed(&hosts, "nas.local"));
+        assert!(allowed(&hosts, "[::1]"));
+        assert!(!allowed(&hosts, "127.0.0.1"));
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// one sample per second for the last five minutes
+const CAPACITY: usize = 300;
+/// points kept for the whole transfer once it's over
+const STORED_POINTS: usize = 120;
+
+/// Speeds in bytes/s, `interval` seconds apart
+#[derive(Debug, Clone, Default, Serialize, specta::Type)]
+pub struct SpeedSeries {
+    pub interval: u32,
+    pub samples: Vec<u64>,
+}
+
+/// Ring buffer of recent speed samples plus a coarse series covering the whole run
+pub struct SpeedHistory {
+    recent: VecDeque<u64>,
+    overall: Vec<u64>,
+    interval: u32,
+    bucket: (u64, u32),
+}
+
+impl Default for SpeedHistory {
+    fn default() -> Self {
+        SpeedHistory {
+            recent: VecDeque::with_capacity(CAPACITY),
+            overall: Vec::with_capacity(STORED_POINTS * 2),
+            interval: 1,
+            bucket: (0, 0),
+        }
+    }
+}
+
+impl SpeedHistory {
+    /// Record one second worth of throughput
+    pub fn push(&mut self, bytes_per_sec: u64) {
+        if self.recent.len() == CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(bytes_per_sec);
+
+        self.bucket.0 += bytes_per_sec;
+        self.bucket.1 += 1;
+        if self.bucket.1 < self.interval {
+            return;
+        }
+        self.overall.push(self.bucket.0 / self.interval as u64);
+        self.bucket = (0, 0);
+
+        // halve the resolution instead of growing forever
+        if self.overall.len() == STORED_POINTS * 2 {
+            self.overall = self
+                .overall
+                .chunks(2)
+                .map(|pair| pair.iter().sum::<u64>() / pair.len() as u64)
+                .collect();
+            self.interval *= 2;
+        }
+    }
+
+    pub fn current(&self) -> u64 {
+        self.recent.back().copied().unwrap_or(0)
+    }
+
+    /// Mean over the whole run so far
+    pub fn average(&self) -> u64 {
+        let bytes = self.overall.iter().sum::<u64>() * self.interval as u64 + self.bucket.0;
+        let seconds = self.overall.len() as u64 * self.interval as u64 + self.bucket.1 as u64;
+        bytes.checked_div(seconds).unwrap_or(0)
+    }
+
+    pub fn recent(&self) -> SpeedSeries {
+        SpeedSeries {
+            interval: 1,
+            samples: self.recent.iter().copied().collect(),
+        }
+    }
+
+    /// Whole run at a resolution that fits STORED_POINTS
+    pub fn downsampled(&self) -> SpeedSeries {
+        let mut samples = self.overall.clone();
+        if self.bucket.1 > 0 {
+            samples.push(self.bucket.0 / self.bucket.1 as u64);
+        }
+        SpeedSeries {
+            interval: self.interval,
+            samples,
+        }
+    }
+}
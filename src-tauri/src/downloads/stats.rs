@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use time::{Date, Month, OffsetDateTime};
+use uuid::Uuid;
+
+use super::client;
+use super::manager::DownloadManager;
+use super::units::Units;
+use crate::database::{Activity, ActivityFilter};
+use crate::i18n;
+use crate::notifications;
+use crate::settings::{self, BandwidthConfig};
+
+/// How often received bytes are written down and checked against the cap
+const FLUSH_EVERY: Duration = Duration::from_secs(10);
+/// Activity kinds that end a run of a download
+const RUN_ENDS: [&str; 4] = ["completed", "failed", "paused", "cancelled"];
+
+/// set while this billing month is over `bandwidth.monthly_cap_mib`
+static CAPPED: AtomicBool = AtomicBool::new(false);
+
+/// Bytes received per day and where the month stands against the cap
+#[derive(Debug, Serialize, specta::Type)]
+pub struct TransferStats {
+    /// oldest first, days with nothing received are left out
+    pub days: Vec<DayTotal>,
+    /// received since `period_start`
+    pub period_total: u64,
+    pub period_start: String,
+    /// first day of the next billing month
+    pub next_reset: String,
+    /// bytes, None without a cap
+    pub cap: Option<u64>,
+    pub cap_reached: bool,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct DayTotal {
+    /// "YYYY-MM-DD", UTC
+    pub day: String,
+    pub bytes: u64,
+}
+
+/// Time span `export` covers, unix seconds, inclusive, open ended when left out
+#[derive(Debug, Clone, Default, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct Period {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, specta::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// What one download fetched in its runs that started within a period
+#[derive(Debug, Serialize)]
+pub struct JournalRow {
+    pub id: Uuid,
+    pub url: String,
+    pub filename: String,
+    /// bytes, None when the server didn't say
+    pub size: Option<i64>,
+    /// bytes received in these runs, a resume doesn't count what was there before
+    pub received: u64,
+    /// unix seconds the first run started
+    pub started: i64,
+    /// seconds spent running
+    pub duration: u64,
+    /// bytes/s while running
+    pub average_speed: u64,
+    /// how the last run ended: completed, failed, paused or cancelled
+    pub status: String,
+}
+
+/// Per download rows of the runs that started within `period`, oldest first, as CSV or
+/// pretty JSON. Removed downloads still have theirs, from when they were added.
+pub fn export(app: &AppHandle, period: &Period, format: ExportFormat) -> Result<String, String> {
+    let manager = app.state::<DownloadManager>();
+    let db = manager.db();
+    let mut kinds = vec!["started".to_string()];
+    kinds.extend(RUN_ENDS.iter().map(|k| k.to_string()));
+    let filter = ActivityFilter {
+        kinds,
+        since: period.since,
+        until: period.until,
+        limit: Some(i64::MAX),
+        ..Default::default()
+    };
+    let mut activity = db.get_activity(&filter).map_err(|e| e.to_string())?;
+    activity.reverse();
+
+    let mut rows = journal(&activity);
+    for row in &mut rows {
+        let record = db.get_download_by_id(&row.id).map_err(|e| e.to_string())?;
+        let detail = match record {
+            Some(record) => (record.url, record.filename, record.size),
+            None => added(app, &row.id)?,
+        };
+        (row.url, row.filename, row.size) = detail;
+    }
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&rows).map_err(|e| e.to_string()),
+        ExportFormat::Csv => Ok(csv(&rows)),
+    }
+}
+
+/// Runs in `activity`, oldest first, folded into a row per download. A run counts when
+/// its start is in there, the bytes it received are those its end reported over the ones
+/// it started from. Url, name and size are left for the caller.
+pub fn journal(activity: &[Activity]) -> Vec<JournalRow> {
+    let mut rows: Vec<JournalRow> = Vec::new();
+    // download -> (started at, bytes it started from)
+    let mut open: HashMap<Uuid, (i64, u64)> = HashMap::new();
+    for entry in activity {
+        let Some(id) = entry.download_id else {
+            continue;
+        };
+        let bytes = |key: &str| entry.detail.get(key).and_then(|v| v.as_u64());
+        if entry.kind == "started" {
+            let resume = entry.detail.get("resume").and_then(|v| v.as_bool());
+            let from = match resume {
+                Some(true) => bytes("from").unwrap_or(0),
+                _ => 0,
+            };
+            open.insert(id, (entry.at, from));
+            continue;
+        }
+        let Some((started, from)) = open.remove(&id) else {
+            continue;
+        };
+        let index = match rows.iter().position(|r| r.id == id) {
+            Some(index) => index,
+            None => {
+                rows.push(JournalRow {
+                    id,
+                    url: String::new(),
+                    filename: String::new(),
+                    size: None,
+                    received: 0,
+                    started,
+                    duration: 0,
+                    average_speed: 0,
+                    status: String::new(),
+                });
+                rows.len() - 1
+            }
+        };
+        let row = &mut rows[index];
+        row.received += bytes("downloaded").unwrap_or(0).saturating_sub(from);
+        row.duration += entry.at.saturating_sub(started).max(0) as u64;
+        row.average_speed = row
+            .received
+            .checked_div(row.duration)
+            .unwrap_or(row.received);
+        row.status = entry.kind.clone();
+    }
+    rows
+}
+
+/// `rows` as RFC 4180 CSV with a header line, times in UTC
+pub fn csv(rows: &[JournalRow]) -> String {
+    let mut out =
+        String::from("id,url,filename,size,received,started,duration,average_speed,status\r\n");
+    for row in rows {
+        let started = OffsetDateTime::from_unix_timestamp(row.started)
+            .map(|t| {
+                format!(
+                    "{} {:02}:{:02}:{:02}",
+                    day(t.date()),
+                    t.hour(),
+                    t.minute(),
+                    t.second()
+                )
+            })
+            .unwrap_or_default();
+        let cells = [
+            row.id.to_string(),
+            cell(&row.url),
+            cell(&row.filename),
+            row.size.map(|s| s.to_string()).unwrap_or_default(),
+            row.received.to_string(),
+            started,
+            row.duration.to_string(),
+            row.average_speed.to_string(),
+            row.status.clone(),
+        ];
+        out.push_str(&cells.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+// quoted when it has to be, and kept from being read as a formula by spreadsheets
+fn cell(value: &str) -> String {
+    let value = match value.starts_with(['=', '+', '-', '@']) {
+        true => format!("'{}", value),
+        false => value.to_string(),
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+// url, name and size a removed download was added with
+fn added(app: &AppHandle, id: &Uuid) -> Result<(String, String, Option<i64>), String> {
+    let filter = ActivityFilter {
+        kinds: vec!["added".into()],
+        download_id: Some(*id),
+        limit: Some(1),
+        ..Default::default()
+    };
+    let manager = app.state::<DownloadManager>();
+    let activity = manager
+        .db()
+        .get_activity(&filter)
+        .map_err(|e| e.to_string())?;
+    let detail = activity.first().map(|a| &a.detail);
+    let text = |key: &str| {
+        detail
+            .and_then(|d| d.get(key))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let size = detail.and_then(|d| d.get("size")).and_then(|v| v.as_i64());
+    Ok((text("url"), text("filename"), size))
+}
+
+/// Non-priority downloads are held while this is set
+pub fn cap_reached() -> bool {
+    CAPPED.load(Ordering::Relaxed)
+}
+
+/// The last `days` days, and the billing month however far back it started
+pub fn report(app: &AppHandle, days: u32) -> Result<TransferStats, String> {
+    flush(app);
+    let config = settings::load_or_create(app).bandwidth;
+    let today = OffsetDateTime::now_utc().date();
+    let (start, next) = period(today, config.billing_day);
+    let from = (today - time::Duration::days(days.saturating_sub(1) as i64)).min(start);
+    let manager = app.state::<DownloadManager>();
+    let totals = manager
+        .db()
+        .get_received_since(&day(from))
+        .map_err(|e| e.to_string())?;
+
+    let period_start = day(start);
+    let period_total = totals
+        .iter()
+        .filter(|(day, _)| *day >= period_start)
+        .map(|(_, bytes)| *bytes as u64)
+        .sum();
+    let first = day(today - time::Duration::days(days.saturating_sub(1) as i64));
+    Ok(TransferStats {
+        days: totals
+            .into_iter()
+            .filter(|(day, _)| *day >= first)
+            .map(|(day, bytes)| DayTotal {
+                day,
+                bytes: bytes as u64,
+            })
+            .collect(),
+        period_total,
+        period_start,
+        next_reset: day(next),
+        cap: cap(&config),
+        cap_reached: cap_reached(),
+    })
+}
+
+/// Write what was received since the last flush, then pause non-priority downloads
+/// the first time the month goes over the cap
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_EVERY);
+        loop {
+            interval.tick().await;
+            let app = app.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                flush(&app);
+                check(&app);
+            })
+            .await;
+        }
+    });
+}
+
+fn flush(app: &AppHandle) {
+    let received = client::take_received();
+    if received == 0 {
+        return;
+    }
+    let today = day(OffsetDateTime::now_utc().date());
+    let manager = app.state::<DownloadManager>();
+    if let Err(e) = manager.db().add_received(&today, received) {
+        eprintln!("Failed to save transfer stats: {}", e);
+    }
+}
+
+fn check(app: &AppHandle) {
+    let config = settings::load_or_create(app).bandwidth;
+    let Some(cap) = cap(&config) else {
+        CAPPED.store(false, Ordering::Relaxed);
+        return;
+    };
+    let today = OffsetDateTime::now_utc().date();
+    let (start, next) = period(today, config.billing_day);
+    let manager = app.state::<DownloadManager>();
+    let total: u64 = match manager.db().get_received_since(&day(start)) {
+        Ok(days) => days.iter().map(|(_, bytes)| *bytes as u64).sum(),
+        Err(e) => {
+            eprintln!("Failed to read transfer stats: {}", e);
+            return;
+        }
+    };
+    let reached = total >= cap;
+    // a new billing month or a raised cap lets them go again, the user resumes them
+    if !reached || CAPPED.swap(true, Ordering::Relaxed) {
+        CAPPED.store(reached, Ordering::Relaxed);
+        return;
+    }
+    let paused = manager.pause_unprioritized();
+    manager.log_activity(
+        "cap_reached",
+        None,
+        serde_json::json!({ "received": total, "cap": cap, "paused": paused }),
+    );
+    let units = Units::from_config(&settings::load_or_create(app).app);
+    let message = i18n::tf(
+        "notify.cap_reached",
+        &[("cap", &units.size(cap)), ("date", &day(next))],
+    );
+    notifications::notify(app, "cap_reached", None, message);
+}
+
+fn cap(config: &BandwidthConfig) -> Option<u64> {
+    (config.monthly_cap_mib > 0).then_some(config.monthly_cap_mib << 20)
+}
+
+// first day of the billing month `today` is in, and of the one after
+fn period(today: Date, billing_day: u8) -> (Date, Date) {
+    let billing_day = billing_day.clamp(1, 28);
+    let on = |year: i32, month: Month| {
+        Date::from_calendar_date(year, month, billing_day).unwrap_or(today)
+    };
+    let this = on(today.year(), today.month());
+    let previous = match today.month() {
+        Month::January => on(today.year() - 1, Month::December),
+        month => on(today.year(), month.previous()),
+    };
+    let following = match today.month() {
+        Month::December => on(today.year() + 1, Month::January),
+        month => on(today.year(), month.next()),
+    };
+    if today >= this {
+        (this, following)
+    } else {
+        (previous, this)
+    }
+}
+
+fn day(date: Date) -> String {
+    format!(
+        "{}-{:02}-{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
@@ -0,0 +1,435 @@
+use icu_normalizer::{ComposingNormalizerBorrowed, DecomposingNormalizerBorrowed};
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+use super::category;
+use super::winpath;
+use crate::settings::AppSettings;
+
+/// Variables a template can use, e.g. `{date}/{host}/{filename}` or `{category}/{filename}`
+pub const VARIABLES: &[&str] = &[
+    "filename", "name", "ext", "host", "category", "date", "year", "month", "day",
+];
+
+/// Where a new download goes: the category's template, else the global one, under the
+/// download location. `category` is one set by hand, else it's guessed from the name and
+/// content type. Missing folders are created.
+pub fn destination(
+    settings: &AppSettings,
+    base: &Path,
+    url: &Url,
+    filename: &str,
+    content_type: Option<&str>,
+    category: Option<&'static str>,
+) -> Result<PathBuf, String> {
+    let destination = planned(settings, base, url, filename, content_type, category)?;
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(winpath::long_path(parent)).map_err(|e| e.to_string())?;
+    }
+    Ok(destination)
+}
+
+/// [`destination`] without creating anything, for showing where a download would go
+pub fn planned(
+    settings: &AppSettings,
+    base: &Path,
+    url: &Url,
+    filename: &str,
+    content_type: Option<&str>,
+    category: Option<&'static str>,
+) -> Result<PathBuf, String> {
+    let category = category.unwrap_or_else(|| category::category(filename, content_type));
+    let template = settings
+        .download
+        .category_templates
+        .get(category)
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or(&settings.download.filename_template);
+
+    let filename = &normalize(&settings.download.filename_unicode, filename);
+    let today = time::OffsetDateTime::now_utc().date();
+    let path = Path::new(filename);
+    let value = |name: &str| -> Option<String> {
+        Some(match name {
+            "filename" => filename.to_string(),
+            "name" => path.file_stem()?.to_string_lossy().to_string(),
+            "ext" => path.extension()?.to_string_lossy().to_string(),
+            "host" => url.host_str()?.trim_start_matches("www.").to_string(),
+            "category" => category.to_string(),
+            "date" => format!("{}-{:02}-{:02}", today.year(), today.month() as u8, today.day()),
+            "year" => today.year().to_string(),
+            "month" => format!("{:02}", today.month() as u8),
+            "day" => format!("{:02}", today.day()),
+            _ => return None,
+        })
+    };
+
+    let relative = render(template, value)?;
+    // a template that renders to nothing still has to name the file
+    let relative = if relative.as_os_str().is_empty() {
+        PathBuf::from(winpath::component(&sanitize(filename)).as_ref())
+    } else {
+        relative
+    };
+    Ok(base.join(relative))
+}
+
+/// Fill `{variable}`s in; `/` in the template separates folders, values can't add any.
+/// Unknown variables are an error so a typo doesn't end up as a folder name.
+pub fn render(
+    template: &str,
+    value: impl Fn(&str) -> Option<String>,
+) -> Result<PathBuf, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed {{ in template {}", template))?;
+        let name = &rest[start + 1..start + end];
+        if !VARIABLES.contains(&name) {
+            return Err(format!("Unknown template variable {{{}}}", name));
+        }
+        // known but unavailable, e.g. {ext} of a file without one
+        out.push_str(&sanitize(&value(name).unwrap_or_default()));
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+
+    // only plain names, the result has to stay inside the download location and be creatable
+    let mut path = PathBuf::new();
+    for part in out.split(['/', '\\']) {
+        let part = part.trim();
+        if part.is_empty() || part == "." {
+            continue;
+        }
+        match Path::new(part).components().next() {
+            Some(Component::Normal(_)) => path.push(winpath::component(part).as_ref()),
+            _ => return Err(format!("Template {} leaves the download folder", template)),
+        }
+    }
+    Ok(path)
+}
+
+/// `name` as written to disk per the `filename_unicode` setting: "nfc" composes accents so
+/// macOS-style decomposed names match, "ascii" transliterates for servers and filesystems
+/// that mangle anything else. Anything else keeps it as is
+pub fn normalize(mode: &str, name: &str) -> String {
+    match mode {
+        "nfc" => ComposingNormalizerBorrowed::new_nfc().normalize(name).into_owned(),
+        "ascii" => transliterate(name),
+        _ => name.to_string(),
+    }
+}
+
+// decompose, drop the accents, spell out letters that have no base form,
+// and replace each run of whatever is left with one underscore
+fn transliterate(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut replaced = false;
+    for c in DecomposingNormalizerBorrowed::new_nfkd().normalize_iter(name.chars()) {
+        if c.is_ascii() {
+            out.push(c);
+            replaced = false;
+            continue;
+        }
+        if is_combining(c) {
+            continue;
+        }
+        let spelled = match c {
+            'ß' => "ss",
+            'æ' => "ae",
+            'Æ' => "AE",
+            'œ' => "oe",
+            'Œ' => "OE",
+            'ø' => "o",
+            'Ø' => "O",
+            'đ' | 'ð' => "d",
+            'Đ' | 'Ð' => "D",
+            'ł' => "l",
+            'Ł' => "L",
+            'þ' => "th",
+            'Þ' => "Th",
+            'ı' => "i",
+            '‘' | '’' => "'",
+            '“' | '”' => "\"",
+            '–' | '—' => "-",
+            _ if !replaced => {
+                out.push('_');
+                replaced = true;
+                continue;
+            }
+            _ => continue,
+        };
+        out.push_str(spelled);
+        replaced = false;
+    }
+    out
+}
+
+// the combining diacritical mark blocks
+fn is_combining(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+// values become one path component
+fn sanitize(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match value.trim() {
+        "." | ".." => "_".into(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{existing, probe};
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION};
+
+    const CASES: u64 = 400;
+
+    // xorshift, a failing case comes back from the seed in its message
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n.max(1)
+        }
+
+        fn pick<'a>(&mut self, from: &[&'a str]) -> &'a str {
+            from[self.below(from.len() as u64) as usize]
+        }
+    }
+
+    // scripts, decomposed accents, emoji and what one filesystem or another refuses
+    const PIECES: &[&str] = &[
+        "report", "final", "v2", " ", ".", "-", "_", "отчёт", "Ελληνικά", "中文", "日本語の",
+        "한국어", "العربية", "עברית", "हिन्दी", "ไทย", "e\u{301}te\u{301}", "Å", "ß", "😀",
+        "👨‍👩‍👧", "%", "%2", "+", "&", "#", "'", "(1)", "CON", "nul", "COM1", "lpt9", ":",
+        "*", "?", "\"", "<", ">", "|", "\t", "\u{7f}", "..",
+    ];
+    const EXTENSIONS: &[&str] = &["", ".pdf", ".tar.gz", ".exe", ".зип", ".日本", "."];
+    const SEPARATORS: &[&str] = &["/", "\\", "../", "..\\", "C:\\", "//"];
+
+    fn name(rng: &mut Rng) -> String {
+        let mut name: String = (0..1 + rng.below(5)).map(|_| rng.pick(PIECES)).collect();
+        name.push_str(rng.pick(EXTENSIONS));
+        name
+    }
+
+    // percent-encode what a browser would, and a few more bytes at random
+    fn encode(rng: &mut Rng, name: &str) -> String {
+        let mut out = String::new();
+        for &b in name.as_bytes() {
+            let plain = b.is_ascii_alphanumeric() || b"-._~".contains(&b);
+            if plain && rng.below(4) != 0 {
+                out.push(b as char);
+            } else {
+                out.push_str(&format!("%{:02X}", b));
+            }
+        }
+        out
+    }
+
+    fn disposition(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_DISPOSITION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn expected(name: &str) -> Option<String> {
+        match name.trim() {
+            "" | "." | ".." => None,
+            name => Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn url_names_are_percent_decoded() {
+        let cases = [
+            ("https://a.test/f/%D0%BE%D1%82%D1%87%D1%91%D1%82.pdf", "отчёт.pdf"),
+            ("https://a.test/%E6%97%A5%E6%9C%AC%E8%AA%9E.zip?x=1#y", "日本語.zip"),
+            ("https://a.test/%D8%B9%D8%B1%D8%A8%D9%8A.txt", "عربي.txt"),
+            ("https://a.test/%F0%9F%98%80%20smile.png", "😀 smile.png"),
+            ("https://a.test/100%25%20done.txt", "100% done.txt"),
+            ("https://a.test/50%zz.bin", "50%zz.bin"),
+            ("https://a.test/dir%2F..%2Fsecret.txt", "secret.txt"),
+            ("https://a.test/%5C..%5Cevil.exe", "evil.exe"),
+            ("https://a.test/..%2F", "download"),
+            ("https://a.test/", "download"),
+        ];
+        for (url, name) in cases {
+            assert_eq!(probe::extract_filename_from_url(url), name, "{}", url);
+        }
+    }
+
+    #[test]
+    fn header_names_are_decoded_and_lose_their_folders() {
+        let cases = [
+            ("attachment; filename*=UTF-8''%E4%B8%AD%E6%96%87.zip", "中文.zip"),
+            ("attachment; filename*=iso-8859-1'fr'caf%E9.txt", "café.txt"),
+            (r#"attachment; filename="plain.txt"; filename*=UTF-8''%C3%A9t%C3%A9.txt"#, "été.txt"),
+            (r#"attachment; filename*=UTF-8''%C3%A9t%C3%A9.txt; filename="plain.txt""#, "été.txt"),
+            (r#"attachment; FILENAME="../../etc/passwd""#, "passwd"),
+            (r#"attachment; filename="C:\Windows\system32\x.exe""#, "x.exe"),
+            ("attachment; filename*=UTF-8''..%2F..%2F.bashrc", ".bashrc"),
+        ];
+        for (value, name) in cases {
+            let found = probe::extract_filename_from_headers(&disposition(value));
+            assert_eq!(found.as_deref(), Some(name), "{}", value);
+        }
+        let nothing = disposition(r#"attachment; filename="../""#);
+        assert_eq!(probe::extract_filename_from_headers(&nothing), None);
+    }
+
+    #[test]
+    fn extracted_names_are_the_last_segment_decoded() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let name = name(&mut rng);
+            let mut path = String::new();
+            for _ in 0..rng.below(3) {
+                let separator = rng.pick(SEPARATORS);
+                path.push_str(&encode(&mut rng, separator));
+                path.push_str(&encode(&mut rng, "dir"));
+            }
+            if !path.is_empty() {
+                path.push_str(&encode(&mut rng, "/"));
+            }
+            let url = format!("https://a.test/files/{}{}", path, encode(&mut rng, &name));
+            let from_url = probe::extract_filename_from_url(&url);
+            let header = format!("attachment; filename*=UTF-8''{}{}", path, encode(&mut rng, &name));
+            let from_header = probe::extract_filename_from_headers(&disposition(&header));
+
+            let last = name.rsplit(['/', '\\']).next().unwrap();
+            let want = expected(last);
+            assert_eq!(from_header, want, "seed {}: {:?}", seed, name);
+            assert_eq!(from_url, want.unwrap_or("download".into()), "seed {}: {:?}", seed, name);
+            assert!(!from_url.contains(['/', '\\']), "seed {}: {:?}", seed, from_url);
+        }
+    }
+
+    #[test]
+    fn planned_paths_stay_in_the_download_folder() {
+        let templates = [
+            "{filename}",
+            "{category}/{filename}",
+            "{host}/{name}.{ext}",
+            "{date}/{year}-{month}/{name}",
+            "",
+        ];
+        let base = Path::new("/downloads");
+        let url = Url::parse("https://www.a.test/file").unwrap();
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let mut settings = AppSettings::default();
+            settings.download.filename_template = rng.pick(&templates).to_string();
+            settings.download.filename_unicode = rng.pick(&["keep", "nfc", "ascii"]).to_string();
+            // a name given by hand or a script isn't cleaned up before it gets here
+            let mut name = name(&mut rng);
+            if rng.below(3) == 0 {
+                name = format!("{}{}", rng.pick(SEPARATORS), name);
+            }
+            let Ok(path) = planned(&settings, base, &url, &name, None, None) else {
+                continue;
+            };
+            let relative = path.strip_prefix(base).expect("outside the download folder");
+            assert!(relative.components().count() > 0, "seed {}: {:?}", seed, name);
+            for part in relative.components() {
+                let Component::Normal(part) = part else {
+                    panic!("seed {}: {:?} became {:?}", seed, name, path);
+                };
+                let part = part.to_str().unwrap();
+                assert_eq!(part, part.trim(), "seed {}: {:?}", seed, name);
+                assert!(
+                    !part.contains(['/', '\\', ':', '*', '?', '"', '<', '>', '|'])
+                        && !part.chars().any(char::is_control),
+                    "seed {}: {:?} became {:?}",
+                    seed,
+                    name,
+                    part
+                );
+            }
+            if settings.download.filename_unicode == "ascii" {
+                assert!(path.to_str().unwrap().is_ascii(), "seed {}: {:?}", seed, path);
+            }
+        }
+    }
+
+    #[test]
+    fn windows_rules_leave_creatable_names() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let name = name(&mut rng);
+            let cleaned = sanitize(&name);
+            let part = winpath::normalize(&cleaned);
+            assert!(!part.is_empty(), "seed {}: {:?}", seed, name);
+            assert!(!part.ends_with(['.', ' ']), "seed {}: {:?}", seed, part);
+            let stem = part.split('.').next().unwrap().trim_end().to_ascii_uppercase();
+            let devices = ["CON", "PRN", "AUX", "NUL", "COM1", "LPT9"];
+            assert!(!devices.contains(&stem.as_str()), "seed {}: {:?}", seed, part);
+            assert_eq!(winpath::normalize(&part), part, "seed {}: {:?}", seed, part);
+        }
+    }
+
+    #[test]
+    fn nfc_names_match_however_they_were_composed() {
+        let decompose = DecomposingNormalizerBorrowed::new_nfd();
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let name = name(&mut rng);
+            let decomposed = decompose.normalize(&name);
+            assert_eq!(
+                normalize("nfc", &name),
+                normalize("nfc", &decomposed),
+                "seed {}: {:?}",
+                seed,
+                name
+            );
+            assert!(normalize("ascii", &name).is_ascii(), "seed {}: {:?}", seed, name);
+        }
+    }
+
+    #[test]
+    fn conflicts_get_a_free_name_next_to_the_original() {
+        let dir = std::env::temp_dir().join(format!("tur-names-{}", uuid::Uuid::now_v7()));
+        for seed in 0..CASES / 10 {
+            let mut rng = Rng::new(seed);
+            let name = winpath::normalize(&sanitize(&name(&mut rng))).into_owned();
+            let folder = dir.join(seed.to_string());
+            std::fs::create_dir_all(&folder).unwrap();
+            let original = folder.join(&name);
+            let mut taken = Vec::new();
+            for _ in 0..4 {
+                let free = existing::free_name(&original);
+                assert!(!free.exists(), "seed {}: {:?}", seed, free);
+                assert_eq!(free.parent(), Some(folder.as_path()), "seed {}: {:?}", seed, free);
+                assert_eq!(free.extension(), original.extension(), "seed {}: {:?}", seed, free);
+                std::fs::write(&free, b"").unwrap();
+                taken.push(free);
+            }
+            assert_eq!(taken[0], original);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+use super::category;
+use super::manager::DownloadManager;
+use crate::events::ThumbnailReady;
+
+/// Longest side of a generated thumbnail
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Version resource fields kept for executables
+const EXE_FIELDS: &[&str] = &[
+    "ProductName",
+    "FileDescription",
+    "CompanyName",
+    "FileVersion",
+];
+
+/// What the history view shows for a finished download
+#[derive(Debug, Clone, Default, Serialize, specta::Type)]
+pub struct Thumbnail {
+    /// png for images and videos, ico for executables
+    pub path: Option<PathBuf>,
+    /// version info of executables
+    pub info: BTreeMap<String, String>,
+}
+
+impl Thumbnail {
+    fn is_empty(&self) -> bool {
+        self.path.is_none() && self.info.is_empty()
+    }
+}
+
+type Job = (AppHandle, Uuid, PathBuf);
+
+// one worker for all of them, decoding large images shouldn't compete with downloads
+static QUEUE: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+
+/// Generate the thumbnail of a finished download in the background
+pub fn queue(app: &AppHandle, id: Uuid, path: PathBuf) {
+    let sender = QUEUE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("tur-thumbs".into())
+            .spawn(move || {
+                for (app, id, path) in receiver {
+                    if let Err(e) = process(&app, &id, &path) {
+                        eprintln!("Failed to create thumbnail for {}: {}", id, e);
+                    }
+                }
+            })
+            .expect("failed to spawn thumbnail worker");
+        Mutex::new(sender)
+    });
+    let _ = sender.lock().unwrap().send((app.clone(), id, path));
+}
+
+fn process(app: &AppHandle, id: &Uuid, path: &Path) -> Result<(), String> {
+    let dir = crate::profiles::data_dir(app)?.join("thumbnails");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let thumbnail = generate(&dir, id, path)?;
+    if thumbnail.is_empty() {
+        return Ok(());
+    }
+    let manager = app.state::<DownloadManager>();
+    manager
+        .db()
+        .save_thumbnail(id, thumbnail.path.as_deref(), &thumbnail.info)
+        .map_err(|e| e.to_string())?;
+    let payload = ThumbnailReady {
+        id: *id,
+        thumbnail,
+    };
+    if let Err(e) = app.emit("thumbnail_ready", payload) {
+        eprintln!("Failed to emit thumbnail_ready event: {}", e);
+    }
+    Ok(())
+}
+
+/// Thumbnail of `path` written into `dir`, empty for files there's nothing to show for
+pub fn generate(dir: &Path, id: &Uuid, path: &Path) -> Result<Thumbnail, String> {
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let name = id.as_simple().to_string();
+
+    match (category::category(&filename, None), extension.as_str()) {
+        ("image", _) => {
+            let target = dir.join(format!("{}.png", name));
+            image::open(path)
+                .map_err(|e| e.to_string())?
+                .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                .save(&target)
+                .map_err(|e| e.to_string())?;
+            Ok(Thumbnail {
+                path: Some(target),
+                ..Default::default()
+            })
+        }
+        ("video", _) => {
+            let target = dir.join(format!("{}.png", name));
+            Ok(Thumbnail {
+                path: first_frame(path, &target).then_some(target),
+                ..Default::default()
+            })
+        }
+        (_, "exe" | "dll") => executable(dir, &name, path),
+        _ => Ok(Thumbnail::default()),
+    }
+}
+
+// best effort, only when ffmpeg is installed
+fn first_frame(path: &Path, target: &Path) -> bool {
+    let scale = format!(
+        "scale='min({0},iw)':'min({0},ih)':force_original_aspect_ratio=decrease",
+        THUMBNAIL_SIZE
+    );
+    Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-vf", &scale])
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+        && target.exists()
+}
+
+// first icon group and the version strings of a PE file
+fn executable(dir: &Path, name: &str, path: &Path) -> Result<Thumbnail, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let file = pelite::PeFile::from_bytes(&data).map_err(|e| e.to_string())?;
+    let Ok(resources) = file.resources() else {
+        return Ok(Thumbnail::default());
+    };
+    let mut thumbnail = Thumbnail::default();
+
+    if let Some(Ok((_, group))) = resources.icons().next() {
+        let mut icon = Vec::new();
+        if group.write(&mut icon).is_ok() {
+            let target = dir.join(format!("{}.ico", name));
+            std::fs::write(&target, icon).map_err(|e| e.to_string())?;
+            thumbnail.path = Some(target);
+        }
+    }
+
+    if let Ok(version) = resources.version_info() {
+        if let Some(lang) = version.translation().first() {
+            for field in EXE_FIELDS {
+                if let Some(value) = version.value(*lang, field) {
+                    let value = value.trim_end_matches('\0').trim();
+                    if !value.is_empty() {
+                        thumbnail.info.insert(field.to_string(), value.to_string());
+                    }
+                }
+            }
+        }
+    }
+    Ok(thumbnail)
+}
@@ -0,0 +1,71 @@
+use crate::settings::AppConfig;
+
+/// How speeds and sizes are spelled out, from `app.speed_unit` and `app.unit_prefix`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Units {
+    /// Mbit/s instead of MB/s, sizes stay in bytes
+    pub bits: bool,
+    /// powers of 1000 (MB) instead of 1024 (MiB)
+    pub si: bool,
+}
+
+impl Units {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Units {
+            bits: config.speed_unit == "bits",
+            si: config.unit_prefix == "si",
+        }
+    }
+
+    /// "12.3 MiB/s", "98.7 Mbit/s" or "12.3 MB/s"
+    pub fn speed(&self, bytes_per_sec: u64) -> String {
+        if self.bits {
+            // line rates are always decimal
+            let (value, prefix) = scale(bytes_per_sec as f64 * 8.0, 1000.0);
+            format!("{} {}bit/s", value, prefix)
+        } else {
+            format!("{}/s", self.size(bytes_per_sec))
+        }
+    }
+
+    /// "1.4 GiB" or "1.5 GB"
+    pub fn size(&self, bytes: u64) -> String {
+        if self.si {
+            let (value, prefix) = scale(bytes as f64, 1000.0);
+            format!("{} {}B", value, prefix)
+        } else {
+            let (value, prefix) = scale(bytes as f64, 1024.0);
+            let prefix = if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}i", prefix.to_ascii_uppercase())
+            };
+            format!("{} {}B", value, prefix)
+        }
+    }
+}
+
+/// "2h 05m", "4m 09s" or "37s"
+pub fn eta(seconds: u64) -> String {
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
+
+// value with one decimal past the first prefix, plus the SI prefix letter
+fn scale(mut value: f64, step: f64) -> (String, &'static str) {
+    const PREFIXES: [&str; 6] = ["", "k", "M", "G", "T", "P"];
+    let mut i = 0;
+    while value >= step && i < PREFIXES.len() - 1 {
+        value /= step;
+        i += 1;
+    }
+    let value = if i == 0 {
+        format!("{}", value as u64)
+    } else {
+        format!("{:.1}", value)
+    };
+    (value, PREFIXES[i])
+}
@@ -0,0 +1,131 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Longest path the plain Win32 file APIs accept, without the terminating nul
+const MAX_PATH: usize = 259;
+
+/// Device names Windows opens instead of a file, with or without an extension
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "CONIN$",
+    "CONOUT$",
+];
+
+/// A file or folder name Windows can create, no-op elsewhere
+pub fn component(name: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        normalize(name)
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// `path` with the `\\?\` prefix when it is too long for the plain APIs, no-op elsewhere
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+    match path.to_str().and_then(extend) {
+        Some(extended) => Cow::Owned(extended.into()),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Strip the trailing dots and spaces Windows drops silently and rename reserved device
+/// names, `nul.txt` becomes `_nul.txt`
+pub fn normalize(name: &str) -> Cow<'_, str> {
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        return Cow::Owned("_".into());
+    }
+    // `CON.tar.gz` and `con .txt` are the device too
+    let stem = trimmed.split('.').next().unwrap_or(trimmed).trim_end();
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return Cow::Owned(format!("_{}", trimmed));
+    }
+    if trimmed.len() == name.len() {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(trimmed.to_string())
+    }
+}
+
+/// The `\\?\` form of an absolute path over `MAX_PATH`, None when it fits or can't take one
+pub fn extend(path: &str) -> Option<String> {
+    if path.len() <= MAX_PATH || path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    // the prefix turns off separator translation, so only backslashes from here on
+    let path = path.replace('/', "\\");
+    if let Some(share) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{}", share));
+    }
+    let bytes = path.as_bytes();
+    let absolute = bytes.len() > 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && bytes[2] == b'\\';
+    absolute.then(|| format!(r"\\?\{}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_names_get_a_prefix() {
+        assert_eq!(normalize("CON"), "_CON");
+        assert_eq!(normalize("nul.txt"), "_nul.txt");
+        assert_eq!(normalize("Com1.tar.gz"), "_Com1.tar.gz");
+        assert_eq!(normalize("lpt9 .log"), "_lpt9 .log");
+        assert_eq!(normalize("conout$"), "_conout$");
+    }
+
+    #[test]
+    fn names_that_only_start_like_devices_are_kept() {
+        assert_eq!(normalize("CONFIG.sys"), "CONFIG.sys");
+        assert_eq!(normalize("com10.txt"), "com10.txt");
+        assert_eq!(normalize("nullable.rs"), "nullable.rs");
+        assert!(matches!(normalize("report.pdf"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_dropped() {
+        assert_eq!(normalize("setup.exe. "), "setup.exe");
+        assert_eq!(normalize("notes..."), "notes");
+        assert_eq!(normalize("aux. "), "_aux");
+        assert_eq!(normalize(" . "), "_");
+        assert_eq!(normalize(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn short_paths_are_left_alone() {
+        assert_eq!(extend(r"C:\Users\me\Downloads\file.iso"), None);
+    }
+
+    #[test]
+    fn long_drive_paths_get_the_prefix() {
+        let path = format!(r"C:\Downloads\{}\file.bin", "a".repeat(300));
+        let extended = extend(&path).unwrap();
+        assert_eq!(extended, format!(r"\\?\{}", path));
+
+        let slashed = path.replace('\\', "/");
+        assert_eq!(extend(&slashed).unwrap(), extended);
+    }
+
+    #[test]
+    fn long_unc_paths_use_the_unc_prefix() {
+        let path = format!(r"\\nas\share\{}.bin", "b".repeat(300));
+        assert_eq!(
+            extend(&path).unwrap(),
+            format!(r"\\?\UNC\nas\share\{}.bin", "b".repeat(300))
+        );
+    }
+
+    #[test]
+    fn prefixed_and_relative_paths_are_not_extended() {
+        let long = "c".repeat(300);
+        assert_eq!(extend(&format!(r"\\?\C:\{}", long)), None);
+        assert_eq!(extend(&format!(r"downloads\{}", long)), None);
+    }
+}
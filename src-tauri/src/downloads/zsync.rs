@@ -0,0 +1,461 @@
+use md4::Md4;
+use reqwest::{header, StatusCode};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+use super::client::HttpClient;
+use super::core::Transfer;
+use super::io;
+use super::winpath;
+
+/// Control files past this are refused, one for a 100 GiB file is well under it
+const MAX_CONTROL: u64 = 256 << 20;
+/// Bytes read from the old copy at a time
+const READ_SIZE: usize = 4 << 20;
+
+/// What a delta update saved, logged as `delta_update`
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub control: String,
+    /// bytes taken from the old copy
+    pub reused: u64,
+    /// bytes fetched from the server
+    pub fetched: u64,
+}
+
+/// A `.zsync` control file: the target's size and hash plus a weak and a strong
+/// checksum per block
+#[derive(Debug)]
+pub struct Control {
+    pub blocksize: usize,
+    pub length: u64,
+    pub sha1: Option<String>,
+    seq_matches: usize,
+    rsum_bytes: usize,
+    blocks: Vec<Block>,
+}
+
+#[derive(Debug)]
+struct Block {
+    rsum: u32,
+    checksum: Vec<u8>,
+}
+
+impl Control {
+    /// Headers up to the first empty line, then the block checksums
+    pub fn parse(data: &[u8]) -> Result<Control, String> {
+        let end = data
+            .windows(2)
+            .position(|w| w == b"\n\n")
+            .ok_or("No end of the zsync headers")?;
+        let headers = std::str::from_utf8(&data[..end]).map_err(|e| e.to_string())?;
+        let mut fields = HashMap::new();
+        for line in headers.lines() {
+            if let Some((key, value)) = line.split_once(':') {
+                fields
+                    .entry(key.trim().to_ascii_lowercase())
+                    .or_insert_with(|| value.trim().to_string());
+            }
+        }
+        // gzip targets are rebuilt from the compressed stream, not supported
+        if fields.contains_key("z-map2") {
+            return Err("Compressed zsync targets aren't supported".into());
+        }
+        let number = |key: &str| -> Result<u64, String> {
+            fields
+                .get(key)
+                .ok_or_else(|| format!("No {} in the zsync headers", key))?
+                .parse()
+                .map_err(|_| format!("Bad {} in the zsync headers", key))
+        };
+        let blocksize = number("blocksize")? as usize;
+        let length = number("length")?;
+        if !blocksize.is_power_of_two() || blocksize < 16 {
+            return Err(format!("Bad zsync block size {}", blocksize));
+        }
+        let lengths: Vec<usize> = fields
+            .get("hash-lengths")
+            .map(String::as_str)
+            .unwrap_or("1,4,16")
+            .split(',')
+            .map(|n| n.trim().parse().map_err(|_| "Bad Hash-Lengths".to_string()))
+            .collect::<Result<_, _>>()?;
+        let [seq_matches, rsum_bytes, checksum_bytes] = lengths[..] else {
+            return Err("Bad Hash-Lengths".into());
+        };
+        if !(1..=2).contains(&seq_matches)
+            || !(1..=4).contains(&rsum_bytes)
+            || !(3..=16).contains(&checksum_bytes)
+        {
+            return Err("Bad Hash-Lengths".into());
+        }
+
+        let count = length.div_ceil(blocksize as u64) as usize;
+        let body = &data[end + 2..];
+        let entry = rsum_bytes + checksum_bytes;
+        if body.len() < count * entry {
+            return Err("The zsync file is cut short".into());
+        }
+        let blocks = body
+            .chunks_exact(entry)
+            .take(count)
+            .map(|entry| {
+                let (rsum, checksum) = entry.split_at(rsum_bytes);
+                Block {
+                    rsum: rsum.iter().fold(0, |n, b| (n << 8) | *b as u32),
+                    checksum: checksum.to_vec(),
+                }
+            })
+            .collect();
+        Ok(Control {
+            blocksize,
+            length,
+            sha1: fields.get("sha-1").map(|h| h.to_ascii_lowercase()),
+            seq_matches,
+            rsum_bytes,
+            blocks,
+        })
+    }
+
+    /// Where each block can be copied from in `seed`, None for the ones to fetch. Every
+    /// offset is tried, so blocks that moved in the new version are found too.
+    pub fn matches(&self, seed: &Path) -> std::io::Result<Vec<Option<u64>>> {
+        let bs = self.blocksize;
+        let mut table: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, block) in self.blocks.iter().enumerate() {
+            table.entry(block.rsum).or_default().push(i);
+        }
+        let mut found = vec![None; self.blocks.len()];
+        let mut file = File::open(winpath::long_path(seed))?;
+        let mut buf: Vec<u8> = Vec::new();
+        // file offset of buf[0]
+        let mut base = 0u64;
+        let mut eof = false;
+        let mut pos = 0;
+        let mut rsum = None;
+        loop {
+            // two blocks ahead for `seq_matches` 2, zeros past the end like zsync pads
+            if !eof && buf.len() < pos + 2 * bs {
+                buf.drain(..pos);
+                base += pos as u64;
+                pos = 0;
+                let filled = buf.len();
+                buf.resize(filled + READ_SIZE.max(2 * bs), 0);
+                let read = fill(&mut file, &mut buf[filled..])?;
+                buf.truncate(filled + read);
+                eof = read == 0;
+                continue;
+            }
+            if pos >= buf.len() {
+                break;
+            }
+            let window = |at: usize| padded(&buf, at, bs);
+            let (a, b) = *rsum.get_or_insert_with(|| weak(&window(pos)));
+            let mut matched = false;
+            if let Some(candidates) = table.get(&self.mask(a, b)) {
+                let digest = strong(&window(pos));
+                let mut next = None;
+                for &i in candidates {
+                    if found[i].is_some()
+                        || digest[..self.blocks[i].checksum.len()] != self.blocks[i].checksum
+                    {
+                        continue;
+                    }
+                    // short checksums only count together with the block after
+                    if self.seq_matches > 1 && i + 1 < self.blocks.len() {
+                        let following = next.get_or_insert_with(|| strong(&window(pos + bs)));
+                        let expected = &self.blocks[i + 1];
+                        if following[..expected.checksum.len()] != expected.checksum {
+                            continue;
+                        }
+                    }
+                    found[i] = Some(base + pos as u64);
+                    matched = true;
+                }
+            }
+            if matched {
+                pos += bs;
+                rsum = None;
+                continue;
+            }
+            let old = buf[pos];
+            let new = buf.get(pos + bs).copied().unwrap_or(0);
+            rsum = Some(roll(a, b, old, new, bs));
+            pos += 1;
+        }
+        Ok(found)
+    }
+
+    // only the last `rsum_bytes` of a, b made it into the control file
+    fn mask(&self, a: u16, b: u16) -> u32 {
+        let rsum = ((a as u32) << 16) | b as u32;
+        match self.rsum_bytes {
+            4 => rsum,
+            n => rsum & ((1 << (8 * n)) - 1),
+        }
+    }
+}
+
+/// Build `path` from `seed` and the blocks of `url` that changed, when the server has
+/// `<url>.zsync`. Ok(None) when there's no control file or nothing in `seed` to reuse,
+/// the whole file is fetched then. Assembled next to `path` and moved over it once the
+/// SHA-1 checks out, so `seed` can be the file being replaced.
+pub async fn update(
+    client: &HttpClient,
+    url: &Url,
+    seed: &Path,
+    path: &Path,
+    size: u64,
+    transfer: &Transfer,
+) -> Result<Option<Report>, String> {
+    if !seed.is_file() {
+        return Ok(None);
+    }
+    let mut control_url = url.clone();
+    control_url.set_path(&format!("{}.zsync", url.path()));
+    let Some(data) = fetch_control(client, &control_url).await? else {
+        return Ok(None);
+    };
+    let control = Arc::new(Control::parse(&data)?);
+    // a control file left over from the previous release
+    if control.length != size {
+        return Ok(None);
+    }
+    let found = {
+        let control = control.clone();
+        let seed = seed.to_path_buf();
+        tokio::task::spawn_blocking(move || control.matches(&seed))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?
+    };
+    if found.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+
+    let temp = temp_path(path);
+    let result = assemble(client, url, seed, &temp, &control, &found, transfer).await;
+    let report = match result {
+        Ok(fetched) if !transfer.is_stopped() => Report {
+            control: control_url.to_string(),
+            reused: size - fetched,
+            fetched,
+        },
+        other => {
+            let _ = std::fs::remove_file(&temp);
+            return other.map(|_| None);
+        }
+    };
+    std::fs::rename(&temp, winpath::long_path(path)).map_err(|e| e.to_string())?;
+    Ok(Some(report))
+}
+
+// copy the blocks found, fetch the runs between them, check the whole; fetched bytes
+async fn assemble(
+    client: &HttpClient,
+    url: &Url,
+    seed: &Path,
+    temp: &Path,
+    control: &Arc<Control>,
+    found: &[Option<u64>],
+    transfer: &Transfer,
+) -> Result<u64, String> {
+    let bs = control.blocksize as u64;
+    let length = control.length;
+    let block = |i: usize| (i as u64 * bs, ((i as u64 + 1) * bs).min(length));
+    let file = Arc::new(File::create(winpath::long_path(temp)).map_err(|e| e.to_string())?);
+    file.set_len(length).map_err(|e| e.to_string())?;
+    {
+        let file = file.clone();
+        let seed = seed.to_path_buf();
+        let copies: Vec<_> = found
+            .iter()
+            .enumerate()
+            .filter_map(|(i, from)| from.map(|from| (from, block(i))))
+            .collect();
+        let reused = tokio::task::spawn_blocking(move || copy_blocks(&seed, &file, &copies))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        transfer.add_downloaded(reused);
+    }
+
+    // runs of missing blocks, one range request each
+    let mut runs: Vec<(u64, u64)> = Vec::new();
+    for (i, from) in found.iter().enumerate() {
+        if from.is_some() {
+            continue;
+        }
+        let (start, end) = block(i);
+        match runs.last_mut() {
+            Some(last) if last.1 == start => last.1 = end,
+            _ => runs.push((start, end)),
+        }
+    }
+    let mut fetched = 0;
+    for (start, end) in runs {
+        if transfer.is_stopped() {
+            return Ok(fetched);
+        }
+        fetched += fetch_range(client, url, &file, start, end, transfer).await?;
+    }
+    file.sync_all().map_err(|e| e.to_string())?;
+    drop(file);
+
+    if let Some(expected) = control.sha1.clone() {
+        let temp = temp.to_path_buf();
+        let actual = tokio::task::spawn_blocking(move || sha1_file(&temp))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        if actual != expected {
+            return Err("The assembled file doesn't match the zsync SHA-1".into());
+        }
+    }
+    Ok(fetched)
+}
+
+// None when the server has no control file for it
+async fn fetch_control(client: &HttpClient, url: &Url) -> Result<Option<Vec<u8>>, String> {
+    let mut response = client.send(client.inner().get(url.clone())).await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = client.chunk(&mut response).await? {
+        data.extend_from_slice(&chunk);
+        if data.len() as u64 > MAX_CONTROL {
+            return Err(format!("{} is too large for a zsync file", url));
+        }
+    }
+    Ok(Some(data))
+}
+
+async fn fetch_range(
+    client: &HttpClient,
+    url: &Url,
+    file: &Arc<File>,
+    start: u64,
+    end: u64,
+    transfer: &Transfer,
+) -> Result<u64, String> {
+    let request = client
+        .inner()
+        .get(url.clone())
+        .header(header::RANGE, format!("bytes={}-{}", start, end - 1));
+    let mut response = client
+        .send(request)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(format!("{} ignored the range request", url));
+    }
+    let mut offset = start;
+    while let Some(chunk) = client.chunk(&mut response).await? {
+        if offset + chunk.len() as u64 > end {
+            return Err(format!("{} sent more than the range asked for", url));
+        }
+        let len = chunk.len() as u64;
+        io::write_at(file, chunk, offset)
+            .await
+            .map_err(|e| e.to_string())?;
+        offset += len;
+        transfer.add_downloaded(len);
+        if transfer.is_stopped() {
+            break;
+        }
+    }
+    if offset != end && !transfer.is_stopped() {
+        return Err(format!("{} ended the range early", url));
+    }
+    Ok(offset - start)
+}
+
+fn copy_blocks(seed: &Path, out: &File, copies: &[(u64, (u64, u64))]) -> std::io::Result<u64> {
+    let mut seed = File::open(winpath::long_path(seed))?;
+    let mut out = out;
+    let mut block = Vec::new();
+    let mut copied = 0;
+    for &(from, (start, end)) in copies {
+        block.resize((end - start) as usize, 0);
+        seed.seek(SeekFrom::Start(from))?;
+        let read = fill(&mut seed, &mut block)?;
+        // the last block of the old copy may be short, zsync matched it zero padded
+        block[read..].fill(0);
+        out.seek(SeekFrom::Start(start))?;
+        out.write_all(&block)?;
+        copied += end - start;
+    }
+    Ok(copied)
+}
+
+fn sha1_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(winpath::long_path(path))?;
+    let mut hasher = Sha1::new();
+    let mut buf = vec![0; READ_SIZE];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hex::encode(hasher.finalize()));
+        }
+        hasher.update(&buf[..read]);
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".zsync-part");
+    path.with_file_name(name)
+}
+
+// read until `buf` is full or the file ends
+fn fill(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn padded(buf: &[u8], at: usize, len: usize) -> Vec<u8> {
+    let mut block = buf[at.min(buf.len())..(at + len).min(buf.len())].to_vec();
+    block.resize(len, 0);
+    block
+}
+
+/// zsync's rolling checksum of a block
+fn weak(block: &[u8]) -> (u16, u16) {
+    let len = block.len();
+    block
+        .iter()
+        .enumerate()
+        .fold((0u16, 0u16), |(a, b), (i, &c)| {
+            (
+                a.wrapping_add(c as u16),
+                b.wrapping_add(((len - i) as u16).wrapping_mul(c as u16)),
+            )
+        })
+}
+
+// move the window one byte, `old` drops out and `new` comes in
+fn roll(a: u16, b: u16, old: u8, new: u8, len: usize) -> (u16, u16) {
+    let a = a.wrapping_sub(old as u16).wrapping_add(new as u16);
+    let b = b
+        .wrapping_sub((len as u16).wrapping_mul(old as u16))
+        .wrapping_add(a);
+    (a, b)
+}
+
+fn strong(block: &[u8]) -> Vec<u8> {
+    Md4::digest(block).to_vec()
+}
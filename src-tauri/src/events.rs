@@ -0,0 +1,229 @@
+use serde::Serialize;
+use specta::datatype::DataType;
+use specta::TypeCollection;
+use std::path::PathBuf;
+use url::Url;
+use uuid::Uuid;
+
+use crate::downloads::core::WorkerEvent;
+use crate::downloads::thumbnail::Thumbnail;
+use crate::downloads::DownloadOptions;
+use crate::notifications::Notification;
+
+type Payload = fn(&mut TypeCollection) -> DataType;
+
+/// Every event the backend emits with its payload, `bindings` exports them as is
+pub const EVENTS: [(&str, Payload); 19] = [
+    ("queue_download", payload::<QueueDownload>),
+    ("download_progress", payload::<DownloadProgress>),
+    ("download_debug", payload::<DownloadDebug>),
+    ("download_completed", payload::<DownloadFinished>),
+    ("download_paused", payload::<DownloadFinished>),
+    ("download_cancelled", payload::<DownloadFinished>),
+    ("download_failed", payload::<DownloadFinished>),
+    ("presigned_url_expired", payload::<DownloadFinished>),
+    ("download_retry_scheduled", payload::<RetryScheduled>),
+    ("thumbnail_ready", payload::<ThumbnailReady>),
+    ("duplicate_download", payload::<DuplicateDownload>),
+    ("history_synced", payload::<HistorySynced>),
+    ("notification", payload::<Notification>),
+    ("deep-link-received", payload::<DeepLinkReceived>),
+    ("session_restore_available", payload::<SessionRestore>),
+    ("private_target_held", payload::<PrivateTargetHeld>),
+    ("quarantine_changed", payload::<QuarantineChanged>),
+    ("size_mismatch", payload::<SizeMismatch>),
+    ("url_health", payload::<UrlHealth>),
+];
+
+fn payload<T: specta::Type>(types: &mut TypeCollection) -> DataType {
+    T::reference(types, &[]).inner
+}
+
+/// A download entering the queue, or a resume checking on one
+#[derive(Debug, Clone, Serialize, specta::Type)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueueDownload {
+    /// from the extension or the add dialog
+    External(Queued),
+    DeepLink(Queued),
+    /// a resume is asking the server whether the file changed
+    ResumeCheck {
+        id: Uuid,
+        url: String,
+        filename: String,
+        size: Option<i64>,
+    },
+    ResumeReady {
+        id: Uuid,
+        url: String,
+        filename: String,
+        size: Option<i64>,
+        bytes_received: i64,
+        resume_supported: bool,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        /// the file changed, it starts over
+        needs_restart: bool,
+    },
+    Upload {
+        id: Uuid,
+        url: String,
+        filename: String,
+        size: u64,
+        destination: String,
+        direction: String,
+        status: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Queued {
+    pub id: Uuid,
+    pub url: String,
+    pub filename: String,
+    pub size: Option<i64>,
+    pub destination: String,
+    pub job_id: Option<Uuid>,
+    pub resume_supported: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub status: String,
+    /// name of the rule that matched
+    pub rule: Option<String>,
+}
+
+/// Sent every progress tick of a running download
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct DownloadProgress {
+    pub id: Uuid,
+    pub downloaded: u64,
+    pub size: Option<u64>,
+    /// bytes per second
+    pub speed: u64,
+    /// seconds
+    pub eta: Option<u64>,
+    pub speed_text: String,
+    pub eta_text: Option<String>,
+}
+
+/// What one download's connections did since the last one, in debug mode only
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct DownloadDebug {
+    pub id: Uuid,
+    pub events: Vec<WorkerEvent>,
+}
+
+/// Sent once a download stops, whichever way
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct DownloadFinished {
+    pub id: Uuid,
+    pub url: String,
+    pub downloaded: i64,
+    pub error: Option<String>,
+    /// notification text in the user's language
+    pub message: String,
+    pub average_speed: u64,
+    pub average_speed_text: String,
+    /// average speed over what one connection got at the start, for completed
+    /// multi-connection downloads
+    pub acceleration: Option<f64>,
+    /// what cancelling did with the file, None for any other ending or when there was none
+    pub partial: Option<PartialFile>,
+}
+
+/// What a cancelled download left on disk, see `download.on_cancel`
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct PartialFile {
+    /// "kept" with its saved ranges for a resume, "truncated" or "deleted"
+    pub action: String,
+    /// bytes still in the file
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct RetryScheduled {
+    pub id: Uuid,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// seconds
+    pub retry_in: u64,
+    /// unix milliseconds
+    pub retry_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct ThumbnailReady {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub thumbnail: Thumbnail,
+}
+
+/// A finished download with the same content as an earlier one
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct DuplicateDownload {
+    pub id: Uuid,
+    pub path: PathBuf,
+    pub existing_id: Uuid,
+    pub existing_path: PathBuf,
+    pub size: u64,
+    /// replaced by a hard link to the earlier file
+    pub linked: bool,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct HistorySynced {
+    pub merged: usize,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct DeepLinkReceived {
+    pub url: String,
+    pub options: DownloadOptions,
+    /// "startup" or "command_line"
+    #[serde(rename = "type")]
+    pub source: String,
+}
+
+/// Downloads that were running at the last quit
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct SessionRestore {
+    pub ids: Vec<Uuid>,
+}
+
+/// Links from outside that point at this machine or the local network, not added.
+/// They wait in the backend until `token` confirms them.
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct PrivateTargetHeld {
+    /// confirms them through `confirm_private_targets`
+    pub token: Uuid,
+    pub urls: Vec<Url>,
+    /// one per url, which address it resolved to
+    pub reasons: Vec<String>,
+}
+
+/// A download going into or out of quarantine
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct QuarantineChanged {
+    pub id: Uuid,
+    /// "unscanned", "scanning" or "flagged", null once released
+    pub state: Option<String>,
+}
+
+/// The server sent another amount than the size it announced. The file was cut or
+/// extended to `actual` and the stored size corrected.
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct SizeMismatch {
+    pub id: Uuid,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// A check of a queued download's url found something other than last time
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct UrlHealth {
+    pub id: Uuid,
+    /// "gone", "changed", or "alive" again after one of those
+    pub health: String,
+    /// HTTP status when gone
+    pub status: Option<u16>,
+}
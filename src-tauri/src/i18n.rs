@@ -0,0 +1,131 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{OnceLock, RwLock};
+
+/// Embedded catalogs, flat `key -> text` JSON with the language's own name under `_name`.
+/// English is the fallback for anything a catalog is missing.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+    ("es", include_str!("../locales/es.json")),
+];
+
+const FALLBACK: &str = "en";
+
+/// Current language, None until set, then "system" is resolved from the environment
+static LANGUAGE: RwLock<Option<&'static str>> = RwLock::new(None);
+
+type Catalog = HashMap<String, String>;
+
+fn catalogs() -> &'static HashMap<&'static str, Catalog> {
+    static PARSED: OnceLock<HashMap<&'static str, Catalog>> = OnceLock::new();
+    PARSED.get_or_init(|| {
+        CATALOGS
+            .iter()
+            .map(|(code, data)| {
+                let catalog = serde_json::from_str(data).unwrap_or_else(|e| {
+                    eprintln!("Broken {} locale catalog: {}", code, e);
+                    Catalog::new()
+                });
+                (*code, catalog)
+            })
+            .collect()
+    })
+}
+
+/// A language the backend has strings for
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Locale {
+    pub code: &'static str,
+    pub name: String,
+}
+
+/// Switch to `language`, a code like "de" or "de-AT", or "system" for the OS locale
+pub fn set_language(language: &str) {
+    *LANGUAGE.write().unwrap() = Some(resolve(language));
+}
+
+/// The catalog `language` maps to, by full tag then primary subtag, English otherwise
+pub fn resolve(language: &str) -> &'static str {
+    let requested = if language.is_empty() || language == "system" {
+        system_language()
+    } else {
+        language.to_string()
+    };
+    // "pt_BR.UTF-8" and "pt-br" both look for "pt-BR" and then "pt"
+    let tag = requested
+        .split(['.', '@'])
+        .next()
+        .unwrap_or("")
+        .replace('_', "-");
+    let primary = tag.split('-').next().unwrap_or("");
+    CATALOGS
+        .iter()
+        .map(|(code, _)| *code)
+        .find(|code| code.eq_ignore_ascii_case(&tag))
+        .or_else(|| {
+            CATALOGS
+                .iter()
+                .map(|(code, _)| *code)
+                .find(|code| code.eq_ignore_ascii_case(primary))
+        })
+        .unwrap_or(FALLBACK)
+}
+
+// the usual POSIX variables, in their order of precedence
+fn system_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX")
+        .unwrap_or_default()
+}
+
+fn current() -> &'static str {
+    if let Some(language) = *LANGUAGE.read().unwrap() {
+        return language;
+    }
+    // CLI runs never load settings, they follow the environment
+    let language = resolve("system");
+    *LANGUAGE.write().unwrap() = Some(language);
+    language
+}
+
+/// Text for `key` in the current language
+pub fn t(key: &str) -> String {
+    let catalogs = catalogs();
+    catalogs
+        .get(current())
+        .and_then(|c| c.get(key))
+        .or_else(|| catalogs.get(FALLBACK).and_then(|c| c.get(key)))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// `t` with `{name}` placeholders filled in
+pub fn tf(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut text = t(key);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// Languages for the settings picker
+#[tauri::command]
+#[specta::specta]
+pub fn get_available_locales() -> Vec<Locale> {
+    let catalogs = catalogs();
+    CATALOGS
+        .iter()
+        .map(|(code, _)| Locale {
+            code,
+            name: catalogs
+                .get(code)
+                .and_then(|c| c.get("_name"))
+                .cloned()
+                .unwrap_or_else(|| code.to_string()),
+        })
+        .collect()
+}
@@ -1,14 +1,103 @@
-use serde_json::json;
-
 use tauri::{Emitter, Manager};
 use tauri_plugin_autostart::MacosLauncher;
 use tauri_plugin_deep_link::DeepLinkExt;
 
+use events::{DeepLinkReceived, SessionRestore};
+
 // use crate::download_manager::DownloadManager;
 pub mod args;
+pub mod bench;
+pub mod bindings;
+pub mod checksums;
+pub mod cli;
 pub mod database;
 pub mod downloads;
+pub mod events;
+pub mod i18n;
+pub mod lock;
+pub mod notifications;
+pub mod profiles;
+pub mod remote;
+pub mod scripting;
+pub mod security;
 pub mod settings;
+pub mod signals;
+pub mod sync;
+pub mod uploads;
+pub mod webhooks;
+
+// every command, once, for the invoke handler and the TypeScript bindings
+macro_rules! commands {
+    ($($with:ident)::+) => {
+        $($with)::+![
+            settings::get_settings,
+            settings::get_locked_settings,
+            settings::update_settings,
+            settings::update_setting,
+            get_autostart,
+            set_autostart,
+            downloads::handle_download_request,
+            downloads::confirm_private_targets,
+            downloads::get_speed_history,
+            downloads::get_thumbnail,
+            downloads::decrypt_download,
+            downloads::extract_links,
+            downloads::get_activity,
+            downloads::get_download_details,
+            downloads::await_download,
+            downloads::get_app_state,
+            downloads::get_dashboard_state,
+            downloads::get_io_metrics,
+            downloads::get_transfer_stats,
+            downloads::export_statistics,
+            downloads::get_recent_destinations,
+            downloads::get_free_space,
+            downloads::reload_engine,
+            downloads::restore_session,
+            downloads::retry_failed,
+            downloads::test_rule,
+            downloads::preview_rewrite,
+            downloads::run_action,
+            downloads::release_quarantine,
+            downloads::refresh_presigned_url,
+            downloads::fetch_preview,
+            downloads::list_contents,
+            downloads::select_files,
+            downloads::peek_archive,
+            downloads::download_archive_member,
+            downloads::pause_download,
+            downloads::pin_download,
+            downloads::download_again,
+            downloads::redownload,
+            downloads::set_retry_policy,
+            downloads::set_speed_limit,
+            downloads::cancel_download,
+            downloads::get_salvageable,
+            downloads::run_maintenance,
+            downloads::get_history_encryption,
+            downloads::set_history_encryption,
+            uploads::start_upload,
+            sync::sync_history,
+            i18n::get_available_locales,
+            notifications::get_notification_history,
+            lock::lock_app,
+            lock::unlock_app,
+            profiles::list_profiles,
+            profiles::switch_profile,
+            notifications::channels::test_notification_channel,
+            webhooks::test_webhook,
+            bench::run_benchmark,
+        ]
+    };
+}
+
+/// Argument and return types of every command, see `bindings`
+pub(crate) fn command_types(
+    types: &mut specta::TypeCollection,
+) -> Vec<specta::datatype::Function> {
+    let collect = commands!(specta::function::collect_functions);
+    collect(types)
+}
 
 pub fn run() {
     tauri::Builder::default()
@@ -23,12 +112,13 @@ pub fn run() {
             
             // Handle deep link if present
             if let Some(url_str) = &parsed_args.deep_link {
-                if let Some((url, _filename, _size_opt)) = downloads::parse_deep_link_url(url_str) {
+                if let Some((url, _filename, _size_opt, options)) = downloads::parse_deep_link_url(url_str) {
                     // Emit event to frontend to handle deep link
-                    let _ = app.emit("deep-link-received", json!({
-                        "url": url.as_str(),
-                        "type": "startup"
-                    }));
+                    let _ = app.emit("deep-link-received", DeepLinkReceived {
+                        url: url.to_string(),
+                        options,
+                        source: "startup".into(),
+                    });
                 }
             }
 
@@ -42,14 +132,7 @@ pub fn run() {
                 }
             }
         }))
-        .invoke_handler(tauri::generate_handler![
-            settings::get_settings,
-            settings::update_settings,
-            settings::update_setting,
-            get_autostart,
-            set_autostart,
-            downloads::handle_download_request,
-        ])
+        .invoke_handler(commands!(tauri::generate_handler))
         .setup(|app| {
             // Parse command line arguments
             let args = args::AppArgs::parse();
@@ -57,22 +140,24 @@ pub fn run() {
             // Handle deep links from startup
             if let Ok(Some(urls)) = app.deep_link().get_current() {
                 for url in urls {
-                    if let Some((parsed_url, _filename, _size_opt)) = downloads::parse_deep_link_url(url.as_str()) {
-                        let _ = app.emit("deep-link-received", json!({
-                            "url": parsed_url.as_str(),
-                            "type": "startup"
-                        }));
+                    if let Some((parsed_url, _filename, _size_opt, options)) = downloads::parse_deep_link_url(url.as_str()) {
+                        let _ = app.emit("deep-link-received", DeepLinkReceived {
+                            url: parsed_url.to_string(),
+                            options,
+                            source: "startup".into(),
+                        });
                     }
                 }
             }
             
             // Handle deep link from command line
             if let Some(url) = &args.deep_link {
-                if let Some((parsed_url, _filename, _size_opt)) = downloads::parse_deep_link_url(url) {
-                    let _ = app.emit("deep-link-received", json!({
-                        "url": parsed_url.as_str(),
-                        "type": "command_line"
-                    }));
+                if let Some((parsed_url, _filename, _size_opt, options)) = downloads::parse_deep_link_url(url) {
+                    let _ = app.emit("deep-link-received", DeepLinkReceived {
+                        url: parsed_url.to_string(),
+                        options,
+                        source: "command_line".into(),
+                    });
                 }
             }
             
@@ -83,13 +168,52 @@ pub fn run() {
                 }
             }
 
+            // Keep startup flags (--chaos, --debug) around for commands
+            app.manage(args);
+            app.manage(downloads::manager::DownloadManager::new(app.handle())?);
+
+            // Offer to resume what was running at the last quit
+            let session = app.state::<downloads::manager::DownloadManager>().pending_session();
+            if !session.is_empty() {
+                let _ = app.emit("session_restore_available", SessionRestore { ids: session });
+            }
+            app.manage(notifications::Notifications::default());
+            tauri::async_runtime::spawn(downloads::manager::start_signal_handler(app.handle().clone()));
+
+            // Chunk writes get their own threads, the lag probe shows the main runtime stays free
+            let settings = settings::load_or_create(app.handle());
+            downloads::io::init(settings.thread.io_threads);
+            tauri::async_runtime::spawn(downloads::io::lag_probe(std::time::Duration::from_millis(100)));
+            i18n::set_language(&settings.app.language);
+
+            // Web dashboard for headless installs, off unless enabled in settings
+            remote::spawn(app.handle(), &settings.remote);
+            sync::spawn(app.handle(), &settings.sync);
+            notifications::spawn(app.handle());
+            downloads::cleanup::spawn(app.handle());
+            downloads::stats::spawn(app.handle());
+            downloads::fair::spawn(app.handle());
+            downloads::health::spawn(app.handle());
+            downloads::maintenance::spawn(app.handle());
+
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // closing the last window, app.exit() or a profile switch's restart pauses
+            // everything first, what was running is offered again on the next launch
+            if let tauri::RunEvent::ExitRequested { code, api, .. } = event {
+                let restart = code == Some(tauri::RESTART_EXIT_CODE);
+                if app.state::<downloads::manager::DownloadManager>().shutdown(app, restart) {
+                    api.prevent_exit();
+                }
+            }
+        });
 }
 
 #[tauri::command]
+#[specta::specta]
 fn get_autostart(app: tauri::AppHandle) -> Result<bool, String> {
     use tauri_plugin_autostart::ManagerExt;
     app.autolaunch()
@@ -98,7 +222,13 @@ fn get_autostart(app: tauri::AppHandle) -> Result<bool, String> {
 }
 
 #[tauri::command]
-fn set_autostart(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+#[specta::specta]
+fn set_autostart(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    enabled: bool,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "set_autostart")?;
     use tauri_plugin_autostart::ManagerExt;
     let autostart = app.autolaunch();
     
@@ -0,0 +1,231 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::downloads::manager::DownloadManager;
+use crate::security::same_secret;
+use crate::settings::{self, policy};
+
+/// A wrong PIN answers this late, so guessing one takes a while
+const WRONG_PIN_DELAY: Duration = Duration::from_secs(2);
+/// Wrong PINs in a row before unlocking is refused for a while
+const FREE_ATTEMPTS: u32 = 5;
+/// How long the first refusal lasts, doubled for every wrong PIN after it
+const LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+/// PBKDF2-HMAC-SHA256 rounds for a new PIN hash
+const PIN_ROUNDS: u32 = 600_000;
+
+/// Wrong PINs in a row and until when unlocking is refused. Held for the whole
+/// attempt, so guesses come in one at a time.
+static FAILURES: Mutex<Failures> = Mutex::const_new(Failures {
+    count: 0,
+    until: None,
+});
+
+struct Failures {
+    count: u32,
+    until: Option<Instant>,
+}
+
+/// Err while guest mode is on. Every command that adds, stops or changes something
+/// calls this first, hiding the buttons in the GUI isn't enough.
+pub fn guard(app: &AppHandle) -> Result<(), String> {
+    if locked(app) {
+        return Err("Guest mode is on, unlock the app to make changes".into());
+    }
+    Ok(())
+}
+
+pub fn locked(app: &AppHandle) -> bool {
+    settings::load_or_create(app).lock.enabled
+}
+
+/// What `lock.pin_hash` stores for `pin`, an empty PIN clears it
+pub fn hash_pin(pin: &str) -> Result<String, String> {
+    if pin.is_empty() {
+        return Ok(String::new());
+    }
+    if pin.chars().count() < 4 {
+        return Err("The PIN needs at least 4 characters".into());
+    }
+    Ok(hash_with(pin, PIN_ROUNDS))
+}
+
+fn hash_with(pin: &str, rounds: u32) -> String {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let salt = hex::encode(salt);
+    let derived = pbkdf2(pin.as_bytes(), salt.as_bytes(), rounds);
+    format!("pbkdf2:{}:{}:{}", rounds, salt, hex::encode(derived))
+}
+
+// whether `pin` is the one `hash` was made from, any PIN is when there's no hash
+fn verify_pin(hash: &str, pin: &str) -> bool {
+    let (derived, expected) = match hash.split(':').collect::<Vec<_>>()[..] {
+        ["pbkdf2", rounds, salt, expected] => {
+            let Ok(rounds) = rounds.parse() else {
+                return false;
+            };
+            let derived = pbkdf2(pin.as_bytes(), salt.as_bytes(), rounds);
+            (hex::encode(derived), expected)
+        }
+        // salted SHA-256 from before, `unlock_app` replaces it once it matched
+        [salt, expected] => (
+            hex::encode(Sha256::digest(format!("{}{}", salt, pin).as_bytes())),
+            expected,
+        ),
+        _ => return hash.is_empty(),
+    };
+    same_secret(derived.as_bytes(), expected.as_bytes())
+}
+
+// PBKDF2-HMAC-SHA256 (RFC 8018) for one 32 byte block
+fn pbkdf2(password: &[u8], salt: &[u8], rounds: u32) -> [u8; 32] {
+    // HMAC with the key's inner and outer pads hashed once, each round only adds its message
+    let mut key = [0u8; 64];
+    if password.len() > key.len() {
+        key[..32].copy_from_slice(&Sha256::digest(password));
+    } else {
+        key[..password.len()].copy_from_slice(password);
+    }
+    let inner = Sha256::new().chain_update(key.map(|b| b ^ 0x36));
+    let outer = Sha256::new().chain_update(key.map(|b| b ^ 0x5c));
+    let mac = |message: &[&[u8]]| -> [u8; 32] {
+        let mut hash = inner.clone();
+        for part in message {
+            hash.update(part);
+        }
+        outer
+            .clone()
+            .chain_update(hash.finalize())
+            .finalize()
+            .into()
+    };
+
+    let mut block = mac(&[salt, &1u32.to_be_bytes()]);
+    let mut derived = block;
+    for _ in 1..rounds {
+        block = mac(&[&block]);
+        for (d, b) in derived.iter_mut().zip(block) {
+            *d ^= b;
+        }
+    }
+    derived
+}
+
+fn set_enabled(app: &AppHandle, enabled: bool) -> Result<(), String> {
+    if policy::get().is_locked("lock.enabled") {
+        return Err("lock.enabled is set by policy".into());
+    }
+    let mut settings = settings::load_or_create(app);
+    settings.lock.enabled = enabled;
+    settings::save(app, &settings)?;
+    let manager = app.state::<DownloadManager>();
+    let kind = if enabled { "locked" } else { "unlocked" };
+    manager.log_activity(kind, None, json!({}));
+    Ok(())
+}
+
+/// Turn guest mode on, the GUI can only look until `unlock_app`
+#[tauri::command]
+#[specta::specta]
+pub fn lock_app(app: AppHandle) -> Result<(), String> {
+    set_enabled(&app, true)
+}
+
+/// Leave guest mode, `pin` is checked when `lock.pin` was set. After `FREE_ATTEMPTS`
+/// wrong PINs in a row unlocking is refused for a while, longer with every one after.
+#[tauri::command]
+#[specta::specta]
+pub async fn unlock_app(app: AppHandle, pin: Option<String>) -> Result<(), String> {
+    let mut failures = FAILURES.lock().await;
+    if let Some(left) = failures
+        .until
+        .and_then(|until| until.checked_duration_since(Instant::now()))
+    {
+        return Err(format!(
+            "Too many wrong PINs, try again in {} s",
+            left.as_secs().max(1)
+        ));
+    }
+    let hash = settings::load_or_create(&app).lock.pin_hash;
+    let pin = pin.unwrap_or_default();
+    let (checked, entered) = (hash.clone(), pin.clone());
+    let matches = tokio::task::spawn_blocking(move || verify_pin(&checked, &entered))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !matches {
+        failures.count += 1;
+        if failures.count >= FREE_ATTEMPTS {
+            let doublings = (failures.count - FREE_ATTEMPTS).min(16);
+            let lockout = (LOCKOUT * (1 << doublings)).min(MAX_LOCKOUT);
+            failures.until = Some(Instant::now() + lockout);
+        }
+        let manager = app.state::<DownloadManager>();
+        let attempts = failures.count;
+        manager.log_activity("unlock_failed", None, json!({ "attempts": attempts }));
+        tokio::time::sleep(WRONG_PIN_DELAY).await;
+        return Err("Wrong PIN".into());
+    }
+    *failures = Failures {
+        count: 0,
+        until: None,
+    };
+    // a hash from before PBKDF2 is replaced while the PIN is at hand
+    if !hash.is_empty() && !hash.starts_with("pbkdf2:") {
+        let rehashed = tokio::task::spawn_blocking(move || hash_pin(&pin))
+            .await
+            .map_err(|e| e.to_string())??;
+        let mut settings = settings::load_or_create(&app);
+        settings.lock.pin_hash = rehashed;
+        settings::save(&app, &settings)?;
+    }
+    set_enabled(&app, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbkdf2_matches_the_published_vectors() {
+        // the first from RFC 7914 section 11, cut to one block
+        let derived = pbkdf2(b"passwd", b"salt", 1);
+        assert_eq!(
+            hex::encode(derived),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        let derived = pbkdf2(b"password", b"salt", 4096);
+        assert_eq!(
+            hex::encode(derived),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn pin_hashes_verify() {
+        let hash = hash_pin("2468").unwrap();
+        assert!(hash.starts_with(&format!("pbkdf2:{}:", PIN_ROUNDS)));
+        // fewer rounds from here on, each full one takes seconds in a debug build
+        let hash = hash_with("2468", 1000);
+        assert!(verify_pin(&hash, "2468"));
+        assert!(!verify_pin(&hash, "2469"));
+        // salted, the same PIN hashes differently every time
+        assert_ne!(hash_with("2468", 1000), hash);
+
+        assert!(hash_pin("123").is_err());
+        assert_eq!(hash_pin("").unwrap(), "");
+        assert!(verify_pin("", "anything"));
+        assert!(!verify_pin("pbkdf2:x:salt:00", "2468"));
+
+        // hashes from before PBKDF2 still unlock
+        let legacy = format!("salt:{}", hex::encode(Sha256::digest(b"salt2468")));
+        assert!(verify_pin(&legacy, "2468"));
+        assert!(!verify_pin(&legacy, "1357"));
+    }
+}
@@ -0,0 +1,185 @@
+#[path = "notifications/channels.rs"]
+pub mod channels;
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+
+use crate::settings;
+
+/// How often held notifications check whether DND lifted
+const DND_POLL: Duration = Duration::from_secs(30);
+
+/// A notification as the frontend shows it and the history lists it
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct Notification {
+    pub id: u64,
+    pub download_id: Option<Uuid>,
+    /// the event that caused it, e.g. "download_completed"
+    pub event: String,
+    pub body: String,
+    /// unix seconds
+    pub created_at: i64,
+    /// shown even during Do Not Disturb
+    pub critical: bool,
+    /// false while it waits for DND to lift, or when notifications are off
+    pub delivered: bool,
+}
+
+/// History plus what's waiting on Do Not Disturb, managed as app state
+#[derive(Default)]
+pub struct Notifications {
+    next_id: AtomicU64,
+    history: Mutex<VecDeque<Notification>>,
+    held: Mutex<Vec<u64>>,
+}
+
+impl Notifications {
+    fn record(&self, notification: Notification, limit: usize) {
+        let mut history = self.history.lock().unwrap();
+        history.push_back(notification);
+        while history.len() > limit.max(1) {
+            history.pop_front();
+        }
+    }
+
+    fn mark_delivered(&self, id: u64) -> Option<Notification> {
+        let mut history = self.history.lock().unwrap();
+        let notification = history.iter_mut().find(|n| n.id == id)?;
+        notification.delivered = true;
+        Some(notification.clone())
+    }
+}
+
+/// Show `body` through the frontend's `notification` event, holding it while DND is on
+/// unless `event` is one of `critical_events`. Channels get it right away either way.
+pub fn notify(app: &AppHandle, event: &str, download_id: Option<Uuid>, body: String) {
+    let settings = settings::load_or_create(app);
+    let config = &settings.notifications;
+    let state = app.state::<Notifications>();
+    let critical = config.critical_events.iter().any(|e| e == event);
+    let mut notification = Notification {
+        id: state.next_id.fetch_add(1, Ordering::Relaxed),
+        download_id,
+        event: event.to_string(),
+        body,
+        created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        critical,
+        delivered: false,
+    };
+
+    let hold = settings.show_notifications && config.respect_dnd && !critical && dnd_active();
+    if settings.show_notifications && !hold {
+        notification.delivered = true;
+        emit(app, &notification);
+    }
+    if hold {
+        state.held.lock().unwrap().push(notification.id);
+    }
+    channels::send(app, &notification);
+    state.record(notification, config.history_limit as usize);
+}
+
+fn emit(app: &AppHandle, notification: &Notification) {
+    if let Err(e) = app.emit("notification", notification) {
+        eprintln!("Failed to emit notification event: {}", e);
+    }
+}
+
+/// Deliver held notifications once Do Not Disturb is off again
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(DND_POLL);
+        loop {
+            interval.tick().await;
+            let state = app.state::<Notifications>();
+            if state.held.lock().unwrap().is_empty() {
+                continue;
+            }
+            let active = tokio::task::spawn_blocking(dnd_active)
+                .await
+                .unwrap_or(false);
+            if active && settings::load_or_create(&app).notifications.respect_dnd {
+                continue;
+            }
+            let held = std::mem::take(&mut *state.held.lock().unwrap());
+            for id in held {
+                // fell out of the history while waiting, nothing left to show
+                if let Some(notification) = state.mark_delivered(id) {
+                    emit(&app, &notification);
+                }
+            }
+        }
+    });
+}
+
+/// Newest last, including the ones held back or not shown
+#[tauri::command]
+#[specta::specta]
+pub fn get_notification_history(state: State<'_, Notifications>) -> Vec<Notification> {
+    state.history.lock().unwrap().iter().cloned().collect()
+}
+
+/// GNOME's "Do Not Disturb" switch turns banners off
+#[cfg(target_os = "linux")]
+pub fn dnd_active() -> bool {
+    std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.notifications", "show-banners"])
+        .output()
+        .is_ok_and(|out| {
+            out.status.success() && String::from_utf8_lossy(&out.stdout).trim() == "false"
+        })
+}
+
+/// Focus modes leave assertions behind while active, older releases have a defaults flag
+#[cfg(target_os = "macos")]
+pub fn dnd_active() -> bool {
+    let focus = std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join("Library/DoNotDisturb/DB/Assertions.json"))
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|data| serde_json::from_slice::<serde_json::Value>(&data).ok())
+        .and_then(|db| {
+            db.get("data")?
+                .as_array()?
+                .first()?
+                .get("storeAssertionRecords")?
+                .as_array()
+                .map(|records| !records.is_empty())
+        })
+        .unwrap_or(false);
+    focus
+        || std::process::Command::new("defaults")
+            .args([
+                "-currentHost",
+                "read",
+                "com.apple.notificationcenterui",
+                "doNotDisturb",
+            ])
+            .output()
+            .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).trim() == "1")
+}
+
+/// Focus Assist, presentation mode and full screen apps all stop the shell accepting toasts
+#[cfg(windows)]
+pub fn dnd_active() -> bool {
+    // QUERY_USER_NOTIFICATION_STATE
+    const QUNS_ACCEPTS_NOTIFICATIONS: i32 = 5;
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SHQueryUserNotificationState(state: *mut i32) -> i32;
+    }
+    let mut state = 0;
+    // SAFETY: the out pointer is a valid i32 for the duration of the call
+    let result = unsafe { SHQueryUserNotificationState(&mut state) };
+    result == 0 && state != QUNS_ACCEPTS_NOTIFICATIONS
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn dnd_active() -> bool {
+    false
+}
@@ -0,0 +1,195 @@
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+use serde_json::json;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use url::Url;
+
+use super::Notification;
+use crate::downloads::client;
+use crate::downloads::manager::DownloadManager;
+use crate::settings::{self, ChannelKind, NotificationChannel};
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Send `notification` to every enabled channel whose filter lets its event through
+pub fn send(app: &AppHandle, notification: &Notification) {
+    let settings = settings::load_or_create(app);
+    let channels: Vec<NotificationChannel> = settings
+        .notifications
+        .channels
+        .iter()
+        .filter(|c| c.enabled && wants(c, &notification.event))
+        .cloned()
+        .collect();
+    if channels.is_empty() {
+        return;
+    }
+    let client = match client::create(&settings) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to create notification client: {}", e);
+            return;
+        }
+    };
+    for channel in channels {
+        let app = app.clone();
+        let client = client.clone();
+        let notification = notification.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = deliver(&client, &channel, &notification.body).await {
+                eprintln!("Notification channel {} failed: {}", channel.name, e);
+                app.state::<DownloadManager>().log_activity(
+                    "notification_failed",
+                    notification.download_id.as_ref(),
+                    json!({ "channel": channel.name, "event": notification.event, "error": e }),
+                );
+            }
+        });
+    }
+}
+
+fn wants(channel: &NotificationChannel, event: &str) -> bool {
+    channel.events.is_empty() || channel.events.iter().any(|e| e == event)
+}
+
+async fn deliver(client: &Client, channel: &NotificationChannel, body: &str) -> Result<(), String> {
+    match &channel.kind {
+        ChannelKind::Smtp {
+            host,
+            port,
+            security,
+            username,
+            password,
+            from,
+            to,
+        } => {
+            let mut transport = match security.as_str() {
+                "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+                "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                    host,
+                )),
+                _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            }
+            .map_err(|e| e.to_string())?
+            .timeout(Some(TIMEOUT));
+            if *port > 0 {
+                transport = transport.port(*port);
+            }
+            if !username.is_empty() {
+                transport =
+                    transport.credentials(Credentials::new(username.clone(), password.clone()));
+            }
+            let mut message = Message::builder()
+                .from(from.parse::<Mailbox>().map_err(|e| e.to_string())?)
+                .subject(format!("tur: {}", body));
+            for to in to {
+                message = message.to(to.parse::<Mailbox>().map_err(|e| e.to_string())?);
+            }
+            let message = message
+                .header(ContentType::TEXT_PLAIN)
+                .body(body.to_string())
+                .map_err(|e| e.to_string())?;
+            transport
+                .build()
+                .send(message)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        ChannelKind::Telegram { bot_token, chat_id } => {
+            let url = format!("https://api.telegram.org/bot{}/sendMessage", bot_token);
+            let response = client
+                .post(url)
+                .timeout(TIMEOUT)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(json!({ "chat_id": chat_id, "text": body }).to_string())
+                .send()
+                .await
+                // the error names the url, which has the token in it
+                .map_err(|e| e.without_url().to_string())?;
+            response
+                .error_for_status()
+                .map_err(|e| e.without_url().to_string())?;
+        }
+        ChannelKind::Ntfy {
+            server,
+            topic,
+            token,
+        } => {
+            let url = format!("{}/{}", server.trim_end_matches('/'), topic);
+            let mut request = client
+                .post(url)
+                .timeout(TIMEOUT)
+                .header("Title", "tur")
+                .body(body.to_string());
+            if !token.is_empty() {
+                request = request.bearer_auth(token);
+            }
+            request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Refuse channels that can't work as written, before they're saved
+pub fn validate(channels: &[NotificationChannel]) -> Result<(), String> {
+    for channel in channels {
+        let name = &channel.name;
+        match &channel.kind {
+            ChannelKind::Smtp {
+                host,
+                security,
+                from,
+                to,
+                ..
+            } => {
+                if host.trim().is_empty() {
+                    return Err(format!("Channel {}: no SMTP host", name));
+                }
+                if !matches!(security.as_str(), "starttls" | "tls" | "none") {
+                    return Err(format!("Channel {}: unknown security {}", name, security));
+                }
+                if to.is_empty() {
+                    return Err(format!("Channel {}: no recipients", name));
+                }
+                for address in std::iter::once(from).chain(to) {
+                    address
+                        .parse::<Mailbox>()
+                        .map_err(|e| format!("Channel {}: {}: {}", name, address, e))?;
+                }
+            }
+            ChannelKind::Telegram { bot_token, chat_id } => {
+                if bot_token.trim().is_empty() || chat_id.trim().is_empty() {
+                    return Err(format!("Channel {}: needs a bot token and a chat id", name));
+                }
+            }
+            ChannelKind::Ntfy { server, topic, .. } => {
+                Url::parse(server).map_err(|e| format!("Channel {}: {}", name, e))?;
+                if topic.trim().is_empty() || topic.contains('/') {
+                    return Err(format!("Channel {}: invalid topic {}", name, topic));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Send a test message through `channel`, which doesn't have to be saved yet
+#[tauri::command]
+#[specta::specta]
+pub async fn test_notification_channel(
+    app: AppHandle,
+    channel: NotificationChannel,
+) -> Result<(), String> {
+    crate::lock::guard(&app)?;
+    validate(std::slice::from_ref(&channel))?;
+    let client = client::create(&settings::load_or_create(&app))?;
+    deliver(&client, &channel, &crate::i18n::t("notify.test")).await
+}
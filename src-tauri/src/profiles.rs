@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::args::AppArgs;
+
+/// Name of the profile kept directly in the app data dir
+pub const DEFAULT: &str = "default";
+/// holds the active profile's name, in the app data dir
+const ACTIVE_FILE: &str = "profile";
+/// other profiles get a folder each in here
+const PROFILES_DIR: &str = "profiles";
+
+/// Profiles to switch between and where the running one keeps its data
+#[derive(Debug, Serialize, specta::Type)]
+pub struct Profiles {
+    pub active: String,
+    /// `default` first, then the rest by name
+    pub profiles: Vec<String>,
+    pub data_dir: String,
+    /// started with `--data-dir`, which takes the place of every profile
+    pub overridden: bool,
+}
+
+/// Where the database, settings, metadata and everything else of this run goes:
+/// the `--data-dir` folder, or the active profile's
+pub fn data_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    let dir = match flag(app) {
+        Some(dir) => dir,
+        None => resolve(&app.path().app_data_dir().map_err(|e| e.to_string())?),
+    };
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// The data dir for commands that run without the app, like `tur wait`. None when
+/// the app never ran there.
+pub fn cli_data_dir(flag: Option<&Path>) -> Option<PathBuf> {
+    let dir = match flag {
+        Some(dir) => std::path::absolute(dir).ok()?,
+        None => resolve(&dirs::data_dir()?.join("tur")),
+    };
+    dir.exists().then_some(dir)
+}
+
+/// The folder of `base`'s active profile
+fn resolve(base: &Path) -> PathBuf {
+    folder(base, &active(base))
+}
+
+fn folder(base: &Path, name: &str) -> PathBuf {
+    match name {
+        DEFAULT => base.to_path_buf(),
+        name => base.join(PROFILES_DIR).join(name),
+    }
+}
+
+fn active(base: &Path) -> String {
+    std::fs::read_to_string(base.join(ACTIVE_FILE))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| valid(name))
+        .unwrap_or_else(|| DEFAULT.to_string())
+}
+
+fn flag<R: Runtime>(app: &AppHandle<R>) -> Option<PathBuf> {
+    let dir = app.try_state::<AppArgs>()?.data_dir.clone()?;
+    match std::path::absolute(&dir) {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            eprintln!("Failed to resolve --data-dir {}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+// names end up as folder names, keep them portable
+fn valid(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Profiles created so far and the one in use
+#[tauri::command]
+#[specta::specta]
+pub fn list_profiles(app: AppHandle) -> Result<Profiles, String> {
+    let base = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let mut profiles: Vec<String> = std::fs::read_dir(base.join(PROFILES_DIR))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| valid(name) && name != DEFAULT)
+                .collect()
+        })
+        .unwrap_or_default();
+    profiles.sort();
+    profiles.insert(0, DEFAULT.to_string());
+    Ok(Profiles {
+        active: active(&base),
+        profiles,
+        data_dir: data_dir(&app)?.to_string_lossy().to_string(),
+        overridden: flag(&app).is_some(),
+    })
+}
+
+/// Make `name` the active profile, creating it if it's new, and restart into it.
+/// Running downloads are paused and saved first, like on quit.
+#[tauri::command]
+#[specta::specta]
+pub fn switch_profile(app: AppHandle, webview: tauri::Webview, name: String) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "switch_profile")?;
+    let name = name.trim();
+    if !valid(name) {
+        return Err(format!(
+            "Invalid profile name {:?}, use up to 32 letters, digits, - or _",
+            name
+        ));
+    }
+    if flag(&app).is_some() {
+        return Err("Started with --data-dir, profiles can't be switched".into());
+    }
+    let base = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    if active(&base) == name {
+        return Ok(());
+    }
+    std::fs::create_dir_all(folder(&base, name)).map_err(|e| e.to_string())?;
+    std::fs::write(base.join(ACTIVE_FILE), name).map_err(|e| e.to_string())?;
+    app.request_restart();
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{header, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use tauri::{AppHandle, Manager};
+use tokio::net::TcpListener;
+use url::{Host, Url};
+use uuid::Uuid;
+
+use crate::downloads::manager::DownloadManager;
+use crate::security::same_secret;
+use crate::settings::{self, RemoteConfig};
+
+/// Page and script in one file, no build step or assets on disk
+const INDEX: &str = include_str!("remote/index.html");
+
+/// A running download as the web dashboard lists it
+#[derive(Debug, Serialize)]
+struct ActiveDownload {
+    id: Uuid,
+    filename: String,
+    direction: String,
+    size: Option<u64>,
+    downloaded: u64,
+    speed: u64,
+}
+
+/// Serve the dashboard when `remote.enabled`. It always asks for a token, one is made
+/// and saved as `remote.token` when there's none, loopback binds included.
+pub fn spawn(app: &AppHandle, config: &RemoteConfig) {
+    if !config.enabled {
+        return;
+    }
+    let ip: IpAddr = match config.bind.parse() {
+        Ok(ip) => ip,
+        Err(e) => {
+            eprintln!("Invalid remote bind address {}: {}", config.bind, e);
+            return;
+        }
+    };
+    let token = match config.token.as_str() {
+        "" => match new_token(app) {
+            Ok(token) => token,
+            Err(e) => {
+                eprintln!("Remote dashboard not started, no token: {}", e);
+                return;
+            }
+        },
+        token => token.to_string(),
+    };
+
+    let addr = SocketAddr::new(ip, config.port);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to start remote dashboard on {}: {}", addr, e);
+                return;
+            }
+        };
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let app = app.clone();
+            let token = token.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| serve(req, app.clone(), ip, token.clone()));
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
+
+// random, saved so the page keeps working across restarts
+fn new_token(app: &AppHandle) -> Result<String, String> {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let mut settings = settings::load_or_create(app);
+    settings.remote.token = token.clone();
+    settings::save(app, &settings)?;
+    eprintln!("Made a remote dashboard token, it's remote.token in the settings");
+    Ok(token)
+}
+
+async fn serve(
+    req: Request<Incoming>,
+    app: AppHandle,
+    bind: IpAddr,
+    token: String,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    // a page on another site could point its own name at this address, DNS rebinding
+    if !known_host(&req, bind) {
+        return Ok(reply(StatusCode::MISDIRECTED_REQUEST, "text/plain", "Unknown host"));
+    }
+    if !authorized(&req, &token) {
+        return Ok(reply(StatusCode::UNAUTHORIZED, "text/plain", "Missing or wrong token"));
+    }
+
+    let manager = app.state::<DownloadManager>();
+    let segments: Vec<&str> = req.uri().path().trim_matches('/').split('/').collect();
+    let response = match (req.method(), segments.as_slice()) {
+        (&Method::GET, [""]) => reply(StatusCode::OK, "text/html; charset=utf-8", INDEX),
+        (&Method::GET, ["api", "downloads"]) => match active(&manager) {
+            Ok(list) => json(&list),
+            Err(e) => reply(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", &e),
+        },
+        (&Method::POST, ["api", "downloads", id, action]) => {
+            let Ok(id) = Uuid::parse_str(id) else {
+                return Ok(reply(StatusCode::BAD_REQUEST, "text/plain", "Bad download id"));
+            };
+            let found = match *action {
+                "pause" => manager.pause(&id),
+                "cancel" => manager.cancel(&id, None),
+                _ => return Ok(reply(StatusCode::NOT_FOUND, "text/plain", "Unknown action")),
+            };
+            if found {
+                reply(StatusCode::NO_CONTENT, "text/plain", "")
+            } else {
+                reply(StatusCode::NOT_FOUND, "text/plain", "Download isn't running")
+            }
+        }
+        _ => reply(StatusCode::NOT_FOUND, "text/plain", "Not found"),
+    };
+    Ok(response)
+}
+
+// Host is localhost, a loopback address or the one bound to. A wildcard bind takes any
+// address, only names are what a rebinding page can send.
+fn known_host(req: &Request<Incoming>, bind: IpAddr) -> bool {
+    let Some(host) = req.headers().get(header::HOST).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let Ok(url) = Url::parse(&format!("http://{}", host)) else {
+        return false;
+    };
+    let ip = match url.host() {
+        Some(Host::Domain(name)) => return name.eq_ignore_ascii_case("localhost"),
+        Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+        None => return false,
+    };
+    ip.is_loopback() || ip == bind || bind.is_unspecified()
+}
+
+// bearer header from the page's fetches, `?token=` for opening the page itself
+fn authorized(req: &Request<Incoming>, token: &str) -> bool {
+    if token.is_empty() {
+        return false;
+    }
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let query = req.uri().query().and_then(|q| {
+        url::form_urlencoded::parse(q.as_bytes())
+            .find(|(k, _)| k == "token")
+            .map(|(_, v)| v.into_owned())
+    });
+    let matches = |given: Option<&str>| {
+        given.is_some_and(|given| same_secret(given.as_bytes(), token.as_bytes()))
+    };
+    matches(bearer) || matches(query.as_deref())
+}
+
+fn active(manager: &DownloadManager) -> Result<Vec<ActiveDownload>, String> {
+    let records = manager
+        .db()
+        .get_downloads_by_status(None)
+        .map_err(|e| e.to_string())?;
+    Ok(records
+        .into_iter()
+        .filter_map(|record| {
+            let instance = manager.instance(&record.id)?;
+            let speed = instance.speed.lock().unwrap().current();
+            Some(ActiveDownload {
+                id: record.id,
+                filename: record.filename,
+                direction: record.direction,
+                size: instance.size(),
+                downloaded: instance.transfer.downloaded(),
+                speed,
+            })
+        })
+        .collect())
+}
+
+fn json<T: Serialize>(value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_string(value) {
+        Ok(body) => reply(StatusCode::OK, "application/json", &body),
+        Err(e) => reply(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", &e.to_string()),
+    }
+}
+
+fn reply(status: StatusCode, content_type: &str, body: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
@@ -0,0 +1,361 @@
+use rhai::{Engine, EvalAltResult, Map, Scope};
+use serde_json::json;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tauri::{AppHandle, Manager};
+use url::Url;
+use uuid::Uuid;
+
+use crate::database;
+use crate::downloads::manager::DownloadManager;
+use crate::downloads::{self, category, DownloadOptions};
+use crate::settings::{self, ScriptingConfig};
+
+/// Points in a download's life a script can hook into, each is a script function
+/// taking the download as a map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    /// before the destination is picked, so renames and moves cost nothing
+    Add,
+    Complete,
+    Fail,
+}
+
+impl Hook {
+    fn function(self) -> &'static str {
+        match self {
+            Hook::Add => "on_add",
+            Hook::Complete => "on_complete",
+            Hook::Fail => "on_fail",
+        }
+    }
+}
+
+/// The download as scripts see it, `destination` is empty in `on_add`
+#[derive(Debug, Clone, Default)]
+pub struct ScriptDownload {
+    pub id: Uuid,
+    pub url: String,
+    pub filename: String,
+    pub folder: String,
+    pub destination: String,
+    pub size: Option<i64>,
+    pub content_type: Option<String>,
+    pub category: String,
+    pub error: Option<String>,
+}
+
+impl ScriptDownload {
+    /// A stored download, `error` for `on_fail`
+    pub fn of(record: &database::Download, category: &str, error: Option<&str>) -> Self {
+        let destination = Path::new(&record.destination);
+        ScriptDownload {
+            id: record.id,
+            url: record.url.clone(),
+            filename: record.filename.clone(),
+            folder: destination
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            destination: record.destination.clone(),
+            size: record.size,
+            content_type: record.content_type.clone(),
+            category: category.to_string(),
+            error: error.map(String::from),
+        }
+    }
+
+    fn to_map(&self) -> Map {
+        let mut map = Map::new();
+        map.insert("id".into(), self.id.to_string().into());
+        map.insert("url".into(), self.url.clone().into());
+        map.insert("filename".into(), self.filename.clone().into());
+        map.insert("folder".into(), self.folder.clone().into());
+        map.insert("destination".into(), self.destination.clone().into());
+        map.insert("size".into(), self.size.map_or(().into(), Into::into));
+        map.insert(
+            "content_type".into(),
+            self.content_type.clone().map_or(().into(), Into::into),
+        );
+        map.insert("category".into(), self.category.clone().into());
+        map.insert(
+            "error".into(),
+            self.error.clone().map_or(().into(), Into::into),
+        );
+        map
+    }
+}
+
+/// What scripts asked for, applied once every script ran
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Rename(String),
+    Move(PathBuf),
+    Category(&'static str),
+    Download(Url),
+}
+
+/// Run `hook` in every script that defines it, in file name order. Nothing runs while
+/// scripting is off. A script that fails is logged and the ones after it still run.
+pub async fn run(app: &AppHandle, hook: Hook, download: ScriptDownload) -> Vec<Action> {
+    let config = settings::load_or_create(app).scripting;
+    if !config.enabled {
+        return Vec::new();
+    }
+    let folder = match config.folder.as_str() {
+        "" => match crate::profiles::data_dir(app) {
+            Ok(dir) => dir.join("scripts"),
+            Err(e) => {
+                eprintln!("Failed to find the scripts folder: {}", e);
+                return Vec::new();
+            }
+        },
+        folder => PathBuf::from(folder),
+    };
+    let id = download.id;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        run_scripts(&config, &folder, hook, &download)
+    })
+    .await;
+    let (actions, failures) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Script task failed: {}", e);
+            return Vec::new();
+        }
+    };
+    let manager = app.state::<DownloadManager>();
+    for (script, error) in failures {
+        eprintln!("Script {} failed in {}: {}", script, hook.function(), error);
+        manager.log_activity(
+            "script_failed",
+            Some(&id),
+            json!({ "script": script, "hook": hook.function(), "error": error }),
+        );
+    }
+    actions
+}
+
+// (actions, [(script name, error)])
+fn run_scripts(
+    config: &ScriptingConfig,
+    folder: &Path,
+    hook: Hook,
+    download: &ScriptDownload,
+) -> (Vec<Action>, Vec<(String, String)>) {
+    let mut scripts: Vec<PathBuf> = match std::fs::read_dir(folder) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rhai"))
+            .collect(),
+        // no folder, no scripts
+        Err(_) => return (Vec::new(), Vec::new()),
+    };
+    scripts.sort();
+
+    let actions = Rc::new(RefCell::new(Vec::new()));
+    let engine = engine(config, &actions);
+    let mut failures = Vec::new();
+    for script in scripts {
+        let name = script
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let before = actions.borrow().len();
+        let result = std::fs::read_to_string(&script)
+            .map_err(|e| e.to_string())
+            .and_then(|source| engine.compile(source).map_err(|e| e.to_string()))
+            .and_then(|ast| {
+                let defined = ast
+                    .iter_functions()
+                    .any(|f| f.name == hook.function() && f.params.len() == 1);
+                if !defined {
+                    return Ok(());
+                }
+                engine
+                    .call_fn::<rhai::Dynamic>(
+                        &mut Scope::new(),
+                        &ast,
+                        hook.function(),
+                        (download.to_map(),),
+                    )
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            // half a script's wishes are worse than none
+            actions.borrow_mut().truncate(before);
+            failures.push((name, e));
+        }
+    }
+    let actions = actions.borrow().clone();
+    (actions, failures)
+}
+
+// rhai has no file, network or process access of its own, the functions below are all
+// a script can do besides computing
+fn engine(config: &ScriptingConfig, actions: &Rc<RefCell<Vec<Action>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(config.max_operations)
+        .set_max_call_levels(32)
+        .set_max_string_size(1 << 20)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000)
+        .disable_symbol("eval");
+    engine.on_print(|text| eprintln!("[script] {}", text));
+    engine.on_debug(|text, _, _| eprintln!("[script] {}", text));
+
+    let list = actions.clone();
+    engine.register_fn(
+        "rename",
+        move |name: &str| -> Result<(), Box<EvalAltResult>> {
+            let name = name.trim();
+            if name.is_empty() || Path::new(name).file_name() != Some(name.as_ref()) {
+                return Err(format!("rename takes a file name, not {:?}", name).into());
+            }
+            list.borrow_mut().push(Action::Rename(name.to_string()));
+            Ok(())
+        },
+    );
+    let list = actions.clone();
+    engine.register_fn(
+        "move_to",
+        move |folder: &str| -> Result<(), Box<EvalAltResult>> {
+            let folder = PathBuf::from(folder.trim());
+            if !folder.is_absolute() {
+                return Err(format!("move_to takes an absolute folder, not {:?}", folder).into());
+            }
+            list.borrow_mut().push(Action::Move(folder));
+            Ok(())
+        },
+    );
+    let list = actions.clone();
+    engine.register_fn(
+        "set_category",
+        move |name: &str| -> Result<(), Box<EvalAltResult>> {
+            let category = category::known(name).ok_or_else(|| {
+                format!("unknown category {:?}, one of {:?}", name, category::NAMES)
+            })?;
+            list.borrow_mut().push(Action::Category(category));
+            Ok(())
+        },
+    );
+    let list = actions.clone();
+    engine.register_fn(
+        "download",
+        move |url: &str| -> Result<(), Box<EvalAltResult>> {
+            let url = Url::parse(url).map_err(|e| format!("download {:?}: {}", url, e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("download takes http(s) urls, not {}", url).into());
+            }
+            list.borrow_mut().push(Action::Download(url));
+            Ok(())
+        },
+    );
+    engine
+}
+
+/// Apply what `on_add` asked for to a download that isn't stored yet
+pub fn apply_added(
+    app: &AppHandle,
+    actions: Vec<Action>,
+    filename: &mut String,
+    base: &mut PathBuf,
+    options: &mut DownloadOptions,
+) {
+    for action in actions {
+        match action {
+            Action::Rename(name) => *filename = name,
+            Action::Move(folder) => *base = folder,
+            Action::Category(name) => options.category = Some(name.to_string()),
+            Action::Download(url) => downloads::queue_from_script(app, url),
+        }
+    }
+}
+
+/// Run `on_complete` or `on_fail` for a stored download and apply what the scripts asked
+/// for. Moves and renames take the file along, a partial one included. Returns where the
+/// file is now.
+pub async fn finished(
+    app: &AppHandle,
+    hook: Hook,
+    record: &database::Download,
+    category: &str,
+    error: Option<&str>,
+) -> PathBuf {
+    let actions = run(app, hook, ScriptDownload::of(record, category, error)).await;
+    if actions.is_empty() {
+        return PathBuf::from(&record.destination);
+    }
+    let manager = app.state::<DownloadManager>();
+    let mut destination = PathBuf::from(&record.destination);
+    let mut options = record.options.clone();
+    for action in actions {
+        match action {
+            Action::Rename(name) => destination.set_file_name(name),
+            Action::Move(folder) => {
+                destination = folder.join(destination.file_name().unwrap_or_default())
+            }
+            Action::Category(name) => options.category = Some(name.to_string()),
+            Action::Download(url) => downloads::queue_from_script(app, url),
+        }
+    }
+
+    if options != record.options {
+        if let Err(e) = manager.db().set_options(&record.id, &options) {
+            eprintln!("Failed to save options of {}: {}", record.id, e);
+        }
+    }
+    if destination != Path::new(&record.destination) {
+        let from = PathBuf::from(&record.destination);
+        let moved = tauri::async_runtime::spawn_blocking({
+            let destination = destination.clone();
+            move || relocate(&from, &destination)
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+        let filename = destination
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match moved.and_then(|()| {
+            manager
+                .db()
+                .update_destination(&record.id, &filename, &destination.to_string_lossy())
+                .map_err(|e| e.to_string())
+        }) {
+            Ok(()) => {
+                manager.log_activity(
+                    "moved",
+                    Some(&record.id),
+                    json!({ "from": record.destination, "to": destination, "by": hook.function() }),
+                );
+                return destination;
+            }
+            Err(e) => eprintln!("Script move of {} failed: {}", record.destination, e),
+        }
+    }
+    PathBuf::from(&record.destination)
+}
+
+// rename, or copy and delete across filesystems. Never replaces a file that's there
+fn relocate(from: &Path, to: &Path) -> Result<(), String> {
+    if to.exists() {
+        return Err(format!("{} already exists", to.display()));
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    if !from.exists() {
+        // nothing written yet, only the record moves
+        return Ok(());
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to).map_err(|e| e.to_string())?;
+    std::fs::remove_file(from).map_err(|e| e.to_string())
+}
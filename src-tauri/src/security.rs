@@ -0,0 +1,73 @@
+use serde_json::json;
+use tauri::{AppHandle, Manager, Webview};
+
+use crate::downloads::manager::DownloadManager;
+use crate::lock;
+
+/// The only window privileged commands are taken from
+const MAIN_WINDOW: &str = "main";
+
+/// Check a privileged command before it runs: one that writes settings, overwrites or
+/// uploads files, or starts a program. `capabilities/privileged.json` only grants them
+/// to the main window; this checks again that the call came from the app's own pages
+/// there and that guest mode is off. A denial is logged as `security_denied`.
+pub fn authorize(app: &AppHandle, webview: &Webview, command: &str) -> Result<(), String> {
+    let url = webview.url().map(|url| url.to_string()).unwrap_or_default();
+    let reason = if webview.label() != MAIN_WINDOW {
+        "window"
+    } else if !local(webview) {
+        "origin"
+    } else if lock::locked(app) {
+        "locked"
+    } else {
+        return Ok(());
+    };
+    eprintln!(
+        "Denied {} from {} ({}): {}",
+        command,
+        webview.label(),
+        url,
+        reason
+    );
+    if let Some(manager) = app.try_state::<DownloadManager>() {
+        manager.log_activity(
+            "security_denied",
+            None,
+            json!({
+                "command": command,
+                "window": webview.label(),
+                "url": url,
+                "reason": reason,
+            }),
+        );
+    }
+    match reason {
+        "locked" => lock::guard(app),
+        _ => Err(format!("{} isn't allowed from here", command)),
+    }
+}
+
+/// The call came from the app's own pages in the main window, what `authorize` lets through
+/// while unlocked
+pub fn own_page(webview: &Webview) -> bool {
+    webview.label() == MAIN_WINDOW && local(webview)
+}
+
+/// `a == b` in a time that only depends on the length, for tokens and hashes someone
+/// could be guessing at
+pub fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// the bundled frontend, or the dev server in debug builds
+fn local(webview: &Webview) -> bool {
+    let Ok(url) = webview.url() else {
+        return false;
+    };
+    let dev = cfg!(debug_assertions) && url.host_str() == Some("localhost");
+    match url.scheme() {
+        "tauri" => true,
+        "http" | "https" => url.host_str() == Some("tauri.localhost") || dev,
+        _ => false,
+    }
+}
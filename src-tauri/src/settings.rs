@@ -1,4 +1,5 @@
 pub mod config;
+pub mod policy;
 pub mod store;
 
 pub use config::*;
@@ -1,17 +1,43 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct AppSettings {
     pub app: AppConfig,
     pub shortcuts: ShortcutConfig,
     pub download: DownloadConfig,
     pub thread: ThreadConfig,
     pub session: SessionConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub s3: S3Config,
+    #[serde(default)]
+    pub chaos: ChaosConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    #[serde(default)]
+    pub scripting: ScriptingConfig,
+    #[serde(default)]
+    pub bandwidth: BandwidthConfig,
+    #[serde(default)]
+    pub lock: LockConfig,
+    /// "send to" commands offered for each download in history
+    #[serde(default)]
+    pub actions: Vec<SendAction>,
     pub send_anonymous_metrics: bool,
     pub show_notifications: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct AppConfig {
     pub show_tray_icon: bool,
     pub quit_on_close: bool,
@@ -21,9 +47,24 @@ pub struct AppConfig {
     pub show_download_progress: bool,
     pub show_segment_progress: bool,
     pub autostart: bool,
+    /// backend strings (CLI, notifications), a code from `get_available_locales` or "system"
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// "bytes" (MB/s) or "bits" (Mbit/s) for speeds the backend formats
+    #[serde(default = "default_speed_unit")]
+    pub speed_unit: String,
+    /// "binary" (MiB) or "si" (MB)
+    #[serde(default = "default_unit_prefix")]
+    pub unit_prefix: String,
+    /// emit `download_debug` events on what each connection does, as `--debug` does
+    #[serde(default)]
+    pub debug_events: bool,
+    /// ms between `download_progress` events of a running download
+    #[serde(default = "default_progress_interval_ms")]
+    pub progress_interval_ms: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct ShortcutConfig {
     pub go_home: String,
     pub open_settings: String,
@@ -35,27 +76,438 @@ pub struct ShortcutConfig {
     pub quit_app: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct DownloadConfig {
     pub download_location: String,
     pub num_threads: u8,
     pub chunk_size: u32,
     pub socket_buffer_size: u32,
+    /// KiB/s per download, 0 is unlimited
     pub speed_limit: u64,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    /// first matching host wins over `user_agent`
+    #[serde(default)]
+    pub user_agent_rules: Vec<UserAgentRule>,
+    /// path under `download_location`, see `downloads::template::VARIABLES`
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    /// category name to template, wins over `filename_template`
+    #[serde(default)]
+    pub category_templates: BTreeMap<String, String>,
+    /// give every multi-link add its own dated subfolder
+    #[serde(default)]
+    pub batch_subfolders: bool,
+    /// multi-link adds skip what robots.txt disallows and keep to its crawl-delay
+    #[serde(default)]
+    pub respect_robots: bool,
+    /// delete what failed downloads left at their destination once they're this many days
+    /// old, 0 as soon as they fail for good. None keeps them
+    #[serde(default)]
+    pub cleanup_partials_days: Option<u32>,
+    /// what cancelling leaves of a download's file: "truncate" cuts it back to the bytes
+    /// downloaded in one piece from the start, "delete" removes it
+    #[serde(default = "default_on_cancel")]
+    pub on_cancel: String,
+    /// replace a completed file identical to an earlier download with a hard link to it
+    #[serde(default)]
+    pub hardlink_duplicates: bool,
+    /// how non-ASCII names are written to disk: "keep", "nfc" or "ascii" (transliterated).
+    /// The database keeps the name the server sent
+    #[serde(default = "default_filename_unicode")]
+    pub filename_unicode: String,
+    /// octal mode bits for completed files on Unix, e.g. "644", empty keeps the umask's
+    #[serde(default)]
+    pub file_mode: String,
+    /// make completed files with one of `executable_extensions` executable on Unix
+    #[serde(default)]
+    pub mark_executable: bool,
+    #[serde(default = "default_executable_extensions")]
+    pub executable_extensions: Vec<String>,
+    /// tag completed files with their source url, xattrs or the Windows Zone.Identifier
+    #[serde(default = "default_record_origin")]
+    pub record_origin: bool,
+    /// hold completed downloads as unscanned until `scan_command` passes or the user
+    /// releases them, see `downloads::quarantine`
+    #[serde(default)]
+    pub quarantine: bool,
+    /// scanner run on each quarantined file with TUR_PATH set, exit code 0 releases it,
+    /// e.g. `clamscan --no-summary "$TUR_PATH"`
+    #[serde(default)]
+    pub scan_command: String,
+    /// fetch only what changed from the file a download replaces, when the server has a
+    /// `.zsync` file for it
+    #[serde(default = "default_true")]
+    pub delta_updates: bool,
+    /// how fresh ranges are sized: "fibonacci" growing ones, "fixed" ones of `chunk_size`
+    /// MiB, or "adaptive" ones fitted to the speed and round trip measured while it runs
+    #[serde(default = "default_range_strategy")]
+    pub range_strategy: String,
+    /// minutes between checks that queued downloads' urls still answer, 0 turns them off,
+    /// see `downloads::health`
+    #[serde(default = "default_health_check_minutes")]
+    pub health_check_minutes: u32,
+    /// look for mirrors of new downloads in Link headers and mirror list pages, see
+    /// `downloads::mirrors`
+    #[serde(default = "default_true")]
+    pub discover_mirrors: bool,
+    /// extensions refused when a download is added, e.g. "exe" on a managed install
+    #[serde(default)]
+    pub blocked_extensions: Vec<String>,
+    /// shell command run after each completed download, with TUR_PATH, TUR_URL and TUR_ID set
+    #[serde(default)]
+    pub post_command: String,
+    /// end post_command runs that are still going when tur quits
+    #[serde(default)]
+    pub stop_commands_on_quit: bool,
+    /// per host or url overrides, see `downloads::rules`
+    #[serde(default)]
+    pub rules: Vec<DownloadRule>,
+    /// url rewrites applied in order before a new download is probed, see `downloads::rewrite`
+    #[serde(default)]
+    pub rewrites: Vec<RewriteRule>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl DownloadConfig {
+    /// `file_mode` parsed, None when empty or not an octal mode
+    pub fn file_mode_bits(&self) -> Option<u32> {
+        u32::from_str_radix(self.file_mode.trim(), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+    }
+
+    /// `filename` ends in one of `blocked_extensions`
+    pub fn is_blocked(&self, filename: &str) -> bool {
+        let Some((_, extension)) = filename.rsplit_once('.') else {
+            return false;
+        };
+        self.blocked_extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(extension))
+    }
+}
+
+/// `host` is an exact name or `*.example.com` for the domain and its subdomains
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+pub struct UserAgentRule {
+    pub host: String,
+    pub user_agent: String,
+}
+
+/// Overrides for downloads whose url matches, the first matching rule in the list wins.
+/// Options given with the download itself still win over the rule.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct DownloadRule {
+    pub name: String,
+    /// exact host or `*.example.com`, empty matches every host
+    pub host: String,
+    /// regex searched for in the whole url, empty matches every url
+    pub pattern: String,
+    pub connections: Option<u8>,
+    /// KiB/s
+    pub speed_limit: Option<u64>,
+    pub user_agent: Option<String>,
+    /// folder used instead of the download location, batches keep their subfolder
+    pub destination: Option<String>,
+    /// one of `downloads::category::NAMES`
+    pub category: Option<String>,
+}
+
+/// Turns a url into the one actually downloaded, e.g. a pinned mirror or without tracking
+/// parameters. Every matching rule applies, in list order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct RewriteRule {
+    pub name: String,
+    /// exact host or `*.example.com`, empty matches every host
+    pub host: String,
+    /// regex over the whole url, replaced with `replacement` (`$1`, `${name}` for groups).
+    /// Empty leaves the url as is
+    pub pattern: String,
+    pub replacement: String,
+    /// query parameters dropped afterwards, `utm_*` for every one starting with `utm_`
+    pub strip_params: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct ThreadConfig {
     pub total_connections: u8,
     pub per_task_connections: u8,
+    /// downloads from one host running at once, the rest wait their turn. 0 is no cap
+    #[serde(default)]
+    pub max_concurrent_per_host: u8,
+    /// threads writing chunks to disk, 0 uses tokio's blocking pool. Read at startup
+    #[serde(default = "default_io_threads")]
+    pub io_threads: u8,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct SessionConfig {
     pub history: bool,
     pub metadata: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// local addresses or interface names (eth0, en0) to spread workers over,
+    /// e.g. Ethernet + LTE to add up both uplinks; empty uses the default route
+    pub interfaces: Vec<String>,
+    /// receive window in KiB, 0 leaves it adaptive. Worth raising on high latency links
+    pub socket_buffer_size: u32,
+    pub tcp_nodelay: bool,
+    /// seconds, 0 turns keepalive off
+    pub tcp_keepalive: u64,
+    /// seconds a whole request may take, body included. 0 is no limit, which large
+    /// downloads need, stalls are caught by `read_timeout` instead
+    pub request_timeout: u64,
+    /// seconds without a byte before a connection counts as stalled, 0 waits forever
+    pub read_timeout: u64,
+    /// `http://` or `https://` proxy url for every request, empty follows the
+    /// environment's `HTTPS_PROXY` and friends, "none" goes direct
+    pub proxy: String,
+    /// accept any certificate, for self-signed hosts on a trusted network only
+    pub allow_insecure: bool,
+    /// PEM file with extra root certificates, e.g. a corporate CA
+    pub ca_certificate: String,
+    /// links from deep links, the extension, scripts and other apps can't reach
+    /// localhost, the LAN or cloud metadata addresses until the user confirms
+    pub block_private_targets: bool,
+    /// host names or addresses that pass anyway, e.g. a NAS
+    pub allowed_private_hosts: Vec<String>,
+}
+
+/// Automatic retry of failed downloads, a download's own policy replaces it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub enabled: bool,
+    pub delay_minutes: u32,
+    pub max_attempts: u32,
+}
+
+/// Credentials for `s3://bucket/key` urls, presigned https urls don't need any
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct S3Config {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// temporary credentials only
+    pub session_token: String,
+    pub region: String,
+    /// MinIO, R2 and other S3 compatible hosts, empty for AWS
+    pub endpoint: String,
+    /// send `x-amz-request-payer: requester` for requester pays buckets
+    pub requester_pays: bool,
+}
+
+/// Fault injection for QA, also switched on by `--chaos`
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    /// chance per request and per chunk that the connection drops
+    pub reset_rate: f64,
+    /// chance per chunk that the read stalls for `slow_ms`
+    pub slow_rate: f64,
+    pub slow_ms: u64,
+    /// chance per request of a synthetic 503
+    pub error_rate: f64,
+    /// same seed, same sequence of faults
+    pub seed: u64,
+}
+
+/// Built-in web dashboard for headless installs, read at startup
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct RemoteConfig {
+    pub enabled: bool,
+    /// 0.0.0.0 opens it to the LAN
+    pub bind: String,
+    pub port: u16,
+    /// asked for as `?token=` on the page and bearer auth on the API, made on the first
+    /// start when empty
+    pub token: String,
+}
+
+/// History shared through a folder another tool keeps in sync (Dropbox, Syncthing)
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub enabled: bool,
+    pub folder: String,
+    /// minutes between automatic syncs, read at startup
+    pub interval_minutes: u32,
+}
+
+/// Monthly transfer cap for metered connections, see `downloads::stats`, and how
+/// running downloads share the link, see `downloads::fair`
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// MiB received per billing month before downloads pause, 0 is no cap
+    pub monthly_cap_mib: u64,
+    /// day of the month the count starts over, 1 to 28
+    pub billing_day: u8,
+    /// KiB/s over all downloads together, 0 is no limit
+    pub total_limit: u64,
+    /// split the link evenly between running downloads whatever their connection
+    /// count, priority ones get a double share
+    pub balance: bool,
+}
+
+/// Guest mode, see `lock`: history and progress stay visible, nothing can be changed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct LockConfig {
+    pub enabled: bool,
+    /// "pbkdf2:rounds:salt:hex" of the PIN `unlock_app` asks for, empty for none. Set
+    /// through `lock.pin`, which takes the PIN itself. `get_settings` never shows it.
+    pub pin_hash: String,
+}
+
+/// When notifications wait, `show_notifications` still turns them off entirely
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// hold notifications while the OS is in Do Not Disturb / Focus Assist
+    pub respect_dnd: bool,
+    /// events shown even then, e.g. "download_failed"
+    pub critical_events: Vec<String>,
+    /// notifications kept for `get_notification_history`
+    pub history_limit: u32,
+    /// email, Telegram and ntfy, sent to whatever DND or `show_notifications` say
+    pub channels: Vec<NotificationChannel>,
+}
+
+/// Somewhere besides the app that notifications go to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct NotificationChannel {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// notification events like "download_completed", empty is all of them
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChannelKind {
+    Smtp {
+        host: String,
+        /// 0 picks the usual one for `security`
+        #[serde(default)]
+        port: u16,
+        /// "starttls", "tls" or "none"
+        #[serde(default = "default_smtp_security")]
+        security: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: String,
+        from: String,
+        to: Vec<String>,
+    },
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+    },
+    Ntfy {
+        #[serde(default = "default_ntfy_server")]
+        server: String,
+        topic: String,
+        /// access token for protected topics
+        #[serde(default)]
+        token: String,
+    },
+}
+
+/// Rhai scripts with `on_add`, `on_complete` and `on_fail` functions, see `scripting`
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct ScriptingConfig {
+    pub enabled: bool,
+    /// every `.rhai` file in it runs, by name. Empty is `scripts` in the app data folder
+    pub folder: String,
+    /// steps a script may take per hook before it's stopped
+    pub max_operations: u64,
+}
+
+/// JSON POSTed to `url` on download events, e.g. for ntfy, Gotify or home automation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    /// HMAC-SHA256 key for the `X-Tur-Signature` header, empty sends it unsigned
+    pub secret: String,
+    /// "download_completed", "download_failed" and "queue_empty", empty is all of them
+    pub events: Vec<String>,
+    pub enabled: bool,
+}
+
+/// A command run for one download on request, e.g. `vlc {path}`. Words are split like a
+/// shell would but no shell runs it, see `downloads::actions::PLACEHOLDERS`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct SendAction {
+    pub name: String,
+    pub command: String,
+}
+
+impl AppSettings {
+    /// Clamp values the client can't use into range
+    pub fn validate(&mut self) {
+        let network = &mut self.network;
+        // older settings kept the buffer size under download
+        if network.socket_buffer_size == 0 {
+            network.socket_buffer_size = self.download.socket_buffer_size;
+        }
+        if network.socket_buffer_size > 0 {
+            network.socket_buffer_size = network
+                .socket_buffer_size
+                .clamp(MIN_SOCKET_BUFFER_KIB, MAX_SOCKET_BUFFER_KIB);
+        }
+        network.tcp_keepalive = network.tcp_keepalive.min(MAX_TCP_KEEPALIVE);
+        network.interfaces.retain(|i| !i.trim().is_empty());
+        network.proxy = network.proxy.trim().to_string();
+        self.download.num_threads = self.download.num_threads.max(1);
+        if !["keep", "nfc", "ascii"].contains(&self.download.filename_unicode.as_str()) {
+            self.download.filename_unicode = default_filename_unicode();
+        }
+        if !["truncate", "delete"].contains(&self.download.on_cancel.as_str()) {
+            self.download.on_cancel = default_on_cancel();
+        }
+        if !["fibonacci", "fixed", "adaptive"].contains(&self.download.range_strategy.as_str()) {
+            self.download.range_strategy = default_range_strategy();
+        }
+        if !["bytes", "bits"].contains(&self.app.speed_unit.as_str()) {
+            self.app.speed_unit = default_speed_unit();
+        }
+        if !["binary", "si"].contains(&self.app.unit_prefix.as_str()) {
+            self.app.unit_prefix = default_unit_prefix();
+        }
+        if self.download.file_mode_bits().is_none() {
+            self.download.file_mode.clear();
+        }
+        self.sync.interval_minutes = self.sync.interval_minutes.max(1);
+        self.app.progress_interval_ms = self
+            .app
+            .progress_interval_ms
+            .clamp(MIN_PROGRESS_INTERVAL_MS, MAX_PROGRESS_INTERVAL_MS);
+    }
+}
+
+const MIN_SOCKET_BUFFER_KIB: u32 = 64;
+const MAX_SOCKET_BUFFER_KIB: u32 = 16 * 1024;
+const MAX_TCP_KEEPALIVE: u64 = 2 * 60 * 60;
+const MIN_PROGRESS_INTERVAL_MS: u64 = 50;
+const MAX_PROGRESS_INTERVAL_MS: u64 = 10_000;
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
@@ -64,6 +516,18 @@ impl Default for AppSettings {
             download: DownloadConfig::default(),
             thread: ThreadConfig::default(),
             session: SessionConfig::default(),
+            network: NetworkConfig::default(),
+            retry: RetryConfig::default(),
+            s3: S3Config::default(),
+            chaos: ChaosConfig::default(),
+            remote: RemoteConfig::default(),
+            sync: SyncConfig::default(),
+            notifications: NotificationConfig::default(),
+            webhooks: Vec::new(),
+            scripting: ScriptingConfig::default(),
+            bandwidth: BandwidthConfig::default(),
+            lock: LockConfig::default(),
+            actions: Vec::new(),
             send_anonymous_metrics: false,
             show_notifications: true,
         }
@@ -81,6 +545,11 @@ impl Default for AppConfig {
             show_download_progress: true,
             show_segment_progress: true,
             autostart: false,
+            language: default_language(),
+            speed_unit: default_speed_unit(),
+            unit_prefix: default_unit_prefix(),
+            debug_events: false,
+            progress_interval_ms: default_progress_interval_ms(),
         }
     }
 }
@@ -108,6 +577,31 @@ impl Default for DownloadConfig {
             chunk_size: 16,
             socket_buffer_size: 0,
             speed_limit: 0,
+            user_agent: default_user_agent(),
+            user_agent_rules: Vec::new(),
+            filename_template: default_filename_template(),
+            category_templates: BTreeMap::new(),
+            batch_subfolders: false,
+            respect_robots: false,
+            cleanup_partials_days: None,
+            on_cancel: default_on_cancel(),
+            hardlink_duplicates: false,
+            filename_unicode: default_filename_unicode(),
+            file_mode: String::new(),
+            mark_executable: false,
+            executable_extensions: default_executable_extensions(),
+            record_origin: true,
+            quarantine: false,
+            scan_command: String::new(),
+            delta_updates: true,
+            range_strategy: default_range_strategy(),
+            health_check_minutes: default_health_check_minutes(),
+            discover_mirrors: true,
+            blocked_extensions: Vec::new(),
+            post_command: String::new(),
+            stop_commands_on_quit: false,
+            rules: Vec::new(),
+            rewrites: Vec::new(),
         }
     }
 }
@@ -117,6 +611,8 @@ impl Default for ThreadConfig {
         Self {
             total_connections: 1,
             per_task_connections: 1,
+            max_concurrent_per_host: 0,
+            io_threads: default_io_threads(),
         }
     }
 }
@@ -130,6 +626,189 @@ impl Default for SessionConfig {
     }
 }
 
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            interfaces: Vec::new(),
+            socket_buffer_size: 0,
+            tcp_nodelay: true,
+            tcp_keepalive: 60,
+            request_timeout: 0,
+            read_timeout: 60,
+            proxy: String::new(),
+            allow_insecure: false,
+            ca_certificate: String::new(),
+            block_private_targets: true,
+            allowed_private_hosts: Vec::new(),
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay_minutes: 5,
+            max_attempts: 3,
+        }
+    }
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+            session_token: String::new(),
+            region: "us-east-1".into(),
+            endpoint: String::new(),
+            requester_pays: false,
+        }
+    }
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reset_rate: 0.001,
+            slow_rate: 0.01,
+            slow_ms: 250,
+            error_rate: 0.05,
+            seed: 0x7475_7221,
+        }
+    }
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1".into(),
+            port: 7451,
+            token: String::new(),
+        }
+    }
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            respect_dnd: true,
+            critical_events: Vec::new(),
+            history_limit: 200,
+            channels: Vec::new(),
+        }
+    }
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            url: String::new(),
+            secret: String::new(),
+            events: Vec::new(),
+            enabled: true,
+        }
+    }
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            folder: String::new(),
+            max_operations: 1_000_000,
+        }
+    }
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            monthly_cap_mib: 0,
+            billing_day: 1,
+            total_limit: 0,
+            balance: true,
+        }
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            folder: String::new(),
+            interval_minutes: 15,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_smtp_security() -> String {
+    "starttls".into()
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".into()
+}
+
+fn default_language() -> String {
+    "system".into()
+}
+
+fn default_speed_unit() -> String {
+    "bytes".into()
+}
+
+fn default_unit_prefix() -> String {
+    "binary".into()
+}
+
+fn default_io_threads() -> u8 {
+    2
+}
+
+fn default_filename_template() -> String {
+    "{filename}".into()
+}
+
+fn default_filename_unicode() -> String {
+    "keep".into()
+}
+
+fn default_progress_interval_ms() -> u64 {
+    100
+}
+
+fn default_on_cancel() -> String {
+    "truncate".into()
+}
+
+fn default_range_strategy() -> String {
+    "fibonacci".into()
+}
+
+fn default_health_check_minutes() -> u32 {
+    30
+}
+
+fn default_executable_extensions() -> Vec<String> {
+    vec!["sh".into(), "AppImage".into(), "run".into()]
+}
+
+fn default_record_origin() -> bool {
+    true
+}
+
+fn default_user_agent() -> String {
+    "tur/1.0 (Download Manager)".into()
+}
+
 fn get_default_download_dir() -> String {
     dirs::download_dir()
         .and_then(|path| path.to_str().map(|s| s.to_string()))
@@ -0,0 +1,117 @@
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use super::config::AppSettings;
+
+/// Overrides where the policy file is looked for, mostly for testing a deployment
+const POLICY_ENV: &str = "TUR_POLICY_FILE";
+
+/// Settings pinned by an administrator or parent in a read-only file, shaped like the
+/// settings themselves, e.g.
+/// `{"download": {"download_location": "/srv/downloads"}, "network": {"proxy": "http://proxy:3128"}}`.
+/// Every value in it is locked.
+#[derive(Debug, Default)]
+pub struct Policy {
+    pub path: Option<PathBuf>,
+    values: Map<String, Value>,
+}
+
+impl Policy {
+    fn read() -> Self {
+        let Some(path) = policy_path() else {
+            return Policy::default();
+        };
+        let values = match std::fs::read(&path) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(Value::Object(values)) => values,
+                Ok(_) => {
+                    eprintln!("Ignoring policy {}: not a JSON object", path.display());
+                    return Policy::default();
+                }
+                Err(e) => {
+                    eprintln!("Ignoring policy {}: {}", path.display(), e);
+                    return Policy::default();
+                }
+            },
+            // no managed install
+            Err(_) => return Policy::default(),
+        };
+        Policy {
+            path: Some(path),
+            values,
+        }
+    }
+
+    /// Keys the user can't change, in the dotted form `update_setting` takes
+    pub fn locked(&self) -> Vec<String> {
+        let mut locked = Vec::new();
+        for (section, value) in &self.values {
+            match value {
+                Value::Object(fields) => {
+                    locked.extend(fields.keys().map(|field| format!("{}.{}", section, field)))
+                }
+                _ => locked.push(section.clone()),
+            }
+        }
+        locked
+    }
+
+    pub fn is_locked(&self, key: &str) -> bool {
+        let (section, field) = match key.split_once('.') {
+            Some((section, field)) => (section, Some(field)),
+            None => (key, None),
+        };
+        match self.values.get(section) {
+            Some(Value::Object(fields)) => field.is_none_or(|f| fields.contains_key(f)),
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    /// Put the locked values over `settings`, a policy that doesn't fit is skipped whole
+    pub fn enforce(&self, settings: &mut AppSettings) {
+        if self.values.is_empty() {
+            return;
+        }
+        let Ok(Value::Object(mut merged)) = serde_json::to_value(&*settings) else {
+            return;
+        };
+        for (section, value) in &self.values {
+            match (merged.get_mut(section), value) {
+                (Some(Value::Object(current)), Value::Object(fields)) => {
+                    current.extend(fields.clone());
+                }
+                _ => {
+                    merged.insert(section.clone(), value.clone());
+                }
+            }
+        }
+        match serde_json::from_value(Value::Object(merged)) {
+            Ok(enforced) => *settings = enforced,
+            Err(e) => eprintln!("Policy doesn't match the settings, not applied: {}", e),
+        }
+    }
+}
+
+/// The policy, read once per run
+pub fn get() -> &'static Policy {
+    static POLICY: OnceLock<Policy> = OnceLock::new();
+    POLICY.get_or_init(Policy::read)
+}
+
+// system wide locations a user without admin rights can't write to
+fn policy_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(POLICY_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    if cfg!(windows) {
+        std::env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("tur\\policy.json"))
+    } else if cfg!(target_os = "macos") {
+        Some(PathBuf::from(
+            "/Library/Application Support/tur/policy.json",
+        ))
+    } else {
+        Some(PathBuf::from("/etc/tur/policy.json"))
+    }
+}
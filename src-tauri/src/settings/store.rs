@@ -1,16 +1,35 @@
-use super::config::AppSettings;
-use tauri::AppHandle;
+use super::config::{AppSettings, ChannelKind};
+use super::policy;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
 use tauri_plugin_store::StoreExt;
 
+use crate::downloads::manager::DownloadManager;
+
 const STORE_PATH: &str = "settings.json";
 const SETTINGS_KEY: &str = "settings";
+/// What a secret shows as in `get_settings`. Sent back like this, the stored one stays.
+pub const REDACTED: &str = "********";
+
+// settings.json of the active profile, relative paths would land in the app data dir
+fn store_path(app: &AppHandle) -> PathBuf {
+    match crate::profiles::data_dir(app) {
+        Ok(dir) => dir.join(STORE_PATH),
+        Err(e) => {
+            eprintln!("Warning: Failed to find the profile folder: {}", e);
+            PathBuf::from(STORE_PATH)
+        }
+    }
+}
 
 pub fn load_or_create(app: &AppHandle) -> AppSettings {
     match load_existing(app) {
         Ok(settings) => settings,
         Err(_) => {
             // Store doesn't exist or is corrupted, create with defaults
-            let default_settings = AppSettings::default();
+            let mut default_settings = AppSettings::default();
+            policy::get().enforce(&mut default_settings);
             if let Err(e) = save(app, &default_settings) {
                 eprintln!("Warning: Failed to save default settings: {}", e);
             }
@@ -20,31 +39,40 @@ pub fn load_or_create(app: &AppHandle) -> AppSettings {
 }
 
 fn load_existing(app: &AppHandle) -> Result<AppSettings, String> {
-    let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
+    let store = app.store(store_path(app)).map_err(|e| e.to_string())?;
     
     // Check if store exists and has our settings key
     match store.get(SETTINGS_KEY) {
         Some(value) => {
-            serde_json::from_value(value.clone())
-                .map_err(|e| format!("Failed to deserialize settings: {}", e))
+            let mut settings: AppSettings = serde_json::from_value(value.clone())
+                .map_err(|e| format!("Failed to deserialize settings: {}", e))?;
+            policy::get().enforce(&mut settings);
+            settings.validate();
+            Ok(settings)
         }
         None => Err("Settings key not found in store".to_string()),
     }
 }
 
 pub fn save(app: &AppHandle, settings: &AppSettings) -> Result<(), String> {
-    let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
+    let store = app.store(store_path(app)).map_err(|e| e.to_string())?;
     
-    let value = serde_json::to_value(settings)
+    let mut settings = settings.clone();
+    settings.validate();
+    let value = serde_json::to_value(&settings)
         .map_err(|e| format!("Failed to serialize settings: {}", e))?;
     
     store.set(SETTINGS_KEY, value);
     store.save().map_err(|e| e.to_string())?;
+    crate::i18n::set_language(&settings.app.language);
     
     Ok(())
 }
 
 pub fn update_field(app: &AppHandle, key: &str, value: serde_json::Value) -> Result<(), String> {
+    if policy::get().is_locked(key) {
+        return Err(format!("{} is set by policy", key));
+    }
     let mut settings = load_or_create(app);
     
     let parts: Vec<&str> = key.split('.').collect();
@@ -65,6 +93,46 @@ pub fn update_field(app: &AppHandle, key: &str, value: serde_json::Value) -> Res
         ["session", field] => {
             update_session_field(&mut settings.session, field, value)?;
         }
+        ["network", field] => {
+            update_network_field(&mut settings.network, field, value)?;
+        }
+        ["retry", field] => {
+            update_retry_field(&mut settings.retry, field, value)?;
+        }
+        ["s3", field] => {
+            update_s3_field(&mut settings.s3, field, value)?;
+        }
+        ["chaos", field] => {
+            update_chaos_field(&mut settings.chaos, field, value)?;
+        }
+        ["remote", field] => {
+            update_remote_field(&mut settings.remote, field, value)?;
+        }
+        ["sync", field] => {
+            update_sync_field(&mut settings.sync, field, value)?;
+        }
+        ["notifications", field] => {
+            update_notifications_field(&mut settings.notifications, field, value)?;
+        }
+        ["scripting", field] => {
+            update_scripting_field(&mut settings.scripting, field, value)?;
+        }
+        ["bandwidth", field] => {
+            update_bandwidth_field(&mut settings.bandwidth, field, value)?;
+        }
+        ["lock", field] => {
+            update_lock_field(&mut settings.lock, field, value)?;
+        }
+        ["webhooks"] => {
+            let webhooks: Vec<_> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            crate::webhooks::validate(&webhooks)?;
+            settings.webhooks = webhooks;
+        }
+        ["actions"] => {
+            let actions: Vec<_> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            crate::downloads::actions::validate(&actions)?;
+            settings.actions = actions;
+        }
         ["send_anonymous_metrics"] => {
             settings.send_anonymous_metrics = value.as_bool().unwrap_or(false);
         }
@@ -91,6 +159,11 @@ fn update_app_field(
         "show_download_progress" => config.show_download_progress = value.as_bool().unwrap_or(true),
         "show_segment_progress" => config.show_segment_progress = value.as_bool().unwrap_or(true),
         "autostart" => config.autostart = value.as_bool().unwrap_or(false),
+        "language" => config.language = value.as_str().unwrap_or("system").to_string(),
+        "speed_unit" => config.speed_unit = value.as_str().unwrap_or("bytes").to_string(),
+        "unit_prefix" => config.unit_prefix = value.as_str().unwrap_or("binary").to_string(),
+        "debug_events" => config.debug_events = value.as_bool().unwrap_or(false),
+        "progress_interval_ms" => config.progress_interval_ms = value.as_u64().unwrap_or(100),
         _ => return Err(format!("Unknown app field: {}", field)),
     }
     Ok(())
@@ -127,6 +200,58 @@ fn update_download_field(
         "chunk_size" => config.chunk_size = value.as_u64().unwrap_or(16) as u32,
         "socket_buffer_size" => config.socket_buffer_size = value.as_u64().unwrap_or(0) as u32,
         "speed_limit" => config.speed_limit = value.as_u64().unwrap_or(0),
+        "user_agent" => config.user_agent = value.as_str().unwrap_or("").to_string(),
+        "user_agent_rules" => {
+            config.user_agent_rules = serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        "filename_template" => {
+            config.filename_template = value.as_str().unwrap_or("{filename}").trim().to_string()
+        }
+        "category_templates" => {
+            config.category_templates = serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        "batch_subfolders" => config.batch_subfolders = value.as_bool().unwrap_or(false),
+        "respect_robots" => config.respect_robots = value.as_bool().unwrap_or(false),
+        "cleanup_partials_days" => {
+            config.cleanup_partials_days = value.as_u64().map(|days| days as u32)
+        }
+        "on_cancel" => config.on_cancel = value.as_str().unwrap_or("truncate").to_string(),
+        "hardlink_duplicates" => config.hardlink_duplicates = value.as_bool().unwrap_or(false),
+        "file_mode" => config.file_mode = value.as_str().unwrap_or("").trim().to_string(),
+        "mark_executable" => config.mark_executable = value.as_bool().unwrap_or(false),
+        "record_origin" => config.record_origin = value.as_bool().unwrap_or(true),
+        "quarantine" => config.quarantine = value.as_bool().unwrap_or(false),
+        "scan_command" => config.scan_command = value.as_str().unwrap_or("").to_string(),
+        "delta_updates" => config.delta_updates = value.as_bool().unwrap_or(true),
+        "range_strategy" => {
+            config.range_strategy = value.as_str().unwrap_or("fibonacci").to_string()
+        }
+        "discover_mirrors" => config.discover_mirrors = value.as_bool().unwrap_or(true),
+        "health_check_minutes" => {
+            config.health_check_minutes = value.as_u64().unwrap_or(30) as u32
+        }
+        "executable_extensions" => {
+            config.executable_extensions =
+                serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        "filename_unicode" => {
+            config.filename_unicode = value.as_str().unwrap_or("keep").to_string()
+        }
+        "blocked_extensions" => {
+            config.blocked_extensions = serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        "post_command" => config.post_command = value.as_str().unwrap_or("").to_string(),
+        "stop_commands_on_quit" => config.stop_commands_on_quit = value.as_bool().unwrap_or(false),
+        "rules" => {
+            let rules: Vec<_> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            crate::downloads::rules::validate(&rules)?;
+            config.rules = rules
+        }
+        "rewrites" => {
+            let rewrites: Vec<_> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            crate::downloads::rewrite::validate(&rewrites)?;
+            config.rewrites = rewrites
+        }
         _ => return Err(format!("Unknown download field: {}", field)),
     }
     Ok(())
@@ -140,6 +265,10 @@ fn update_thread_field(
     match field {
         "total_connections" => config.total_connections = value.as_u64().unwrap_or(1) as u8,
         "per_task_connections" => config.per_task_connections = value.as_u64().unwrap_or(1) as u8,
+        "max_concurrent_per_host" => {
+            config.max_concurrent_per_host = value.as_u64().unwrap_or(0) as u8
+        }
+        "io_threads" => config.io_threads = value.as_u64().unwrap_or(2).min(16) as u8,
         _ => return Err(format!("Unknown thread field: {}", field)),
     }
     Ok(())
@@ -158,17 +287,275 @@ fn update_session_field(
     Ok(())
 }
 
+fn update_network_field(
+    config: &mut super::config::NetworkConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "interfaces" => {
+            config.interfaces = serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        "socket_buffer_size" => config.socket_buffer_size = value.as_u64().unwrap_or(0) as u32,
+        "tcp_nodelay" => config.tcp_nodelay = value.as_bool().unwrap_or(true),
+        "tcp_keepalive" => config.tcp_keepalive = value.as_u64().unwrap_or(60),
+        "request_timeout" => config.request_timeout = value.as_u64().unwrap_or(0),
+        "read_timeout" => config.read_timeout = value.as_u64().unwrap_or(60),
+        "proxy" => config.proxy = value.as_str().unwrap_or("").to_string(),
+        "allow_insecure" => config.allow_insecure = value.as_bool().unwrap_or(false),
+        "ca_certificate" => config.ca_certificate = value.as_str().unwrap_or("").trim().to_string(),
+        "block_private_targets" => config.block_private_targets = value.as_bool().unwrap_or(true),
+        "allowed_private_hosts" => {
+            config.allowed_private_hosts =
+                serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        _ => return Err(format!("Unknown network field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_retry_field(
+    config: &mut super::config::RetryConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "enabled" => config.enabled = value.as_bool().unwrap_or(true),
+        "delay_minutes" => config.delay_minutes = value.as_u64().unwrap_or(5) as u32,
+        "max_attempts" => config.max_attempts = value.as_u64().unwrap_or(3) as u32,
+        _ => return Err(format!("Unknown retry field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_s3_field(
+    config: &mut super::config::S3Config,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let text = || value.as_str().unwrap_or("").trim().to_string();
+    match field {
+        "access_key_id" => config.access_key_id = text(),
+        "secret_access_key" => config.secret_access_key = text(),
+        "session_token" => config.session_token = text(),
+        "region" => config.region = Some(text()).filter(|r| !r.is_empty()).unwrap_or("us-east-1".into()),
+        "endpoint" => config.endpoint = text(),
+        "requester_pays" => config.requester_pays = value.as_bool().unwrap_or(false),
+        _ => return Err(format!("Unknown s3 field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_chaos_field(
+    config: &mut super::config::ChaosConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "enabled" => config.enabled = value.as_bool().unwrap_or(false),
+        "reset_rate" => config.reset_rate = value.as_f64().unwrap_or(0.0).clamp(0.0, 1.0),
+        "slow_rate" => config.slow_rate = value.as_f64().unwrap_or(0.0).clamp(0.0, 1.0),
+        "slow_ms" => config.slow_ms = value.as_u64().unwrap_or(250),
+        "error_rate" => config.error_rate = value.as_f64().unwrap_or(0.0).clamp(0.0, 1.0),
+        "seed" => config.seed = value.as_u64().unwrap_or(0),
+        _ => return Err(format!("Unknown chaos field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_remote_field(
+    config: &mut super::config::RemoteConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "enabled" => config.enabled = value.as_bool().unwrap_or(false),
+        "bind" => config.bind = value.as_str().unwrap_or("127.0.0.1").trim().to_string(),
+        "port" => config.port = value.as_u64().unwrap_or(7451) as u16,
+        "token" => config.token = value.as_str().unwrap_or("").trim().to_string(),
+        _ => return Err(format!("Unknown remote field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_sync_field(
+    config: &mut super::config::SyncConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "enabled" => config.enabled = value.as_bool().unwrap_or(false),
+        "folder" => config.folder = value.as_str().unwrap_or("").trim().to_string(),
+        "interval_minutes" => config.interval_minutes = value.as_u64().unwrap_or(15) as u32,
+        _ => return Err(format!("Unknown sync field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_scripting_field(
+    config: &mut super::config::ScriptingConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "enabled" => config.enabled = value.as_bool().unwrap_or(false),
+        "folder" => config.folder = value.as_str().unwrap_or("").trim().to_string(),
+        "max_operations" => config.max_operations = value.as_u64().unwrap_or(1_000_000).max(1),
+        _ => return Err(format!("Unknown scripting field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_bandwidth_field(
+    config: &mut super::config::BandwidthConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "monthly_cap_mib" => config.monthly_cap_mib = value.as_u64().unwrap_or(0),
+        "billing_day" => config.billing_day = value.as_u64().unwrap_or(1).clamp(1, 28) as u8,
+        "total_limit" => config.total_limit = value.as_u64().unwrap_or(0),
+        "balance" => config.balance = value.as_bool().unwrap_or(true),
+        _ => return Err(format!("Unknown bandwidth field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_lock_field(
+    config: &mut super::config::LockConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "enabled" => config.enabled = value.as_bool().unwrap_or(false),
+        "pin" => config.pin_hash = crate::lock::hash_pin(value.as_str().unwrap_or_default())?,
+        _ => return Err(format!("Unknown lock field: {}", field)),
+    }
+    Ok(())
+}
+
+fn update_notifications_field(
+    config: &mut super::config::NotificationConfig,
+    field: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match field {
+        "respect_dnd" => config.respect_dnd = value.as_bool().unwrap_or(true),
+        "critical_events" => {
+            config.critical_events = serde_json::from_value(value).map_err(|e| e.to_string())?
+        }
+        "history_limit" => config.history_limit = value.as_u64().unwrap_or(200) as u32,
+        "channels" => {
+            let channels: Vec<_> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            crate::notifications::channels::validate(&channels)?;
+            config.channels = channels
+        }
+        _ => return Err(format!("Unknown notifications field: {}", field)),
+    }
+    Ok(())
+}
+
+/// The settings for the GUI. The PIN hash never leaves the backend, the other secrets
+/// (tokens, passwords, keys) don't while guest mode is on. Both show as `REDACTED`.
 #[tauri::command]
+#[specta::specta]
 pub fn get_settings(app: AppHandle) -> AppSettings {
-    load_or_create(&app)
+    let mut settings = load_or_create(&app);
+    let locked = settings.lock.enabled;
+    for (key, value) in secrets(&mut settings) {
+        if !value.is_empty() && (locked || key == "lock.pin_hash") {
+            *value = REDACTED.to_string();
+        }
+    }
+    settings
+}
+
+// every field holding a secret, by where it is
+fn secrets(settings: &mut AppSettings) -> Vec<(String, &mut String)> {
+    let mut secrets = vec![
+        ("lock.pin_hash".to_string(), &mut settings.lock.pin_hash),
+        ("remote.token".to_string(), &mut settings.remote.token),
+        ("s3.secret_access_key".to_string(), &mut settings.s3.secret_access_key),
+        ("s3.session_token".to_string(), &mut settings.s3.session_token),
+    ];
+    for (i, webhook) in settings.webhooks.iter_mut().enumerate() {
+        secrets.push((format!("webhooks.{}.secret", i), &mut webhook.secret));
+    }
+    for (i, channel) in settings.notifications.channels.iter_mut().enumerate() {
+        let secret = match &mut channel.kind {
+            ChannelKind::Smtp { password, .. } => password,
+            ChannelKind::Telegram { bot_token, .. } => bot_token,
+            ChannelKind::Ntfy { token, .. } => token,
+        };
+        secrets.push((format!("notifications.channels.{}", i), secret));
+    }
+    secrets
+}
+
+// secrets that come back redacted keep the stored value, so does the PIN hash, which
+// only changes through `lock.pin`
+fn keep_secrets(app: &AppHandle, settings: &mut AppSettings) {
+    let mut stored = load_or_create(app);
+    let stored: HashMap<String, String> = secrets(&mut stored)
+        .into_iter()
+        .map(|(key, value)| (key, value.clone()))
+        .collect();
+    for (key, value) in secrets(settings) {
+        if value == REDACTED || key == "lock.pin_hash" {
+            *value = stored.get(&key).cloned().unwrap_or_default();
+        }
+    }
+}
+
+/// Keys pinned by the policy file, shown as read-only in the settings
+#[tauri::command]
+#[specta::specta]
+pub fn get_locked_settings() -> Vec<String> {
+    policy::get().locked()
 }
 
 #[tauri::command]
-pub fn update_settings(app: AppHandle, settings: AppSettings) -> Result<(), String> {
-    save(&app, &settings)
+#[specta::specta]
+pub fn update_settings(
+    app: AppHandle,
+    webview: tauri::Webview,
+    mut settings: AppSettings,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "update_settings")?;
+    keep_secrets(&app, &mut settings);
+    save(&app, &settings)?;
+    log_change(&app, None, None);
+    Ok(())
 }
 
 #[tauri::command]
-pub fn update_setting(app: AppHandle, key: String, value: serde_json::Value) -> Result<(), String> {
-    update_field(&app, &key, value)
+#[specta::specta]
+pub fn update_setting(
+    app: AppHandle,
+    webview: tauri::Webview,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    crate::security::authorize(&app, &webview, "update_setting")?;
+    update_field(&app, &key, value.clone())?;
+    log_change(&app, Some(&key), Some(value));
+    Ok(())
+}
+
+// credentials and tokens are logged as changed, without the value
+fn log_change(app: &AppHandle, key: Option<&str>, value: Option<serde_json::Value>) {
+    let Some(manager) = app.try_state::<DownloadManager>() else {
+        return;
+    };
+    let secret = key.is_some_and(|k| {
+        ["secret", "token", "password", "access_key", "pin"]
+            .iter()
+            .any(|s| k.contains(s))
+    });
+    let value = if secret { None } else { value };
+    manager.log_activity(
+        "settings_changed",
+        None,
+        serde_json::json!({ "key": key, "value": value }),
+    );
 }
\ No newline at end of file
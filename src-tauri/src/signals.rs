@@ -0,0 +1,75 @@
+use tokio::signal;
+
+/// Listeners for the ways a user or the OS asks us to quit: Ctrl+C everywhere, SIGTERM and
+/// SIGHUP on unix, Ctrl+Break, a closed console window, logoff and shutdown on Windows.
+/// A request that comes in between two `recv`s is kept for the next one.
+pub struct Signals {
+    #[cfg(unix)]
+    unix: [signal::unix::Signal; 3],
+    #[cfg(windows)]
+    ctrl_c: signal::windows::CtrlC,
+    #[cfg(windows)]
+    ctrl_break: signal::windows::CtrlBreak,
+    #[cfg(windows)]
+    close: signal::windows::CtrlClose,
+    #[cfg(windows)]
+    logoff: signal::windows::CtrlLogoff,
+    #[cfg(windows)]
+    shutdown: signal::windows::CtrlShutdown,
+}
+
+impl Signals {
+    /// Start listening, from here on these no longer end the process by themselves
+    pub fn new() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use signal::unix::{signal, SignalKind};
+            Ok(Signals {
+                unix: [
+                    signal(SignalKind::interrupt())?,
+                    signal(SignalKind::terminate())?,
+                    signal(SignalKind::hangup())?,
+                ],
+            })
+        }
+        #[cfg(windows)]
+        {
+            use signal::windows::{ctrl_break, ctrl_c, ctrl_close, ctrl_logoff, ctrl_shutdown};
+            Ok(Signals {
+                ctrl_c: ctrl_c()?,
+                ctrl_break: ctrl_break()?,
+                close: ctrl_close()?,
+                logoff: ctrl_logoff()?,
+                shutdown: ctrl_shutdown()?,
+            })
+        }
+        #[cfg(not(any(unix, windows)))]
+        Ok(Signals {})
+    }
+
+    /// Wait for the next one. Windows ends the process about five seconds after a console
+    /// close, logoff or shutdown, so whatever follows has to be quick.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        {
+            let [interrupt, terminate, hangup] = &mut self.unix;
+            tokio::select! {
+                _ = interrupt.recv() => {}
+                _ = terminate.recv() => {}
+                _ = hangup.recv() => {}
+            }
+        }
+        #[cfg(windows)]
+        tokio::select! {
+            _ = self.ctrl_c.recv() => {}
+            _ = self.ctrl_break.recv() => {}
+            _ = self.close.recv() => {}
+            _ = self.logoff.recv() => {}
+            _ = self.shutdown.recv() => {}
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = signal::ctrl_c().await;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+use crate::database::Download;
+use crate::downloads::manager::DownloadManager;
+use crate::events::HistorySynced;
+use crate::settings::{self, SyncConfig};
+
+/// Snapshots live in `<folder>/tur-sync/<device>/<unix ms>.json`
+const SYNC_DIR: &str = "tur-sync";
+
+/// the timer and the command share the state file, one run at a time
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// What this device remembers between syncs, kept in the app data dir
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SyncState {
+    device_id: String,
+    /// unix seconds of the last export, later changes go in the next snapshot
+    last_export: i64,
+    /// newest snapshot merged from each other device
+    merged: HashMap<String, String>,
+}
+
+impl SyncState {
+    fn path(app: &AppHandle) -> Result<PathBuf, String> {
+        let dir = crate::profiles::data_dir(app)?;
+        Ok(dir.join("sync.json"))
+    }
+
+    fn load(app: &AppHandle) -> Result<Self, String> {
+        let mut state: SyncState = std::fs::read(Self::path(app)?)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        if state.device_id.is_empty() {
+            state.device_id = Uuid::now_v7().as_simple().to_string();
+        }
+        Ok(state)
+    }
+
+    fn save(&self, app: &AppHandle) -> Result<(), String> {
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(Self::path(app)?, data).map_err(|e| e.to_string())
+    }
+}
+
+/// Counts of one sync run
+#[derive(Debug, Default, Serialize, specta::Type)]
+pub struct SyncReport {
+    pub exported: usize,
+    pub merged: usize,
+}
+
+/// Export what changed here, then merge newer records from the other devices
+pub fn sync_now(app: &AppHandle, config: &SyncConfig) -> Result<SyncReport, String> {
+    if config.folder.is_empty() {
+        return Err("No sync folder configured".into());
+    }
+    let _running = RUNNING.lock().unwrap();
+    let root = Path::new(&config.folder).join(SYNC_DIR);
+    let manager = app.state::<DownloadManager>();
+    let mut state = SyncState::load(app)?;
+    let mut report = SyncReport::default();
+
+    // export, records keep their own updated_at so the newest edit wins everywhere
+    let started = time::OffsetDateTime::now_utc();
+    let changed = manager
+        .db()
+        .get_updated_since(state.last_export)
+        .map_err(|e| e.to_string())?;
+    if !changed.is_empty() {
+        let dir = root.join(&state.device_id);
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let name = format!("{}.json", started.unix_timestamp_nanos() / 1_000_000);
+        let data = serde_json::to_vec(&changed).map_err(|e| e.to_string())?;
+        // sync clients may pick up a half written file, only complete ones get the real name
+        let partial = dir.join(format!("{}.partial", name));
+        std::fs::write(&partial, data).map_err(|e| e.to_string())?;
+        std::fs::rename(&partial, dir.join(&name)).map_err(|e| e.to_string())?;
+        report.exported = changed.len();
+    }
+    state.last_export = started.unix_timestamp();
+
+    // merge
+    let devices = match std::fs::read_dir(&root) {
+        Ok(devices) => devices,
+        Err(_) => return state.save(app).map(|_| report),
+    };
+    for device in devices.flatten() {
+        let device_id = device.file_name().to_string_lossy().to_string();
+        if device_id == state.device_id || !device.path().is_dir() {
+            continue;
+        }
+        let merged = state.merged.get(&device_id).cloned().unwrap_or_default();
+        for (name, path) in new_snapshots(&device.path(), &merged) {
+            let records: Vec<Download> = match std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+            {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("Skipping sync snapshot {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            for mut record in records {
+                // running here, the live state is ours
+                if manager.instance(&record.id).is_some() {
+                    continue;
+                }
+                // nothing is running it on this device
+                record.status.get_or_insert_with(|| "paused".into());
+                // the device its destination is on, None when that's this one
+                let from = record.device.take().unwrap_or_else(|| device_id.clone());
+                record.device = (from != state.device_id).then_some(from);
+                match manager.db().merge_download(&record) {
+                    Ok(true) => report.merged += 1,
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Failed to merge {}: {}", record.id, e),
+                }
+            }
+            state.merged.insert(device_id.clone(), name);
+        }
+    }
+
+    state.save(app)?;
+    if report.merged > 0 {
+        if let Err(e) = app.emit("history_synced", HistorySynced { merged: report.merged }) {
+            eprintln!("Failed to emit history_synced event: {}", e);
+        }
+    }
+    Ok(report)
+}
+
+// snapshot names are millisecond timestamps, so name order is time order
+fn new_snapshots(dir: &Path, after: &str) -> Vec<(String, PathBuf)> {
+    let mut snapshots: Vec<(String, PathBuf)> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| (e.file_name().to_string_lossy().to_string(), e.path()))
+                .filter(|(name, _)| name.ends_with(".json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    let key = |name: &str| name.trim_end_matches(".json").parse::<u128>().unwrap_or(0);
+    snapshots.retain(|(name, _)| key(name) > key(after));
+    snapshots.sort_by_key(|(name, _)| key(name));
+    snapshots
+}
+
+/// Sync every `interval_minutes` when enabled
+pub fn spawn(app: &AppHandle, config: &SyncConfig) {
+    if !config.enabled {
+        return;
+    }
+    let period = Duration::from_secs(config.interval_minutes as u64 * 60);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let config = settings::load_or_create(&app).sync;
+            if !config.enabled {
+                continue;
+            }
+            let app = app.clone();
+            let result = tokio::task::spawn_blocking(move || sync_now(&app, &config)).await;
+            if let Ok(Err(e)) = result {
+                eprintln!("History sync failed: {}", e);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn sync_history(app: AppHandle) -> Result<SyncReport, String> {
+    crate::lock::guard(&app)?;
+    let config = settings::load_or_create(&app).sync;
+    tokio::task::spawn_blocking(move || sync_now(&app, &config))
+        .await
+        .map_err(|e| e.to_string())?
+}
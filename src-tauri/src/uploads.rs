@@ -0,0 +1,275 @@
+#[path = "uploads/multipart.rs"]
+pub mod multipart;
+#[path = "uploads/tus.rs"]
+pub mod tus;
+
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Emitter;
+use url::Url;
+use uuid::Uuid;
+
+use crate::database;
+use crate::downloads::client::{self, HttpClient};
+use crate::downloads::core::Transfer;
+use crate::downloads::manager::DownloadManager;
+use crate::downloads::{io, DownloadOptions};
+use crate::events::QueueDownload;
+use crate::i18n;
+use crate::settings::{self, AppSettings};
+
+/// Bytes per tus PATCH and the smallest S3 part, progress and pausing move in these steps
+pub const CHUNK_SIZE: u64 = 8 << 20;
+
+/// How an endpoint takes uploads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tus,
+    S3Multipart,
+}
+
+impl Protocol {
+    /// `s3://bucket/key` goes multipart, anything else is spoken to as a tus server
+    pub fn of(endpoint: &Url) -> Self {
+        match endpoint.scheme() {
+            "s3" => Protocol::S3Multipart,
+            _ => Protocol::Tus,
+        }
+    }
+}
+
+/// What a paused upload needs to pick up again, the server knows the rest
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadState {
+    /// tus: upload resource from the creation request
+    pub location: Option<Url>,
+    /// s3: multipart upload id
+    pub upload_id: Option<String>,
+    /// s3: fixed when the upload is created so parts line up on resume
+    pub part_size: u64,
+    /// s3: ETag of every finished part, in part order
+    pub parts: Vec<String>,
+}
+
+impl UploadState {
+    fn path<R: tauri::Runtime>(handle: &tauri::AppHandle<R>, id: &Uuid) -> PathBuf {
+        let mut p = crate::profiles::data_dir(handle)
+            .expect("cannot resolve AppData/metadata")
+            .join("metadata");
+        std::fs::create_dir_all(&p).ok();
+        p.push(format!("{}.upload.json", id.as_simple()));
+        p
+    }
+
+    pub fn load<R: tauri::Runtime>(handle: &tauri::AppHandle<R>, id: &Uuid) -> Option<Self> {
+        let data = std::fs::read(Self::path(handle, id)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    pub fn save<R: tauri::Runtime>(
+        &self,
+        handle: &tauri::AppHandle<R>,
+        id: &Uuid,
+    ) -> Result<(), String> {
+        let data = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        std::fs::write(Self::path(handle, id), data).map_err(|e| e.to_string())
+    }
+
+    /// Drop saved state, used once finished or cancelled
+    pub fn remove<R: tauri::Runtime>(handle: &tauri::AppHandle<R>, id: &Uuid) {
+        let _ = std::fs::remove_file(Self::path(handle, id));
+    }
+}
+
+/// Send `path` until done or stopped through `transfer`, `state` is kept current for a later resume
+pub async fn run(
+    client: &HttpClient,
+    protocol: Protocol,
+    target: &Url,
+    path: &Path,
+    transfer: &Transfer,
+    state: &mut UploadState,
+) -> Result<(), String> {
+    let file = Arc::new(File::open(path).map_err(|e| e.to_string())?);
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match protocol {
+        Protocol::Tus => tus::upload(client, target, &file, size, &filename, transfer, state).await,
+        Protocol::S3Multipart => multipart::upload(client, target, &file, size, transfer, state).await,
+    }
+}
+
+/// Tell the server to drop what it has so far, best effort
+pub async fn abort(client: &HttpClient, protocol: Protocol, target: &Url, state: &UploadState) {
+    let result = match protocol {
+        Protocol::Tus => tus::terminate(client, state).await,
+        Protocol::S3Multipart => multipart::abort(client, target, state).await,
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to abort upload: {}", e);
+    }
+}
+
+/// Next `len` bytes of the source, reads go through the blocking pool like writes do
+async fn read_chunk(file: &Arc<File>, offset: u64, len: u64) -> Result<bytes::Bytes, String> {
+    let data = io::read_at(file, offset, len as usize)
+        .await
+        .map_err(|e| e.to_string())?;
+    if (data.len() as u64) < len {
+        return Err("Source file shrank during the upload".into());
+    }
+    Ok(data)
+}
+
+/// Error statuses become the error, with S3's `<Code>` when the body has one
+async fn check(response: Response) -> Result<Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(match xml_tag(&body, "Code") {
+        Some(code) => format!("Unexpected status {} ({})", status, code),
+        None => format!("Unexpected status {}", status),
+    })
+}
+
+/// Text of the first `<tag>`, enough for the few fields S3 answers with
+fn xml_tag<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + body[start..].find(&format!("</{}>", tag))?;
+    Some(&body[start..end])
+}
+
+/// Queue `path` for upload to `endpoint`, it shows up in history like a download
+#[tauri::command]
+#[specta::specta]
+pub fn start_upload(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    manager: tauri::State<'_, DownloadManager>,
+    path: PathBuf,
+    endpoint: Url,
+    options: Option<DownloadOptions>,
+) -> Result<Uuid, String> {
+    crate::security::authorize(&app, &webview, "start_upload")?;
+    let metadata = std::fs::metadata(&path).map_err(|e| e.to_string())?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("Upload source without a file name")?;
+    let endpoint = with_key(endpoint, &filename);
+    let source = path.to_string_lossy().to_string();
+
+    let id = Uuid::now_v7();
+    let db = manager.db();
+    db.insert_upload(&id, endpoint.as_str(), &filename, &source, metadata.len() as i64)
+        .map_err(|e| e.to_string())?;
+    let options = options.unwrap_or_default();
+    if options != DownloadOptions::default() {
+        db.set_options(&id, &options).map_err(|e| e.to_string())?;
+    }
+
+    let payload = QueueDownload::Upload {
+        id,
+        url: endpoint.to_string(),
+        filename,
+        size: metadata.len(),
+        destination: source,
+        direction: "upload".into(),
+        status: "queued".into(),
+    };
+    manager.log_activity("added", Some(&id), json!(payload));
+    if let Err(e) = app.emit("queue_download", payload) {
+        eprintln!("Failed to emit queue_download event: {}", e);
+    }
+
+    manager.start(&app, &id, false)?;
+    Ok(id)
+}
+
+// `s3://bucket/dir/` names the object after the file
+fn with_key(mut endpoint: Url, filename: &str) -> Url {
+    if Protocol::of(&endpoint) == Protocol::S3Multipart && endpoint.path().ends_with('/') {
+        let path = format!("{}{}", endpoint.path(), filename);
+        endpoint.set_path(&path);
+    }
+    endpoint
+}
+
+/// Abort on the server side and forget the saved state, for cancelled uploads
+pub async fn discard(app: &tauri::AppHandle, record: &database::Download) {
+    if let Some(state) = UploadState::load(app, &record.id) {
+        let settings = settings::load_or_create(app);
+        if let Ok(endpoint) = Url::parse(&record.url) {
+            match client::for_target(&settings, &endpoint, &record.options) {
+                Ok((target, client)) => abort(&client, Protocol::of(&endpoint), &target, &state).await,
+                Err(e) => eprintln!("Failed to abort upload: {}", e),
+            }
+        }
+    }
+    UploadState::remove(app, &record.id);
+}
+
+/// `tur upload <file> <endpoint>`: one upload in the terminal, S3 credentials come from
+/// the usual AWS_* variables since the app's settings store needs the GUI
+pub async fn run_cli(path: &Path, endpoint: &str) -> Result<(), String> {
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| i18n::t("cli.upload_no_filename"))?;
+    let endpoint = with_key(Url::parse(endpoint).map_err(|e| e.to_string())?, &filename);
+
+    let mut settings = AppSettings::default();
+    let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let s3 = &mut settings.s3;
+    s3.access_key_id = env("AWS_ACCESS_KEY_ID").unwrap_or_default();
+    s3.secret_access_key = env("AWS_SECRET_ACCESS_KEY").unwrap_or_default();
+    s3.session_token = env("AWS_SESSION_TOKEN").unwrap_or_default();
+    s3.endpoint = env("AWS_ENDPOINT_URL").unwrap_or_default();
+    if let Some(region) = env("AWS_REGION").or_else(|| env("AWS_DEFAULT_REGION")) {
+        s3.region = region;
+    }
+
+    let (target, client) = client::for_target(&settings, &endpoint, &DownloadOptions::default())?;
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let transfer = Arc::new(Transfer::default());
+    let progress = {
+        let transfer = transfer.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                let sent = transfer.downloaded();
+                let percent = (sent * 100).checked_div(size).unwrap_or(100);
+                let line = i18n::tf(
+                    "cli.upload_progress",
+                    &[("sent", &sent), ("size", &size), ("percent", &percent)],
+                );
+                eprint!("\r{}", line);
+                let _ = std::io::stderr().flush();
+            }
+        })
+    };
+
+    let mut state = UploadState::default();
+    let result = run(&client, Protocol::of(&endpoint), &target, path, &transfer, &mut state).await;
+    progress.abort();
+    eprintln!();
+    if result.is_err() {
+        abort(&client, Protocol::of(&endpoint), &target, &state).await;
+    }
+    result
+}
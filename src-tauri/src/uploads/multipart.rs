@@ -0,0 +1,113 @@
+use reqwest::header::ETAG;
+use std::fs::File;
+use std::sync::Arc;
+use url::Url;
+
+use super::{check, read_chunk, xml_tag, UploadState, CHUNK_SIZE};
+use crate::downloads::client::HttpClient;
+use crate::downloads::core::Transfer;
+
+/// S3 caps an upload at 10000 parts, parts grow past `CHUNK_SIZE` for files over ~78 GiB
+const MAX_PARTS: u64 = 10_000;
+
+/// CreateMultipartUpload, one UploadPart per chunk, CompleteMultipartUpload.
+/// Requests are signed by the client, `target` is the resolved object url.
+pub async fn upload(
+    client: &HttpClient,
+    target: &Url,
+    file: &Arc<File>,
+    size: u64,
+    transfer: &Transfer,
+    state: &mut UploadState,
+) -> Result<(), String> {
+    // multipart needs at least one part, a plain PUT is the way for empty files
+    if size == 0 {
+        let request = client.inner().put(target.clone()).body(Vec::new());
+        return check(client.send(request).await?).await.map(|_| ());
+    }
+
+    let upload_id = match &state.upload_id {
+        Some(upload_id) => upload_id.clone(),
+        None => {
+            let upload_id = create(client, target).await?;
+            state.upload_id = Some(upload_id.clone());
+            state.part_size = CHUNK_SIZE.max(size.div_ceil(MAX_PARTS));
+            state.parts.clear();
+            upload_id
+        }
+    };
+    let mut offset = (state.parts.len() as u64 * state.part_size).min(size);
+    transfer.add_downloaded(offset);
+
+    while offset < size {
+        if transfer.is_stopped() {
+            return Ok(());
+        }
+        let len = state.part_size.min(size - offset);
+        let data = read_chunk(file, offset, len).await?;
+        let mut url = target.clone();
+        url.query_pairs_mut()
+            .append_pair("partNumber", &(state.parts.len() + 1).to_string())
+            .append_pair("uploadId", &upload_id);
+        let response = check(client.send(client.inner().put(url).body(data)).await?).await?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|e| e.to_str().ok())
+            .ok_or("S3 didn't return an ETag for the part")?;
+        state.parts.push(etag.to_string());
+        transfer.add_downloaded(len);
+        offset += len;
+    }
+
+    complete(client, target, &upload_id, &state.parts).await
+}
+
+/// AbortMultipartUpload, otherwise the stored parts keep being billed
+pub async fn abort(client: &HttpClient, target: &Url, state: &UploadState) -> Result<(), String> {
+    let Some(upload_id) = &state.upload_id else {
+        return Ok(());
+    };
+    let mut url = target.clone();
+    url.query_pairs_mut().append_pair("uploadId", upload_id);
+    check(client.send(client.inner().delete(url)).await?).await.map(|_| ())
+}
+
+async fn create(client: &HttpClient, target: &Url) -> Result<String, String> {
+    let mut url = target.clone();
+    url.query_pairs_mut().append_key_only("uploads");
+    let response = check(client.send(client.inner().post(url)).await?).await?;
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    xml_tag(&body, "UploadId")
+        .map(String::from)
+        .ok_or_else(|| "S3 didn't return an UploadId".into())
+}
+
+async fn complete(
+    client: &HttpClient,
+    target: &Url,
+    upload_id: &str,
+    parts: &[String],
+) -> Result<(), String> {
+    let mut body = String::from("<CompleteMultipartUpload>");
+    for (n, etag) in parts.iter().enumerate() {
+        body.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            n + 1,
+            etag.replace('"', "&quot;")
+        ));
+    }
+    body.push_str("</CompleteMultipartUpload>");
+
+    let mut url = target.clone();
+    url.query_pairs_mut().append_pair("uploadId", upload_id);
+    let response = check(client.send(client.inner().post(url).body(body)).await?).await?;
+    // S3 can answer 200 and still report the failure in the body
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    match xml_tag(&body, "Code") {
+        Some(code) if body.contains("<Error>") => {
+            Err(format!("Completing the upload failed ({})", code))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,125 @@
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::{Response, StatusCode};
+use std::fs::File;
+use std::sync::Arc;
+use url::Url;
+
+use super::{check, read_chunk, UploadState, CHUNK_SIZE};
+use crate::downloads::client::HttpClient;
+use crate::downloads::core::Transfer;
+
+/// tus.io core protocol plus the creation and termination extensions
+const VERSION: &str = "1.0.0";
+
+pub async fn upload(
+    client: &HttpClient,
+    endpoint: &Url,
+    file: &Arc<File>,
+    size: u64,
+    filename: &str,
+    transfer: &Transfer,
+    state: &mut UploadState,
+) -> Result<(), String> {
+    // the server is the authority on how much it has, ask it instead of trusting our count
+    let resumed = match &state.location {
+        Some(location) => server_offset(client, location)
+            .await?
+            .map(|offset| (location.clone(), offset)),
+        None => None,
+    };
+    let (location, mut offset) = match resumed {
+        Some(resumed) => resumed,
+        // never created, or the server forgot it
+        None => {
+            let location = create(client, endpoint, size, filename).await?;
+            state.location = Some(location.clone());
+            (location, 0)
+        }
+    };
+    transfer.add_downloaded(offset);
+
+    while offset < size {
+        if transfer.is_stopped() {
+            return Ok(());
+        }
+        let len = CHUNK_SIZE.min(size - offset);
+        let data = read_chunk(file, offset, len).await?;
+        let request = client
+            .inner()
+            .patch(location.clone())
+            .header("Tus-Resumable", VERSION)
+            .header("Upload-Offset", offset)
+            .header(CONTENT_TYPE, "application/offset+octet-stream")
+            .body(data);
+        let response = check(client.send(request).await?).await?;
+        let next = upload_offset(&response).ok_or("tus server didn't report the new offset")?;
+        if next <= offset {
+            return Err(format!("tus server went back from offset {} to {}", offset, next));
+        }
+        transfer.add_downloaded(next - offset);
+        offset = next;
+    }
+    Ok(())
+}
+
+/// Termination extension, servers without it just keep the partial upload
+pub async fn terminate(client: &HttpClient, state: &UploadState) -> Result<(), String> {
+    let Some(location) = &state.location else {
+        return Ok(());
+    };
+    let request = client
+        .inner()
+        .delete(location.clone())
+        .header("Tus-Resumable", VERSION);
+    check(client.send(request).await?).await.map(|_| ())
+}
+
+async fn create(
+    client: &HttpClient,
+    endpoint: &Url,
+    size: u64,
+    filename: &str,
+) -> Result<Url, String> {
+    use base64::Engine;
+    let name = base64::engine::general_purpose::STANDARD.encode(filename);
+    let request = client
+        .inner()
+        .post(endpoint.clone())
+        .header("Tus-Resumable", VERSION)
+        .header("Upload-Length", size)
+        .header("Upload-Metadata", format!("filename {}", name));
+    let response = check(client.send(request).await?).await?;
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|l| l.to_str().ok())
+        .ok_or("tus server didn't return an upload location")?;
+    // relative locations are common
+    endpoint.join(location).map_err(|e| e.to_string())
+}
+
+/// Offset of an existing upload, None once the server no longer has it
+async fn server_offset(client: &HttpClient, location: &Url) -> Result<Option<u64>, String> {
+    let request = client
+        .inner()
+        .head(location.clone())
+        .header("Tus-Resumable", VERSION);
+    let response = client.send(request).await?;
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::FORBIDDEN => Ok(None),
+        _ => {
+            let response = check(response).await?;
+            Ok(Some(upload_offset(&response).ok_or("tus server didn't report an offset")?))
+        }
+    }
+}
+
+fn upload_offset(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get("Upload-Offset")?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
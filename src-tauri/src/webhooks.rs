@@ -0,0 +1,150 @@
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use url::Url;
+use uuid::Uuid;
+
+use crate::database::Download;
+use crate::downloads::client;
+use crate::downloads::manager::DownloadManager;
+use crate::downloads::s3::hmac;
+use crate::settings::{self, Webhook};
+
+/// What a webhook can subscribe to
+pub const EVENTS: [&str; 3] = ["download_completed", "download_failed", "queue_empty"];
+
+/// Waits before the second and third attempt
+const RETRY_DELAYS: [Duration; 2] = [Duration::from_secs(5), Duration::from_secs(30)];
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// POST `event` to every enabled webhook that wants it, in the background.
+/// `message` is the notification text, so services that show a title and message
+/// (Gotify, ntfy's JSON API) need no mapping.
+pub fn fire(
+    app: &AppHandle,
+    event: &str,
+    download: Option<&Download>,
+    error: Option<&str>,
+    message: &str,
+) {
+    let settings = settings::load_or_create(app);
+    let hooks: Vec<Webhook> = settings
+        .webhooks
+        .iter()
+        .filter(|hook| hook.enabled && wants(hook, event))
+        .cloned()
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+    let client = match client::create(&settings) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to create webhook client: {}", e);
+            return;
+        }
+    };
+    let body = payload(event, download, error, message);
+    for hook in hooks {
+        let app = app.clone();
+        let client = client.clone();
+        let body = body.clone();
+        let id = download.map(|d| d.id);
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = deliver(&client, &hook, &body).await {
+                eprintln!("Webhook {} failed: {}", hook.name, e);
+                app.state::<DownloadManager>().log_activity(
+                    "webhook_failed",
+                    id.as_ref(),
+                    json!({ "webhook": hook.name, "event": body["event"], "error": e }),
+                );
+            }
+        });
+    }
+}
+
+fn wants(hook: &Webhook, event: &str) -> bool {
+    hook.events.is_empty() || hook.events.iter().any(|e| e == event)
+}
+
+fn payload(event: &str, download: Option<&Download>, error: Option<&str>, message: &str) -> Value {
+    json!({
+        "event": event,
+        "delivery": Uuid::now_v7(),
+        "timestamp": time::OffsetDateTime::now_utc().unix_timestamp(),
+        "title": "tur",
+        "message": message,
+        "download": download.map(|d| json!({
+            "id": d.id,
+            "filename": d.filename,
+            "url": d.url,
+            "destination": d.destination,
+            "size": d.size,
+            "bytes_received": d.bytes_received,
+            "error": error,
+        })),
+    })
+}
+
+// up to three attempts, a 4xx other than 429 won't get better by asking again
+async fn deliver(client: &Client, hook: &Webhook, body: &Value) -> Result<StatusCode, String> {
+    let mut delays = RETRY_DELAYS.iter();
+    loop {
+        let error = match send(client, hook, body).await {
+            Ok(status) if status.is_success() => return Ok(status),
+            Ok(status) if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS => {
+                return Err(status.to_string())
+            }
+            Ok(status) => status.to_string(),
+            Err(e) => e,
+        };
+        match delays.next() {
+            Some(delay) => tokio::time::sleep(*delay).await,
+            None => return Err(error),
+        }
+    }
+}
+
+async fn send(client: &Client, hook: &Webhook, body: &Value) -> Result<StatusCode, String> {
+    let event = body["event"].as_str().unwrap_or_default().to_string();
+    let body = serde_json::to_vec(body).map_err(|e| e.to_string())?;
+    let mut request = client
+        .post(&hook.url)
+        .timeout(TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Tur-Event", event);
+    // receivers recompute this over the raw body with the shared secret
+    if !hook.secret.is_empty() {
+        let signature = hex::encode(hmac(hook.secret.as_bytes(), &body));
+        request = request.header("X-Tur-Signature", format!("sha256={}", signature));
+    }
+    let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+    Ok(response.status())
+}
+
+/// Refuse hooks that can't be delivered, before they're saved
+pub fn validate(hooks: &[Webhook]) -> Result<(), String> {
+    for hook in hooks {
+        let url = Url::parse(&hook.url).map_err(|e| format!("Webhook {}: {}", hook.name, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Webhook {}: only http and https urls", hook.name));
+        }
+        if let Some(event) = hook.events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+            return Err(format!("Webhook {}: unknown event {}", hook.name, event));
+        }
+    }
+    Ok(())
+}
+
+/// Send a `test` event to `webhook` once, no retries, and return the response status
+#[tauri::command]
+#[specta::specta]
+pub async fn test_webhook(app: AppHandle, webhook: Webhook) -> Result<u16, String> {
+    crate::lock::guard(&app)?;
+    validate(std::slice::from_ref(&webhook))?;
+    let client = client::create(&settings::load_or_create(&app))?;
+    let body = payload("test", None, None, &crate::i18n::t("webhook.test"));
+    let status = send(&client, &webhook, &body).await?;
+    Ok(status.as_u16())
+}
@@ -0,0 +1,1236 @@
+//! Engine scenarios against mock servers, no Tauri runtime involved.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tur_lib::bench::server::{pattern_byte, ServerConfig, TestServer};
+use tur_lib::bench::{self, BenchOptions};
+use tur_lib::database;
+use tur_lib::downloads::archive;
+use tur_lib::downloads::cleanup;
+use tur_lib::downloads::client::{self, HttpClient, RangeRetry};
+use tur_lib::downloads::core::{self, Download, Strategy, Transfer, WorkerEvent};
+use tur_lib::downloads::existing::{self, Existing};
+use tur_lib::downloads::health::{self, Health};
+use tur_lib::downloads::mirrors;
+use tur_lib::downloads::probe;
+use tur_lib::downloads::zsync;
+use tur_lib::downloads::DownloadOptions;
+use tur_lib::settings::{AppSettings, ChaosConfig};
+use url::Url;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+const SIZE: usize = 5 << 20;
+
+fn body(size: usize) -> Vec<u8> {
+    (0..size as u64).map(pattern_byte).collect()
+}
+
+fn temp_path() -> PathBuf {
+    std::env::temp_dir().join(format!("tur-test-{}.bin", Uuid::now_v7().as_simple()))
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder().no_proxy().build().unwrap()
+}
+
+// "bytes=a-b" against `len`, None when absent or malformed
+fn requested_range(req: &Request, len: usize) -> Option<(usize, usize)> {
+    let value = req.headers.get("range")?.to_str().ok()?;
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.parse().ok()?;
+    let end = end.parse::<usize>().map(|e| e + 1).unwrap_or(len).min(len);
+    Some((start, end))
+}
+
+/// Serves `data` honoring single Range headers
+struct Ranged(Vec<u8>);
+
+impl Respond for Ranged {
+    fn respond(&self, req: &Request) -> ResponseTemplate {
+        let len = self.0.len();
+        match requested_range(req, len) {
+            Some((start, _)) if start >= len => ResponseTemplate::new(416)
+                .insert_header("Content-Range", format!("bytes */{}", len)),
+            Some((start, end)) => ResponseTemplate::new(206)
+                .insert_header("Accept-Ranges", "bytes")
+                .insert_header("Content-Range", format!("bytes {}-{}/{}", start, end - 1, len))
+                .set_body_bytes(self.0[start..end].to_vec()),
+            None => ResponseTemplate::new(200)
+                .insert_header("Accept-Ranges", "bytes")
+                .set_body_bytes(self.0.clone()),
+        }
+    }
+}
+
+async fn run(url: Url, size: usize, threads: u8, path: &Path) -> (Download, Arc<Transfer>) {
+    let transfer = Arc::new(Transfer::default());
+    let download = Download::new(size, threads, Strategy::Fixed(1))
+        .run(client(), url, path, threads, transfer.clone())
+        .await
+        .unwrap();
+    (download, transfer)
+}
+
+fn assert_file(path: &Path, expected: &[u8]) {
+    let written = std::fs::read(path).unwrap();
+    assert_eq!(written.len(), expected.len());
+    assert!(written == expected, "file content differs from server body");
+    std::fs::remove_file(path).ok();
+}
+
+fn record(etag: Option<&str>, size: Option<i64>) -> database::Download {
+    database::Download {
+        id: Uuid::now_v7(),
+        filename: "file.bin".into(),
+        status: Some("paused".into()),
+        size,
+        bytes_received: 0,
+        url: "http://localhost/file.bin".into(),
+        etag: etag.map(String::from),
+        content_type: None,
+        last_modified: None,
+        destination: String::new(),
+        accept_ranges: true,
+        updated_at: 0,
+        options: Default::default(),
+        direction: "download".into(),
+        job_id: None,
+        quarantine: None,
+        acceleration: None,
+        pinned: false,
+        device: None,
+    }
+}
+
+#[tokio::test]
+async fn range_requests_cover_the_whole_file() {
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+
+    let path = temp_path();
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let (download, transfer) = run(url, SIZE, 4, &path).await;
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    assert_eq!(transfer.downloaded(), SIZE as u64);
+    // every connection was opened ahead of its first range
+    assert!(transfer.workers().iter().all(|w| w.setup_ms.is_some()));
+    // one connection was measured before the rest joined
+    assert!(transfer.baseline().is_some());
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn sequential_mode_never_splits_ranges() {
+    const RANGE: usize = 4 << 20;
+    let server = MockServer::start().await;
+    let size = 5 * RANGE;
+    let data = body(size);
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+
+    let path = temp_path();
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let transfer = Arc::new(Transfer::default());
+    let download = Download::new(size, 3, Strategy::Fibonacci)
+        .with_sequential(true)
+        .with_preallocation(false)
+        .run(client(), url, &path, 3, transfer.clone())
+        .await
+        .unwrap();
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    let mut starts: Vec<usize> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|req| requested_range(req, size))
+        // leaving out the sample one connection's speed is measured with
+        .filter(|&range| range != (0, 128 << 10))
+        .map(|(start, _)| start)
+        .collect();
+    starts.sort();
+    // one request per fixed range, none of them stolen from
+    assert_eq!(starts, (0..size).step_by(RANGE).collect::<Vec<_>>());
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn priority_pieces_fetch_head_and_tail_first() {
+    const PIECE: usize = 4 << 20;
+    let server = MockServer::start().await;
+    let size = 5 * PIECE;
+    let data = body(size);
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+
+    let path = temp_path();
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let transfer = Arc::new(Transfer::default());
+    let download = Download::new(size, 1, Strategy::Fixed(4))
+        .with_priority_pieces(true)
+        .run(client(), url, &path, 1, transfer.clone())
+        .await
+        .unwrap();
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    let starts: Vec<usize> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|req| requested_range(req, size))
+        .map(|(start, _)| start)
+        .collect();
+    assert_eq!(starts, [0, 4 * PIECE, PIECE, 2 * PIECE, 3 * PIECE]);
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn probe_reads_head_metadata() {
+    let server = MockServer::start().await;
+    Mock::given(method("HEAD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Accept-Ranges", "bytes")
+                .insert_header("ETag", "\"v1\"")
+                .insert_header("Content-Disposition", "attachment; filename=\"report.pdf\""),
+        )
+        .mount(&server)
+        .await;
+
+    let url = Url::parse(&format!("{}/download?id=7", server.uri())).unwrap();
+    let remote = probe::probe(&client().into(), &url).await.unwrap();
+
+    assert_eq!(remote.filename, "report.pdf");
+    assert_eq!(remote.etag.as_deref(), Some("v1"));
+    assert!(remote.resume_supported);
+}
+
+#[tokio::test]
+async fn probe_falls_back_to_get_without_head() {
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(405))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let remote = probe::probe(&client().into(), &url).await.unwrap();
+
+    assert_eq!(remote.size, Some(SIZE as i64));
+    assert!(remote.resume_supported);
+    assert_eq!(remote.filename, "file.bin");
+}
+
+#[tokio::test]
+async fn existing_file_compared_by_its_ends() {
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    Mock::given(method("GET"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let client = client().into();
+
+    let path = temp_path();
+    std::fs::write(&path, &data).unwrap();
+    let same = existing::compare(&client, &url, &path, SIZE as u64).await;
+
+    // only the last byte differs
+    let mut changed = data.clone();
+    changed[SIZE - 1] ^= 0xff;
+    std::fs::write(&path, &changed).unwrap();
+    let different = existing::compare(&client, &url, &path, SIZE as u64).await;
+    let other_size = existing::compare(&client, &url, &path, SIZE as u64 + 1).await;
+    let free = existing::free_name(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(same, Some(Existing::Same));
+    assert_eq!(different, Some(Existing::Different));
+    assert_eq!(other_size, None);
+    assert_ne!(free, path);
+    assert!(free.to_string_lossy().ends_with(" (1).bin"));
+}
+
+// what zsyncmake writes for `data`, with the short checksums it picks for large files
+fn zsync_control(data: &[u8], blocksize: usize) -> Vec<u8> {
+    use md4::{Digest, Md4};
+    use sha1::Sha1;
+
+    let mut control = format!(
+        "zsync: 0.6.2\nBlocksize: {}\nLength: {}\nHash-Lengths: 2,2,5\nSHA-1: {}\n\n",
+        blocksize,
+        data.len(),
+        hex::encode(Sha1::digest(data))
+    )
+    .into_bytes();
+    for block in data.chunks(blocksize) {
+        let mut block = block.to_vec();
+        block.resize(blocksize, 0);
+        let (mut a, mut b) = (0u16, 0u16);
+        for (i, &c) in block.iter().enumerate() {
+            a = a.wrapping_add(c as u16);
+            b = b.wrapping_add(((blocksize - i) as u16).wrapping_mul(c as u16));
+        }
+        // two rsum bytes keep only b
+        control.extend_from_slice(&b.to_be_bytes());
+        control.extend_from_slice(&Md4::digest(&block)[..5]);
+    }
+    control
+}
+
+#[tokio::test]
+async fn delta_update_fetches_only_changed_blocks() {
+    const BLOCK: usize = 2048;
+    // pattern_byte repeats every 251 bytes, blocks need to be told apart here
+    let mut state = 1u32;
+    let data: Vec<u8> = (0..1 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect();
+    let server = MockServer::start().await;
+    Mock::given(path("/file.bin.zsync"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(zsync_control(&data, BLOCK)))
+        .mount(&server)
+        .await;
+    Mock::given(path("/file.bin"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let client = client().into();
+
+    // the old version: a few bytes changed, some inserted, the tail missing
+    let mut old = data.clone();
+    old[100_000..100_050].fill(0);
+    old.splice(500_000..500_000, [7u8; 37]);
+    old.truncate(old.len() - 10_000);
+    let seed = temp_path();
+    std::fs::write(&seed, &old).unwrap();
+
+    let path = temp_path();
+    let transfer = Transfer::default();
+    let report = zsync::update(&client, &url, &seed, &path, data.len() as u64, &transfer)
+        .await
+        .unwrap()
+        .expect("nothing reused");
+    std::fs::remove_file(&seed).ok();
+
+    assert_eq!(report.reused + report.fetched, data.len() as u64);
+    assert!(report.fetched <= 10 * BLOCK as u64, "fetched {}", report.fetched);
+    assert_eq!(transfer.downloaded(), data.len() as u64);
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn balancer_splits_by_download_not_connection() {
+    let options = BenchOptions {
+        size_mib: 4,
+        latency_ms: 0,
+        threads: vec![1, 8],
+        fairness_mib: 4,
+        ..Default::default()
+    };
+    let results = bench::run_fairness(&options).await.unwrap();
+    let (shared, balanced) = (&results[0], &results[1]);
+
+    assert_eq!(balanced.mode, "balanced");
+    assert!(balanced.verified(), "balanced index {}", balanced.index);
+    assert!(balanced.index > shared.index, "{:?}", results);
+}
+
+#[tokio::test]
+async fn resume_restarts_when_etag_changes() {
+    let server = MockServer::start().await;
+    Mock::given(method("HEAD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Accept-Ranges", "bytes")
+                .insert_header("ETag", "\"v2\""),
+        )
+        .mount(&server)
+        .await;
+
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let remote = probe::probe(&client().into(), &url).await.unwrap();
+
+    assert!(probe::needs_restart(&record(Some("v1"), None), &remote, true));
+    assert!(!probe::needs_restart(&record(Some("v2"), None), &remote, true));
+    assert!(probe::needs_restart(&record(Some("v2"), None), &remote, false));
+}
+
+#[tokio::test]
+async fn health_check_tells_gone_from_changed() {
+    let server = MockServer::start().await;
+    Mock::given(method("HEAD"))
+        .and(path("/file.bin"))
+        .respond_with(ResponseTemplate::new(200).insert_header("ETag", "\"v2\""))
+        .mount(&server)
+        .await;
+    Mock::given(method("HEAD"))
+        .and(path("/gone.bin"))
+        .respond_with(ResponseTemplate::new(410))
+        .mount(&server)
+        .await;
+    Mock::given(path("/broken.bin"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let client = client().into();
+    let url = |name: &str| Url::parse(&format!("{}/{}", server.uri(), name)).unwrap();
+    let check = |name: &str, etag: &str| {
+        let url = url(name);
+        let stored = record(Some(etag), None);
+        let client = &client;
+        async move { health::check(client, &url, &stored).await }
+    };
+
+    assert_eq!(check("file.bin", "v2").await, Ok(Health::Alive));
+    assert_eq!(check("file.bin", "v1").await, Ok(Health::Changed));
+    assert_eq!(check("gone.bin", "v1").await, Ok(Health::Gone(410)));
+    // neither alive nor gone, it's asked again next time
+    assert!(check("broken.bin", "v1").await.is_err());
+}
+
+#[tokio::test]
+async fn mirrors_from_link_headers_share_the_ranges() {
+    let data = body(SIZE);
+    let (primary, mirror, other) = (
+        MockServer::start().await,
+        MockServer::start().await,
+        MockServer::start().await,
+    );
+    let link = |server: &MockServer, pri: u32| {
+        format!("<{}/pub/file.bin>; rel=duplicate; pri={}", server.uri(), pri)
+    };
+    Mock::given(method("HEAD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Accept-Ranges", "bytes")
+                .insert_header("Content-Length", SIZE.to_string())
+                .append_header("Link", format!("{}, {}", link(&other, 2), link(&mirror, 1)))
+                .append_header("Link", "<http://127.0.0.1:9/pub/file.bin>; rel=duplicate"),
+        )
+        .mount(&primary)
+        .await;
+    for server in [&primary, &mirror] {
+        Mock::given(method("GET"))
+            .respond_with(Ranged(data.clone()))
+            .mount(server)
+            .await;
+    }
+    Mock::given(method("HEAD"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&mirror)
+        .await;
+    // another file under the same name
+    Mock::given(wiremock::matchers::any())
+        .respond_with(Ranged(body(SIZE / 2)))
+        .mount(&other)
+        .await;
+
+    let url = Url::parse(&format!("{}/file.bin", primary.uri())).unwrap();
+    let found = mirrors::discover(&client().into(), &url, SIZE as i64).await;
+    let expected = Url::parse(&format!("{}/pub/file.bin", mirror.uri())).unwrap();
+    assert_eq!(found, vec![expected]);
+
+    let path = temp_path();
+    let transfer = Arc::new(Transfer::default());
+    let download = Download::new(SIZE, 4, Strategy::Fixed(1))
+        .with_mirrors(found)
+        .run(client(), url, &path, 4, transfer.clone())
+        .await
+        .unwrap();
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    assert!(ranges(&mirror).await > 0);
+    assert!(ranges(&primary).await > 0);
+    assert_file(&path, &data);
+}
+
+// GETs for ranges of the file, not counting the one each source is measured with
+async fn ranges(server: &MockServer) -> usize {
+    let requests = server.received_requests().await.unwrap();
+    requests
+        .iter()
+        .filter(|r| r.method.as_str() == "GET")
+        .filter(|r| requested_range(r, usize::MAX) != Some((0, 128 << 10)))
+        .count()
+}
+
+#[tokio::test]
+async fn slow_mirror_gets_no_connections() {
+    let data = body(SIZE);
+    let (primary, mirror) = (MockServer::start().await, MockServer::start().await);
+    Mock::given(method("GET"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&primary)
+        .await;
+    let far = Ranged(data.clone());
+    Mock::given(method("GET"))
+        .respond_with(move |req: &Request| far.respond(req).set_delay(Duration::from_secs(1)))
+        .mount(&mirror)
+        .await;
+
+    let url = Url::parse(&format!("{}/file.bin", primary.uri())).unwrap();
+    let slow = Url::parse(&format!("{}/file.bin", mirror.uri())).unwrap();
+    let path = temp_path();
+    let transfer = Arc::new(Transfer::default());
+    let download = Download::new(SIZE, 4, Strategy::Fixed(1))
+        .with_mirrors(vec![slow])
+        .run(client(), url, &path, 4, transfer.clone())
+        .await
+        .unwrap();
+
+    assert!(download.is_complete());
+    // measured once, then left alone
+    assert_eq!(mirror.received_requests().await.unwrap().len(), 1);
+    assert_eq!(ranges(&mirror).await, 0);
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn resume_continues_from_saved_state() {
+    let server = TestServer::start(ServerConfig {
+        size: SIZE,
+        latency: Duration::ZERO,
+        rate: 2 << 20,
+    })
+    .await
+    .unwrap();
+    let path = temp_path();
+
+    // stop part way through and keep the state like a pause would
+    let transfer = Arc::new(Transfer::default());
+    let stopper = transfer.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        stopper.stop();
+    });
+    let paused = Download::new(SIZE, 2, Strategy::Fixed(1))
+        .run(client(), server.url(), &path, 2, transfer.clone())
+        .await
+        .unwrap();
+    assert!(!paused.is_complete());
+    assert!(transfer.downloaded() > 0);
+
+    let saved = bincode::encode_to_vec(&paused, bincode::config::standard()).unwrap();
+    let restored = Download::restore(&mut saved.as_slice(), SIZE).unwrap();
+    assert_eq!(restored.remaining(), paused.remaining());
+
+    let resumed = Arc::new(Transfer::new(transfer.downloaded()));
+    let done = restored
+        .run(client(), server.url(), &path, 4, resumed.clone())
+        .await
+        .unwrap();
+
+    assert!(resumed.error().is_none());
+    assert!(done.is_complete());
+    assert_file(&path, &body(SIZE));
+}
+
+#[tokio::test]
+async fn cancel_truncates_to_the_contiguous_bytes() {
+    let server = TestServer::start(ServerConfig {
+        size: SIZE,
+        latency: Duration::ZERO,
+        rate: 2 << 20,
+    })
+    .await
+    .unwrap();
+    let path = temp_path();
+
+    let transfer = Arc::new(Transfer::default());
+    let stopper = transfer.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        stopper.stop();
+    });
+    let stopped = Download::new(SIZE, 4, Strategy::Fixed(1))
+        .run(client(), server.url(), &path, 4, transfer.clone())
+        .await
+        .unwrap();
+    assert!(!stopped.is_complete());
+
+    // preallocated, so everything past the first gap is zeros or stray ranges
+    let kept = stopped.contiguous();
+    assert!(kept > 0 && kept <= SIZE - stopped.remaining());
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(kept as u64)
+        .unwrap();
+    assert!(std::fs::read(&path).unwrap() == body(SIZE)[..kept]);
+
+    // the state of the cut file still resumes, the ranges past it are fetched again
+    let saved = bincode::encode_to_vec(&stopped, bincode::config::standard()).unwrap();
+    let mut restored = Download::restore(&mut saved.as_slice(), SIZE).unwrap();
+    restored.reconcile(kept as u64).unwrap();
+    assert_eq!(restored.remaining(), SIZE - kept);
+    let done = restored
+        .run(client(), server.url(), &path, 4, Arc::new(Transfer::default()))
+        .await
+        .unwrap();
+    assert!(done.is_complete());
+    assert_file(&path, &body(SIZE));
+}
+
+#[tokio::test]
+async fn sweep_spares_kept_cancels_and_synced_records() {
+    let server = TestServer::start(ServerConfig {
+        size: SIZE,
+        latency: Duration::ZERO,
+        rate: 2 << 20,
+    })
+    .await
+    .unwrap();
+    let path = temp_path();
+
+    let transfer = Arc::new(Transfer::default());
+    let stopper = transfer.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        stopper.stop();
+    });
+    let stopped = Download::new(SIZE, 4, Strategy::Fixed(1))
+        .run(client(), server.url(), &path, 4, transfer)
+        .await
+        .unwrap();
+    assert!(!stopped.is_complete());
+    let kept = std::fs::read(&path).unwrap();
+    let failed_path = temp_path();
+    std::fs::write(&failed_path, &kept).unwrap();
+
+    let db = database::Database::new(&temp_path().with_extension("db")).unwrap();
+    let (cancelled, failed) = (Uuid::now_v7(), Uuid::now_v7());
+    for (id, destination) in [(cancelled, &path), (failed, &failed_path)] {
+        let destination = destination.to_string_lossy();
+        db.insert_download(
+            &id,
+            server.url().as_str(),
+            "file.bin",
+            &destination,
+            Some(SIZE as i64),
+            None,
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    // what cancel_download(keep_partial: true) and a failure leave in history
+    db.update_status(&cancelled, Some("cancelled")).unwrap();
+    db.update_status(&failed, Some("failed")).unwrap();
+    // failed on another device, at a path that happens to exist here too
+    let synced = database::Download {
+        id: Uuid::now_v7(),
+        status: Some("failed".into()),
+        destination: path.to_string_lossy().to_string(),
+        device: Some("laptop".into()),
+        ..record(None, Some(SIZE as i64))
+    };
+    db.merge_download(&synced).unwrap();
+
+    // a day on, with partials set to go as soon as they fail for good
+    let now = time::OffsetDateTime::now_utc().unix_timestamp() + 24 * 60 * 60;
+    let expired = cleanup::expired(&db, 0, now, |_| false).unwrap();
+    for record in &expired {
+        std::fs::remove_file(&record.destination).unwrap();
+    }
+    assert_eq!(expired.iter().map(|r| r.id).collect::<Vec<_>>(), [failed]);
+    assert!(std::fs::read(&path).unwrap() == kept);
+    assert!(!failed_path.exists());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn history_encryption_round_trips() {
+    let path = temp_path().with_extension("db");
+    let db = database::Database::new(&path).unwrap();
+    let id = Uuid::now_v7();
+    db.insert_download(
+        &id,
+        "http://localhost/file.bin",
+        "file.bin",
+        "/tmp/file.bin",
+        None,
+        None,
+        None,
+        None,
+        true,
+    )
+    .unwrap();
+    let key = [7u8; 32];
+    let activity = |db: &database::Database| {
+        let filter = database::ActivityFilter::default();
+        db.get_activity(&filter).unwrap().len()
+    };
+
+    // the copy can't be made, the database goes on as it was
+    let mut copy = path.clone().into_os_string();
+    copy.push("-rekey");
+    std::fs::create_dir(&copy).unwrap();
+    assert!(db.set_encrypted(Some(&key)).is_err());
+    std::fs::remove_dir(&copy).unwrap();
+    assert!(!db.is_encrypted());
+    db.log_activity("added", Some(&id), &serde_json::json!({})).unwrap();
+
+    db.set_encrypted(Some(&key)).unwrap();
+    assert!(db.is_encrypted() && database::is_encrypted(&path));
+    assert!(db.get_download_by_id(&id).unwrap().is_some());
+
+    // the original can't be moved aside once its connection is closed, it's opened
+    // again with its own key
+    let mut backup = path.clone().into_os_string();
+    backup.push("-backup");
+    std::fs::create_dir(&backup).unwrap();
+    std::fs::write(std::path::Path::new(&backup).join("taken"), b"").unwrap();
+    assert!(db.set_encrypted(None).is_err());
+    std::fs::remove_dir_all(&backup).unwrap();
+    assert!(db.is_encrypted());
+    db.log_activity("started", Some(&id), &serde_json::json!({})).unwrap();
+    assert_eq!(activity(&db), 2);
+
+    db.set_encrypted(None).unwrap();
+    assert!(!db.is_encrypted());
+    drop(db);
+    let db = database::Database::new(&path).unwrap();
+    assert!(db.get_download_by_id(&id).unwrap().is_some());
+    assert_eq!(activity(&db), 2);
+}
+
+#[tokio::test]
+async fn follows_redirects() {
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    Mock::given(path("/old"))
+        .respond_with(
+            ResponseTemplate::new(302).insert_header("Location", format!("{}/file.bin", server.uri())),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+
+    let path = temp_path();
+    let url = Url::parse(&format!("{}/old", server.uri())).unwrap();
+    let (download, transfer) = run(url, SIZE, 2, &path).await;
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn size_mismatch_resizes_the_file() {
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    Mock::given(method("GET"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+
+    // HEAD said more than there is, then less
+    for announced in [SIZE + (3 << 20), SIZE - (3 << 20)] {
+        let path = temp_path();
+        let (download, transfer) = run(url.clone(), announced, 2, &path).await;
+
+        assert!(transfer.error().is_none(), "{:?}", transfer.error());
+        assert!(download.is_complete());
+        assert_eq!(transfer.resized(), Some(SIZE as u64));
+        assert_file(&path, &data);
+    }
+}
+
+#[tokio::test]
+async fn compressed_body_falls_back_to_one_stream() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(&data).unwrap();
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Accept-Ranges", "bytes")
+                .insert_header("Content-Encoding", "gzip")
+                .set_body_bytes(encoder.finish().unwrap()),
+        )
+        .mount(&server)
+        .await;
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+
+    // the ranges give up without writing anything
+    let path = temp_path();
+    let (download, transfer) = run(url.clone(), SIZE, 4, &path).await;
+    assert!(transfer.is_encoded());
+    assert!(transfer.error().is_none(), "{:?}", transfer.error());
+    assert!(!download.is_complete());
+
+    let transfer = Transfer::default();
+    core::stream_single(&client().into(), &url, &path, &transfer, None)
+        .await
+        .unwrap();
+    assert!(transfer.is_encoded());
+    assert_eq!(transfer.downloaded(), SIZE as u64);
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn waits_out_too_many_requests() {
+    let server = MockServer::start().await;
+    let data = body(SIZE);
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(Ranged(data.clone()))
+        .mount(&server)
+        .await;
+
+    let path = temp_path();
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let (download, transfer) = run(url, SIZE, 1, &path).await;
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    let statuses = server.received_requests().await.unwrap().len();
+    assert!(statuses > SIZE >> 20, "the throttled request should have been retried");
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn gives_up_on_client_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let path = temp_path();
+    let url = Url::parse(&format!("{}/missing.bin", server.uri())).unwrap();
+    let (download, transfer) = run(url, SIZE, 2, &path).await;
+
+    assert!(transfer.error().is_some());
+    assert!(!download.is_complete());
+    std::fs::remove_file(&path).ok();
+}
+
+// range of the request on `stream`, the whole `len` without one
+async fn read_range(stream: &mut TcpStream, len: usize) -> Option<(usize, usize)> {
+    let mut buf = vec![0u8; 4096];
+    let mut read = 0;
+    while !buf[..read].windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf[read..]).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => read += n,
+        }
+    }
+    let request = String::from_utf8_lossy(&buf[..read]).to_lowercase();
+    let range = request
+        .lines()
+        .find_map(|l| l.strip_prefix("range: bytes="))
+        .and_then(|r| r.trim().split_once('-'))
+        .map(|(s, e)| (s.parse().unwrap(), e.parse::<usize>().unwrap() + 1))
+        .unwrap_or((0, len));
+    Some(range)
+}
+
+fn partial_head(start: usize, end: usize, len: usize) -> String {
+    format!(
+        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+        end - start,
+        start,
+        end - 1,
+        len
+    )
+}
+
+/// The first connection sends half its range, then hangs up or, with `stall`, goes quiet
+async fn flaky_server(data: Arc<Vec<u8>>, stall: bool) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let data = data.clone();
+            let first = connections.fetch_add(1, Ordering::SeqCst) == 0;
+            tokio::spawn(async move {
+                let Some((start, end)) = read_range(&mut stream, data.len()).await else {
+                    return;
+                };
+                let _ = stream.write_all(partial_head(start, end, data.len()).as_bytes()).await;
+                let cut = if first { start + (end - start) / 2 } else { end };
+                let _ = stream.write_all(&data[start..cut]).await;
+                if first && stall {
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                }
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+
+    Url::parse(&format!("http://{}/file.bin", addr)).unwrap()
+}
+
+#[tokio::test]
+async fn recovers_from_dropped_connection() {
+    let data = Arc::new(body(SIZE));
+    let url = flaky_server(data.clone(), false).await;
+
+    let path = temp_path();
+    let (download, transfer) = run(url, SIZE, 1, &path).await;
+
+    assert!(transfer.error().is_none());
+    assert!(download.is_complete());
+    assert_eq!(transfer.downloaded(), SIZE as u64);
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn trace_follows_ranges_and_retries() {
+    let data = Arc::new(body(SIZE));
+    let url = flaky_server(data.clone(), false).await;
+
+    let path = temp_path();
+    let transfer = Arc::new(Transfer::default());
+    transfer.enable_trace();
+    let download = Download::new(SIZE, 1, Strategy::Fixed(1))
+        .run(client(), url, &path, 1, transfer.clone())
+        .await
+        .unwrap();
+    assert!(download.is_complete());
+
+    let events = transfer.take_trace();
+    let mut next = 0;
+    for event in &events {
+        if let WorkerEvent::Assigned { worker, range, .. } = event {
+            assert_eq!((*worker, range.start), (0, next));
+            next = range.end;
+        }
+    }
+    assert_eq!(next, SIZE as u64);
+    let retries: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            WorkerEvent::Retry { worker, attempt, .. } => Some((*worker, *attempt)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(retries, [(0, 1)]);
+    assert!(transfer.take_trace().is_empty());
+    assert_file(&path, &data);
+}
+
+#[tokio::test]
+async fn stalled_connection_retried_per_policy() {
+    let data = Arc::new(body(SIZE));
+    let url = flaky_server(data.clone(), true).await;
+
+    let path = temp_path();
+    let transfer = Arc::new(Transfer::default());
+    let policy = RangeRetry {
+        retries: 1,
+        backoff_ms: 10,
+        stall_timeout: 1,
+    };
+    let client = HttpClient::from(client()).with_retry(policy);
+    let download = Download::new(SIZE, 1, Strategy::Fixed(1))
+        .run(client, url, &path, 1, transfer.clone())
+        .await
+        .unwrap();
+
+    assert!(transfer.error().is_none(), "{:?}", transfer.error());
+    assert!(download.is_complete());
+    assert_eq!(transfer.workers()[0].retries, 1);
+    assert_file(&path, &data);
+}
+
+/// Serves one connection at a time, anything arriving while one is open gets a 403
+async fn single_session_server(data: Arc<Vec<u8>>) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let open = Arc::new(AtomicUsize::new(0));
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let data = data.clone();
+            let open = open.clone();
+            tokio::spawn(async move {
+                let Some((start, end)) = read_range(&mut stream, data.len()).await else {
+                    return;
+                };
+                if open.fetch_add(1, Ordering::SeqCst) > 0 {
+                    let head = "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    let _ = stream.write_all(head.as_bytes()).await;
+                } else {
+                    let _ = stream.write_all(partial_head(start, end, data.len()).as_bytes()).await;
+                    for chunk in data[start..end].chunks(256 << 10) {
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        let _ = stream.write_all(chunk).await;
+                    }
+                }
+                open.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+
+    Url::parse(&format!("http://{}/file.bin", addr)).unwrap()
+}
+
+#[tokio::test]
+async fn single_session_goes_on_with_one_connection() {
+    let data = Arc::new(body(SIZE));
+    let url = single_session_server(data.clone()).await;
+
+    let path = temp_path();
+    let (download, transfer) = run(url, SIZE, 4, &path).await;
+
+    assert!(transfer.error().is_none(), "{:?}", transfer.error());
+    assert!(transfer.is_single_session());
+    assert!(download.is_complete());
+    assert_file(&path, &data);
+}
+
+/// A ZIP holding `files`, deflated when the flag is set
+fn zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    let (mut out, mut directory) = (Vec::new(), Vec::new());
+    for (name, data, deflate) in files {
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        let packed = match deflate {
+            true => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            false => data.to_vec(),
+        };
+        let method: u16 = if *deflate { 8 } else { 0 };
+        // version, flags, method, time, date, crc, sizes, name and extra lengths
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&[0; 4]);
+        fields.extend_from_slice(&crc.sum().to_le_bytes());
+        fields.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        directory.extend_from_slice(&fields);
+        // comment length, disk, internal and external attributes, local header offset
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&(out.len() as u32).to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&fields);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&packed);
+    }
+    let offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+#[tokio::test]
+async fn archive_members_fetched_alone() {
+    let big = body(SIZE);
+    let text = b"readme ".repeat(1000);
+    let archive = zip(&[
+        ("big.bin", &big, false),
+        ("docs/readme.txt", &text, true),
+        ("../escape.txt", b"x", false),
+    ]);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/pack.zip"))
+        .respond_with(Ranged(archive.clone()))
+        .mount(&server)
+        .await;
+    let url = Url::parse(&format!("{}/pack.zip", server.uri())).unwrap();
+    let client = client().into();
+    let size = archive.len() as u64;
+
+    let members = archive::list(&client, &url, size).await.unwrap();
+    let names: Vec<&str> = members.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["big.bin", "docs/readme.txt", "../escape.txt"]);
+    assert_eq!(members[1].size, text.len() as u64);
+    assert!(members[1].compressed_size < members[1].size);
+
+    let folder = temp_path();
+    let transfer = Transfer::default();
+    let wanted = ["docs/readme.txt".to_string()];
+    archive::extract(&client, &url, size, &wanted, &folder, &transfer)
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(folder.join("docs/readme.txt")).unwrap(), text);
+    assert!(!folder.join("big.bin").exists());
+    // the whole archive counts as done, the big member was never asked for
+    assert_eq!(transfer.downloaded(), size);
+    let fetched: usize = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|r| requested_range(r, archive.len()))
+        .map(|(start, end)| end - start)
+        .sum();
+    assert!(fetched < SIZE / 2, "fetched {} bytes", fetched);
+
+    let escape = ["../escape.txt".to_string()];
+    let result = archive::extract(&client, &url, size, &escape, &folder, &transfer).await;
+    assert!(result.is_err());
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn archive_zip64_sizes_from_the_extra_field() {
+    let name = b"huge.iso";
+    let mut record = Vec::new();
+    record.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    // made by, needed, flags, stored, time, date, crc
+    record.extend_from_slice(&[45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0]);
+    // both sizes saturated, the offset fits
+    record.extend_from_slice(&[0xFF; 8]);
+    record.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    record.extend_from_slice(&20u16.to_le_bytes());
+    record.extend_from_slice(&[0; 10]);
+    record.extend_from_slice(&1234u32.to_le_bytes());
+    record.extend_from_slice(name);
+    record.extend_from_slice(&0x0001u16.to_le_bytes());
+    record.extend_from_slice(&16u16.to_le_bytes());
+    record.extend_from_slice(&(5u64 << 32).to_le_bytes());
+    record.extend_from_slice(&(5u64 << 32).to_le_bytes());
+
+    let members = archive::parse(&record, 1).unwrap();
+    assert_eq!(members[0].name, "huge.iso");
+    assert_eq!(members[0].size, 5 << 32);
+    assert_eq!(members[0].compressed_size, 5 << 32);
+    assert!(members[0].supported);
+    // cut off in its extra field
+    record.truncate(record.len() - 20);
+    assert!(archive::parse(&record, 1).is_err());
+}
+
+#[tokio::test]
+async fn guarded_clients_stay_off_private_addresses() {
+    let server = MockServer::start().await;
+    let port = server.address().port();
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body(16)))
+        .mount(&server)
+        .await;
+    // by name to the same server, then to its address written out
+    Mock::given(method("GET"))
+        .and(path("/moved"))
+        .respond_with(
+            ResponseTemplate::new(302)
+                .insert_header("Location", format!("http://127.0.0.1:{}/file.bin", port)),
+        )
+        .mount(&server)
+        .await;
+    let url = Url::parse(&format!("http://localhost:{}/file.bin", port)).unwrap();
+    let moved = Url::parse(&format!("http://localhost:{}/moved", port)).unwrap();
+    let mut settings = AppSettings::default();
+    let guarded = DownloadOptions {
+        guarded: true,
+        ..Default::default()
+    };
+    let get = |settings: &AppSettings, options: &DownloadOptions, url: &Url| {
+        let client = client::for_download(settings, url, options).unwrap();
+        let url = url.clone();
+        async move { client.get(url).send().await }
+    };
+
+    // localhost only resolves to loopback, nothing is left to connect to
+    assert!(get(&settings, &guarded, &url).await.is_err());
+    assert!(get(&settings, &DownloadOptions::default(), &url).await.is_ok());
+
+    settings.network.allowed_private_hosts = vec!["localhost".into()];
+    assert!(get(&settings, &guarded, &url).await.is_ok());
+    // the redirect target isn't an allowed host
+    assert!(get(&settings, &guarded, &moved).await.is_err());
+
+    settings.network.block_private_targets = false;
+    assert!(get(&settings, &guarded, &moved).await.is_ok());
+}
+
+#[tokio::test]
+async fn chaos_503_comes_back_as_a_response() {
+    let server = MockServer::start().await;
+    let url = Url::parse(&format!("{}/file.bin", server.uri())).unwrap();
+    let chaos = ChaosConfig {
+        enabled: true,
+        error_rate: 1.0,
+        ..Default::default()
+    };
+    let client = HttpClient::from(client()).with_chaos(&chaos);
+
+    let response = client.send(client.inner().get(url.clone())).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[reqwest::header::RETRY_AFTER], "1");
+    assert_eq!(response.url(), &url);
+    // made up before it was sent
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
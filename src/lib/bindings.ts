@@ -0,0 +1,1413 @@
+// Generated by `tur gen-bindings` from the Rust types, do not edit.
+
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export const commands = {
+  /** The settings for the GUI. The PIN hash never leaves the backend, the other secrets (tokens, passwords, keys) don't while guest mode is on. Both show as `REDACTED`. */
+  getSettings: () => invoke<AppSettings>("get_settings"),
+  /** Keys pinned by the policy file, shown as read-only in the settings */
+  getLockedSettings: () => invoke<string[]>("get_locked_settings"),
+  updateSettings: (settings: AppSettings) => invoke<null>("update_settings", { settings }),
+  updateSetting: (key: string, value: JsonValue) => invoke<null>("update_setting", { key, value }),
+  getAutostart: () => invoke<boolean>("get_autostart"),
+  setAutostart: (enabled: boolean) => invoke<null>("set_autostart", { enabled }),
+  handleDownloadRequest: (request: DownloadRequest) => invoke<null>("handle_download_request", { request }),
+  /** Add the links a `private_target_held` event held back, `token` is the one it carried. A token confirms once. */
+  confirmPrivateTargets: (token: string) => invoke<null>("confirm_private_targets", { token }),
+  /** Speed samples for the graph, live while running and downsampled once completed */
+  getSpeedHistory: (id: string) => invoke<SpeedSeries>("get_speed_history", { id }),
+  /** Thumbnail and file metadata of a finished download, None until one was made */
+  getThumbnail: (id: string) => invoke<Thumbnail | null>("get_thumbnail", { id }),
+  /** Decrypt a finished encrypted download into `target`, or in place when it's left out. Returns where the plaintext ended up. */
+  decryptDownload: (id: string, target: string | null) => invoke<string>("decrypt_download", { id, target }),
+  /** Links in a finished HTML or PDF download, grouped by type, to queue follow-ups from */
+  extractLinks: (id: string) => invoke<Partial<{ [key in string]: string[] }>>("extract_links", { id }),
+  /** Timeline of downloads and settings changes, newest first */
+  getActivity: (filter: ActivityFilter | null) => invoke<Activity[]>("get_activity", { filter }),
+  /** The stored row, the settings `id` last ran with (threads, limits, UA, proxy) and, while it runs, segments, per worker speed and retries and the url after redirects */
+  getDownloadDetails: (id: string) => invoke<DownloadDetails>("get_download_details", { id }),
+  /** Resolves with the final row once `id` completed, or failed with no retry left pending, so a flow can chain something after one download */
+  awaitDownload: (id: string) => invoke<Download>("await_download", { id }),
+  /** Running downloads with their progress, queued ones, the newest `history` (50 when left out) finished ones and the settings, in one call for a frontend that just (re)loaded */
+  getAppState: (history: number | null) => invoke<AppState>("get_app_state", { history }),
+  /** Global speed, remaining bytes and queue ETA, with a per category breakdown */
+  getDashboardState: () => invoke<DashboardState>("get_dashboard_state"),
+  /** Writer pool, runtime latency and connection reuse counters, reset on every call */
+  getIoMetrics: () => invoke<IoMetrics>("get_io_metrics"),
+  /** Bytes received per day over the last `days` (31 when left out) and the billing month against `bandwidth.monthly_cap_mib` */
+  getTransferStats: (days: number | null) => invoke<TransferStats>("get_transfer_stats", { days }),
+  /** Per download url, size, time spent, average speed and how it ended for the runs that started in `period`, as CSV or JSON, for reporting bandwidth use */
+  exportStatistics: (period: Period, format: ExportFormat) => invoke<string>("export_statistics", { period, format }),
+  /** Folders downloads were saved to lately, the download location first, with the room left on their drives. `limit` is 8 when left out. */
+  getRecentDestinations: (limit: number | null) => invoke<RecentFolder[]>("get_recent_destinations", { limit }),
+  /** Room left on the drive `path` is on, for a folder picked by hand */
+  getFreeSpace: (path: string) => invoke<Space | null>("get_free_space", { path }),
+  /** Restart running downloads on clients built from the current settings (proxy, TLS, UA) */
+  reloadEngine: () => invoke<string[]>("reload_engine"),
+  /** Resume what was running when the app last quit, see the `session_restore_available` event */
+  restoreSession: () => invoke<string[]>("restore_session"),
+  /** Queue failed downloads again, `ids` or every failed one when left out, resuming those whose partial data still matches the server. Cancelled downloads are skipped. */
+  retryFailed: (ids: string[] | null) => invoke<RetrySummary>("retry_failed", { ids }),
+  /** Which of the download rules a link to `url` would get, None when no rule matches */
+  testRule: (url: string) => invoke<RuleMatch | null>("test_rule", { url }),
+  /** `url` after the rewrite rules, `rewrites` tries a list out before it's saved */
+  previewRewrite: (url: string, rewrites: RewriteRule[] | null) => invoke<Rewrite>("preview_rewrite", { url, rewrites }),
+  /** Run the send-to action called `action` for a download, e.g. "Open in VLC" */
+  runAction: (id: string, action: string) => invoke<null>("run_action", { id, action }),
+  /** Let download `id` out of quarantine without waiting for the scanner */
+  releaseQuarantine: (id: string) => invoke<null>("release_quarantine", { id }),
+  /** Answer to `presigned_url_expired`: swap in the re-signed url and pick up where it stopped */
+  refreshPresignedUrl: (id: string, url: string) => invoke<null>("refresh_presigned_url", { id, url }),
+  /** First `bytes` of `url` in a temp file, so the GUI can show it before committing to it */
+  fetchPreview: (url: string, bytes: number, options: DownloadOptions | null) => invoke<Preview>("fetch_preview", { url, bytes, options }),
+  /** Files inside download `id`, a ZIP archive, read from its central directory without fetching the rest */
+  listContents: (id: string) => invoke<Member[]>("list_contents", { id }),
+  /** Fetch only `files` out of download `id`, a ZIP archive, into a folder named after it. An empty list goes back to the whole archive. Not while it's running. */
+  selectFiles: (id: string, files: string[]) => invoke<null>("select_files", { id, files }),
+  /** Files inside the ZIP at `url`, read from its central directory without fetching the rest */
+  peekArchive: (url: string, options: DownloadOptions | null) => invoke<Member[]>("peek_archive", { url, options }),
+  /** Queue just `member` of the ZIP at `url`, unpacked into a folder named after the archive */
+  downloadArchiveMember: (url: string, member: string, options: DownloadOptions | null) => invoke<null>("download_archive_member", { url, member, options }),
+  pauseDownload: (ids: string[]) => invoke<null>("pause_download", { ids }),
+  /** Keep `ids` at the top of the history, or unpin them */
+  pinDownload: (ids: string[], pinned: boolean) => invoke<null>("pin_download", { ids, pinned }),
+  /** Fetch finished or failed download `id` again from the start, same file, same headers */
+  downloadAgain: (id: string) => invoke<null>("download_again", { id }),
+  /** Add download `id` again as a new one with the same url, headers, category and limits. Its destination is worked out again by the rules and the filename template, and a file already there is handled like for any other add. */
+  redownload: (id: string) => invoke<null>("redownload", { id }),
+  /** How workers of download `id` retry a failing range: `policy`, else the aggressive preset when `aggressive` is set, else the default. Used from its next start on. */
+  setRetryPolicy: (id: string, policy: RangeRetry | null, aggressive: boolean) => invoke<null>("set_retry_policy", { id, policy, aggressive }),
+  /** KiB/s for one download, None to follow the global limit again */
+  setSpeedLimit: (id: string, limit: number | null) => invoke<null>("set_speed_limit", { id, limit }),
+  cancelDownload: (ids: string[], keepPartial: boolean | null) => invoke<null>("cancel_download", { ids, keepPartial }),
+  /** What cancelling a download would leave, for the keep or discard prompt */
+  getSalvageable: (id: string) => invoke<Salvage>("get_salvageable", { id }),
+  /** Check and compact the history database now, what the idle task does once a day. Refused while downloads run or wait, a full VACUUM would hold up their progress writes. */
+  runMaintenance: () => invoke<Maintenance>("run_maintenance"),
+  /** Whether the history database is encrypted with the keyring's key */
+  getHistoryEncryption: () => invoke<boolean>("get_history_encryption"),
+  /** Encrypt the history database with SQLCipher, its key kept in the OS keyring, or turn it back into plain SQLite. The key stays in the keyring for a later encrypt. */
+  setHistoryEncryption: (enabled: boolean) => invoke<null>("set_history_encryption", { enabled }),
+  /** Queue `path` for upload to `endpoint`, it shows up in history like a download */
+  startUpload: (path: string, endpoint: string, options: DownloadOptions | null) => invoke<string>("start_upload", { path, endpoint, options }),
+  syncHistory: () => invoke<SyncReport>("sync_history"),
+  /** Languages for the settings picker */
+  getAvailableLocales: () => invoke<Locale[]>("get_available_locales"),
+  /** Newest last, including the ones held back or not shown */
+  getNotificationHistory: () => invoke<Notification[]>("get_notification_history"),
+  /** Turn guest mode on, the GUI can only look until `unlock_app` */
+  lockApp: () => invoke<null>("lock_app"),
+  /** Leave guest mode, `pin` is checked when `lock.pin` was set. After `FREE_ATTEMPTS` wrong PINs in a row unlocking is refused for a while, longer with every one after. */
+  unlockApp: (pin: string | null) => invoke<null>("unlock_app", { pin }),
+  /** Profiles created so far and the one in use */
+  listProfiles: () => invoke<Profiles>("list_profiles"),
+  /** Make `name` the active profile, creating it if it's new, and restart into it. Running downloads are paused and saved first, like on quit. */
+  switchProfile: (name: string) => invoke<null>("switch_profile", { name }),
+  /** Send a test message through `channel`, which doesn't have to be saved yet */
+  testNotificationChannel: (channel: NotificationChannel) => invoke<null>("test_notification_channel", { channel }),
+  /** Send a `test` event to `webhook` once, no retries, and return the response status */
+  testWebhook: (webhook: Webhook) => invoke<number>("test_webhook", { webhook }),
+  /** `run` for the GUI, with the file capped at `MAX_SIZE_MIB`. `io_threads` is ignored, the writer pool is the one `thread.io_threads` started with the app */
+  runBenchmark: (options: BenchOptions | null) => invoke<BenchResult[]>("run_benchmark", { options }),
+};
+
+export type Events = {
+  "queue_download": QueueDownload;
+  "download_progress": DownloadProgress;
+  "download_debug": DownloadDebug;
+  "download_completed": DownloadFinished;
+  "download_paused": DownloadFinished;
+  "download_cancelled": DownloadFinished;
+  "download_failed": DownloadFinished;
+  "presigned_url_expired": DownloadFinished;
+  "download_retry_scheduled": RetryScheduled;
+  "thumbnail_ready": ThumbnailReady;
+  "duplicate_download": DuplicateDownload;
+  "history_synced": HistorySynced;
+  "notification": Notification;
+  "deep-link-received": DeepLinkReceived;
+  "session_restore_available": SessionRestore;
+  "private_target_held": PrivateTargetHeld;
+  "quarantine_changed": QuarantineChanged;
+  "size_mismatch": SizeMismatch;
+  "url_health": UrlHealth;
+};
+
+/** Listen to a backend event with its payload typed */
+export function listenTo<E extends keyof Events>(
+  event: E,
+  handler: (payload: Events[E]) => void,
+): Promise<UnlistenFn> {
+  return listen<Events[E]>(event, (e) => handler(e.payload));
+}
+
+export type ActiveDownload = ({ id: string; filename: string; status: string | null; size: number | null; bytes_received: number; url: string; etag: string | null; content_type: string | null; last_modified: string | null; destination: string; accept_ranges: boolean; updated_at: number; options: DownloadOptions; 
+/**
+ * "download" or "upload", uploads read from `destination` and send to `url`
+ */
+direction: string; 
+/**
+ * batch the download was added with, see the `jobs` table
+ */
+job_id: string | null; 
+/**
+ * "unscanned", "scanning" or "flagged" while a completed file is held back, see
+ * `downloads::quarantine`. None once it's released or was never held.
+ */
+quarantine?: string | null; 
+/**
+ * average speed over one connection's, kept when a multi-connection download completes
+ */
+acceleration?: number | null; 
+/**
+ * kept at the top of the history, for ones fetched again and again
+ */
+pinned?: boolean; 
+/**
+ * the device a sync brought this record from, its `destination` is a path there.
+ * None once this device wrote its status, or when it never left it
+ */
+device?: string | null }) & { 
+/**
+ * the last `download_progress` as of now
+ */
+progress: DownloadProgress }
+
+/**
+ * One entry of the activity timeline
+ */
+export type Activity = { id: number; 
+/**
+ * unix seconds
+ */
+at: number; 
+/**
+ * added, started, paused, failed, cancelled, completed, settings_changed
+ */
+kind: string; download_id: string | null; detail: JsonValue }
+
+/**
+ * What `get_activity` returns, every field narrows it down
+ */
+export type ActivityFilter = { kinds: string[]; download_id: string | null; 
+/**
+ * unix seconds, inclusive
+ */
+since: number | null; until: number | null; 
+/**
+ * 500 when left out
+ */
+limit: number | null }
+
+export type AppConfig = { show_tray_icon: boolean; quit_on_close: boolean; sidebar: string; theme: string; button_label: string; show_download_progress: boolean; show_segment_progress: boolean; autostart: boolean; 
+/**
+ * backend strings (CLI, notifications), a code from `get_available_locales` or "system"
+ */
+language?: string; 
+/**
+ * "bytes" (MB/s) or "bits" (Mbit/s) for speeds the backend formats
+ */
+speed_unit?: string; 
+/**
+ * "binary" (MiB) or "si" (MB)
+ */
+unit_prefix?: string; 
+/**
+ * emit `download_debug` events on what each connection does, as `--debug` does
+ */
+debug_events?: boolean; 
+/**
+ * ms between `download_progress` events of a running download
+ */
+progress_interval_ms?: number }
+
+export type AppSettings = { app: AppConfig; shortcuts: ShortcutConfig; download: DownloadConfig; thread: ThreadConfig; session: SessionConfig; network?: NetworkConfig; retry?: RetryConfig; s3?: S3Config; chaos?: ChaosConfig; remote?: RemoteConfig; sync?: SyncConfig; notifications?: NotificationConfig; webhooks?: Webhook[]; scripting?: ScriptingConfig; bandwidth?: BandwidthConfig; lock?: LockConfig; 
+/**
+ * "send to" commands offered for each download in history
+ */
+actions?: SendAction[]; send_anonymous_metrics: boolean; show_notifications: boolean }
+
+/**
+ * Everything the frontend shows, so a reload mid-download doesn't have to rebuild it
+ * from events
+ */
+export type AppState = { 
+/**
+ * running right now, oldest first
+ */
+active: ActiveDownload[]; 
+/**
+ * stored as in progress but not running, and failed ones with a retry scheduled
+ */
+queued: Download[]; history: HistoryPage; dashboard: DashboardState; settings: AppSettings }
+
+/**
+ * Monthly transfer cap for metered connections, see `downloads::stats`, and how
+ * running downloads share the link, see `downloads::fair`
+ */
+export type BandwidthConfig = { 
+/**
+ * MiB received per billing month before downloads pause, 0 is no cap
+ */
+monthly_cap_mib: number; 
+/**
+ * day of the month the count starts over, 1 to 28
+ */
+billing_day: number; 
+/**
+ * KiB/s over all downloads together, 0 is no limit
+ */
+total_limit: number; 
+/**
+ * split the link evenly between running downloads whatever their connection
+ * count, priority ones get a double share
+ */
+balance: boolean }
+
+/**
+ * Subfolder for a batch, `tur-<date>-<name>` under the download location
+ */
+export type BatchOptions = { 
+/**
+ * "batch" when left out
+ */
+name: string | null; 
+/**
+ * fetch every link even with `download.respect_robots` on
+ */
+ignore_robots: boolean }
+
+/**
+ * Knobs for a benchmark run, anything left out falls back to the default
+ */
+export type BenchOptions = { size_mib: number; latency_ms: number; 
+/**
+ * per connection cap on the test server, 0 for unlimited
+ */
+rate_kib: number; threads: number[]; 
+/**
+ * range size for the fixed strategy
+ */
+chunk_mib: number; 
+/**
+ * run through the fault injecting client, results then double as a retry self-test
+ */
+chaos: boolean; 
+/**
+ * writer threads, 0 for tokio's blocking pool; compare both through `lag_ms`.
+ * Only `tur bench` starts them, `run_benchmark` measures the pool the app runs with.
+ */
+io_threads: number; 
+/**
+ * MiB/s shared by two downloads at once for `run_fairness`, 0 skips it
+ */
+fairness_mib: number }
+
+export type BenchResult = { threads: number; strategy: string; millis: number; mib_per_sec: number; 
+/**
+ * file on disk matched what the server sent
+ */
+verified: boolean; 
+/**
+ * worst wake-up delay of a task ticking next to the download
+ */
+lag_ms: number; 
+/**
+ * GETs the server answered, each one a round trip the download waited on
+ */
+requests: number }
+
+export type CategoryState = { active: number; speed: number; remaining: number }
+
+/**
+ * Fault injection for QA, also switched on by `--chaos`
+ */
+export type ChaosConfig = { enabled: boolean; 
+/**
+ * chance per request and per chunk that the connection drops
+ */
+reset_rate: number; 
+/**
+ * chance per chunk that the read stalls for `slow_ms`
+ */
+slow_rate: number; slow_ms: number; 
+/**
+ * chance per request of a synthetic 503
+ */
+error_rate: number; 
+/**
+ * same seed, same sequence of faults
+ */
+seed: number }
+
+/**
+ * Requests against new connections since the last `take_metrics`.
+ * Every request past `opened` went out on a pooled connection.
+ */
+export type ConnectionMetrics = { requests: number; 
+/**
+ * connection attempts, failed ones included
+ */
+opened: number; reused: number; reuse_percent: number }
+
+/**
+ * Totals over every running download
+ */
+export type DashboardState = { active: number; 
+/**
+ * bytes/s over the last second
+ */
+speed: number; downloaded: number; remaining: number; 
+/**
+ * downloads that don't report a size, left out of `remaining`
+ */
+unknown_size: number; 
+/**
+ * seconds until everything sized is done at the current speed
+ */
+eta: number | null; categories: Partial<{ [key in string]: CategoryState }> }
+
+export type DayTotal = { 
+/**
+ * "YYYY-MM-DD", UTC
+ */
+day: string; bytes: number }
+
+export type DeepLinkReceived = { url: string; options: DownloadOptions; 
+/**
+ * "startup" or "command_line"
+ */
+type: string }
+
+export type Download = { id: string; filename: string; status: string | null; size: number | null; bytes_received: number; url: string; etag: string | null; content_type: string | null; last_modified: string | null; destination: string; accept_ranges: boolean; updated_at: number; options: DownloadOptions; 
+/**
+ * "download" or "upload", uploads read from `destination` and send to `url`
+ */
+direction: string; 
+/**
+ * batch the download was added with, see the `jobs` table
+ */
+job_id: string | null; 
+/**
+ * "unscanned", "scanning" or "flagged" while a completed file is held back, see
+ * `downloads::quarantine`. None once it's released or was never held.
+ */
+quarantine?: string | null; 
+/**
+ * average speed over one connection's, kept when a multi-connection download completes
+ */
+acceleration?: number | null; 
+/**
+ * kept at the top of the history, for ones fetched again and again
+ */
+pinned?: boolean; 
+/**
+ * the device a sync brought this record from, its `destination` is a path there.
+ * None once this device wrote its status, or when it never left it
+ */
+device?: string | null }
+
+export type DownloadConfig = { download_location: string; num_threads: number; chunk_size: number; socket_buffer_size: number; 
+/**
+ * KiB/s per download, 0 is unlimited
+ */
+speed_limit: number; user_agent?: string; 
+/**
+ * first matching host wins over `user_agent`
+ */
+user_agent_rules?: UserAgentRule[]; 
+/**
+ * path under `download_location`, see `downloads::template::VARIABLES`
+ */
+filename_template?: string; 
+/**
+ * category name to template, wins over `filename_template`
+ */
+category_templates?: Partial<{ [key in string]: string }>; 
+/**
+ * give every multi-link add its own dated subfolder
+ */
+batch_subfolders?: boolean; 
+/**
+ * multi-link adds skip what robots.txt disallows and keep to its crawl-delay
+ */
+respect_robots?: boolean; 
+/**
+ * delete what failed downloads left at their destination once they're this many days
+ * old, 0 as soon as they fail for good. None keeps them
+ */
+cleanup_partials_days?: number | null; 
+/**
+ * what cancelling leaves of a download's file: "truncate" cuts it back to the bytes
+ * downloaded in one piece from the start, "delete" removes it
+ */
+on_cancel?: string; 
+/**
+ * replace a completed file identical to an earlier download with a hard link to it
+ */
+hardlink_duplicates?: boolean; 
+/**
+ * how non-ASCII names are written to disk: "keep", "nfc" or "ascii" (transliterated).
+ * The database keeps the name the server sent
+ */
+filename_unicode?: string; 
+/**
+ * octal mode bits for completed files on Unix, e.g. "644", empty keeps the umask's
+ */
+file_mode?: string; 
+/**
+ * make completed files with one of `executable_extensions` executable on Unix
+ */
+mark_executable?: boolean; executable_extensions?: string[]; 
+/**
+ * tag completed files with their source url, xattrs or the Windows Zone.Identifier
+ */
+record_origin?: boolean; 
+/**
+ * hold completed downloads as unscanned until `scan_command` passes or the user
+ * releases them, see `downloads::quarantine`
+ */
+quarantine?: boolean; 
+/**
+ * scanner run on each quarantined file with TUR_PATH set, exit code 0 releases it,
+ * e.g. `clamscan --no-summary "$TUR_PATH"`
+ */
+scan_command?: string; 
+/**
+ * fetch only what changed from the file a download replaces, when the server has a
+ * `.zsync` file for it
+ */
+delta_updates?: boolean; 
+/**
+ * how fresh ranges are sized: "fibonacci" growing ones, "fixed" ones of `chunk_size`
+ * MiB, or "adaptive" ones fitted to the speed and round trip measured while it runs
+ */
+range_strategy?: string; 
+/**
+ * minutes between checks that queued downloads' urls still answer, 0 turns them off,
+ * see `downloads::health`
+ */
+health_check_minutes?: number; 
+/**
+ * look for mirrors of new downloads in Link headers and mirror list pages, see
+ * `downloads::mirrors`
+ */
+discover_mirrors?: boolean; 
+/**
+ * extensions refused when a download is added, e.g. "exe" on a managed install
+ */
+blocked_extensions?: string[]; 
+/**
+ * shell command run after each completed download, with TUR_PATH, TUR_URL and TUR_ID set
+ */
+post_command?: string; 
+/**
+ * end post_command runs that are still going when tur quits
+ */
+stop_commands_on_quit?: boolean; 
+/**
+ * per host or url overrides, see `downloads::rules`
+ */
+rules?: DownloadRule[]; 
+/**
+ * url rewrites applied in order before a new download is probed, see `downloads::rewrite`
+ */
+rewrites?: RewriteRule[] }
+
+/**
+ * What one download's connections did since the last one, in debug mode only
+ */
+export type DownloadDebug = { id: string; events: WorkerEvent[] }
+
+/**
+ * A stored download with what it last ran with, for the details pane
+ */
+export type DownloadDetails = ({ id: string; filename: string; status: string | null; size: number | null; bytes_received: number; url: string; etag: string | null; content_type: string | null; last_modified: string | null; destination: string; accept_ranges: boolean; updated_at: number; options: DownloadOptions; 
+/**
+ * "download" or "upload", uploads read from `destination` and send to `url`
+ */
+direction: string; 
+/**
+ * batch the download was added with, see the `jobs` table
+ */
+job_id: string | null; 
+/**
+ * "unscanned", "scanning" or "flagged" while a completed file is held back, see
+ * `downloads::quarantine`. None once it's released or was never held.
+ */
+quarantine?: string | null; 
+/**
+ * average speed over one connection's, kept when a multi-connection download completes
+ */
+acceleration?: number | null; 
+/**
+ * kept at the top of the history, for ones fetched again and again
+ */
+pinned?: boolean; 
+/**
+ * the device a sync brought this record from, its `destination` is a path there.
+ * None once this device wrote its status, or when it never left it
+ */
+device?: string | null }) & { 
+/**
+ * None until it has been started once
+ */
+environment: Environment | null; 
+/**
+ * automatic retries of the whole download so far
+ */
+attempts: number; 
+/**
+ * how its next run retries a failing range, from its options and the settings
+ */
+retry_policy: RangeRetry; 
+/**
+ * None unless it's running
+ */
+live: LiveState | null }
+
+/**
+ * Sent once a download stops, whichever way
+ */
+export type DownloadFinished = { id: string; url: string; downloaded: number; error: string | null; 
+/**
+ * notification text in the user's language
+ */
+message: string; average_speed: number; average_speed_text: string; 
+/**
+ * average speed over what one connection got at the start, for completed
+ * multi-connection downloads
+ */
+acceleration: number | null; 
+/**
+ * what cancelling did with the file, None for any other ending or when there was none
+ */
+partial: PartialFile | null }
+
+/**
+ * Per download overrides, stored with the record so resumes use them too
+ */
+export type DownloadOptions = { 
+/**
+ * replaces the host rules and the global user agent
+ */
+user_agent: string | null; 
+/**
+ * sent as is, wins over `page_url`
+ */
+referer: string | null; 
+/**
+ * page the link was captured from (browser extension, deep link)
+ */
+page_url: string | null; 
+/**
+ * replaces `network.interfaces`, an empty list uses the default route
+ */
+interfaces: string[] | null; 
+/**
+ * replaces the `retry` settings for this download
+ */
+retry: RetryConfig | null; 
+/**
+ * replaces how workers retry a failing range, see `client::range_retry`
+ */
+range_retry: RangeRetry | null; 
+/**
+ * retry ranges with `RangeRetry::AGGRESSIVE` when `range_retry` isn't set, for flaky
+ * servers and mirrors
+ */
+aggressive_retry: boolean; 
+/**
+ * fetch front to back, for watching media while it downloads
+ */
+sequential: boolean; 
+/**
+ * let the file grow with the download instead of sizing it up front
+ */
+skip_preallocation: boolean; 
+/**
+ * first and last few MiB before the rest, for archive indexes and media headers
+ */
+priority_pieces: boolean; 
+/**
+ * encrypted while downloading, readable only after `decrypt_download`
+ */
+encrypt: boolean; 
+/**
+ * save under this name instead of the one from Content-Disposition or the url
+ */
+filename: string | null; 
+/**
+ * replaces `download.num_threads`
+ */
+connections: number | null; 
+/**
+ * KiB/s, replaces `download.speed_limit`
+ */
+speed_limit: number | null; 
+/**
+ * one of `category::NAMES` instead of guessing from the name
+ */
+category: string | null; 
+/**
+ * keeps running past the monthly transfer cap
+ */
+priority: boolean; 
+/**
+ * older copy of the file, only the blocks that changed are fetched when the server
+ * has a `.zsync` file next to it
+ */
+delta_from: string | null; 
+/**
+ * one connection at a time, still resuming by range, for servers whose session
+ * tokens turn parallel requests away; set by itself once they do
+ */
+single_session: boolean; 
+/**
+ * other urls with the same file, ranges are spread over them and the url itself;
+ * found when the download is added if none are given, see `mirrors`
+ */
+mirrors: string[]; 
+/**
+ * files to take out of a ZIP instead of fetching all of it, unpacked into a folder
+ * named after the archive, see `archive`
+ */
+members: string[]; 
+/**
+ * no `download_progress` events, only how it ends, for big batches nobody watches
+ */
+quiet: boolean; 
+/**
+ * came from outside the app and wasn't confirmed, every connection stays off private
+ * addresses, see `safety`. Set by the app whatever the caller sends.
+ */
+guarded: boolean }
+
+/**
+ * Sent every progress tick of a running download
+ */
+export type DownloadProgress = { id: string; downloaded: number; size: number | null; 
+/**
+ * bytes per second
+ */
+speed: number; 
+/**
+ * seconds
+ */
+eta: number | null; speed_text: string; eta_text: string | null }
+
+export type DownloadRequest = 
+/**
+ * New downloads from external sources (browser extension, manual add, drag & drop)
+ */
+{ type: "New"; data: { urls: string[]; options?: DownloadOptions; 
+/**
+ * put them all in one new subfolder, e.g. links from one crawl or list
+ */
+batch?: BatchOptions | null } } | 
+/**
+ * Resume existing downloads from history
+ */
+{ type: "Resume"; data: string[] } | 
+/**
+ * Deep link URLs (cold start, app fetches headers)
+ */
+{ type: "DeepLink"; data: { urls: string[]; options?: DownloadOptions } }
+
+/**
+ * Overrides for downloads whose url matches, the first matching rule in the list wins.
+ * Options given with the download itself still win over the rule.
+ */
+export type DownloadRule = { name: string; 
+/**
+ * exact host or `*.example.com`, empty matches every host
+ */
+host: string; 
+/**
+ * regex searched for in the whole url, empty matches every url
+ */
+pattern: string; connections: number | null; 
+/**
+ * KiB/s
+ */
+speed_limit: number | null; user_agent: string | null; 
+/**
+ * folder used instead of the download location, batches keep their subfolder
+ */
+destination: string | null; 
+/**
+ * one of `downloads::category::NAMES`
+ */
+category: string | null }
+
+/**
+ * A finished download with the same content as an earlier one
+ */
+export type DuplicateDownload = { id: string; path: string; existing_id: string; existing_path: string; size: number; 
+/**
+ * replaced by a hard link to the earlier file
+ */
+linked: boolean }
+
+/**
+ * The settings a download ran with, kept so a slow or broken download can be explained
+ * after the fact. Taken every time the engine starts it.
+ */
+export type Environment = { 
+/**
+ * unix seconds
+ */
+started_at: number; version: string; threads: number; 
+/**
+ * KiB/s, 0 is unlimited
+ */
+speed_limit: number; user_agent: string; 
+/**
+ * without credentials, empty follows the environment's proxy variables
+ */
+proxy: string; interfaces: string[]; request_timeout: number; read_timeout: number; socket_buffer_size: number; tcp_nodelay: boolean; tcp_keepalive: number; allow_insecure: boolean; ca_certificate: string; chaos: boolean; 
+/**
+ * how failing ranges were retried
+ */
+range_retry?: RangeRetry }
+
+export type ExportFormat = "csv" | "json"
+
+/**
+ * Newest first
+ */
+export type HistoryPage = { downloads: Download[]; 
+/**
+ * finished, failed and paused downloads in all, for paging
+ */
+total: number }
+
+export type HistorySynced = { merged: number }
+
+/**
+ * Write and scheduling latencies since the last `take_metrics`
+ */
+export type IoMetrics = { writer_threads: boolean; writes: number; 
+/**
+ * time a chunk waited for a thread before its write started
+ */
+avg_queue_us: number; max_queue_us: number; avg_write_us: number; 
+/**
+ * worst delay seen by the lag probe, i.e. how late a UI command could start
+ */
+max_runtime_lag_us: number; 
+/**
+ * whether workers got pooled connections or opened new ones
+ */
+connections: ConnectionMetrics }
+
+export type JsonValue = null | boolean | number | string | JsonValue[] | Partial<{ [key in string]: JsonValue }>
+
+/**
+ * What the engine is doing right now
+ */
+export type LiveState = { downloaded: number; 
+/**
+ * bytes/s over the last second
+ */
+speed: number; 
+/**
+ * after redirects, None before the first response
+ */
+url: string | null; 
+/**
+ * empty for single connection downloads
+ */
+segments: Segment[]; workers: WorkerState[]; 
+/**
+ * bytes/s it's capped at, 0 is unlimited
+ */
+speed_limit: number }
+
+/**
+ * A language the backend has strings for
+ */
+export type Locale = { code: string; name: string }
+
+/**
+ * Guest mode, see `lock`: history and progress stay visible, nothing can be changed
+ */
+export type LockConfig = { enabled: boolean; 
+/**
+ * "pbkdf2:rounds:salt:hex" of the PIN `unlock_app` asks for, empty for none. Set
+ * through `lock.pin`, which takes the PIN itself. `get_settings` never shows it.
+ */
+pin_hash: string }
+
+/**
+ * What a maintenance run did to the history database
+ */
+export type Maintenance = { 
+/**
+ * bytes on disk, write-ahead log included
+ */
+size_before: number; size_after: number; 
+/**
+ * false when `integrity_check` found damage, `problems` says where
+ */
+ok: boolean; problems: string[]; took_ms: number }
+
+/**
+ * A file or folder inside a remote ZIP archive
+ */
+export type Member = { 
+/**
+ * path inside the archive, `/` separated, folders end in `/`
+ */
+name: string; 
+/**
+ * bytes once extracted
+ */
+size: number; 
+/**
+ * bytes it takes in the archive, what fetching it costs
+ */
+compressed_size: number; is_dir: boolean; 
+/**
+ * stored or deflated and not encrypted, the only kinds that can be fetched
+ */
+supported: boolean }
+
+export type NetworkConfig = { 
+/**
+ * local addresses or interface names (eth0, en0) to spread workers over,
+ * e.g. Ethernet + LTE to add up both uplinks; empty uses the default route
+ */
+interfaces: string[]; 
+/**
+ * receive window in KiB, 0 leaves it adaptive. Worth raising on high latency links
+ */
+socket_buffer_size: number; tcp_nodelay: boolean; 
+/**
+ * seconds, 0 turns keepalive off
+ */
+tcp_keepalive: number; 
+/**
+ * seconds a whole request may take, body included. 0 is no limit, which large
+ * downloads need, stalls are caught by `read_timeout` instead
+ */
+request_timeout: number; 
+/**
+ * seconds without a byte before a connection counts as stalled, 0 waits forever
+ */
+read_timeout: number; 
+/**
+ * `http://` or `https://` proxy url for every request, empty follows the
+ * environment's `HTTPS_PROXY` and friends, "none" goes direct
+ */
+proxy: string; 
+/**
+ * accept any certificate, for self-signed hosts on a trusted network only
+ */
+allow_insecure: boolean; 
+/**
+ * PEM file with extra root certificates, e.g. a corporate CA
+ */
+ca_certificate: string; 
+/**
+ * links from deep links, the extension, scripts and other apps can't reach
+ * localhost, the LAN or cloud metadata addresses until the user confirms
+ */
+block_private_targets: boolean; 
+/**
+ * host names or addresses that pass anyway, e.g. a NAS
+ */
+allowed_private_hosts: string[] }
+
+/**
+ * A notification as the frontend shows it and the history lists it
+ */
+export type Notification = { id: number; download_id: string | null; 
+/**
+ * the event that caused it, e.g. "download_completed"
+ */
+event: string; body: string; 
+/**
+ * unix seconds
+ */
+created_at: number; 
+/**
+ * shown even during Do Not Disturb
+ */
+critical: boolean; 
+/**
+ * false while it waits for DND to lift, or when notifications are off
+ */
+delivered: boolean }
+
+/**
+ * Somewhere besides the app that notifications go to
+ */
+export type NotificationChannel = ({ kind: "smtp"; host: string; port?: number; security?: string; username?: string; password?: string; from: string; to: string[] } | { kind: "telegram"; bot_token: string; chat_id: string } | { kind: "ntfy"; server?: string; topic: string; token?: string }) & { name?: string; enabled?: boolean; 
+/**
+ * notification events like "download_completed", empty is all of them
+ */
+events?: string[] }
+
+/**
+ * When notifications wait, `show_notifications` still turns them off entirely
+ */
+export type NotificationConfig = { 
+/**
+ * hold notifications while the OS is in Do Not Disturb / Focus Assist
+ */
+respect_dnd: boolean; 
+/**
+ * events shown even then, e.g. "download_failed"
+ */
+critical_events: string[]; 
+/**
+ * notifications kept for `get_notification_history`
+ */
+history_limit: number; 
+/**
+ * email, Telegram and ntfy, sent to whatever DND or `show_notifications` say
+ */
+channels: NotificationChannel[] }
+
+/**
+ * What a cancelled download left on disk, see `download.on_cancel`
+ */
+export type PartialFile = { 
+/**
+ * "kept" with its saved ranges for a resume, "truncated" or "deleted"
+ */
+action: string; 
+/**
+ * bytes still in the file
+ */
+size: number }
+
+/**
+ * Time span `export` covers, unix seconds, inclusive, open ended when left out
+ */
+export type Period = { since: number | null; until: number | null }
+
+/**
+ * The start of a remote file on disk, with what it looks like
+ */
+export type Preview = { 
+/**
+ * temp file holding the fetched bytes
+ */
+path: string; bytes: number; 
+/**
+ * the file goes on past `bytes`
+ */
+partial: boolean; filename: string; 
+/**
+ * full size, when the server said
+ */
+size: number | null; 
+/**
+ * what the server claims
+ */
+content_type: string | null; 
+/**
+ * sniffed from the magic bytes, None when unrecognised
+ */
+mime: string | null; extension: string | null; category: string }
+
+/**
+ * Links from outside that point at this machine or the local network, not added.
+ * They wait in the backend until `token` confirms them.
+ */
+export type PrivateTargetHeld = { 
+/**
+ * confirms them through `confirm_private_targets`
+ */
+token: string; urls: string[]; 
+/**
+ * one per url, which address it resolved to
+ */
+reasons: string[] }
+
+/**
+ * Profiles to switch between and where the running one keeps its data
+ */
+export type Profiles = { active: string; 
+/**
+ * `default` first, then the rest by name
+ */
+profiles: string[]; data_dir: string; 
+/**
+ * started with `--data-dir`, which takes the place of every profile
+ */
+overridden: boolean }
+
+/**
+ * A download going into or out of quarantine
+ */
+export type QuarantineChanged = { id: string; 
+/**
+ * "unscanned", "scanning" or "flagged", null once released
+ */
+state: string | null }
+
+/**
+ * A download entering the queue, or a resume checking on one
+ */
+export type QueueDownload = 
+/**
+ * from the extension or the add dialog
+ */
+({ type: "external" } & Queued) | ({ type: "deep_link" } & Queued) | 
+/**
+ * a resume is asking the server whether the file changed
+ */
+{ type: "resume_check"; id: string; url: string; filename: string; size: number | null } | { type: "resume_ready"; id: string; url: string; filename: string; size: number | null; bytes_received: number; resume_supported: boolean; etag: string | null; last_modified: string | null; needs_restart: boolean } | { type: "upload"; id: string; url: string; filename: string; size: number; destination: string; direction: string; status: string }
+
+export type Queued = { id: string; url: string; filename: string; size: number | null; destination: string; job_id: string | null; resume_supported: boolean; etag: string | null; last_modified: string | null; status: string; 
+/**
+ * name of the rule that matched
+ */
+rule: string | null }
+
+/**
+ * How workers retry a range that failed, per download
+ */
+export type RangeRetry = { 
+/**
+ * tries after the first before the download fails
+ */
+retries: number; 
+/**
+ * ms before the first retry, doubled for each one after it up to 30 s
+ */
+backoff_ms: number; 
+/**
+ * seconds a connection may go without data before the range is tried again,
+ * 0 leaves it to `network.read_timeout`
+ */
+stall_timeout: number }
+
+/**
+ * A folder downloads were saved to, for the add dialog to suggest
+ */
+export type RecentFolder = { path: string; 
+/**
+ * unix seconds of the newest download saved there, None for the download location
+ * when nothing went there yet
+ */
+last_used: number | null; 
+/**
+ * downloads saved there among the recent ones
+ */
+downloads: number; 
+/**
+ * the configured download location
+ */
+default: boolean; 
+/**
+ * false once it was moved or deleted, a download there creates it again
+ */
+exists: boolean; space: Space | null }
+
+/**
+ * Built-in web dashboard for headless installs, read at startup
+ */
+export type RemoteConfig = { enabled: boolean; 
+/**
+ * 0.0.0.0 opens it to the LAN
+ */
+bind: string; port: number; 
+/**
+ * asked for as `?token=` on the page and bearer auth on the API, made on the first
+ * start when empty
+ */
+token: string }
+
+/**
+ * Automatic retry of failed downloads, a download's own policy replaces it
+ */
+export type RetryConfig = { enabled: boolean; delay_minutes: number; max_attempts: number }
+
+export type RetryScheduled = { id: string; attempt: number; max_attempts: number; 
+/**
+ * seconds
+ */
+retry_in: number; 
+/**
+ * unix milliseconds
+ */
+retry_at: number }
+
+/**
+ * How `retry_failed` put each download back in
+ */
+export type RetrySummary = { 
+/**
+ * continuing from their partial data
+ */
+resumed: string[]; 
+/**
+ * changed on the server or the partial file is gone, started over
+ */
+restarted: string[]; 
+/**
+ * not failed, or the server couldn't be reached
+ */
+skipped: string[] }
+
+/**
+ * A url before and after the rewrite rules, with the ones that changed it
+ */
+export type Rewrite = { original: string; rewritten: string; 
+/**
+ * names of the rules that changed something, in the order they ran
+ */
+applied: string[] }
+
+/**
+ * Turns a url into the one actually downloaded, e.g. a pinned mirror or without tracking
+ * parameters. Every matching rule applies, in list order.
+ */
+export type RewriteRule = { name: string; 
+/**
+ * exact host or `*.example.com`, empty matches every host
+ */
+host: string; 
+/**
+ * regex over the whole url, replaced with `replacement` (`$1`, `${name}` for groups).
+ * Empty leaves the url as is
+ */
+pattern: string; replacement: string; 
+/**
+ * query parameters dropped afterwards, `utm_*` for every one starting with `utm_`
+ */
+strip_params: string[] }
+
+/**
+ * The rule a url gets and where it sits in the list
+ */
+export type RuleMatch = { index: number; rule: DownloadRule }
+
+/**
+ * Credentials for `s3://bucket/key` urls, presigned https urls don't need any
+ */
+export type S3Config = { access_key_id: string; secret_access_key: string; 
+/**
+ * temporary credentials only
+ */
+session_token: string; region: string; 
+/**
+ * MinIO, R2 and other S3 compatible hosts, empty for AWS
+ */
+endpoint: string; 
+/**
+ * send `x-amz-request-payer: requester` for requester pays buckets
+ */
+requester_pays: boolean }
+
+/**
+ * What cancelling a download could keep of it, for asking before it's thrown away
+ */
+export type Salvage = { 
+/**
+ * bytes on disk, all of them kept when it's cancelled for a later resume
+ */
+downloaded: number; 
+/**
+ * bytes in one piece from the start, what a truncated file keeps
+ */
+contiguous: number; 
+/**
+ * a later resume picks up where it stopped instead of starting over
+ */
+resumable: boolean }
+
+/**
+ * Rhai scripts with `on_add`, `on_complete` and `on_fail` functions, see `scripting`
+ */
+export type ScriptingConfig = { enabled: boolean; 
+/**
+ * every `.rhai` file in it runs, by name. Empty is `scripts` in the app data folder
+ */
+folder: string; 
+/**
+ * steps a script may take per hook before it's stopped
+ */
+max_operations: number }
+
+/**
+ * A range still to fetch, `start` moves up as bytes land
+ */
+export type Segment = { start: number; end: number }
+
+/**
+ * A command run for one download on request, e.g. `vlc {path}`. Words are split like a
+ * shell would but no shell runs it, see `downloads::actions::PLACEHOLDERS`
+ */
+export type SendAction = { name: string; command: string }
+
+export type SessionConfig = { history: boolean; metadata: boolean }
+
+/**
+ * Downloads that were running at the last quit
+ */
+export type SessionRestore = { ids: string[] }
+
+export type ShortcutConfig = { go_home: string; open_settings: string; add_download: string; open_details: string; open_history: string; toggle_sidebar: string; cancel_download: string; quit_app: string }
+
+/**
+ * The server sent another amount than the size it announced. The file was cut or
+ * extended to `actual` and the stored size corrected.
+ */
+export type SizeMismatch = { id: string; expected: number; actual: number }
+
+/**
+ * Room on the drive a folder is on
+ */
+export type Space = { 
+/**
+ * bytes this user can still write
+ */
+free: number; total: number }
+
+/**
+ * Speeds in bytes/s, `interval` seconds apart
+ */
+export type SpeedSeries = { interval: number; samples: number[] }
+
+/**
+ * History shared through a folder another tool keeps in sync (Dropbox, Syncthing)
+ */
+export type SyncConfig = { enabled: boolean; folder: string; 
+/**
+ * minutes between automatic syncs, read at startup
+ */
+interval_minutes: number }
+
+/**
+ * Counts of one sync run
+ */
+export type SyncReport = { exported: number; merged: number }
+
+export type ThreadConfig = { total_connections: number; per_task_connections: number; 
+/**
+ * downloads from one host running at once, the rest wait their turn. 0 is no cap
+ */
+max_concurrent_per_host?: number; 
+/**
+ * threads writing chunks to disk, 0 uses tokio's blocking pool. Read at startup
+ */
+io_threads?: number }
+
+/**
+ * What the history view shows for a finished download
+ */
+export type Thumbnail = { 
+/**
+ * png for images and videos, ico for executables
+ */
+path: string | null; 
+/**
+ * version info of executables
+ */
+info: Partial<{ [key in string]: string }> }
+
+export type ThumbnailReady = ({ 
+/**
+ * png for images and videos, ico for executables
+ */
+path: string | null; 
+/**
+ * version info of executables
+ */
+info: Partial<{ [key in string]: string }> }) & { id: string }
+
+/**
+ * Bytes received per day and where the month stands against the cap
+ */
+export type TransferStats = { 
+/**
+ * oldest first, days with nothing received are left out
+ */
+days: DayTotal[]; 
+/**
+ * received since `period_start`
+ */
+period_total: number; period_start: string; 
+/**
+ * first day of the next billing month
+ */
+next_reset: string; 
+/**
+ * bytes, None without a cap
+ */
+cap: number | null; cap_reached: boolean }
+
+/**
+ * A check of a queued download's url found something other than last time
+ */
+export type UrlHealth = { id: string; 
+/**
+ * "gone", "changed", or "alive" again after one of those
+ */
+health: string; 
+/**
+ * HTTP status when gone
+ */
+status: number | null }
+
+/**
+ * `host` is an exact name or `*.example.com` for the domain and its subdomains
+ */
+export type UserAgentRule = { host: string; user_agent: string }
+
+/**
+ * JSON POSTed to `url` on download events, e.g. for ntfy, Gotify or home automation
+ */
+export type Webhook = { name: string; url: string; 
+/**
+ * HMAC-SHA256 key for the `X-Tur-Signature` header, empty sends it unsigned
+ */
+secret: string; 
+/**
+ * "download_completed", "download_failed" and "queue_empty", empty is all of them
+ */
+events: string[]; enabled: boolean }
+
+/**
+ * What a worker or the coordinator just did, for `download_debug` in debug mode
+ */
+export type WorkerEvent = 
+/**
+ * `worker` fetches `range` from `url`, again from another source after a mirror failed
+ */
+{ kind: "assigned"; worker: number; range: Segment; url: string } | 
+/**
+ * once a second while it runs
+ */
+{ kind: "speed"; worker: number; speed: number; downloaded: number } | { kind: "retry"; worker: number; attempt: number; retries: number; wait_ms: number; error: string } | 
+/**
+ * the busiest range was cut at `range.start`, the worker asking next takes `range`
+ */
+{ kind: "stolen"; kept: Segment; range: Segment }
+
+/**
+ * One worker as the details pane shows it
+ */
+export type WorkerState = { 
+/**
+ * None while it waits for a range
+ */
+range: Segment | null; downloaded: number; 
+/**
+ * bytes/s over the last second or so
+ */
+speed: number; 
+/**
+ * failed requests it retried
+ */
+retries: number; 
+/**
+ * ms to open its connection ahead of the first range, None when it didn't
+ */
+setup_ms: number | null }